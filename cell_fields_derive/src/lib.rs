@@ -0,0 +1,305 @@
+//! Derives re-exported by rsplay.
+//!
+//! `#[derive(CellFields)]` (as `cell_fields::CellFields`) generates for a struct
+//! `Foo` a `FooCells` struct with the same fields, each wrapped in rsplay's `Cell`,
+//! or `RefCell` for fields marked `#[ref_cell]`, with conversions in both directions.
+//!
+//! `#[derive(SplitBorrow)]` (as `split_cell::SplitBorrow`) generates a `FooFields`
+//! struct with every field in its own `RefCell` and a borrow method per field.
+//!
+//! `#[derive(Trace)]` (as `gc::Trace`) implements tracing for `Gc` by tracing
+//! every field.
+//!
+//! The input is parsed by hand to stay free of dependencies, so only plain
+//! structs with named fields and no generics are supported.
+
+use proc_macro::{Delimiter, Spacing, TokenStream, TokenTree};
+
+#[proc_macro_derive(CellFields, attributes(ref_cell))]
+pub fn derive_cell_fields(input: TokenStream) -> TokenStream {
+    let output = match parse_struct(input) {
+        Ok(input) => expand(&input),
+        Err(message) => format!("compile_error!({:?});", message),
+    };
+    output.parse().unwrap()
+}
+
+#[proc_macro_derive(SplitBorrow)]
+pub fn derive_split_borrow(input: TokenStream) -> TokenStream {
+    let output = match parse_struct(input) {
+        Ok(input) => expand_split_borrow(&input),
+        Err(message) => format!("compile_error!({:?});", message),
+    };
+    output.parse().unwrap()
+}
+
+#[proc_macro_derive(Trace)]
+pub fn derive_trace(input: TokenStream) -> TokenStream {
+    let output = match parse_struct(input) {
+        Ok(input) => expand_trace(&input),
+        Err(message) => format!("compile_error!({:?});", message),
+    };
+    output.parse().unwrap()
+}
+
+struct Struct {
+    vis: String,
+    name: String,
+    fields: Vec<Field>,
+}
+
+struct Field {
+    vis: String,
+    name: String,
+    ty: String,
+    ref_cell: bool,
+}
+
+fn parse_struct(input: TokenStream) -> Result<Struct, String> {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut pos = 0;
+    skip_attributes(&tokens, &mut pos);
+    let vis = parse_visibility(&tokens, &mut pos);
+    match tokens.get(pos) {
+        Some(TokenTree::Ident(ident)) if ident.to_string() == "struct" => pos += 1,
+        _ => return Err("CellFields can only be derived for structs".to_string()),
+    }
+    let name = match tokens.get(pos) {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return Err("Expected a struct name".to_string()),
+    };
+    pos += 1;
+    match tokens.get(pos) {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => Ok(Struct {
+            vis,
+            name,
+            fields: parse_fields(group.stream())?,
+        }),
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '<' => {
+            Err("CellFields does not support generic structs".to_string())
+        }
+        _ => Err("CellFields needs a struct with named fields".to_string()),
+    }
+}
+
+fn parse_fields(input: TokenStream) -> Result<Vec<Field>, String> {
+    split_top_level_commas(input)
+        .into_iter()
+        .filter(|tokens| !tokens.is_empty())
+        .map(|tokens| parse_field(&tokens))
+        .collect()
+}
+
+fn parse_field(tokens: &[TokenTree]) -> Result<Field, String> {
+    let mut pos = 0;
+    let ref_cell = skip_attributes(tokens, &mut pos);
+    let vis = parse_visibility(tokens, &mut pos);
+    let name = match tokens.get(pos) {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return Err("Expected a field name".to_string()),
+    };
+    match tokens.get(pos + 1) {
+        Some(TokenTree::Punct(punct)) if punct.as_char() == ':' => {}
+        _ => return Err(format!("Expected a type for field `{}`", name)),
+    }
+    let ty: TokenStream = tokens[pos + 2..].iter().cloned().collect();
+    Ok(Field {
+        vis,
+        name,
+        ty: ty.to_string(),
+        ref_cell,
+    })
+}
+
+/// Skips `#[...]` attributes, returns whether one of them was `#[ref_cell]`.
+fn skip_attributes(tokens: &[TokenTree], pos: &mut usize) -> bool {
+    let mut ref_cell = false;
+    while let (Some(TokenTree::Punct(punct)), Some(TokenTree::Group(group))) =
+        (tokens.get(*pos), tokens.get(*pos + 1))
+    {
+        if punct.as_char() != '#' || group.delimiter() != Delimiter::Bracket {
+            break;
+        }
+        ref_cell |= group.stream().to_string() == "ref_cell";
+        *pos += 2;
+    }
+    ref_cell
+}
+
+fn parse_visibility(tokens: &[TokenTree], pos: &mut usize) -> String {
+    match tokens.get(*pos) {
+        Some(TokenTree::Ident(ident)) if ident.to_string() == "pub" => {
+            *pos += 1;
+            match tokens.get(*pos) {
+                Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                    *pos += 1;
+                    format!("pub{}", group)
+                }
+                _ => "pub".to_string(),
+            }
+        }
+        _ => String::new(),
+    }
+}
+
+/// Commas inside `<...>` belong to the field's type, not the field list.
+fn split_top_level_commas(input: TokenStream) -> Vec<Vec<TokenTree>> {
+    let mut chunks = vec![Vec::new()];
+    let mut depth = 0usize;
+    let mut after_dash = false;
+    for token in input {
+        if let TokenTree::Punct(punct) = &token {
+            match punct.as_char() {
+                ',' if depth == 0 => {
+                    chunks.push(Vec::new());
+                    continue;
+                }
+                '<' => depth += 1,
+                // The `>` of `->` doesn't close anything.
+                '>' if !after_dash => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            after_dash = punct.as_char() == '-' && punct.spacing() == Spacing::Joint;
+        } else {
+            after_dash = false;
+        }
+        chunks.last_mut().unwrap().push(token);
+    }
+    chunks
+}
+
+fn expand(input: &Struct) -> String {
+    let Struct { vis, name, fields } = input;
+    let cells = format!("{}Cells", name);
+    let mut field_defs = String::new();
+    let mut wrap = String::new();
+    let mut unwrap = String::new();
+    for field in fields {
+        let cell = if field.ref_cell {
+            "crate::ref_cell::RefCell"
+        } else {
+            "crate::cell::Cell"
+        };
+        field_defs += &format!("{} {}: {}<{}>,", field.vis, field.name, cell, field.ty);
+        wrap += &format!("{0}: {1}::new(value.{0}),", field.name, cell);
+        unwrap += &format!("{0}: self.{0}.into_inner(),", field.name);
+    }
+    format!(
+        "
+        #[doc = \"`{name}` with every field wrapped in a cell, generated by `CellFields`.\"]
+        {vis} struct {cells} {{ {field_defs} }}
+
+        impl ::core::convert::From<{name}> for {cells} {{
+            fn from(value: {name}) -> Self {{
+                Self {{ {wrap} }}
+            }}
+        }}
+
+        impl {cells} {{
+            #[allow(dead_code)]
+            {vis} fn into_inner(self) -> {name} {{
+                {name} {{ {unwrap} }}
+            }}
+        }}
+
+        impl {name} {{
+            #[allow(dead_code)]
+            {vis} fn into_cells(self) -> {cells} {{
+                ::core::convert::From::from(self)
+            }}
+        }}
+        ",
+        name = name,
+        vis = vis,
+        cells = cells,
+        field_defs = field_defs,
+        wrap = wrap,
+        unwrap = unwrap,
+    )
+}
+
+fn expand_split_borrow(input: &Struct) -> String {
+    let Struct { vis, name, fields } = input;
+    let split = format!("{}Fields", name);
+    let mut field_defs = String::new();
+    let mut accessors = String::new();
+    let mut wrap = String::new();
+    let mut unwrap = String::new();
+    for Field {
+        vis: field_vis,
+        name: field,
+        ty,
+        ..
+    } in fields
+    {
+        field_defs += &format!("{}: crate::ref_cell::RefCell<{}>,", field, ty);
+        accessors += &format!(
+            "
+            #[allow(dead_code)]
+            #[track_caller]
+            {vis} fn {field}(&self) -> crate::refs::Ref<'_, {ty}> {{
+                self.{field}.borrow()
+            }}
+
+            #[allow(dead_code)]
+            #[track_caller]
+            {vis} fn {field}_mut(&self) -> crate::refs::RefMut<'_, {ty}> {{
+                self.{field}.borrow_mut()
+            }}
+            ",
+            vis = field_vis,
+            field = field,
+            ty = ty,
+        );
+        wrap += &format!("{0}: crate::ref_cell::RefCell::new(value.{0}),", field);
+        unwrap += &format!("{0}: fields.{0}.into_inner(),", field);
+    }
+    format!(
+        "
+        #[doc = \"`{name}` with a borrow flag per field, generated by `SplitBorrow`.\"]
+        {vis} struct {split} {{ {field_defs} }}
+
+        impl {split} {{ {accessors} }}
+
+        impl crate::split_cell::SplitBorrow for {name} {{
+            type Fields = {split};
+
+            fn split(value: Self) -> {split} {{
+                {split} {{ {wrap} }}
+            }}
+
+            fn join(fields: {split}) -> Self {{
+                {name} {{ {unwrap} }}
+            }}
+        }}
+        ",
+        name = name,
+        vis = vis,
+        split = split,
+        field_defs = field_defs,
+        accessors = accessors,
+        wrap = wrap,
+        unwrap = unwrap,
+    )
+}
+
+fn expand_trace(input: &Struct) -> String {
+    let trace_fields: String = input
+        .fields
+        .iter()
+        .map(|field| format!("crate::gc::Trace::trace(&self.{}, tracer);", field.name))
+        .collect();
+    format!(
+        "
+        // SAFETY: Traces exactly the fields, which trace what they own.
+        unsafe impl crate::gc::Trace for {name} {{
+            #[allow(unused_variables)]
+            fn trace(&self, tracer: &mut crate::gc::Tracer<'_>) {{
+                {trace_fields}
+            }}
+        }}
+        ",
+        name = input.name,
+        trace_fields = trace_fields,
+    )
+}
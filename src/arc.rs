@@ -0,0 +1,939 @@
+use std::alloc::Layout;
+use std::borrow::Borrow;
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ops::Deref;
+use std::pin::Pin;
+use std::process;
+use std::ptr::{self, NonNull};
+
+// The counts go through loom when model checking, run the models with
+// `RUSTFLAGS="--cfg loom" cargo test --release loom_`.
+#[cfg(loom)]
+use loom::{
+    hint,
+    sync::atomic::{self, AtomicUsize, Ordering},
+};
+#[cfg(not(loom))]
+use std::{
+    hint,
+    sync::atomic::{self, AtomicUsize, Ordering},
+};
+
+/// Far below usize::MAX, so the threads racing past the check in `clone` can't
+/// overflow the count before one of them aborts.
+const MAX_REFCOUNT: usize = isize::MAX as usize;
+
+/// The weak count while `Arc::is_unique` checks the strong count, which keeps
+/// `downgrade` from creating a Weak in between.
+const LOCKED: usize = usize::MAX;
+
+/// The heap allocation shared by all clones of an `Arc` and its `Weak`s.
+///
+/// The value is dropped when the last `Arc` goes away, the allocation itself once
+/// there are no `Weak`s left either. All `Arc`s together hold one weak reference,
+/// so `weak` only reaches zero when both are gone.
+///
+/// `repr(C)` pins the counts in front of the value, so `Arc::from_raw` can find
+/// them from a pointer to the value.
+#[repr(C)]
+struct ArcInner<T: ?Sized> {
+    strong: AtomicUsize,
+    weak: AtomicUsize,
+    // Dropped separately from the allocation, which Weaks keep alive.
+    value: ManuallyDrop<T>,
+}
+
+fn check_overflow(old: usize) {
+    if old > MAX_REFCOUNT {
+        // Unwinding would leave the count too high for everybody else, and
+        // leaking clones in a loop must not lead to a use after free.
+        process::abort();
+    }
+}
+
+/// The offset of a value with the given alignment in its ArcInner.
+fn value_offset(align: usize) -> usize {
+    let value = Layout::from_size_align(0, align).unwrap();
+    Layout::new::<ArcInner<()>>().extend(value).unwrap().1
+}
+
+/// A thread-safe reference-counted pointer, the `Rc` to share values between
+/// threads: `Arc<Mutex<T>>`.
+///
+/// The counts are atomic, the value only ever shared: it needs to be `Sync` to be
+/// reachable from several threads and `Send` because any of them may drop it.
+pub struct Arc<T: ?Sized> {
+    ptr: NonNull<ArcInner<T>>,
+    // Tells dropck that we own an ArcInner<T>.
+    _marker: PhantomData<ArcInner<T>>,
+}
+
+// SAFETY: Clones on other threads share the value and the last one drops it.
+unsafe impl<T: ?Sized + Sync + Send> Send for Arc<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for Arc<T> {}
+
+impl<T> Arc<T> {
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        let boxed = Box::new(ArcInner {
+            strong: AtomicUsize::new(1),
+            weak: AtomicUsize::new(1),
+            value: ManuallyDrop::new(value),
+        });
+        let ptr = NonNull::from(Box::leak(boxed));
+        #[cfg(feature = "leak-check")]
+        crate::leak_check::register(
+            ptr.as_ptr() as usize,
+            "Arc",
+            std::any::type_name::<T>(),
+            std::panic::Location::caller(),
+        );
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// A pinned `Arc`, for values that must not move once they are shared.
+    ///
+    /// The value is never moved by an `Arc`, and a `Pin<Arc<T>>` doesn't give access
+    /// to `get_mut`, `make_mut` or `try_unwrap` which could move it.
+    #[track_caller]
+    pub fn pin(value: T) -> Pin<Self> {
+        // SAFETY: See above, the value stays in place until it is dropped.
+        unsafe { Pin::new_unchecked(Arc::new(value)) }
+    }
+
+    /// Moves the value out if this is the only `Arc`, outstanding `Weak`s don't matter.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        // Acquire pairs with the Release decrements of the Arcs dropped before.
+        // With the count at zero Weaks can't upgrade anymore.
+        if this
+            .inner()
+            .strong
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return Err(this);
+        }
+        // SAFETY: We were the last Arc and nobody can create new ones.
+        Ok(unsafe { Arc::take_value(this) })
+    }
+
+    /// Returns the value if this is the last `Arc`, otherwise just drops this one.
+    ///
+    /// Unlike `Arc::try_unwrap(this).ok()`, exactly one of several threads calling
+    /// this on the last clones gets the value.
+    pub fn into_inner(this: Self) -> Option<T> {
+        let this = ManuallyDrop::new(this);
+        if this.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return None;
+        }
+        atomic::fence(Ordering::Acquire);
+        // SAFETY: We were the last Arc.
+        Some(unsafe { Arc::take_value(ManuallyDrop::into_inner(this)) })
+    }
+
+    /// Clone-on-write: clones the value into a fresh `Arc` unless this is the only
+    /// one. Outstanding `Weak`s are left with the old allocation and won't upgrade.
+    #[track_caller]
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        // Taking the strong count to zero keeps Weaks from upgrading meanwhile.
+        if this
+            .inner()
+            .strong
+            .compare_exchange(1, 0, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            *this = Arc::new((**this).clone());
+        } else if this.inner().weak.load(Ordering::Relaxed) != 1 {
+            // Only Weaks are left, move the value away from them. Nothing in between
+            // can panic, `this` is overwritten without being dropped.
+            unsafe {
+                // SAFETY: We were the last Arc and nobody can create new ones.
+                let value = Arc::take_value(ptr::read(this));
+                ptr::write(this, Arc::new(value));
+            }
+        } else {
+            // Nobody else can reach the allocation, we are unique after all.
+            this.inner().strong.store(1, Ordering::Release);
+        }
+        // SAFETY: `this` is the only Arc now and borrowed mutably.
+        unsafe { &mut (*this.ptr.as_ptr()).value }
+    }
+
+    /// Moves out the value of an Arc whose strong count already dropped to zero.
+    ///
+    /// SAFETY: No other Arc may point to the allocation.
+    unsafe fn take_value(this: Self) -> T {
+        let this = ManuallyDrop::new(this);
+        let value = ManuallyDrop::take(&mut (*this.ptr.as_ptr()).value);
+        // The weak reference held by all the Arcs together.
+        drop(Weak { ptr: this.ptr });
+        value
+    }
+}
+
+impl<T: ?Sized> Arc<T> {
+    /// A mutable reference to the value if there are no other `Arc`s or `Weak`s.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Arc::is_unique(this) {
+            // SAFETY: Nobody else can reach the value while `this` is borrowed mutably.
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        let weak = &this.inner().weak;
+        let mut current = weak.load(Ordering::Relaxed);
+        loop {
+            if current == LOCKED {
+                // is_unique is looking, it will be done shortly.
+                hint::spin_loop();
+                current = weak.load(Ordering::Relaxed);
+                continue;
+            }
+            check_overflow(current);
+            // Acquire pairs with the Release in is_unique, see there.
+            match weak.compare_exchange_weak(
+                current,
+                current + 1,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return Weak { ptr: this.ptr },
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// A pointer to the value, valid as long as some `Arc` keeps it alive.
+    pub fn as_ptr(this: &Self) -> *const T {
+        // Not through Deref, the pointer keeps the provenance of the whole allocation.
+        unsafe { ptr::addr_of_mut!((*this.ptr.as_ptr()).value) as *const T }
+    }
+
+    /// Turns the `Arc` into a pointer to its value, for example to pass it
+    /// through a `void*` user-data argument. The reference is leaked until it comes
+    /// back through `Arc::from_raw`.
+    pub fn into_raw(this: Self) -> *const T {
+        let this = ManuallyDrop::new(this);
+        Arc::as_ptr(&this)
+    }
+
+    /// Takes back a reference given out by `Arc::into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw` of an `Arc<U>` where `U` has the same
+    /// layout as `T`, and each such pointer may only be taken back once.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let offset = value_offset(mem::align_of_val(&*ptr));
+        // Moves the address back to the start of the ArcInner, keeping the metadata.
+        let inner = (ptr as *const ArcInner<T>).byte_sub(offset) as *mut ArcInner<T>;
+        Self {
+            ptr: NonNull::new_unchecked(inner),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Adds a reference to the value behind a pointer from `Arc::into_raw`,
+    /// to be released with `Arc::decrement_strong_count` or `Arc::from_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw` and its value still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let this = ManuallyDrop::new(Arc::from_raw(ptr));
+        mem::forget(Arc::clone(&this));
+    }
+
+    /// Releases a reference to the value behind a pointer from `Arc::into_raw`,
+    /// dropping the value if it was the last one.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Arc::into_raw` and the reference it stands for
+    /// not have been released yet.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Arc::from_raw(ptr));
+    }
+
+    /// Whether both point to the same allocation, rather than to equal values.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    /// The number of `Arc`s, which other threads may change at any moment.
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.load(Ordering::Relaxed)
+    }
+
+    /// The number of `Weak`s, which other threads may change at any moment.
+    pub fn weak_count(this: &Self) -> usize {
+        match this.inner().weak.load(Ordering::Relaxed) {
+            // Only while another thread's get_mut has found no Weaks.
+            LOCKED => 0,
+            count => count - 1,
+        }
+    }
+
+    fn is_unique(this: &Self) -> bool {
+        // Locking the weak count at one makes sure there are no Weaks and that none
+        // can be created while the strong count is checked: a Weak could upgrade
+        // and drop the Arc again in between. Acquire pairs with the Release
+        // decrement of dropped Weaks, which may have used the value after upgrading.
+        if this
+            .inner()
+            .weak
+            .compare_exchange(1, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        // Acquire pairs with the Release decrements of other Arcs, so their uses of
+        // the value happen before ours. With us holding the only Arc mutably and no
+        // Weaks the count can't go back up.
+        let unique = this.inner().strong.load(Ordering::Acquire) == 1;
+        // Release makes downgrades after this see our uses of the value.
+        this.inner().weak.store(1, Ordering::Release);
+        unique
+    }
+
+    fn inner(&self) -> &ArcInner<T> {
+        // SAFETY: The allocation is alive as long as there is an Arc pointing to it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+// Moving an Arc never moves its value.
+impl<T: ?Sized> Unpin for Arc<T> {}
+
+impl<T: ?Sized> Clone for Arc<T> {
+    fn clone(&self) -> Self {
+        // Relaxed is enough: a new reference can only be made from an existing one,
+        // which already keeps the value alive, and passing the clone to another
+        // thread synchronizes by itself.
+        check_overflow(self.inner().strong.fetch_add(1, Ordering::Relaxed));
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Arc<T> {
+    fn drop(&mut self) {
+        // Release makes every use of the value through this Arc happen before the
+        // decrement, so the thread dropping the value sees them all.
+        if self.inner().strong.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        // Pairs with the Release decrements of all the other Arcs.
+        atomic::fence(Ordering::Acquire);
+        unsafe {
+            // SAFETY: We were the last Arc, nobody can reach the value anymore.
+            ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).value);
+        }
+        // The weak reference held by all the Arcs together.
+        drop(Weak { ptr: self.ptr });
+    }
+}
+
+impl<T: ?Sized> Deref for Arc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Arc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Arc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Default> Default for Arc<T> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Arc<T> {
+    #[track_caller]
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Arc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Arc<T> {}
+
+impl<T: ?Sized + Debug> Debug for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + Display> Display for Arc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+/// A non-owning reference to the value of an `Arc`, which doesn't keep the value
+/// alive, for caches and observer lists that shouldn't extend its lifetime.
+pub struct Weak<T: ?Sized> {
+    // Points to usize::MAX for Weak::new(), which has no allocation.
+    ptr: NonNull<ArcInner<T>>,
+}
+
+// SAFETY: A Weak upgrades to an Arc, so it may move and be shared just as well.
+unsafe impl<T: ?Sized + Sync + Send> Send for Weak<T> {}
+unsafe impl<T: ?Sized + Sync + Send> Sync for Weak<T> {}
+
+impl<T> Weak<T> {
+    /// A Weak that never upgrades, without allocating anything.
+    pub fn new() -> Self {
+        Self {
+            ptr: NonNull::new(ptr::without_provenance_mut(usize::MAX)).unwrap(),
+        }
+    }
+}
+
+impl<T: ?Sized> Weak<T> {
+    /// Returns an `Arc` to the value unless it was already dropped.
+    pub fn upgrade(&self) -> Option<Arc<T>> {
+        // Unlike Arc::clone this can't just increment: the count may have dropped
+        // to zero with the value being dropped right now, an increment would hand
+        // out an Arc to it. So only a nonzero count is incremented, in a CAS loop.
+        // Once at zero the count never goes up again, Arc::clone needs an Arc.
+        //
+        // Orderings:
+        // - Relaxed loads and failures: the count read doesn't publish anything,
+        //   a failed attempt touches no memory but the count.
+        // - Acquire on success: the value may have been written through
+        //   `&mut T` by make_mut, which then releases the count with a Release
+        //   store; the new Arc must see those writes.
+        // make_mut taking the count from one to zero for a moment makes us fail,
+        // which is right, it may move the value away.
+        self.inner()?
+            .strong
+            .fetch_update(Ordering::Acquire, Ordering::Relaxed, |current| {
+                if current == 0 {
+                    return None;
+                }
+                check_overflow(current);
+                Some(current + 1)
+            })
+            .ok()?;
+        Some(Arc {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        ptr::addr_eq(self.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    /// The number of `Arc`s, zero once the value was dropped.
+    pub fn strong_count(&self) -> usize {
+        self.inner()
+            .map_or(0, |inner| inner.strong.load(Ordering::Relaxed))
+    }
+
+    /// The number of `Weak`s, or zero once the value was dropped. Only
+    /// approximate while other threads are cloning or dropping.
+    pub fn weak_count(&self) -> usize {
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return 0,
+        };
+        let weak = inner.weak.load(Ordering::Relaxed);
+        if inner.strong.load(Ordering::Relaxed) == 0 {
+            0
+        } else {
+            // Excluding the weak reference held by the Arcs.
+            weak - 1
+        }
+    }
+
+    /// The counts, never a reference to the whole `ArcInner`: the value may be
+    /// dropped by another thread meanwhile.
+    fn inner(&self) -> Option<WeakInner<'_>> {
+        if (self.ptr.as_ptr() as *mut u8).addr() == usize::MAX {
+            return None;
+        }
+        let inner = self.ptr.as_ptr();
+        // SAFETY: The allocation lives as long as there are Weaks pointing to it,
+        // only the fields of the counts are borrowed.
+        unsafe {
+            Some(WeakInner {
+                strong: &*ptr::addr_of!((*inner).strong),
+                weak: &*ptr::addr_of!((*inner).weak),
+            })
+        }
+    }
+}
+
+/// The counts of the allocation behind a `Weak`.
+struct WeakInner<'a> {
+    strong: &'a AtomicUsize,
+    weak: &'a AtomicUsize,
+}
+
+impl<T: ?Sized> Clone for Weak<T> {
+    fn clone(&self) -> Self {
+        if let Some(inner) = self.inner() {
+            // Relaxed like Arc::clone, and the count can't be LOCKED: is_unique
+            // only locks it when there are no Weaks at all.
+            check_overflow(inner.weak.fetch_add(1, Ordering::Relaxed));
+        }
+        Self { ptr: self.ptr }
+    }
+}
+
+impl<T: ?Sized> Drop for Weak<T> {
+    fn drop(&mut self) {
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return,
+        };
+        if inner.weak.fetch_sub(1, Ordering::Release) != 1 {
+            return;
+        }
+        // Pairs with the Release decrements of all the other Weaks and Arcs.
+        atomic::fence(Ordering::Acquire);
+        // SAFETY: No Arc or Weak is left, the box came from Box::leak and its
+        // value was already dropped (ManuallyDrop won't drop it again).
+        #[cfg(feature = "leak-check")]
+        crate::leak_check::unregister(self.ptr.as_ptr() as *mut u8 as usize);
+        drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+    }
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(Weak)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Arc, Weak};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<Arc<Mutex<i32>>>();
+        assert_sync::<Arc<Mutex<i32>>>();
+        assert_not_impl!(Arc<std::cell::Cell<i32>>: Send);
+        assert_not_impl!(Arc<std::sync::MutexGuard<'static, i32>>: Send);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let total = Arc::new(Mutex::new(0));
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                let total = total.clone();
+                s.spawn(move || *total.lock().unwrap() += 1);
+            }
+        });
+
+        assert_eq!(*total.lock().unwrap(), 8);
+        assert_eq!(Arc::strong_count(&total), 1);
+    }
+
+    #[test]
+    fn test_dropped_once_by_last_thread() {
+        let drops = AtomicUsize::new(0);
+        let arc = Arc::new(DropCounter(&drops));
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                let arc = arc.clone();
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        drop(arc.clone());
+                    }
+                });
+            }
+        });
+
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(arc);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_formatting_and_comparison() {
+        let arc = Arc::new(5);
+        let clone = arc.clone();
+
+        assert!(Arc::ptr_eq(&arc, &clone));
+        assert_eq!(arc, Arc::new(5));
+        assert_eq!(format!("{} {:?}", arc, Arc::new("x")), "5 \"x\"");
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let arc = Arc::new(5);
+        let clone = arc.clone();
+
+        let arc = Arc::try_unwrap(arc).unwrap_err();
+        drop(clone);
+
+        assert_eq!(Arc::try_unwrap(arc), Ok(5));
+    }
+
+    #[test]
+    fn test_into_inner_once_across_threads() {
+        let drops = AtomicUsize::new(0);
+        let arc = Arc::new(DropCounter(&drops));
+
+        let mut arcs = vec![arc.clone(); 7];
+        arcs.push(arc);
+
+        let unwrapped = thread::scope(|s| {
+            let threads: Vec<_> = arcs
+                .into_iter()
+                .map(|arc| s.spawn(move || Arc::into_inner(arc).is_some()))
+                .collect();
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .filter(|&unwrapped| unwrapped)
+                .count()
+        });
+
+        assert_eq!(unwrapped, 1);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut arc = Arc::new(1);
+        let clone = arc.clone();
+
+        assert!(Arc::get_mut(&mut arc).is_none());
+        drop(clone);
+        *Arc::get_mut(&mut arc).unwrap() += 1;
+
+        assert_eq!(*arc, 2);
+    }
+
+    #[test]
+    fn test_make_mut() {
+        let mut arc = Arc::new(vec![1]);
+        let clone = arc.clone();
+
+        Arc::make_mut(&mut arc).push(2);
+        let ptr = arc.as_ptr();
+        Arc::make_mut(&mut arc).push(3);
+
+        assert_eq!(*clone, [1]);
+        assert_eq!(*arc, [1, 2, 3]);
+        assert_eq!(arc.as_ptr(), ptr);
+    }
+
+    #[test]
+    fn test_upgrade_while_alive() {
+        let drops = AtomicUsize::new(0);
+        let arc = Arc::new(DropCounter(&drops));
+        let weak = Arc::downgrade(&arc);
+
+        assert!(weak.upgrade().is_some());
+        drop(arc);
+
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert!(weak.upgrade().is_none());
+        assert!(Weak::<i32>::new().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_reexported_from_sync() {
+        let arc = crate::sync::Arc::new(1);
+        let weak: crate::sync::Weak<i32> = Arc::downgrade(&arc);
+
+        assert_eq!(weak.upgrade().as_deref(), Some(&1));
+    }
+
+    #[test]
+    fn test_upgrade_races_with_drop() {
+        for _ in 0..100 {
+            let drops = AtomicUsize::new(0);
+            let arc = Arc::new(DropCounter(&drops));
+            let weak = Arc::downgrade(&arc);
+
+            thread::scope(|s| {
+                s.spawn(move || drop(arc));
+                for _ in 0..2 {
+                    let weak = weak.clone();
+                    s.spawn(move || {
+                        while let Some(arc) = weak.upgrade() {
+                            drop(arc);
+                        }
+                    });
+                }
+            });
+
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    #[test]
+    fn test_weak_blocks_get_mut() {
+        let mut arc = Arc::new(1);
+        let weak = Arc::downgrade(&arc);
+
+        assert!(Arc::get_mut(&mut arc).is_none());
+        drop(weak);
+
+        assert!(Arc::get_mut(&mut arc).is_some());
+    }
+
+    #[test]
+    fn test_make_mut_disassociates_weaks() {
+        let mut arc = Arc::new(1);
+        let weak = Arc::downgrade(&arc);
+
+        *Arc::make_mut(&mut arc) += 1;
+
+        assert!(weak.upgrade().is_none());
+        assert_eq!(*arc, 2);
+    }
+
+    #[test]
+    fn test_weak_counts() {
+        let arc = Arc::new(1);
+        let weak = Arc::downgrade(&arc);
+        let clone = weak.clone();
+
+        assert_eq!(Arc::weak_count(&arc), 2);
+        assert_eq!((weak.strong_count(), weak.weak_count()), (1, 2));
+        assert!(weak.ptr_eq(&clone));
+
+        drop(arc);
+        assert_eq!((weak.strong_count(), weak.weak_count()), (0, 0));
+        assert_send::<Weak<Mutex<i32>>>();
+        assert_sync::<Weak<Mutex<i32>>>();
+    }
+
+    #[test]
+    fn test_raw_round_trip_across_threads() {
+        let drops = AtomicUsize::new(0);
+        let raw = Arc::into_raw(Arc::new(DropCounter(&drops)));
+        // Raw pointers aren't Send, user data crosses threads in a wrapper.
+        struct UserData<'a>(*const DropCounter<'a>);
+        unsafe impl Send for UserData<'_> {}
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                unsafe { Arc::increment_strong_count(raw) };
+                let user_data = UserData(raw);
+                s.spawn(move || {
+                    let arc = unsafe { Arc::from_raw(user_data.0) };
+                    assert_eq!(Arc::as_ptr(&arc), user_data.0);
+                });
+            }
+        });
+
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        unsafe { Arc::decrement_strong_count(raw) };
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_pin_shared_between_threads() {
+        use std::marker::PhantomPinned;
+
+        struct Pinned {
+            value: i32,
+            _pinned: PhantomPinned,
+        }
+
+        let pinned = Arc::pin(Pinned {
+            value: 7,
+            _pinned: PhantomPinned,
+        });
+        let address = &*pinned as *const Pinned as usize;
+
+        thread::scope(|s| {
+            let pinned = pinned.clone();
+            s.spawn(move || {
+                assert_eq!(pinned.value, 7);
+                assert_eq!(&*pinned as *const Pinned as usize, address);
+            });
+        });
+    }
+
+    // Stress tests of the interleavings of downgrade, upgrade, drop and make_mut,
+    // `loom_tests` explores them exhaustively. Run them with `cargo miri test`
+    // and `-Zmiri-many-seeds` to also check the accesses of racing Weaks.
+
+    #[test]
+    fn test_downgrade_upgrade_drop_interleavings() {
+        for _ in 0..200 {
+            let drops = AtomicUsize::new(0);
+            let arc = Arc::new((DropCounter(&drops), 42));
+            let weak = Arc::downgrade(&arc);
+            let clone = arc.clone();
+
+            thread::scope(|s| {
+                s.spawn(move || drop(arc));
+                s.spawn(move || {
+                    let weak = Arc::downgrade(&clone);
+                    drop(clone);
+                    if let Some(arc) = weak.upgrade() {
+                        assert_eq!(arc.1, 42);
+                    }
+                });
+                s.spawn(|| {
+                    while let Some(arc) = weak.upgrade() {
+                        assert_eq!(arc.1, 42);
+                        drop(Arc::downgrade(&arc));
+                    }
+                });
+            });
+
+            assert!(weak.upgrade().is_none());
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        }
+    }
+
+    #[test]
+    fn test_make_mut_races_with_upgrade() {
+        for _ in 0..200 {
+            let mut arc = Arc::new(vec![1]);
+            let weak = Arc::downgrade(&arc);
+
+            thread::scope(|s| {
+                s.spawn(|| {
+                    for _ in 0..10 {
+                        if let Some(arc) = weak.upgrade() {
+                            assert_eq!(*arc, [1]);
+                        }
+                    }
+                });
+                Arc::make_mut(&mut arc).push(2);
+            });
+
+            assert_eq!(*arc, [1, 2]);
+            // Either the value moved away from the Weak, or it was cloned and the
+            // upgraded Arc to the original is gone by now.
+            assert!(weak.upgrade().is_none());
+        }
+    }
+}
+
+#[cfg(all(test, loom))]
+mod loom_tests {
+    use super::Arc;
+    use loom::thread;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct DropCounter(std::sync::Arc<AtomicUsize>);
+
+    impl Drop for DropCounter {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn loom_upgrade_races_with_last_drop() {
+        loom::model(|| {
+            let drops = std::sync::Arc::new(AtomicUsize::new(0));
+            let arc = Arc::new((DropCounter(drops.clone()), 42));
+            let weak = Arc::downgrade(&arc);
+
+            let upgrader = thread::spawn(move || weak.upgrade().map(|arc| arc.1));
+            drop(arc);
+
+            let upgraded = upgrader.join().unwrap();
+            assert!(upgraded.is_none() || upgraded == Some(42));
+            assert_eq!(drops.load(Ordering::Relaxed), 1);
+        });
+    }
+
+    #[test]
+    fn loom_downgrade_upgrade_drop() {
+        loom::model(|| {
+            let arc = Arc::new(7);
+            let clone = arc.clone();
+            let weak = Arc::downgrade(&arc);
+
+            let dropper = thread::spawn(move || {
+                let weak = Arc::downgrade(&clone);
+                drop(clone);
+                weak.upgrade().map(|arc| *arc)
+            });
+            let upgraded = weak.upgrade().map(|arc| *arc);
+            drop(arc);
+
+            let upgraded_there = dropper.join().unwrap();
+            assert!(upgraded.is_none() || upgraded == Some(7));
+            assert!(upgraded_there.is_none() || upgraded_there == Some(7));
+            assert!(weak.upgrade().is_none());
+        });
+    }
+
+    #[test]
+    fn loom_make_mut_races_with_upgrade() {
+        loom::model(|| {
+            let mut arc = Arc::new(1);
+            let weak = Arc::downgrade(&arc);
+
+            let upgrader = thread::spawn(move || weak.upgrade().map(|arc| *arc));
+            *Arc::make_mut(&mut arc) += 1;
+
+            // An upgrade before make_mut sees the old value, make_mut then
+            // clones. After it, the Weak is left behind.
+            let upgraded = upgrader.join().unwrap();
+            assert!(upgraded.is_none() || upgraded == Some(1));
+            assert_eq!(*arc, 2);
+        });
+    }
+}
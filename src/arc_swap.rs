@@ -0,0 +1,198 @@
+use std::fmt::{self, Debug};
+use std::hint;
+use std::marker::PhantomData;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+
+use crate::arc::Arc;
+use crate::atomic::{AtomicPtr, AtomicUsize};
+
+/// An `Arc<T>` that can be replaced as a whole while other threads read it, for
+/// read-mostly shared state like configuration.
+///
+/// `load` is lock-free and returns a snapshot that stays valid however often the
+/// value is replaced afterwards. Writers take a lock among themselves and wait
+/// for the loads in flight, so they can starve under a steady stream of readers.
+pub struct ArcSwap<T: ?Sized> {
+    // A Box holding the current Arc, AtomicPtr needs a thin pointer.
+    current: AtomicPtr<Arc<T>>,
+    // Loads in flight, which may still clone the Arc behind a replaced pointer.
+    readers: AtomicUsize,
+    // Only writers free the Boxes, one at a time they can read `current` freely.
+    writer: Mutex<()>,
+    _marker: PhantomData<Arc<T>>,
+}
+
+// SAFETY: Behaves like an Arc<T> with loads on other threads cloning it.
+unsafe impl<T: ?Sized + Send + Sync> Send for ArcSwap<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for ArcSwap<T> {}
+
+impl<T: ?Sized> ArcSwap<T> {
+    pub fn new(value: Arc<T>) -> Self {
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            readers: AtomicUsize::new(0),
+            writer: Mutex::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// A snapshot of the current value.
+    pub fn load(&self) -> Arc<T> {
+        // SeqCst orders the increment before loading the pointer, and against the
+        // writer's swap and read of the count: either the writer sees us and waits,
+        // or we see its new pointer.
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        let current = self.current.load(Ordering::SeqCst);
+        // SAFETY: The writer replacing `current` waits for us before freeing it.
+        let value = unsafe { (*current).clone() };
+        // SeqCst: a writer seeing the decrement also sees the clone done, before
+        // it frees the Box.
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+        value
+    }
+
+    pub fn store(&self, value: Arc<T>) {
+        drop(self.swap(value));
+    }
+
+    /// Stores `value` and returns the previous one.
+    pub fn swap(&self, value: Arc<T>) -> Arc<T> {
+        let _writer = self.writer.lock().unwrap();
+        self.replace(value)
+    }
+
+    /// Stores `new` if the current value is the same allocation as `current`.
+    /// Returns the previous value, wrapped in `Ok` if the store happened.
+    pub fn compare_and_swap(&self, current: &Arc<T>, new: Arc<T>) -> Result<Arc<T>, Arc<T>> {
+        let _writer = self.writer.lock().unwrap();
+        // SAFETY: Only writers free the Box and we hold the lock.
+        let previous = unsafe { &*self.current.load(Ordering::SeqCst) };
+        if Arc::ptr_eq(previous, current) {
+            Ok(self.replace(new))
+        } else {
+            Err(previous.clone())
+        }
+    }
+
+    pub fn into_inner(self) -> Arc<T> {
+        let current = self.current.load_relaxed();
+        std::mem::forget(self);
+        // SAFETY: We own the Box and nobody else can reach it anymore.
+        *unsafe { Box::from_raw(current) }
+    }
+
+    /// Swaps in `value` and frees the old Box once no load can be using it.
+    /// Must only be called with the writer lock held.
+    fn replace(&self, value: Arc<T>) -> Arc<T> {
+        let new = Box::into_raw(Box::new(value));
+        let old = self.current.swap(new, Ordering::SeqCst);
+        // SeqCst pairs with the decrements of the loads that still saw `old`.
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            hint::spin_loop();
+        }
+        // SAFETY: Loads from now on see `new`, the ones that saw `old` are done.
+        *unsafe { Box::from_raw(old) }
+    }
+}
+
+impl<T: ?Sized> Drop for ArcSwap<T> {
+    fn drop(&mut self) {
+        // SAFETY: With `&mut self` no load is in flight.
+        drop(unsafe { Box::from_raw(*self.current.get_mut()) });
+    }
+}
+
+impl<T> From<T> for ArcSwap<T> {
+    fn from(value: T) -> Self {
+        Self::new(Arc::new(value))
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for ArcSwap<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ArcSwap").field(&self.load()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArcSwap;
+    use crate::arc::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_load_is_a_snapshot() {
+        let config = ArcSwap::from(1);
+        let before = config.load();
+
+        config.store(Arc::new(2));
+
+        assert_eq!((*before, *config.load()), (1, 2));
+        assert_eq!(*config.swap(Arc::new(3)), 2);
+        assert_eq!(*config.into_inner(), 3);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let config = ArcSwap::from("old");
+        let seen = config.load();
+
+        assert_eq!(
+            config.compare_and_swap(&seen, Arc::new("new")),
+            Ok(seen.clone())
+        );
+        assert_eq!(
+            config.compare_and_swap(&seen, Arc::new("newer")),
+            Err(Arc::new("new"))
+        );
+        assert_eq!(format!("{:?}", config), "ArcSwap(\"new\")");
+    }
+
+    #[test]
+    fn test_readers_see_whole_values() {
+        let config = ArcSwap::from(vec![0; 16]);
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let snapshot = config.load();
+                        assert!(snapshot.iter().all(|&n| n == snapshot[0]));
+                    }
+                });
+            }
+            for i in 1..=1000 {
+                config.store(Arc::new(vec![i; 16]));
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(config.load()[0], 1000);
+    }
+
+    #[test]
+    fn test_concurrent_compare_and_swap_increments() {
+        let counter = ArcSwap::from(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        loop {
+                            let current = counter.load();
+                            let next = Arc::new(*current + 1);
+                            if counter.compare_and_swap(&current, next).is_ok() {
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*counter.load(), 400);
+    }
+}
@@ -0,0 +1,388 @@
+//! Atomics with the memory ordering spelled out in the operation's name.
+//!
+//! The wrappers offer what the crate's lock-free types need: loads, stores,
+//! swaps, compare-exchanges, and counting on `usize`. The named methods
+//! (`load_acquire`, `store_release`, ...) cover the common protocols, the ones
+//! taking an `Ordering` check it in debug builds, with an explanation of why a
+//! combination makes no sense rather than std's bare panic. Counters also check
+//! for wrapping around in debug builds.
+//!
+//! Only the fixed-width integers the wrappers don't cover, like the futex words
+//! in [`sync`](crate::sync) or the values [`AtomicCell`](crate::atomic_cell::AtomicCell)
+//! transmutes, use std's atomics directly. So does [`Arc`](crate::arc::Arc),
+//! which loom swaps them out of for model checking.
+//!
+//! ```ignore
+//! // Publishing: everything written before the release store is visible to
+//! // whoever sees `true` with an acquire load.
+//! data.write(42);
+//! ready.store_release(true);
+//!
+//! if ready.load_acquire() {
+//!     assert_eq!(data.read(), 42);
+//! }
+//! ```
+
+use std::fmt::{self, Debug};
+use std::sync::atomic::{self, Ordering};
+
+fn check_load(order: Ordering) {
+    debug_assert!(
+        !matches!(order, Ordering::Release | Ordering::AcqRel),
+        "A load can't be {:?}, it doesn't publish anything, use Acquire",
+        order
+    );
+}
+
+fn check_store(order: Ordering) {
+    debug_assert!(
+        !matches!(order, Ordering::Acquire | Ordering::AcqRel),
+        "A store can't be {:?}, it doesn't observe anything, use Release",
+        order
+    );
+}
+
+fn check_failure(failure: Ordering) {
+    // The failure ordering only applies to the load of a failed exchange.
+    debug_assert!(
+        !matches!(failure, Ordering::Release | Ordering::AcqRel),
+        "A failed compare_exchange only loads, its ordering can't be {:?}",
+        failure
+    );
+}
+
+/// An ordering fence, synchronizing through the atomic accesses around it.
+pub fn fence(order: Ordering) {
+    debug_assert!(order != Ordering::Relaxed, "A Relaxed fence orders nothing");
+    atomic::fence(order);
+}
+
+/// Later loads can't move before the fence, it makes an earlier Relaxed load
+/// act as an Acquire one.
+pub fn fence_acquire() {
+    atomic::fence(Ordering::Acquire);
+}
+
+/// Earlier stores can't move after the fence, it makes a later Relaxed store act
+/// as a Release one.
+pub fn fence_release() {
+    atomic::fence(Ordering::Release);
+}
+
+macro_rules! atomic {
+    ($(#[$doc:meta])* $name:ident $(<$t:ident>)?, $value:ty, $std:ty) => {
+        $(#[$doc])*
+        #[repr(transparent)]
+        pub struct $name$(<$t>)? {
+            inner: $std,
+        }
+
+        impl$(<$t>)? $name$(<$t>)? {
+            pub const fn new(value: $value) -> Self {
+                Self {
+                    inner: <$std>::new(value),
+                }
+            }
+
+            pub fn into_inner(self) -> $value {
+                self.inner.into_inner()
+            }
+
+            /// No atomics needed, `&mut self` guarantees no other thread has access.
+            pub fn get_mut(&mut self) -> &mut $value {
+                self.inner.get_mut()
+            }
+
+            pub fn load(&self, order: Ordering) -> $value {
+                check_load(order);
+                self.inner.load(order)
+            }
+
+            /// Just the value, no ordering of other memory accesses.
+            pub fn load_relaxed(&self) -> $value {
+                self.inner.load(Ordering::Relaxed)
+            }
+
+            /// Sees everything written before the Release store of the value.
+            pub fn load_acquire(&self) -> $value {
+                self.inner.load(Ordering::Acquire)
+            }
+
+            pub fn store(&self, value: $value, order: Ordering) {
+                check_store(order);
+                self.inner.store(value, order)
+            }
+
+            pub fn store_relaxed(&self, value: $value) {
+                self.inner.store(value, Ordering::Relaxed)
+            }
+
+            /// Publishes everything written before to whoever loads the value
+            /// with Acquire.
+            pub fn store_release(&self, value: $value) {
+                self.inner.store(value, Ordering::Release)
+            }
+
+            pub fn swap(&self, value: $value, order: Ordering) -> $value {
+                self.inner.swap(value, order)
+            }
+
+            /// Both publishes, like `store_release`, and observes, like
+            /// `load_acquire`.
+            pub fn swap_acq_rel(&self, value: $value) -> $value {
+                self.inner.swap(value, Ordering::AcqRel)
+            }
+
+            pub fn compare_exchange(
+                &self,
+                current: $value,
+                new: $value,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<$value, $value> {
+                check_failure(failure);
+                self.inner.compare_exchange(current, new, success, failure)
+            }
+
+            /// May fail spuriously, for retry loops.
+            pub fn compare_exchange_weak(
+                &self,
+                current: $value,
+                new: $value,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<$value, $value> {
+                check_failure(failure);
+                self.inner.compare_exchange_weak(current, new, success, failure)
+            }
+
+            /// The exchange of a lock: Acquire on success, Relaxed on failure.
+            pub fn compare_exchange_acquire(
+                &self,
+                current: $value,
+                new: $value,
+            ) -> Result<$value, $value> {
+                self.inner
+                    .compare_exchange(current, new, Ordering::Acquire, Ordering::Relaxed)
+            }
+
+            /// Like `compare_exchange_acquire`, but may fail spuriously.
+            pub fn compare_exchange_weak_acquire(
+                &self,
+                current: $value,
+                new: $value,
+            ) -> Result<$value, $value> {
+                self.inner
+                    .compare_exchange_weak(current, new, Ordering::Acquire, Ordering::Relaxed)
+            }
+
+            /// The underlying std atomic, for APIs that take one.
+            pub fn as_std(&self) -> &$std {
+                &self.inner
+            }
+        }
+
+        impl$(<$t>)? Default for $name$(<$t>)?
+        where
+            $value: Default,
+        {
+            fn default() -> Self {
+                Self::new(<$value>::default())
+            }
+        }
+
+        impl$(<$t>)? From<$value> for $name$(<$t>)? {
+            fn from(value: $value) -> Self {
+                Self::new(value)
+            }
+        }
+
+        impl$(<$t>)? Debug for $name$(<$t>)? {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                Debug::fmt(&self.load_relaxed(), f)
+            }
+        }
+    };
+}
+
+atomic!(
+    /// A `bool` shared between threads.
+    AtomicBool,
+    bool,
+    atomic::AtomicBool
+);
+
+atomic!(
+    /// A `usize` shared between threads, typically a counter or a bit set.
+    AtomicUsize,
+    usize,
+    atomic::AtomicUsize
+);
+
+atomic!(
+    /// A raw pointer shared between threads, for swapping in new versions of a
+    /// value.
+    AtomicPtr<T>,
+    *mut T,
+    atomic::AtomicPtr<T>
+);
+
+impl AtomicUsize {
+    /// Returns the previous value. Wrapping around is a bug, caught in debug
+    /// builds.
+    pub fn fetch_add(&self, value: usize, order: Ordering) -> usize {
+        let previous = self.inner.fetch_add(value, order);
+        debug_assert!(
+            previous.checked_add(value).is_some(),
+            "AtomicUsize overflowed adding {} to {}",
+            value,
+            previous
+        );
+        previous
+    }
+
+    /// Returns the previous value. Going below zero is a bug, caught in debug
+    /// builds.
+    pub fn fetch_sub(&self, value: usize, order: Ordering) -> usize {
+        let previous = self.inner.fetch_sub(value, order);
+        debug_assert!(
+            previous >= value,
+            "AtomicUsize underflowed subtracting {} from {}",
+            value,
+            previous
+        );
+        previous
+    }
+
+    /// Counting without ordering anything else, like taking a new reference.
+    pub fn fetch_add_relaxed(&self, value: usize) -> usize {
+        self.fetch_add(value, Ordering::Relaxed)
+    }
+
+    /// Counting down while publishing, like dropping a reference: the last
+    /// owner sees the others' writes after an Acquire fence.
+    pub fn fetch_sub_release(&self, value: usize) -> usize {
+        self.fetch_sub(value, Ordering::Release)
+    }
+}
+
+impl AtomicBool {
+    /// Sets the flag, returns the previous value.
+    pub fn fetch_or(&self, value: bool, order: Ordering) -> bool {
+        self.inner.fetch_or(value, order)
+    }
+
+    /// Clears the flag if `value` is false, returns the previous value.
+    pub fn fetch_and(&self, value: bool, order: Ordering) -> bool {
+        self.inner.fetch_and(value, order)
+    }
+}
+
+// SAFETY: Only the pointer is shared, like std's AtomicPtr.
+unsafe impl<T> Send for AtomicPtr<T> {}
+unsafe impl<T> Sync for AtomicPtr<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_publish_with_release_and_acquire() {
+        let data = AtomicUsize::new(0);
+        let ready = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                data.store_relaxed(42);
+                ready.store_release(true);
+            });
+            while !ready.load_acquire() {
+                thread::yield_now();
+            }
+            assert_eq!(data.load_relaxed(), 42);
+        });
+    }
+
+    #[test]
+    fn test_counting() {
+        let counter = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        counter.fetch_add_relaxed(1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(counter.fetch_sub_release(4000), 4000);
+        assert_eq!(format!("{:?}", counter), "0");
+    }
+
+    #[test]
+    fn test_compare_exchange() {
+        let mut value = 1;
+        let ptr = AtomicPtr::new(std::ptr::null_mut());
+
+        assert!(ptr
+            .compare_exchange_acquire(std::ptr::null_mut(), &mut value)
+            .is_ok());
+        assert_eq!(
+            ptr.compare_exchange(
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+                Ordering::AcqRel,
+                Ordering::Acquire
+            ),
+            Err(&mut value as *mut i32)
+        );
+        assert_eq!(
+            ptr.swap_acq_rel(std::ptr::null_mut()),
+            &mut value as *mut i32
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "A load can't be Release")]
+    fn test_release_load_is_caught() {
+        AtomicBool::new(false).load(Ordering::Release);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "A store can't be Acquire")]
+    fn test_acquire_store_is_caught() {
+        AtomicBool::new(false).store(true, Ordering::Acquire);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "its ordering can't be AcqRel")]
+    fn test_releasing_failed_exchange_is_caught() {
+        let _ = AtomicUsize::new(0).compare_exchange(1, 2, Ordering::AcqRel, Ordering::AcqRel);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "AtomicUsize underflowed")]
+    fn test_underflow_is_caught() {
+        AtomicUsize::new(0).fetch_sub_release(1);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn test_overflow_is_caught() {
+        let counter = AtomicUsize::new(usize::MAX - 1);
+
+        let result = std::panic::catch_unwind(|| counter.fetch_add(2, Ordering::AcqRel));
+        let message = result.unwrap_err().downcast::<String>().unwrap();
+        assert_eq!(
+            *message,
+            format!("AtomicUsize overflowed adding 2 to {}", usize::MAX - 1)
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt::{self, Debug};
+use std::hint;
+use std::mem::{self, ManuallyDrop};
+use std::ptr;
+// The value is transmuted to the atomic integer or pointer of its size, which
+// crate::atomic doesn't wrap.
+use std::sync::atomic::{AtomicPtr, AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+use crate::atomic::AtomicBool;
+use crate::unsafe_cell::UnsafeCell;
+
+/// A thread-safe `Cell`. Uses the native atomic instructions when `T` has the size
+/// and alignment of a lock-free atomic integer and falls back to a global table of
+/// spinlocks otherwise.
+///
+/// Like in crossbeam, the native path compares and copies `T` as raw bits, which
+/// assumes `T` has no padding bytes.
+#[repr(transparent)]
+pub struct AtomicCell<T> {
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: All accesses to the value are either atomic or done under a lock,
+// values are moved between threads, so only T: Send is needed.
+unsafe impl<T: Send> Send for AtomicCell<T> {}
+unsafe impl<T: Send> Sync for AtomicCell<T> {}
+
+fn can_transmute<A, B>() -> bool {
+    mem::size_of::<A>() == mem::size_of::<B>() && mem::align_of::<A>() >= mem::align_of::<B>()
+}
+
+/// Reinterprets the bits of `value` as `U`, the caller guarantees the sizes match.
+unsafe fn transmute_bits<T, U>(value: T) -> U {
+    let value = ManuallyDrop::new(value);
+    mem::transmute_copy(&*value)
+}
+
+/// Runs `$atomic_op` with `$a` bound to the matching atomic integer if there is one,
+/// `$fallback_op` otherwise.
+///
+/// Pointer-sized values go through `AtomicPtr`, so that the pointers they may carry,
+/// like in `Option<Box<_>>`, keep their provenance.
+macro_rules! atomic {
+    (@check, $t:ty, $atomic:ty, $ptr:expr, $a:ident, $atomic_op:expr) => {
+        if can_transmute::<$t, $atomic>() {
+            let $a: &$atomic = unsafe {
+                // SAFETY: The atomic has the same size and no stricter alignment than T.
+                &*($ptr as *const $atomic)
+            };
+            break $atomic_op;
+        }
+    };
+    ($t:ty, $ptr:expr, $a:ident, $atomic_op:expr, $fallback_op:expr) => {
+        loop {
+            atomic!(@check, $t, AtomicU8, $ptr, $a, $atomic_op);
+            atomic!(@check, $t, AtomicU16, $ptr, $a, $atomic_op);
+            atomic!(@check, $t, AtomicPtr<()>, $ptr, $a, $atomic_op);
+            atomic!(@check, $t, AtomicU32, $ptr, $a, $atomic_op);
+            atomic!(@check, $t, AtomicU64, $ptr, $a, $atomic_op);
+            break $fallback_op;
+        }
+    };
+}
+
+const LOCK_COUNT: usize = 67;
+
+// Cells that don't fit a native atomic are protected by a lock picked by their address.
+static LOCKS: [AtomicBool; LOCK_COUNT] = {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const UNLOCKED: AtomicBool = AtomicBool::new(false);
+    [UNLOCKED; LOCK_COUNT]
+};
+
+struct LockGuard(&'static AtomicBool);
+
+impl LockGuard {
+    fn lock<T>(ptr: *const T) -> Self {
+        let lock = &LOCKS[ptr as usize % LOCK_COUNT];
+        while lock.compare_exchange_weak_acquire(false, true).is_err() {
+            hint::spin_loop();
+        }
+        LockGuard(lock)
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        self.0.store_release(false);
+    }
+}
+
+impl<T> AtomicCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Whether operations on this type use native atomics instead of locks.
+    pub fn is_lock_free() -> bool {
+        can_transmute::<T, AtomicU8>()
+            || can_transmute::<T, AtomicU16>()
+            || can_transmute::<T, AtomicPtr<()>>()
+            || can_transmute::<T, AtomicU32>()
+            || can_transmute::<T, AtomicU64>()
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    pub fn load(&self) -> T
+    where
+        T: Copy,
+    {
+        let ptr = self.as_ptr();
+        atomic! {
+            T, ptr, a,
+            unsafe { transmute_bits(a.load(Ordering::Acquire)) },
+            {
+                let _guard = LockGuard::lock(ptr);
+                unsafe { ptr::read(ptr) }
+            }
+        }
+    }
+
+    pub fn store(&self, value: T) {
+        drop(self.swap(value));
+    }
+
+    pub fn swap(&self, value: T) -> T {
+        let ptr = self.as_ptr();
+        atomic! {
+            T, ptr, a,
+            unsafe { transmute_bits(a.swap(transmute_bits(value), Ordering::AcqRel)) },
+            {
+                let _guard = LockGuard::lock(ptr);
+                unsafe { ptr::replace(ptr, value) }
+            }
+        }
+    }
+
+    /// Stores `new` if the current value equals `current`. Returns the previous
+    /// value, wrapped in `Ok` if the store happened.
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T>
+    where
+        T: Copy + Eq,
+    {
+        let ptr = self.as_ptr();
+        atomic! {
+            T, ptr, a,
+            {
+                let mut current_bits = unsafe { transmute_bits(current) };
+                let new_bits = unsafe { transmute_bits(new) };
+                loop {
+                    match a.compare_exchange(
+                        current_bits,
+                        new_bits,
+                        Ordering::AcqRel,
+                        Ordering::Acquire,
+                    ) {
+                        Ok(prev) => break Ok(unsafe { transmute_bits(prev) }),
+                        Err(prev_bits) => {
+                            let prev: T = unsafe { transmute_bits(prev_bits) };
+                            // Equal values may have different bit patterns, retry with the actual ones.
+                            if prev != current {
+                                break Err(prev);
+                            }
+                            current_bits = prev_bits;
+                        }
+                    }
+                }
+            },
+            {
+                let _guard = LockGuard::lock(ptr);
+                let prev = unsafe { ptr::read(ptr) };
+                if prev == current {
+                    unsafe { ptr::write(ptr, new) };
+                    Ok(prev)
+                } else {
+                    Err(prev)
+                }
+            }
+        }
+    }
+
+    /// Applies `f` until it either returns `None` or its result is stored without interference.
+    pub fn fetch_update<F>(&self, mut f: F) -> Result<T, T>
+    where
+        T: Copy + Eq,
+        F: FnMut(T) -> Option<T>,
+    {
+        let mut prev = self.load();
+        while let Some(next) = f(prev) {
+            match self.compare_exchange(prev, next) {
+                Ok(prev) => return Ok(prev),
+                Err(actual) => prev = actual,
+            }
+        }
+        Err(prev)
+    }
+}
+
+impl<T: Default> Default for AtomicCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for AtomicCell<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Copy + Debug> Debug for AtomicCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AtomicCell")
+            .field("value", &self.load())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_lock_freedom_depends_on_layout() {
+        assert!(AtomicCell::<u32>::is_lock_free());
+        assert!(AtomicCell::<Option<std::num::NonZeroU64>>::is_lock_free());
+        // Right size, but only 2-aligned.
+        assert!(!AtomicCell::<(u16, u16)>::is_lock_free());
+        assert!(!AtomicCell::<[u8; 3]>::is_lock_free());
+        assert!(!AtomicCell::<[u64; 4]>::is_lock_free());
+    }
+
+    #[test]
+    fn test_native_operations() {
+        let cell = AtomicCell::new(7u32);
+
+        cell.store(8);
+        assert_eq!(cell.swap(9), 8);
+        assert_eq!(cell.compare_exchange(1, 2), Err(9));
+        assert_eq!(cell.compare_exchange(9, 10), Ok(9));
+        assert_eq!(cell.fetch_update(|x| Some(x * 2)), Ok(10));
+        assert_eq!(cell.load(), 20);
+    }
+
+    #[test]
+    fn test_fallback_operations() {
+        let cell = AtomicCell::new([1u64, 2, 3, 4]);
+
+        assert_eq!(cell.swap([5; 4]), [1, 2, 3, 4]);
+        assert_eq!(cell.compare_exchange([0; 4], [6; 4]), Err([5; 4]));
+        assert_eq!(cell.compare_exchange([5; 4], [6; 4]), Ok([5; 4]));
+        assert_eq!(cell.load(), [6; 4]);
+    }
+
+    #[test]
+    fn test_non_copy_values_are_dropped() {
+        let rc = std::rc::Rc::new(());
+        let cell = AtomicCell::new(Some(rc.clone()));
+
+        cell.store(None);
+
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_concurrent_fetch_update() {
+        let native = AtomicCell::new(0u64);
+        let locked = AtomicCell::new((0u64, 0u64));
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        native.fetch_update(|x| Some(x + 1)).unwrap();
+                        locked.fetch_update(|(x, y)| Some((x + 1, y + 2))).unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(native.load(), 4000);
+        assert_eq!(locked.load(), (4000, 8000));
+    }
+}
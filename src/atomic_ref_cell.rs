@@ -0,0 +1,236 @@
+use std::fmt::{self, Debug};
+use std::panic::Location;
+use std::sync::atomic::Ordering;
+
+use crate::atomic::AtomicUsize;
+use crate::ref_cell::{BorrowError, BorrowErrorKind, BorrowMutError};
+use crate::refs::{BorrowFlag, FlagRef, FlagRefMut};
+use crate::unsafe_cell::UnsafeCell;
+
+// The high bit marks a writer, the remaining bits count readers.
+const WRITER: usize = !(usize::MAX >> 1);
+const MAX_READERS: usize = WRITER >> 1;
+
+/// The borrow flag of an [`AtomicRefCell`].
+pub struct AtomicBorrowFlag {
+    state: AtomicUsize,
+}
+
+impl AtomicBorrowFlag {
+    const fn new() -> Self {
+        Self {
+            state: AtomicUsize::new(0),
+        }
+    }
+
+    fn try_acquire_shared(&self) -> Result<(), BorrowErrorKind> {
+        // Optimistically register as a reader, Acquire pairs with the Release of the
+        // last writer so we see its writes.
+        let prev = self.state.fetch_add(1, Ordering::Acquire);
+        if prev & WRITER != 0 {
+            self.state.fetch_sub_release(1);
+            return Err(BorrowErrorKind::BlockedByExclusiveBorrow);
+        }
+        if prev >= MAX_READERS {
+            self.state.fetch_sub_release(1);
+            panic!("Too many AtomicRefCell readers");
+        }
+        Ok(())
+    }
+
+    fn try_acquire_exclusive(&self) -> Result<(), BorrowErrorKind> {
+        // Acquire pairs with the Release of previous readers and writers.
+        match self
+            .state
+            .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+        {
+            Ok(_) => Ok(()),
+            Err(state) if state & WRITER != 0 => Err(BorrowErrorKind::BlockedByExclusiveBorrow),
+            Err(_) => Err(BorrowErrorKind::BlockedBySharedBorrows),
+        }
+    }
+}
+
+impl BorrowFlag for AtomicBorrowFlag {
+    fn release_shared(&self) {
+        // Release publishes our reads-done to the next writer.
+        self.state.fetch_sub_release(1);
+    }
+
+    fn release_exclusive(&self) {
+        // Not a plain store of 0: readers that failed may still be undoing their increment.
+        self.state.fetch_sub_release(WRITER);
+    }
+}
+
+pub type AtomicRef<'cell, T> = FlagRef<'cell, T, AtomicBorrowFlag>;
+pub type AtomicRefMut<'cell, T> = FlagRefMut<'cell, T, AtomicBorrowFlag>;
+
+/// A `RefCell` that can be shared between threads. Conflicting borrows fail
+/// (or panic) instead of blocking.
+pub struct AtomicRefCell<T> {
+    flag: AtomicBorrowFlag,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The atomic borrow flag guarantees there's either one `&mut T` or any number
+// of `&T`, which may live on different threads: `&T` needs Sync, `&mut T` needs Send.
+unsafe impl<T: Send + Sync> Sync for AtomicRefCell<T> {}
+
+impl<T> AtomicRefCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            flag: AtomicBorrowFlag::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> AtomicRef<'_, T> {
+        match self.try_borrow() {
+            Ok(borrow) => borrow,
+            Err(err) => panic!("Value borrowed mutably, can't borrow: {}", err),
+        }
+    }
+
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<AtomicRef<'_, T>, BorrowError> {
+        match self.flag.try_acquire_shared() {
+            Ok(()) => Ok(unsafe {
+                // SAFETY: The flag guarantees there is no writer.
+                FlagRef::new(&self.flag, &*self.value.get())
+            }),
+            Err(_) => Err(BorrowError::new(None, Location::caller())),
+        }
+    }
+
+    #[track_caller]
+    pub fn borrow_mut(&self) -> AtomicRefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(borrow) => borrow,
+            Err(err) => panic!("Value already borrowed: {}", err),
+        }
+    }
+
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, T>, BorrowMutError> {
+        match self.flag.try_acquire_exclusive() {
+            Ok(()) => Ok(unsafe {
+                // SAFETY: The flag guarantees there are no other borrows.
+                FlagRefMut::new(&self.flag, &mut *self.value.get())
+            }),
+            Err(kind) => Err(BorrowMutError::new(kind, None, Location::caller())),
+        }
+    }
+}
+
+impl<T: Default> Default for AtomicRefCell<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Debug> Debug for AtomicRefCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_borrow() {
+            Ok(borrow) => f
+                .debug_struct("AtomicRefCell")
+                .field("value", &*borrow)
+                .finish(),
+            Err(_) => f
+                .debug_struct("AtomicRefCell")
+                .field("value", &format_args!("<borrowed>"))
+                .finish(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_borrow_rules() {
+        let cell = AtomicRefCell::new(42);
+        {
+            let b1 = cell.borrow();
+            let b2 = cell.borrow();
+            assert_eq!((*b1, *b2), (42, 42));
+            assert_eq!(
+                cell.try_borrow_mut().unwrap_err().kind(),
+                BorrowErrorKind::BlockedBySharedBorrows
+            );
+        }
+        {
+            let mut writer = cell.borrow_mut();
+            *writer = 43;
+            assert!(cell.try_borrow().is_err());
+            assert_eq!(
+                cell.try_borrow_mut().unwrap_err().kind(),
+                BorrowErrorKind::BlockedByExclusiveBorrow
+            );
+        }
+        assert_eq!(*cell.borrow(), 43);
+    }
+
+    #[test]
+    fn test_failed_reader_does_not_corrupt_flag() {
+        let cell = AtomicRefCell::new(42);
+
+        let writer = cell.borrow_mut();
+        assert!(cell.try_borrow().is_err());
+        drop(writer);
+
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let cell = AtomicRefCell::new(Vec::new());
+
+        thread::scope(|s| {
+            for i in 0..8 {
+                let cell = &cell;
+                s.spawn(move || loop {
+                    if let Ok(mut v) = cell.try_borrow_mut() {
+                        v.push(i);
+                        break;
+                    }
+                });
+            }
+        });
+
+        let mut values = cell.into_inner();
+        values.sort_unstable();
+        assert_eq!(values, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_guards_can_cross_threads() {
+        let cell = AtomicRefCell::new(42);
+        let borrow = cell.borrow();
+
+        thread::scope(|s| {
+            s.spawn(move || assert_eq!(*borrow, 42));
+        });
+
+        assert!(cell.try_borrow_mut().is_ok());
+    }
+
+    #[test]
+    fn test_debug() {
+        let cell = AtomicRefCell::new(42);
+        assert_eq!(format!("{:?}", cell), "AtomicRefCell { value: 42 }");
+        let _writer = cell.borrow_mut();
+        assert_eq!(format!("{:?}", cell), "AtomicRefCell { value: <borrowed> }");
+    }
+}
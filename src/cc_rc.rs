@@ -0,0 +1,665 @@
+//! A reference-counted pointer that can reclaim reference cycles.
+//!
+//! `CcRc` frees values like `Rc` as soon as their count drops to zero. A value
+//! whose count is decremented without reaching zero may be part of a cycle and
+//! is buffered as a candidate. `collect_cycles` then runs the synchronous
+//! trial deletion of Bacon and Rajan ("Concurrent Cycle Collection in
+//! Reference Counted Systems", 2001) on the candidates: it subtracts the
+//! references coming from inside the candidate subgraphs, and whatever ends up
+//! at zero is only referenced by garbage and gets freed.
+//!
+//! Finding those internal references needs the `Trace` trait, which lists the
+//! `CcRc`s a value owns, including those behind `RefCell`s.
+//!
+//! Garbage values are dropped while their neighbours may already be gone, so a
+//! `Drop` impl must not follow the `CcRc`s it owns: dereferencing or cloning a
+//! `CcRc` to a value that is being collected panics.
+
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+
+/// Lists the `CcRc`s owned by a value, so `collect_cycles` can follow them.
+///
+/// # Safety
+///
+/// `trace` must visit every `CcRc` that the value owns at most once and must not
+/// visit any other. A missed `CcRc` only keeps its cycle alive, a `CcRc` visited
+/// in excess can get a live value freed. Values without `CcRc`s implement it
+/// with an empty `trace`. `trace` must not panic, a collection interrupted
+/// halfway leaves the counts wrong.
+pub unsafe trait Trace {
+    fn trace(&self, tracer: &mut Tracer<'_>);
+}
+
+/// Collects the `CcRc`s a value visits in `Trace::trace`.
+pub struct Tracer<'a> {
+    visit: &'a mut dyn FnMut(NodePtr),
+}
+
+impl Tracer<'_> {
+    pub fn visit<T: Trace + 'static>(&mut self, cc: &CcRc<T>) {
+        (self.visit)(cc.ptr);
+    }
+}
+
+/// The colors of the paper.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Color {
+    /// In use or free.
+    Black,
+    /// Possibly the member of a garbage cycle.
+    Gray,
+    /// A member of a garbage cycle.
+    White,
+    /// A possible root of a garbage cycle.
+    Purple,
+    /// Being freed by `collect_cycles`, which frees the allocation afterwards.
+    Collecting,
+    /// Freed, but the drop of a garbage value moved a `CcRc` pointing here out
+    /// of the garbage. The last such `CcRc` frees the allocation.
+    Dead,
+}
+
+struct CcBox<T: ?Sized> {
+    strong: Cell<usize>,
+    color: Cell<Color>,
+    // Whether the node is in the candidate buffer, which then owns the allocation
+    // even after the value was dropped.
+    buffered: Cell<bool>,
+    value: ManuallyDrop<T>,
+}
+
+type NodePtr = NonNull<CcBox<dyn Trace>>;
+
+thread_local! {
+    // Nodes that may be the root of a garbage cycle.
+    static CANDIDATES: RefCell<Vec<NodePtr>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A single-threaded reference-counted pointer whose cycles `collect_cycles`
+/// reclaims, for graphs that can't be untangled with `Weak`s.
+pub struct CcRc<T: Trace + 'static> {
+    ptr: NodePtr,
+    _marker: PhantomData<CcBox<T>>,
+}
+
+impl<T: Trace + 'static> CcRc<T> {
+    pub fn new(value: T) -> Self {
+        let boxed: Box<CcBox<dyn Trace>> = Box::new(CcBox {
+            strong: Cell::new(1),
+            color: Cell::new(Color::Black),
+            buffered: Cell::new(false),
+            value: ManuallyDrop::new(value),
+        });
+        Self {
+            ptr: NonNull::from(Box::leak(boxed)),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        node(this.ptr).strong.get()
+    }
+
+    /// Whether both point to the same allocation, rather than to equal values.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+}
+
+/// Panics if the value behind `ptr` is being collected or gone, a `Drop` impl of
+/// garbage may hold `CcRc`s to values already dropped.
+fn live<'a>(ptr: NodePtr) -> Header<'a> {
+    let inner = node(ptr);
+    assert!(
+        !matches!(inner.color.get(), Color::Collecting | Color::Dead),
+        "CcRc used while its value is being collected"
+    );
+    inner
+}
+
+impl<T: Trace + 'static> Clone for CcRc<T> {
+    fn clone(&self) -> Self {
+        let inner = live(self.ptr);
+        inner.strong.set(
+            inner
+                .strong
+                .get()
+                .checked_add(1)
+                .expect("CcRc count overflowed"),
+        );
+        // Can't be garbage while somebody clones it.
+        inner.color.set(Color::Black);
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Trace + 'static> Drop for CcRc<T> {
+    fn drop(&mut self) {
+        let inner = node(self.ptr);
+        inner.strong.set(inner.strong.get() - 1);
+        match inner.color.get() {
+            // collect_cycles drops the value and frees the allocation itself.
+            Color::Collecting => return,
+            Color::Dead => {
+                if inner.strong.get() == 0 {
+                    // SAFETY: The value is gone and this was the last CcRc.
+                    drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+                }
+                return;
+            }
+            _ => {}
+        }
+        if inner.strong.get() == 0 {
+            // SAFETY: That was the last reference.
+            unsafe { release(self.ptr) };
+        } else if inner.color.get() != Color::Purple {
+            inner.color.set(Color::Purple);
+            if !inner.buffered.get() {
+                inner.buffered.set(true);
+                // Only leaks if the buffer itself is gone, during thread exit.
+                let _ = CANDIDATES.try_with(|candidates| candidates.borrow_mut().push(self.ptr));
+            }
+        }
+    }
+}
+
+impl<T: Trace + 'static> Deref for CcRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        live(self.ptr);
+        // SAFETY: The value is alive as long as there is a CcRc pointing to it,
+        // unless it is garbage, which isn't handed out.
+        unsafe { &(*self.ptr.as_ptr().cast::<CcBox<T>>()).value }
+    }
+}
+
+impl<T: Trace + Debug + 'static> Debug for CcRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: Trace + Display + 'static> Display for CcRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Trace + 'static> Trace for CcRc<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        tracer.visit(self);
+    }
+}
+
+/// The bookkeeping of a node, borrowed apart from the value, which may be in the
+/// middle of being dropped.
+struct Header<'a> {
+    strong: &'a Cell<usize>,
+    color: &'a Cell<Color>,
+    buffered: &'a Cell<bool>,
+}
+
+fn node<'a>(ptr: NodePtr) -> Header<'a> {
+    let ptr = ptr.as_ptr();
+    // SAFETY: Callers only pass nodes whose allocation is still alive.
+    unsafe {
+        Header {
+            strong: &*ptr::addr_of!((*ptr).strong),
+            color: &*ptr::addr_of!((*ptr).color),
+            buffered: &*ptr::addr_of!((*ptr).buffered),
+        }
+    }
+}
+
+fn children(ptr: NodePtr) -> Vec<NodePtr> {
+    let mut children = Vec::new();
+    // SAFETY: Only nodes whose value wasn't dropped yet are traced.
+    let value = unsafe { &(*ptr.as_ptr()).value };
+    value.trace(&mut Tracer {
+        visit: &mut |child| children.push(child),
+    });
+    children
+}
+
+/// Drops the value of a node whose count reached zero, and frees the allocation
+/// unless the candidate buffer still refers to it.
+///
+/// SAFETY: `ptr` must be alive with its count at zero.
+unsafe fn release(ptr: NodePtr) {
+    ManuallyDrop::drop(&mut (*ptr.as_ptr()).value);
+    let inner = node(ptr);
+    inner.color.set(Color::Black);
+    if !inner.buffered.get() {
+        drop(Box::from_raw(ptr.as_ptr()));
+    }
+}
+
+/// The nodes a collection is dropping. If a drop panics, the ones left over are
+/// marked dead: their values leak, their allocations stay for the `CcRc`s that
+/// may still point to them.
+struct Garbage(Vec<NodePtr>);
+
+impl Drop for Garbage {
+    fn drop(&mut self) {
+        for &ptr in &self.0 {
+            node(ptr).color.set(Color::Dead);
+        }
+    }
+}
+
+/// Frees all garbage cycles among the values whose count was decremented since
+/// the last collection. Returns the number of values freed.
+pub fn collect_cycles() -> usize {
+    // Taken out, dropping the garbage may buffer new candidates.
+    let candidates = CANDIDATES.with(|candidates| candidates.take());
+
+    // Mark roots: subtract the internal references of every candidate subgraph.
+    let mut roots = Vec::new();
+    for ptr in candidates {
+        let inner = node(ptr);
+        if inner.color.get() == Color::Purple {
+            mark_gray(ptr);
+            roots.push(ptr);
+        } else {
+            inner.buffered.set(false);
+            if inner.color.get() == Color::Black && inner.strong.get() == 0 {
+                // The value was released while buffered, only the allocation is left.
+                drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+            }
+        }
+    }
+
+    // Scan roots: nodes still referenced from outside restore their subgraphs.
+    for &ptr in &roots {
+        scan(ptr);
+    }
+
+    // Collect roots: whatever stayed white is garbage.
+    let mut garbage = Vec::new();
+    for &ptr in &roots {
+        node(ptr).buffered.set(false);
+        collect_white(ptr, &mut garbage);
+    }
+    for &ptr in &garbage {
+        node(ptr).color.set(Color::Collecting);
+    }
+    for &ptr in &garbage {
+        // Trial deletion subtracted all references coming from the garbage,
+        // dropping it subtracts them once more. Counted again, the garbage ends
+        // up with the CcRcs that its drops don't drop.
+        for child in children(ptr) {
+            let child_node = node(child);
+            child_node.strong.set(child_node.strong.get() + 1);
+        }
+    }
+    let mut dropping = Garbage(garbage);
+    for &ptr in &dropping.0 {
+        // SAFETY: Nothing outside the garbage refers to these values.
+        unsafe { ManuallyDrop::drop(&mut (*ptr.as_ptr()).value) };
+    }
+    let garbage = std::mem::take(&mut dropping.0);
+    for &ptr in &garbage {
+        if node(ptr).strong.get() == 0 {
+            // SAFETY: The value is gone and no CcRc is left pointing here.
+            drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+        } else {
+            node(ptr).color.set(Color::Dead);
+        }
+    }
+    garbage.len()
+}
+
+fn mark_gray(root: NodePtr) {
+    let mut stack = vec![root];
+    while let Some(ptr) = stack.pop() {
+        let inner = node(ptr);
+        if inner.color.get() == Color::Gray {
+            continue;
+        }
+        inner.color.set(Color::Gray);
+        for child in children(ptr) {
+            let child_node = node(child);
+            child_node.strong.set(child_node.strong.get() - 1);
+            stack.push(child);
+        }
+    }
+}
+
+fn scan(root: NodePtr) {
+    let mut stack = vec![root];
+    while let Some(ptr) = stack.pop() {
+        let inner = node(ptr);
+        if inner.color.get() != Color::Gray {
+            continue;
+        }
+        if inner.strong.get() > 0 {
+            scan_black(ptr);
+        } else {
+            inner.color.set(Color::White);
+            stack.extend(children(ptr));
+        }
+    }
+}
+
+fn scan_black(root: NodePtr) {
+    node(root).color.set(Color::Black);
+    let mut stack = vec![root];
+    while let Some(ptr) = stack.pop() {
+        for child in children(ptr) {
+            let child_node = node(child);
+            child_node.strong.set(child_node.strong.get() + 1);
+            if child_node.color.get() != Color::Black {
+                child_node.color.set(Color::Black);
+                stack.push(child);
+            }
+        }
+    }
+}
+
+fn collect_white(root: NodePtr, garbage: &mut Vec<NodePtr>) {
+    let mut stack = vec![root];
+    while let Some(ptr) = stack.pop() {
+        let inner = node(ptr);
+        // Buffered nodes are roots collected by themselves.
+        if inner.color.get() == Color::White && !inner.buffered.get() {
+            inner.color.set(Color::Black);
+            garbage.push(ptr);
+            stack.extend(children(ptr));
+        }
+    }
+}
+
+macro_rules! trace_nothing {
+    ($($t:ty),*) => {
+        $(unsafe impl Trace for $t {
+            fn trace(&self, _: &mut Tracer<'_>) {}
+        })*
+    };
+}
+
+trace_nothing!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    String,
+    &'static str
+);
+
+// A Cell only holds Copy values, which CcRcs are not.
+unsafe impl<T: Copy> Trace for Cell<T> {
+    fn trace(&self, _: &mut Tracer<'_>) {}
+}
+
+// A mutably borrowed cell is in use, so its value is reachable. Skipping its
+// CcRcs only keeps them alive for this collection.
+unsafe impl<T: Trace> Trace for RefCell<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Ok(value) = self.try_borrow() {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace + ?Sized> Trace for Box<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        (**self).trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_cycles, CcRc, Trace, Tracer};
+    use crate::cell::Cell;
+    use crate::ref_cell::RefCell;
+    use std::panic::{self, AssertUnwindSafe};
+
+    thread_local! {
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    struct Node {
+        name: &'static str,
+        edges: RefCell<Vec<CcRc<Node>>>,
+    }
+
+    impl Node {
+        fn new(name: &'static str) -> CcRc<Node> {
+            CcRc::new(Node {
+                name,
+                edges: RefCell::new(Vec::new()),
+            })
+        }
+
+        fn link(&self, to: &CcRc<Node>) {
+            self.edges.borrow_mut().push(to.clone());
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            DROPS.with(|drops| drops.set(drops.get() + 1));
+        }
+    }
+
+    unsafe impl Trace for Node {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.edges.trace(tracer);
+        }
+    }
+
+    fn drops() -> usize {
+        DROPS.with(|drops| drops.get())
+    }
+
+    // Tests run on their own threads, so the thread-local counts are per test.
+
+    #[test]
+    fn test_acyclic_freed_without_collection() {
+        let a = Node::new("a");
+        a.link(&Node::new("b"));
+
+        drop(a);
+
+        assert_eq!(drops(), 2);
+        assert_eq!(collect_cycles(), 0);
+    }
+
+    #[test]
+    fn test_cycle_collected() {
+        let a = Node::new("a");
+        let b = Node::new("b");
+        a.link(&b);
+        b.link(&a);
+        a.link(&a);
+
+        drop((a, b));
+        assert_eq!(drops(), 0);
+
+        assert_eq!(collect_cycles(), 2);
+        assert_eq!(drops(), 2);
+    }
+
+    #[test]
+    fn test_externally_referenced_cycle_survives() {
+        let a = Node::new("a");
+        let b = Node::new("b");
+        a.link(&b);
+        b.link(&a);
+        drop(b);
+
+        assert_eq!(collect_cycles(), 0);
+        assert_eq!(CcRc::strong_count(&a), 2);
+        assert_eq!(a.edges.borrow()[0].name, "b");
+
+        drop(a);
+        assert_eq!(collect_cycles(), 2);
+    }
+
+    #[test]
+    fn test_garbage_keeping_live_value() {
+        let shared = Node::new("shared");
+        let a = Node::new("a");
+        let b = Node::new("b");
+        a.link(&b);
+        b.link(&a);
+        b.link(&shared);
+        drop((a, b));
+
+        assert_eq!(collect_cycles(), 2);
+        assert_eq!(CcRc::strong_count(&shared), 1);
+        assert_eq!(shared.name, "shared");
+    }
+
+    #[test]
+    fn test_long_cycle() {
+        let first = Node::new("first");
+        let mut last = first.clone();
+        for _ in 0..10_000 {
+            let next = Node::new("next");
+            last.link(&next);
+            last = next;
+        }
+        last.link(&first);
+        drop((first, last));
+
+        assert_eq!(collect_cycles(), 10_001);
+    }
+
+    #[test]
+    fn test_released_while_buffered() {
+        let a = Node::new("a");
+        drop(a.clone());
+
+        drop(a);
+        assert_eq!(drops(), 1);
+
+        assert_eq!(collect_cycles(), 0);
+    }
+
+    #[test]
+    fn test_borrowed_cell_keeps_its_cycle() {
+        let a = Node::new("a");
+        a.link(&a);
+        let edges = a.edges.borrow_mut();
+        drop(a.clone());
+
+        assert_eq!(collect_cycles(), 0);
+        drop(edges);
+        assert_eq!(CcRc::strong_count(&a), 2);
+
+        drop(a);
+        assert_eq!(collect_cycles(), 1);
+    }
+
+    struct Peeking {
+        next: RefCell<Option<CcRc<Peeking>>>,
+    }
+
+    impl Drop for Peeking {
+        fn drop(&mut self) {
+            if let Some(next) = &*self.next.borrow() {
+                next.next.borrow();
+            }
+        }
+    }
+
+    unsafe impl Trace for Peeking {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.next.trace(tracer);
+        }
+    }
+
+    #[test]
+    fn test_drop_reaching_into_garbage_panics() {
+        let a = CcRc::new(Peeking {
+            next: RefCell::new(None),
+        });
+        let b = CcRc::new(Peeking {
+            next: RefCell::new(Some(a.clone())),
+        });
+        *a.next.borrow_mut() = Some(b);
+        drop(a);
+
+        assert!(panic::catch_unwind(collect_cycles).is_err());
+        assert_eq!(collect_cycles(), 0);
+    }
+
+    thread_local! {
+        static ESCAPED: RefCell<Vec<CcRc<Escaping>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    struct Escaping {
+        next: RefCell<Option<CcRc<Escaping>>>,
+    }
+
+    impl Drop for Escaping {
+        fn drop(&mut self) {
+            if let Some(next) = self.next.borrow_mut().take() {
+                ESCAPED.with(|escaped| escaped.borrow_mut().push(next));
+            }
+        }
+    }
+
+    unsafe impl Trace for Escaping {
+        fn trace(&self, tracer: &mut Tracer<'_>) {
+            self.next.trace(tracer);
+        }
+    }
+
+    #[test]
+    fn test_cc_rc_escaping_a_drop_is_dead() {
+        let a = CcRc::new(Escaping {
+            next: RefCell::new(None),
+        });
+        *a.next.borrow_mut() = Some(a.clone());
+        drop(a);
+
+        assert_eq!(collect_cycles(), 1);
+        let escaped = ESCAPED.with(|escaped| escaped.borrow_mut().pop().unwrap());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| escaped.clone()));
+        assert!(result.is_err());
+        // Frees the allocation.
+        drop(escaped);
+    }
+}
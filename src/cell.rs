@@ -1,27 +1,49 @@
+use std::fmt::{self, Display};
 use std::mem;
+use std::ptr;
 
-use crate::unsafe_cell::UnsafeCell;
+// With the `std-backend` feature the cell delegates to `std::cell::Cell`, so the two
+// implementations can be compared against each other behind the same API.
+#[cfg(not(feature = "std-backend"))]
+type Storage<T> = crate::unsafe_cell::UnsafeCell<T>;
+#[cfg(feature = "std-backend")]
+type Storage<T> = std::cell::Cell<T>;
 
+#[repr(transparent)]
 pub struct Cell<T> {
-    value: UnsafeCell<T>,
+    value: Storage<T>,
 }
 
 impl<T> Cell<T> {
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
         Self {
-            value: UnsafeCell::new(value),
+            value: Storage::new(value),
         }
     }
 
-    pub fn set(&self, value: T) {
+    pub fn from_mut(value: &mut T) -> &Cell<T> {
         unsafe {
-            // SAFETY: There are no other references to the UnsafeCell,
-            // it's safe to get a mutable reference (it will be unique) and mutate through it.
-            let cell = &mut *self.value.get();
-            *cell = value;
+            // SAFETY: Cell is repr(transparent) over its storage, which is itself
+            // repr(transparent) over T, and we hold the only reference to the value.
+            &*(value as *mut T as *const Cell<T>)
         }
     }
 
+    pub fn set(&self, value: T) {
+        // The old value is dropped only after the new one is in place, so its
+        // destructor observes a consistent cell.
+        drop(self.replace(value));
+    }
+
+    #[cfg(feature = "std-backend")]
+    pub fn get(&self) -> T
+    where
+        T: Copy,
+    {
+        self.value.get()
+    }
+
+    #[cfg(not(feature = "std-backend"))]
     pub fn get(&self) -> T
     where
         T: Copy,
@@ -29,7 +51,24 @@ impl<T> Cell<T> {
         unsafe {
             // SAFETY: There are no other references to the UnsafeCell,
             // it's safe to access the contents and create a copy from it.
-            return *self.value.get();
+            *self.value.get()
+        }
+    }
+
+    /// Like the atomics' `fetch_update`: stores `f(old)` if it returns `Some`,
+    /// returns `Ok(old)` when the value was updated and `Err(old)` otherwise.
+    pub fn fetch_update<F>(&self, mut f: F) -> Result<T, T>
+    where
+        T: Copy,
+        F: FnMut(T) -> Option<T>,
+    {
+        let prev = self.get();
+        match f(prev) {
+            Some(next) => {
+                self.set(next);
+                Ok(prev)
+            }
+            None => Err(prev),
         }
     }
 
@@ -40,14 +79,75 @@ impl<T> Cell<T> {
         self.replace(Default::default())
     }
 
+    #[cfg(feature = "std-backend")]
     pub fn replace(&self, val: T) -> T {
-        mem::replace(
-            unsafe {
-                // SAFETY: Cell is not Sync, so there are no other concurrent mutations possible.
-                &mut *self.value.get()
-            },
-            val,
-        )
+        self.value.replace(val)
+    }
+
+    #[cfg(not(feature = "std-backend"))]
+    pub fn replace(&self, val: T) -> T {
+        unsafe {
+            // SAFETY: Cell is not Sync, so there are no other concurrent mutations possible,
+            // and it never hands out references to the value.
+            // UnsafeCell::replace goes through the raw pointer instead of creating a `&mut T`,
+            // which would invalidate pointers previously handed out by `as_ptr`.
+            self.value.replace(val)
+        }
+    }
+
+    /// Swaps the values of two cells, which may be the same cell.
+    pub fn swap(&self, other: &Self) {
+        if ptr::eq(self, other) {
+            return;
+        }
+        unsafe {
+            // SAFETY: Cell is not Sync and the two cells don't overlap.
+            ptr::swap(self.as_ptr(), other.as_ptr());
+        }
+    }
+
+    #[cfg(feature = "std-backend")]
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.as_ptr()
+    }
+
+    #[cfg(not(feature = "std-backend"))]
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+
+    /// Takes the value out of the cell, lets `f` work on it and puts it back,
+    /// even if `f` panics. Values stored into the cell by `f` itself are overwritten.
+    pub fn with_taken<R, F>(&self, f: F) -> R
+    where
+        T: Default,
+        F: FnOnce(&mut T) -> R,
+    {
+        struct PutBack<'cell, T: Default> {
+            cell: &'cell Cell<T>,
+            value: T,
+        }
+
+        impl<T: Default> Drop for PutBack<'_, T> {
+            fn drop(&mut self) {
+                self.cell.set(mem::take(&mut self.value));
+            }
+        }
+
+        let mut taken = PutBack {
+            cell: self,
+            value: self.take(),
+        };
+        f(&mut taken.value)
+    }
+
+    /// Installs `temp` until the returned guard is dropped, then restores the previous value.
+    pub fn scoped_replace(&self, temp: T) -> ScopedReplaceGuard<'_, T> {
+        let prev = self.replace(temp);
+        ScopedReplaceGuard {
+            cell: self,
+            prev: Some(prev),
+        }
     }
 
     pub fn get_mut(&mut self) -> &mut T {
@@ -59,6 +159,94 @@ impl<T> Cell<T> {
     }
 }
 
+impl<T: Copy + Display> Display for Cell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.get(), f)
+    }
+}
+
+impl<T: Copy + PartialEq> PartialEq for Cell<T> {
+    fn eq(&self, other: &Cell<T>) -> bool {
+        self.get() == other.get()
+    }
+}
+
+impl<T: Copy + PartialEq> PartialEq<T> for Cell<T> {
+    fn eq(&self, other: &T) -> bool {
+        self.get() == *other
+    }
+}
+
+// Coherence rules don't allow a blanket `impl<T> PartialEq<Cell<T>> for T`,
+// so the symmetric impls are spelled out for the primitive types.
+macro_rules! impl_partial_eq_cell_for {
+    ($($t:ty),*) => {
+        $(
+            impl PartialEq<Cell<$t>> for $t {
+                fn eq(&self, other: &Cell<$t>) -> bool {
+                    *self == other.get()
+                }
+            }
+        )*
+    };
+}
+
+impl_partial_eq_cell_for!(
+    bool, char, i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64
+);
+
+/// A guard returned by [`Cell::scoped_replace`].
+pub struct ScopedReplaceGuard<'cell, T> {
+    cell: &'cell Cell<T>,
+    prev: Option<T>,
+}
+
+impl<T> Drop for ScopedReplaceGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(prev) = self.prev.take() {
+            self.cell.set(prev);
+        }
+    }
+}
+
+// `take` needs no extra impl here: `Option<T>` is `Default` for every `T`.
+impl<T> Cell<Option<T>> {
+    pub fn is_some(&self) -> bool {
+        unsafe {
+            // SAFETY: Cell is not Sync and we don't hand out references to the contents,
+            // so nobody can mutate the value while we look at it.
+            (*self.as_ptr()).is_some()
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        !self.is_some()
+    }
+
+    /// Stores `value` if the cell is empty, otherwise hands it back.
+    pub fn set_if_none(&self, value: T) -> Result<(), T> {
+        if self.is_some() {
+            return Err(value);
+        }
+        self.set(Some(value));
+        Ok(())
+    }
+
+    pub fn get_or_insert_with<F>(&self, f: F) -> T
+    where
+        T: Copy,
+        F: FnOnce() -> T,
+    {
+        if let Some(value) = self.get() {
+            return value;
+        }
+        // If `f` filled the cell re-entrantly, its value gets overwritten.
+        let value = f();
+        self.set(Some(value));
+        value
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,6 +289,183 @@ mod tests {
         assert_eq!(cell.replace(45), 44);
     }
 
+    #[test]
+    fn shared_cell_can_fetch_update() {
+        let cell = Cell::new(7);
+
+        assert_eq!(cell.fetch_update(|_| None), Err(7));
+        assert_eq!(cell.fetch_update(|x| Some(x + 1)), Ok(7));
+        assert_eq!(
+            cell.fetch_update(|x| if x < 8 { Some(x + 1) } else { None }),
+            Err(8)
+        );
+        assert_eq!(cell.get(), 8);
+    }
+
+    #[test]
+    fn shared_cell_scoped_replace_restores_value() {
+        let cell = Cell::new(42);
+        {
+            let _outer = cell.scoped_replace(43);
+            {
+                let _inner = cell.scoped_replace(44);
+                assert_eq!(cell.get(), 44);
+            }
+            assert_eq!(cell.get(), 43);
+        }
+        assert_eq!(cell.get(), 42);
+    }
+
+    #[test]
+    fn shared_cell_scoped_replace_restores_value_on_panic() {
+        let cell = Cell::new(42);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = cell.scoped_replace(43);
+            panic!("boom");
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(cell.get(), 42);
+    }
+
+    // The following tests access the value through raw pointers while the cell
+    // mutates it, run them with `cargo miri test` to check the aliasing.
+    #[test]
+    fn old_value_reads_the_cell_while_dropped() {
+        struct Tracked {
+            id: u32,
+            cell: *const Cell<Option<Tracked>>,
+            seen: std::rc::Rc<std::cell::Cell<Option<u32>>>,
+        }
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                // SAFETY: The cell outlives the values stored in it. Writing the
+                // new value through a `&mut T` while this drop runs in place
+                // would make this read undefined behavior.
+                let current = unsafe { (*(*self.cell).as_ptr()).as_ref().map(|t| t.id) };
+                self.seen.set(current);
+            }
+        }
+
+        let seen = std::rc::Rc::new(std::cell::Cell::new(None));
+        let cell = Cell::new(None);
+        let tracked = |id| {
+            Some(Tracked {
+                id,
+                cell: &cell,
+                seen: seen.clone(),
+            })
+        };
+
+        cell.set(tracked(1));
+        cell.set(tracked(2));
+        assert_eq!(seen.get(), Some(2));
+        drop(cell.replace(tracked(3)));
+        assert_eq!(seen.get(), Some(3));
+        drop(cell.take());
+        assert_eq!(seen.get(), None);
+    }
+
+    #[test]
+    fn raw_pointer_stays_valid_across_set() {
+        let cell = Cell::new(42);
+        let ptr = cell.as_ptr();
+
+        cell.set(43);
+
+        assert_eq!(unsafe { *ptr }, 43);
+        unsafe { *ptr = 44 };
+        assert_eq!(cell.get(), 44);
+    }
+
+    #[test]
+    fn raw_pointer_stays_valid_across_replace_and_take() {
+        let cell = Cell::new(42);
+        let ptr = cell.as_ptr();
+
+        assert_eq!(cell.replace(43), 42);
+        assert_eq!(unsafe { *ptr }, 43);
+        assert_eq!(cell.take(), 43);
+        assert_eq!(unsafe { *ptr }, 0);
+    }
+
+    #[test]
+    fn set_drops_previous_value() {
+        let rc = std::rc::Rc::new(());
+        let cell = Cell::new(Some(rc.clone()));
+
+        cell.set(None);
+
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn cell_compares_with_inner_value() {
+        let cell = Cell::new(42);
+
+        assert!(cell == 42);
+        assert!(42 == cell);
+        assert!(cell != 43);
+        assert!(cell == Cell::new(42));
+    }
+
+    #[test]
+    fn cell_display_passes_through() {
+        let cell = Cell::new(4.2);
+        assert_eq!(format!("{} {:.2}", cell, cell), "4.2 4.20");
+    }
+
+    #[test]
+    fn shared_cells_can_swap() {
+        let (c1, c2) = (Cell::new(1), Cell::new(2));
+
+        c1.swap(&c2);
+        c1.swap(&c1);
+
+        assert_eq!((c1.get(), c2.get()), (2, 1));
+    }
+
+    #[test]
+    fn shared_cell_with_taken_puts_value_back() {
+        let cell = Cell::new(vec![1]);
+
+        let len = cell.with_taken(|v| {
+            v.push(2);
+            v.len()
+        });
+
+        assert_eq!(len, 2);
+        assert_eq!(cell.take(), vec![1, 2]);
+    }
+
+    #[test]
+    fn shared_cell_with_taken_puts_value_back_on_panic() {
+        let cell = Cell::new(vec![1]);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            cell.with_taken(|v| {
+                v.push(2);
+                panic!("boom");
+            })
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(cell.take(), vec![1, 2]);
+    }
+
+    #[test]
+    fn mutable_ref_can_become_cell() {
+        let mut value = 42;
+        {
+            let cell = Cell::from_mut(&mut value);
+            let (p1, p2) = (cell, cell);
+            p1.set(p2.get() + 1);
+        }
+        assert_eq!(value, 43);
+    }
+
     #[test]
     fn mutable_cell_ref_can_get_mut() {
         let mut cell = Cell::new(42);
@@ -117,4 +482,32 @@ mod tests {
 
         assert_eq!(cell.into_inner(), 42);
     }
+
+    #[test]
+    fn option_cell_can_take_non_default_value() {
+        struct NoDefault(i32);
+
+        let cell = Cell::new(Some(NoDefault(42)));
+
+        assert_eq!(cell.take().map(|v| v.0), Some(42));
+        assert!(cell.is_none());
+    }
+
+    #[test]
+    fn option_cell_set_if_none() {
+        let cell = Cell::new(None);
+
+        assert_eq!(cell.set_if_none(42), Ok(()));
+        assert_eq!(cell.set_if_none(43), Err(43));
+        assert_eq!(cell.get(), Some(42));
+    }
+
+    #[test]
+    fn option_cell_get_or_insert_with() {
+        let cell = Cell::new(None);
+
+        assert_eq!(cell.get_or_insert_with(|| 42), 42);
+        assert_eq!(cell.get_or_insert_with(|| unreachable!()), 42);
+        assert!(cell.is_some());
+    }
 }
@@ -0,0 +1,69 @@
+//! An interior-mutable view of a plain struct in one line.
+//!
+//! ```ignore
+//! #[derive(CellFields)]
+//! struct Stats {
+//!     hits: u64,
+//!     #[ref_cell]
+//!     last_miss: String,
+//! }
+//!
+//! let stats = Stats { hits: 0, last_miss: String::new() }.into_cells();
+//! stats.hits.set(stats.hits.get() + 1);
+//! stats.last_miss.borrow_mut().push_str("key");
+//! ```
+//!
+//! The generated code refers to the cells by their paths in this crate.
+
+pub use cell_fields_derive::CellFields;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(CellFields)]
+    struct Stats {
+        hits: u64,
+        pub misses: u64,
+        #[ref_cell]
+        last_miss: String,
+        #[ref_cell]
+        by_key: HashMap<String, Vec<u64>>,
+        callback: Option<fn(u64) -> u64>,
+    }
+
+    fn stats() -> Stats {
+        Stats {
+            hits: 1,
+            misses: 2,
+            last_miss: String::from("a"),
+            by_key: HashMap::new(),
+            callback: None,
+        }
+    }
+
+    #[test]
+    fn test_fields_become_cells() {
+        let cells = stats().into_cells();
+
+        cells.hits.set(cells.hits.get() + 1);
+        cells.last_miss.borrow_mut().push('b');
+        cells.by_key.borrow_mut().insert(String::from("k"), vec![1]);
+
+        let stats = cells.into_inner();
+        assert_eq!(stats.hits, 2);
+        assert_eq!(stats.misses, 2);
+        assert_eq!(stats.last_miss, "ab");
+        assert_eq!(stats.by_key["k"], vec![1]);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let stats = StatsCells::from(stats()).into_inner();
+
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert_eq!(stats.last_miss, "a");
+        assert!(stats.callback.is_none());
+    }
+}
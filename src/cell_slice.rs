@@ -0,0 +1,99 @@
+use crate::cell::Cell;
+
+/// In-place algorithms over shared slices of cells.
+///
+/// `split_at` and friends aren't repeated here, the slice methods already work on `&[Cell<T>]`.
+pub trait CellSlice<T> {
+    fn swap_indices(&self, i: usize, j: usize);
+
+    fn fill(&self, value: T)
+    where
+        T: Clone;
+
+    /// Rotates the slice in place so that the element at `mid` becomes the first one,
+    /// like `<[T]>::rotate_left`.
+    fn rotate(&self, mid: usize);
+}
+
+impl<T> CellSlice<T> for [Cell<T>] {
+    fn swap_indices(&self, i: usize, j: usize) {
+        self[i].swap(&self[j]);
+    }
+
+    fn fill(&self, value: T)
+    where
+        T: Clone,
+    {
+        for cell in self {
+            cell.set(value.clone());
+        }
+    }
+
+    fn rotate(&self, mid: usize) {
+        assert!(mid <= self.len(), "Rotation point {} out of bounds", mid);
+        let (left, right) = self.split_at(mid);
+        reverse(left);
+        reverse(right);
+        reverse(self);
+    }
+}
+
+fn reverse<T>(slice: &[Cell<T>]) {
+    let len = slice.len();
+    for i in 0..len / 2 {
+        slice.swap_indices(i, len - 1 - i);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(slice: &[Cell<i32>]) -> Vec<i32> {
+        slice.iter().map(Cell::get).collect()
+    }
+
+    fn cells(values: &[i32]) -> Vec<Cell<i32>> {
+        values.iter().copied().map(Cell::new).collect()
+    }
+
+    #[test]
+    fn test_swap_indices() {
+        let cells = cells(&[1, 2, 3]);
+        let slice: &[Cell<i32>] = &cells;
+
+        slice.swap_indices(0, 2);
+        slice.swap_indices(1, 1);
+
+        assert_eq!(values(slice), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_fill() {
+        let cells = cells(&[1, 2, 3]);
+        let (left, right) = cells.split_at(1);
+
+        right.fill(7);
+
+        assert_eq!(values(left), vec![1]);
+        assert_eq!(values(&cells), vec![1, 7, 7]);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let cells = cells(&[1, 2, 3, 4, 5]);
+
+        cells.rotate(2);
+        assert_eq!(values(&cells), vec![3, 4, 5, 1, 2]);
+
+        cells.rotate(0);
+        cells.rotate(5);
+        assert_eq!(values(&cells), vec![3, 4, 5, 1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Rotation point 4 out of bounds")]
+    fn test_rotate_out_of_bounds() {
+        cells(&[1, 2, 3]).rotate(4);
+    }
+}
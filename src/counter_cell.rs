@@ -0,0 +1,76 @@
+use crate::cell::Cell;
+
+/// Hands out monotonically increasing ids through a shared reference.
+pub struct CounterCell {
+    first: u64,
+    next: Cell<u64>,
+}
+
+impl CounterCell {
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    pub fn starting_at(first: u64) -> Self {
+        Self {
+            first,
+            next: Cell::new(first),
+        }
+    }
+
+    /// Returns a fresh id, panics when the counter overflows.
+    pub fn next(&self) -> u64 {
+        let id = self.next.get();
+        let next = id.checked_add(1).expect("CounterCell overflowed");
+        self.next.set(next);
+        id
+    }
+
+    /// Returns the id the next call to `next` will hand out.
+    pub fn current(&self) -> u64 {
+        self.next.get()
+    }
+
+    /// Starts over from the id the counter was created with.
+    pub fn reset(&self) {
+        self.next.set(self.first);
+    }
+}
+
+impl Default for CounterCell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_is_monotonic() {
+        let counter = CounterCell::new();
+        let (p1, p2) = (&counter, &counter);
+
+        assert_eq!(p1.next(), 0);
+        assert_eq!(p2.next(), 1);
+        assert_eq!(counter.current(), 2);
+    }
+
+    #[test]
+    fn test_reset() {
+        let counter = CounterCell::starting_at(10);
+        assert_eq!(counter.next(), 10);
+
+        counter.next();
+        counter.reset();
+        assert_eq!(counter.next(), 10);
+    }
+
+    #[test]
+    #[should_panic(expected = "CounterCell overflowed")]
+    fn test_overflow_panics() {
+        let counter = CounterCell::starting_at(u64::MAX);
+        counter.next();
+    }
+}
@@ -0,0 +1,95 @@
+use crate::cell::Cell;
+use crate::unsafe_cell::UnsafeCell;
+
+/// Two buffers of `T`: readers see the front one while the back one is mutated
+/// through a shared reference. `swap_buffers` needs exclusive access, so no
+/// reader can observe a buffer while it's being written.
+pub struct DoubleBufferCell<T> {
+    buffers: [UnsafeCell<T>; 2],
+    front: usize,
+    writing: Cell<bool>,
+}
+
+impl<T> DoubleBufferCell<T> {
+    pub fn new(front: T, back: T) -> Self {
+        Self {
+            buffers: [UnsafeCell::new(front), UnsafeCell::new(back)],
+            front: 0,
+            writing: Cell::new(false),
+        }
+    }
+
+    pub fn read(&self) -> &T {
+        unsafe {
+            // SAFETY: The front buffer is only ever mutated through `&mut self`.
+            &*self.buffers[self.front].get()
+        }
+    }
+
+    /// Runs `f` on the back buffer.
+    ///
+    /// Panics when called from within another `write`.
+    pub fn write<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        assert!(!self.writing.get(), "Back buffer already being written");
+        let _writing = self.writing.scoped_replace(true);
+        let back = unsafe {
+            // SAFETY: Readers only see the front buffer and the `writing` flag
+            // guarantees this is the only mutable reference to the back buffer.
+            &mut *self.buffers[1 - self.front].get()
+        };
+        f(back)
+    }
+
+    pub fn swap_buffers(&mut self) {
+        self.front = 1 - self.front;
+    }
+
+    pub fn into_inner(self) -> (T, T) {
+        let [first, second] = self.buffers;
+        if self.front == 0 {
+            (first.into_inner(), second.into_inner())
+        } else {
+            (second.into_inner(), first.into_inner())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_readers_see_front_until_swap() {
+        let mut cell = DoubleBufferCell::new(1, 1);
+
+        let front = cell.read();
+        cell.write(|back| *back = 2);
+        assert_eq!(*front, 1);
+
+        cell.swap_buffers();
+        assert_eq!(*cell.read(), 2);
+    }
+
+    #[test]
+    fn test_write_returns_closure_result() {
+        let cell = DoubleBufferCell::new(vec![1], vec![2]);
+        assert_eq!(cell.write(|back| back.len()), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Back buffer already being written")]
+    fn test_reentrant_write_panics() {
+        let cell = DoubleBufferCell::new(1, 1);
+        cell.write(|_| cell.write(|_| ()));
+    }
+
+    #[test]
+    fn test_into_inner_returns_front_first() {
+        let mut cell = DoubleBufferCell::new(1, 2);
+        cell.swap_buffers();
+        assert_eq!(cell.into_inner(), (2, 1));
+    }
+}
@@ -0,0 +1,274 @@
+//! Epoch-based memory reclamation, for lock-free types that unlink values other
+//! threads may still be reading.
+//!
+//! A thread [`pin`]s itself before reading shared pointers and stays pinned
+//! while it uses what it read. Unlinked values are handed to
+//! [`Guard::defer`] instead of being freed. A global epoch only advances once
+//! every pinned thread has seen the current one, so after two advances no
+//! thread can still hold a pointer unlinked before them, and the deferred
+//! functions run.
+//!
+//! Reading stays cheap, pinning writes only to a slot of the thread's own.
+
+use std::cell::Cell;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::atomic::Ordering;
+
+use crate::atomic::{self, AtomicUsize};
+use crate::registry::{Entry, Registry};
+use crate::sync::{ignore_poison, Mutex};
+
+// A participant's state is its epoch shifted left by one, or'ed with PINNED.
+const PINNED: usize = 1;
+
+/// How many pins a thread does between attempts to reclaim garbage.
+const PINS_BETWEEN_COLLECTS: usize = 64;
+
+static EPOCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Every thread that ever pinned, records are reused after their thread exits.
+static PARTICIPANTS: Registry<Participant> = Registry::new();
+
+static GARBAGE: Mutex<Vec<Deferred>> = Mutex::new(Vec::new());
+
+struct Participant {
+    state: AtomicUsize,
+}
+
+struct Deferred {
+    epoch: usize,
+    call: Box<dyn FnOnce() + Send>,
+}
+
+/// A pointer to a value that may move to another thread, for deferred calls.
+struct SendPtr<T>(*mut T);
+
+// SAFETY: Only used for values that are Send themselves.
+unsafe impl<T: Send> Send for SendPtr<T> {}
+
+struct Local {
+    participant: &'static Entry<Participant>,
+    pin_depth: Cell<usize>,
+    pins: Cell<usize>,
+}
+
+impl Local {
+    fn register() -> Self {
+        Self {
+            participant: PARTICIPANTS.acquire(|| Participant {
+                state: AtomicUsize::new(0),
+            }),
+            pin_depth: Cell::new(0),
+            pins: Cell::new(0),
+        }
+    }
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        self.participant.release();
+    }
+}
+
+thread_local! {
+    static LOCAL: Local = Local::register();
+}
+
+/// Keeps the current thread pinned, values deferred meanwhile aren't freed
+/// while it lives.
+pub struct Guard {
+    // Pinning is per thread.
+    _not_send: PhantomData<*mut ()>,
+}
+
+/// Pins the current thread. Pins nest, the thread stays pinned until the last
+/// guard is dropped.
+pub fn pin() -> Guard {
+    LOCAL.with(|local| {
+        let depth = local.pin_depth.get();
+        local.pin_depth.set(depth + 1);
+        if depth == 0 {
+            let epoch = EPOCH.load_relaxed();
+            local
+                .participant
+                .state
+                .store((epoch << 1) | PINNED, Ordering::SeqCst);
+            // The pin must be visible to threads advancing the epoch before we
+            // load any shared pointer, a store followed by loads needs SeqCst.
+            atomic::fence(Ordering::SeqCst);
+        }
+    });
+    Guard {
+        _not_send: PhantomData,
+    }
+}
+
+impl Guard {
+    /// Runs `f` once no thread can be using what was unlinked before this call.
+    pub fn defer(&self, f: impl FnOnce() + Send + 'static) {
+        let epoch = EPOCH.load(Ordering::SeqCst);
+        garbage().push(Deferred {
+            epoch,
+            call: Box::new(f),
+        });
+    }
+
+    /// Drops the boxed value behind `ptr` once no thread can be using it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` comes from `Box::into_raw`, is already unreachable for threads that
+    /// pin from now on, and isn't freed otherwise.
+    pub unsafe fn defer_destroy<T: Send + 'static>(&self, ptr: *mut T) {
+        let ptr = SendPtr(ptr);
+        self.defer(move || drop(Box::from_raw(ptr.0)));
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        let collect = LOCAL.with(|local| {
+            let depth = local.pin_depth.get() - 1;
+            local.pin_depth.set(depth);
+            if depth > 0 {
+                return false;
+            }
+            // Release: our reads of shared values happen before whoever frees
+            // them sees us unpinned.
+            local.participant.state.store_release(0);
+            let pins = local.pins.get() + 1;
+            local.pins.set(pins);
+            pins % PINS_BETWEEN_COLLECTS == 0
+        });
+        if collect {
+            self::collect();
+        }
+    }
+}
+
+impl Debug for Guard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Guard { .. }")
+    }
+}
+
+fn garbage() -> crate::sync::MutexGuard<'static, Vec<Deferred>> {
+    ignore_poison(GARBAGE.lock())
+}
+
+/// Advances the epoch if every pinned thread has seen the current one, returns
+/// the epoch after.
+fn try_advance() -> usize {
+    let epoch = EPOCH.load(Ordering::SeqCst);
+    // Pairs with the fence in pin: either we see a thread's pin, or it sees the
+    // current epoch and loads shared pointers only after that.
+    atomic::fence(Ordering::SeqCst);
+    for participant in PARTICIPANTS.iter() {
+        let state = participant.state.load_relaxed();
+        if state & PINNED != 0 && state >> 1 != epoch {
+            return epoch;
+        }
+    }
+    atomic::fence_acquire();
+    match EPOCH.compare_exchange(epoch, epoch + 1, Ordering::SeqCst, Ordering::SeqCst) {
+        Ok(_) => epoch + 1,
+        Err(actual) => actual,
+    }
+}
+
+/// Tries to advance the epoch and runs the deferred functions that became safe.
+/// Threads do this every so often when they unpin, tests call it directly.
+pub fn collect() {
+    let epoch = try_advance();
+    let ready = {
+        let mut garbage = garbage();
+        let (ready, pending) = mem::take(&mut *garbage)
+            .into_iter()
+            .partition(|deferred: &Deferred| deferred.epoch + 2 <= epoch);
+        *garbage = pending;
+        ready
+    };
+    for deferred in ready {
+        (deferred.call)();
+    }
+}
+
+/// Collects until `done` holds, other tests may keep the epoch back briefly.
+#[cfg(test)]
+pub(crate) fn collect_until(done: impl Fn() -> bool) -> bool {
+    for _ in 0..10_000 {
+        if done() {
+            return true;
+        }
+        collect();
+        std::thread::yield_now();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn test_deferred_runs_after_unpin() {
+        let ran = Arc::new(AtomicBool::new(false));
+
+        let guard = pin();
+        let flag = ran.clone();
+        guard.defer(move || flag.store_release(true));
+        for _ in 0..10 {
+            collect();
+        }
+        assert!(!ran.load_acquire());
+        drop(guard);
+
+        assert!(collect_until(|| ran.load_acquire()));
+    }
+
+    #[test]
+    fn test_pinned_thread_holds_back_reclamation() {
+        let ran = Arc::new(AtomicBool::new(false));
+        let (pinned, unpin) = (
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        );
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                let _guard = pin();
+                pinned.store_release(true);
+                while !unpin.load_acquire() {
+                    thread::yield_now();
+                }
+            });
+            while !pinned.load_acquire() {
+                thread::yield_now();
+            }
+            let flag = ran.clone();
+            pin().defer(move || flag.store_release(true));
+            for _ in 0..10 {
+                collect();
+            }
+            assert!(!ran.load_acquire());
+            unpin.store_release(true);
+        });
+
+        assert!(collect_until(|| ran.load_acquire()));
+    }
+
+    #[test]
+    fn test_pins_nest() {
+        let outer = pin();
+        let inner = pin();
+        drop(outer);
+        LOCAL.with(|local| assert_eq!(local.pin_depth.get(), 1));
+        drop(inner);
+        LOCAL.with(|local| assert_eq!(local.participant.state.load_relaxed(), 0));
+    }
+}
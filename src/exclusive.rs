@@ -0,0 +1,125 @@
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Makes any `T` `Sync` by only giving out access through `&mut self`.
+///
+/// A shared `&Exclusive<T>` can't be used for anything, so sharing one between
+/// threads is trivially fine. Useful for keeping `!Sync` values like futures or
+/// our `RefCell` in a struct that needs to be `Sync`.
+#[derive(Default)]
+#[repr(transparent)]
+pub struct Exclusive<T: ?Sized> {
+    value: T,
+}
+
+// SAFETY: No method takes `&self`, so a shared reference grants no access to the value.
+unsafe impl<T: ?Sized> Sync for Exclusive<T> {}
+
+impl<T> Exclusive<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: ?Sized> Exclusive<T> {
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        // SAFETY: The value is structurally pinned, it is never moved out of a pinned Exclusive.
+        unsafe { self.map_unchecked_mut(|exclusive| &mut exclusive.value) }
+    }
+
+    pub fn from_mut(value: &mut T) -> &mut Exclusive<T> {
+        // SAFETY: Exclusive<T> is repr(transparent) over T.
+        unsafe { &mut *(value as *mut T as *mut Exclusive<T>) }
+    }
+}
+
+impl<T> From<T> for Exclusive<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<F: Future + ?Sized> Future for Exclusive<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_pin_mut().poll(cx)
+    }
+}
+
+impl<T: ?Sized> Debug for Exclusive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Reading the value through &self is exactly what we can't do.
+        f.debug_struct("Exclusive").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ref_cell::RefCell;
+
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_makes_non_sync_types_sync() {
+        assert_not_impl!(RefCell<i32>: Sync);
+        assert_sync::<Exclusive<RefCell<i32>>>();
+        assert_sync::<Exclusive<std::rc::Rc<i32>>>();
+        // Send is still inherited from the value.
+        assert_not_impl!(Exclusive<std::rc::Rc<i32>>: Send);
+    }
+
+    #[test]
+    fn test_access_through_mut() {
+        let mut exclusive = Exclusive::new(RefCell::new(1));
+
+        *exclusive.get_mut().borrow_mut() += 1;
+
+        assert_eq!(exclusive.into_inner().into_inner(), 2);
+    }
+
+    #[test]
+    fn test_from_mut() {
+        let mut value = 1;
+
+        *Exclusive::from_mut(&mut value).get_mut() = 2;
+
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_polls_wrapped_future() {
+        use std::task::{RawWaker, RawWakerVTable, Waker};
+
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(Exclusive::new(async { 42 }));
+
+        assert_eq!(future.as_mut().poll(&mut cx), Poll::Ready(42));
+    }
+
+    #[test]
+    fn test_debug_hides_value() {
+        assert_eq!(format!("{:?}", Exclusive::new(1)), "Exclusive { .. }");
+    }
+}
@@ -0,0 +1,176 @@
+use crate::cell::Cell;
+
+/// A boolean cell with the usual flag operations.
+pub struct FlagCell {
+    value: Cell<bool>,
+}
+
+impl FlagCell {
+    pub fn new(value: bool) -> Self {
+        Self {
+            value: Cell::new(value),
+        }
+    }
+
+    pub fn get(&self) -> bool {
+        self.value.get()
+    }
+
+    pub fn set(&self) {
+        self.value.set(true);
+    }
+
+    pub fn clear(&self) {
+        self.value.set(false);
+    }
+
+    pub fn toggle(&self) {
+        self.value.set(!self.value.get());
+    }
+
+    /// Sets the flag and returns its previous value.
+    pub fn test_and_set(&self) -> bool {
+        self.value.replace(true)
+    }
+}
+
+impl Default for FlagCell {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+/// Unsigned integers usable as storage of a [`BitCell`].
+pub trait Bits: Copy {
+    const BITS: u32;
+
+    fn bit(self, index: u32) -> bool;
+
+    fn with_bit(self, index: u32, value: bool) -> Self;
+}
+
+macro_rules! impl_bits {
+    ($($t:ty),*) => {
+        $(
+            impl Bits for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn bit(self, index: u32) -> bool {
+                    self & (1 << index) != 0
+                }
+
+                fn with_bit(self, index: u32, value: bool) -> Self {
+                    if value {
+                        self | (1 << index)
+                    } else {
+                        self & !(1 << index)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_bits!(u8, u16, u32, u64);
+
+/// A cell packing one flag per bit of `B`.
+pub struct BitCell<B> {
+    value: Cell<B>,
+}
+
+impl<B: Bits> BitCell<B> {
+    pub fn new(value: B) -> Self {
+        Self {
+            value: Cell::new(value),
+        }
+    }
+
+    pub fn get(&self) -> B {
+        self.value.get()
+    }
+
+    pub fn set(&self, value: B) {
+        self.value.set(value);
+    }
+
+    pub fn get_bit(&self, index: u32) -> bool {
+        Self::check_index(index);
+        self.value.get().bit(index)
+    }
+
+    pub fn set_bit(&self, index: u32) {
+        self.put_bit(index, true);
+    }
+
+    pub fn clear_bit(&self, index: u32) {
+        self.put_bit(index, false);
+    }
+
+    pub fn toggle_bit(&self, index: u32) {
+        self.put_bit(index, !self.get_bit(index));
+    }
+
+    /// Sets the bit and returns its previous value.
+    pub fn test_and_set_bit(&self, index: u32) -> bool {
+        let prev = self.get_bit(index);
+        self.put_bit(index, true);
+        prev
+    }
+
+    fn put_bit(&self, index: u32, value: bool) {
+        Self::check_index(index);
+        self.value.set(self.value.get().with_bit(index, value));
+    }
+
+    fn check_index(index: u32) {
+        assert!(index < B::BITS, "Bit index {} out of range", index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flag_cell_operations() {
+        let flag = FlagCell::default();
+
+        flag.set();
+        assert!(flag.get());
+        flag.clear();
+        assert!(!flag.get());
+        flag.toggle();
+        assert!(flag.get());
+    }
+
+    #[test]
+    fn test_flag_cell_test_and_set() {
+        let flag = FlagCell::new(false);
+
+        assert!(!flag.test_and_set());
+        assert!(flag.test_and_set());
+    }
+
+    #[test]
+    fn test_bit_cell_per_bit_access() {
+        let bits = BitCell::new(0u8);
+
+        bits.set_bit(0);
+        bits.set_bit(7);
+        assert_eq!(bits.get(), 0b1000_0001);
+        assert!(bits.get_bit(7));
+
+        bits.clear_bit(0);
+        bits.toggle_bit(1);
+        assert_eq!(bits.get(), 0b1000_0010);
+        assert!(bits.test_and_set_bit(1));
+        assert!(!bits.test_and_set_bit(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bit index 32 out of range")]
+    fn test_bit_cell_out_of_range() {
+        let bits = BitCell::new(0u32);
+        bits.set_bit(32);
+    }
+}
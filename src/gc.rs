@@ -0,0 +1,552 @@
+//! A tracing garbage collector for single-threaded object graphs.
+//!
+//! `Gc<T>` pointers are cheap to copy around and may form arbitrary cycles.
+//! Nothing is freed until `collect` runs a mark and sweep over all the values of
+//! the current thread. Mutation goes through the crate's cells, `Gc<RefCell<T>>`.
+//!
+//! ```ignore
+//! #[derive(Trace)]
+//! struct Node {
+//!     value: i32,
+//!     next: RefCell<Option<Gc<Node>>>,
+//! }
+//!
+//! let a = Gc::new(Node { value: 1, next: RefCell::new(None) });
+//! *a.next.borrow_mut() = Some(a.clone());
+//! drop(a);
+//! assert_eq!(gc::collect(), 1);
+//! ```
+//!
+//! There is no stack scanning, the roots are found by counting: every value
+//! counts the `Gc`s pointing to it, and tracing the heap tells how many of them
+//! live inside other `Gc` values. A value with more `Gc`s than that is
+//! referenced from outside the heap, from a local variable or a plain `Box`,
+//! and marking starts there.
+//!
+//! Garbage values are dropped while their neighbours may already be gone, so a
+//! `Drop` impl must not follow the `Gc`s it owns: dereferencing or cloning a
+//! `Gc` to a value that is being collected panics.
+
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+
+pub use cell_fields_derive::Trace;
+
+/// Lists the `Gc`s owned by a value, so `collect` can follow them. Derive it with
+/// `#[derive(Trace)]`, which traces every field.
+///
+/// # Safety
+///
+/// `trace` must visit every `Gc` that the value owns exactly once and must not
+/// visit any other. A `Gc` missed or visited too often can get a live value freed.
+pub unsafe trait Trace {
+    fn trace(&self, tracer: &mut Tracer<'_>);
+}
+
+/// Collects the `Gc`s a value visits in `Trace::trace`.
+pub struct Tracer<'a> {
+    visit: &'a mut dyn FnMut(BoxPtr),
+}
+
+impl Tracer<'_> {
+    pub fn visit<T: Trace + 'static>(&mut self, gc: &Gc<T>) {
+        (self.visit)(gc.ptr);
+    }
+}
+
+struct GcBox<T: ?Sized> {
+    // All the Gcs pointing here, wherever they live.
+    handles: Cell<usize>,
+    // The Gcs found inside other values during a collection.
+    internal: Cell<usize>,
+    marked: Cell<bool>,
+    state: Cell<State>,
+    value: ManuallyDrop<T>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Live,
+    // A collection is dropping the value, its neighbours may be gone already.
+    Collecting,
+    // The value is gone, but a garbage value's drop moved a Gc pointing here
+    // out of the garbage. The last such Gc frees the box.
+    Dead,
+}
+
+type BoxPtr = NonNull<GcBox<dyn Trace>>;
+
+thread_local! {
+    // Every value allocated on this thread and not collected yet.
+    static HEAP: RefCell<Vec<BoxPtr>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A pointer to a garbage-collected value, freed by `collect` once it is no
+/// longer reachable. Unlike `Rc`, cycles are no problem.
+pub struct Gc<T: Trace + 'static> {
+    ptr: BoxPtr,
+    _marker: PhantomData<GcBox<T>>,
+}
+
+impl<T: Trace + 'static> Gc<T> {
+    pub fn new(value: T) -> Self {
+        let boxed: Box<GcBox<dyn Trace>> = Box::new(GcBox {
+            handles: Cell::new(1),
+            internal: Cell::new(0),
+            marked: Cell::new(false),
+            state: Cell::new(State::Live),
+            value: ManuallyDrop::new(value),
+        });
+        let ptr = NonNull::from(Box::leak(boxed));
+        HEAP.with(|heap| heap.borrow_mut().push(ptr));
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether both point to the same value, rather than to equal values.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+}
+
+/// Panics unless the value behind `ptr` is live, a `Drop` impl of garbage may
+/// hold Gcs to values already dropped.
+fn live<'a>(ptr: BoxPtr) -> Header<'a> {
+    let inner = node(ptr);
+    assert!(
+        inner.state.get() == State::Live,
+        "Gc used while its value is being collected"
+    );
+    inner
+}
+
+impl<T: Trace + 'static> Clone for Gc<T> {
+    fn clone(&self) -> Self {
+        let handles = live(self.ptr).handles;
+        handles.set(handles.get().checked_add(1).expect("Gc count overflowed"));
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: Trace + 'static> Drop for Gc<T> {
+    fn drop(&mut self) {
+        let inner = node(self.ptr);
+        let handles = inner.handles.get() - 1;
+        inner.handles.set(handles);
+        if handles == 0 && inner.state.get() == State::Dead {
+            // SAFETY: The value is gone and this was the last Gc pointing here.
+            drop(unsafe { Box::from_raw(self.ptr.as_ptr()) });
+        }
+    }
+}
+
+impl<T: Trace + 'static> Deref for Gc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        live(self.ptr);
+        // SAFETY: A Gc outside of the garbage keeps the value from being
+        // collected, and the value of garbage isn't handed out.
+        unsafe { &(*self.ptr.as_ptr().cast::<GcBox<T>>()).value }
+    }
+}
+
+impl<T: Trace + Debug + 'static> Debug for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: Trace + Display + 'static> Display for Gc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+unsafe impl<T: Trace + 'static> Trace for Gc<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        tracer.visit(self);
+    }
+}
+
+/// The bookkeeping of a box, borrowed apart from the value, which may be in the
+/// middle of being dropped.
+struct Header<'a> {
+    handles: &'a Cell<usize>,
+    internal: &'a Cell<usize>,
+    marked: &'a Cell<bool>,
+    state: &'a Cell<State>,
+}
+
+fn node<'a>(ptr: BoxPtr) -> Header<'a> {
+    let ptr = ptr.as_ptr();
+    // SAFETY: Callers only pass boxes that weren't freed yet.
+    unsafe {
+        Header {
+            handles: &*ptr::addr_of!((*ptr).handles),
+            internal: &*ptr::addr_of!((*ptr).internal),
+            marked: &*ptr::addr_of!((*ptr).marked),
+            state: &*ptr::addr_of!((*ptr).state),
+        }
+    }
+}
+
+fn for_each_child(ptr: BoxPtr, mut f: impl FnMut(Header<'_>, BoxPtr)) {
+    // SAFETY: Callers only pass values that weren't collected yet.
+    let value = unsafe { &(*ptr.as_ptr()).value };
+    value.trace(&mut Tracer {
+        visit: &mut |child| f(node(child), child),
+    });
+}
+
+/// The values a collection took out of `HEAP`, put back when it ends, also if
+/// tracing or a drop panics.
+struct Collection {
+    live: Vec<BoxPtr>,
+    garbage: Vec<BoxPtr>,
+}
+
+impl Drop for Collection {
+    fn drop(&mut self) {
+        // Only left over if a drop panicked. The values not dropped yet leak,
+        // the boxes stay for the Gcs that may still point to them.
+        for &ptr in &self.garbage {
+            node(ptr).state.set(State::Dead);
+        }
+        HEAP.with(|heap| {
+            let mut heap = heap.borrow_mut();
+            // Values allocated by the drops were pushed meanwhile.
+            let allocated_meanwhile = std::mem::take(&mut *heap);
+            heap.append(&mut self.live);
+            heap.extend(allocated_meanwhile);
+        });
+    }
+}
+
+/// Frees every value on this thread that can't be reached anymore. Returns the
+/// number of values freed.
+///
+/// Panics if a `RefCell` in the traced values is mutably borrowed, the values
+/// are then left for the next collection.
+pub fn collect() -> usize {
+    // Taken out, dropping the garbage may allocate new values.
+    let mut collection = Collection {
+        live: HEAP.with(|heap| heap.take()),
+        garbage: Vec::new(),
+    };
+    let heap = &collection.live;
+
+    // Count the Gcs inside the heap, the rest live outside and are roots.
+    for &ptr in heap {
+        node(ptr).internal.set(0);
+        node(ptr).marked.set(false);
+    }
+    for &ptr in heap {
+        for_each_child(ptr, |child, _| child.internal.set(child.internal.get() + 1));
+    }
+
+    // Mark everything reachable from the roots.
+    let mut stack: Vec<BoxPtr> = heap
+        .iter()
+        .copied()
+        .filter(|&ptr| node(ptr).handles.get() > node(ptr).internal.get())
+        .collect();
+    for &ptr in &stack {
+        node(ptr).marked.set(true);
+    }
+    while let Some(ptr) = stack.pop() {
+        for_each_child(ptr, |child, child_ptr| {
+            if !child.marked.replace(true) {
+                stack.push(child_ptr);
+            }
+        });
+    }
+
+    // Sweep.
+    let (live, garbage) = std::mem::take(&mut collection.live)
+        .into_iter()
+        .partition(|&ptr| node(ptr).marked.get());
+    collection.live = live;
+    collection.garbage = garbage;
+    for &ptr in &collection.garbage {
+        node(ptr).state.set(State::Collecting);
+    }
+    for &ptr in &collection.garbage {
+        // SAFETY: Only other garbage refers to these values, and its Gcs can't
+        // reach them anymore.
+        unsafe { ManuallyDrop::drop(&mut (*ptr.as_ptr()).value) };
+    }
+    let garbage = std::mem::take(&mut collection.garbage);
+    for &ptr in &garbage {
+        if node(ptr).handles.get() == 0 {
+            // SAFETY: The value is gone and no Gc is left pointing here.
+            drop(unsafe { Box::from_raw(ptr.as_ptr()) });
+        } else {
+            node(ptr).state.set(State::Dead);
+        }
+    }
+    garbage.len()
+}
+
+/// The number of values on this thread that weren't collected yet.
+pub fn heap_size() -> usize {
+    HEAP.with(|heap| heap.borrow().len())
+}
+
+macro_rules! trace_nothing {
+    ($($t:ty),*) => {
+        $(unsafe impl Trace for $t {
+            fn trace(&self, _: &mut Tracer<'_>) {}
+        })*
+    };
+}
+
+trace_nothing!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64,
+    String,
+    &'static str
+);
+
+// A Cell only holds Copy values, which Gcs are not.
+unsafe impl<T: Copy> Trace for Cell<T> {
+    fn trace(&self, _: &mut Tracer<'_>) {}
+}
+
+unsafe impl<T: Trace> Trace for RefCell<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        self.try_borrow()
+            .expect("gc::collect can't trace a mutably borrowed RefCell")
+            .trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        if let Some(value) = self {
+            value.trace(tracer);
+        }
+    }
+}
+
+unsafe impl<T: Trace + ?Sized> Trace for Box<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        (**self).trace(tracer);
+    }
+}
+
+unsafe impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, tracer: &mut Tracer<'_>) {
+        for value in self {
+            value.trace(tracer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect, heap_size, Gc, Trace};
+    use crate::cell::Cell;
+    use crate::ref_cell::RefCell;
+    use std::panic::{self, AssertUnwindSafe};
+
+    thread_local! {
+        static DROPS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    #[derive(Trace)]
+    struct Node {
+        value: i32,
+        edges: RefCell<Vec<Gc<Node>>>,
+        dropped: Counted,
+    }
+
+    #[derive(Trace)]
+    struct Counted {}
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPS.with(|drops| drops.set(drops.get() + 1));
+        }
+    }
+
+    fn node(value: i32) -> Gc<Node> {
+        Gc::new(Node {
+            value,
+            edges: RefCell::new(Vec::new()),
+            dropped: Counted {},
+        })
+    }
+
+    fn link(from: &Gc<Node>, to: &Gc<Node>) {
+        from.edges.borrow_mut().push(to.clone());
+    }
+
+    fn drops() -> usize {
+        DROPS.with(|drops| drops.get())
+    }
+
+    // Tests run on their own threads, so the heap and counts are per test.
+
+    #[test]
+    fn test_reachable_values_survive() {
+        let a = node(1);
+        let b = node(2);
+        link(&a, &b);
+        drop(b);
+
+        assert_eq!(collect(), 0);
+        assert_eq!(a.edges.borrow()[0].value, 2);
+        assert_eq!(heap_size(), 2);
+
+        drop(a);
+        assert_eq!(collect(), 2);
+        assert_eq!((drops(), heap_size()), (2, 0));
+    }
+
+    #[test]
+    fn test_cycles_collected() {
+        let a = node(1);
+        let b = node(2);
+        link(&a, &b);
+        link(&b, &a);
+        link(&b, &b);
+
+        drop(a);
+        assert_eq!(collect(), 0);
+        drop(b);
+        assert_eq!(collect(), 2);
+        assert_eq!(drops(), 2);
+    }
+
+    #[test]
+    fn test_rooted_from_a_box() {
+        let a = node(1);
+        link(&a, &a);
+        let boxed = Box::new(Some(a));
+
+        assert_eq!(collect(), 0);
+        assert_eq!(boxed.as_ref().as_ref().unwrap().value, 1);
+
+        drop(boxed);
+        assert_eq!(collect(), 1);
+    }
+
+    #[test]
+    fn test_garbage_referring_to_live_values() {
+        let live = node(0);
+        let garbage = node(1);
+        link(&garbage, &garbage);
+        link(&garbage, &live);
+        drop(garbage);
+
+        assert_eq!(collect(), 1);
+        assert_eq!(live.value, 0);
+        link(&live, &live);
+        drop(live);
+        assert_eq!(collect(), 1);
+    }
+
+    #[test]
+    fn test_panicking_trace_keeps_the_heap() {
+        let a = node(1);
+        link(&a, &a);
+        let borrowed = a.edges.borrow_mut();
+
+        assert!(panic::catch_unwind(collect).is_err());
+        drop(borrowed);
+        assert_eq!(heap_size(), 1);
+
+        drop(a);
+        assert_eq!(collect(), 1);
+    }
+
+    #[derive(Trace)]
+    struct Peeking {
+        next: RefCell<Option<Gc<Peeking>>>,
+    }
+
+    impl Drop for Peeking {
+        fn drop(&mut self) {
+            if let Some(next) = &*self.next.borrow() {
+                next.next.borrow();
+            }
+        }
+    }
+
+    #[test]
+    fn test_drop_reaching_into_garbage_panics() {
+        let a = Gc::new(Peeking {
+            next: RefCell::new(None),
+        });
+        let b = Gc::new(Peeking {
+            next: RefCell::new(Some(a.clone())),
+        });
+        *a.next.borrow_mut() = Some(b);
+        drop(a);
+
+        let result = panic::catch_unwind(collect);
+        assert!(result.is_err());
+        assert_eq!(heap_size(), 0);
+        assert_eq!(collect(), 0);
+    }
+
+    thread_local! {
+        static ESCAPED: RefCell<Vec<Gc<Escaping>>> = const { RefCell::new(Vec::new()) };
+    }
+
+    #[derive(Trace)]
+    struct Escaping {
+        next: RefCell<Option<Gc<Escaping>>>,
+    }
+
+    impl Drop for Escaping {
+        fn drop(&mut self) {
+            if let Some(next) = self.next.borrow_mut().take() {
+                ESCAPED.with(|escaped| escaped.borrow_mut().push(next));
+            }
+        }
+    }
+
+    #[test]
+    fn test_gc_escaping_a_drop_is_dead() {
+        let a = Gc::new(Escaping {
+            next: RefCell::new(None),
+        });
+        *a.next.borrow_mut() = Some(a.clone());
+        drop(a);
+
+        assert_eq!(collect(), 1);
+        let escaped = ESCAPED.with(|escaped| escaped.borrow_mut().pop().unwrap());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| escaped.clone()));
+        assert!(result.is_err());
+        // Frees the box.
+        drop(escaped);
+    }
+}
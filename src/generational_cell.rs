@@ -0,0 +1,152 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+use crate::refs::{Ref, RefMut};
+
+/// A cell whose value is reached through `Handle`s that go stale once the value
+/// is replaced, e.g. when an asset is hot-reloaded.
+///
+/// Mutating the value in place keeps handles valid, only `replace` and `set`
+/// start a new generation.
+pub struct GenerationalCell<T> {
+    value: RefCell<T>,
+    generation: Cell<u64>,
+}
+
+/// Refers to the value of a `GenerationalCell` as of a generation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    generation: u64,
+}
+
+impl Handle {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+/// Returned when a handle outlived the value it was issued for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StaleHandle {
+    handle: u64,
+    current: u64,
+}
+
+impl Display for StaleHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Handle from generation {} is stale, the cell is at generation {}",
+            self.handle, self.current
+        )
+    }
+}
+
+impl Error for StaleHandle {}
+
+impl<T> GenerationalCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: RefCell::new(value),
+            generation: Cell::new(0),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+
+    /// Issues a handle to the current value.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            generation: self.generation(),
+        }
+    }
+
+    pub fn is_valid(&self, handle: Handle) -> bool {
+        handle.generation == self.generation()
+    }
+
+    #[track_caller]
+    pub fn get(&self, handle: Handle) -> Result<Ref<'_, T>, StaleHandle> {
+        self.check(handle)?;
+        Ok(self.value.borrow())
+    }
+
+    #[track_caller]
+    pub fn get_mut(&self, handle: Handle) -> Result<RefMut<'_, T>, StaleHandle> {
+        self.check(handle)?;
+        Ok(self.value.borrow_mut())
+    }
+
+    /// Replaces the value and invalidates all handles issued so far.
+    #[track_caller]
+    pub fn replace(&self, value: T) -> T {
+        let previous = self.value.replace(value);
+        self.generation.set(self.generation() + 1);
+        previous
+    }
+
+    #[track_caller]
+    pub fn set(&self, value: T) {
+        drop(self.replace(value));
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn check(&self, handle: Handle) -> Result<(), StaleHandle> {
+        if self.is_valid(handle) {
+            Ok(())
+        } else {
+            Err(StaleHandle {
+                handle: handle.generation,
+                current: self.generation(),
+            })
+        }
+    }
+}
+
+impl<T: Debug> Debug for GenerationalCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GenerationalCell")
+            .field("value", &self.value)
+            .field("generation", &self.generation())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_invalidates_handles() {
+        let cell = GenerationalCell::new("v1");
+        let old = cell.handle();
+        assert_eq!(*cell.get(old).unwrap(), "v1");
+
+        assert_eq!(cell.replace("v2"), "v1");
+
+        assert!(!cell.is_valid(old));
+        assert_eq!(
+            cell.get(old).err().unwrap().to_string(),
+            "Handle from generation 0 is stale, the cell is at generation 1"
+        );
+        assert_eq!(*cell.get(cell.handle()).unwrap(), "v2");
+    }
+
+    #[test]
+    fn test_mutation_in_place_keeps_handles() {
+        let cell = GenerationalCell::new(vec![1]);
+        let handle = cell.handle();
+
+        cell.get_mut(handle).unwrap().push(2);
+
+        assert_eq!(*cell.get(handle).unwrap(), vec![1, 2]);
+        assert_eq!(handle.generation(), cell.generation());
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt::{self, Debug};
+
+use crate::ghost_cell::GhostCell;
+use crate::ref_cell::RefCell;
+
+/// Allocates `GhostCell`s of one brand that live as long as the arena.
+///
+/// Nodes refer to each other with plain `&'arena GhostCell<'brand, _>` references,
+/// so cyclic structures like doubly-linked lists and graphs need neither
+/// reference counting nor runtime borrow flags. Reading and writing nodes goes
+/// through the brand's `GhostToken`. Nodes are freed when the arena is dropped.
+pub struct Arena<'brand, T> {
+    // No Drop impl of our own: Vec's and Box's drop glue lets nodes refer to the
+    // arena itself (the `'arena` of `GhostList` nodes), a Drop impl would not.
+    nodes: RefCell<Vec<Box<GhostCell<'brand, T>>>>,
+}
+
+impl<'brand, T> Arena<'brand, T> {
+    pub fn new() -> Self {
+        Self {
+            nodes: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn alloc(&self, value: T) -> &GhostCell<'brand, T> {
+        let mut nodes = self.nodes.borrow_mut();
+        nodes.push(Box::new(GhostCell::new(value)));
+        // Taken after the push, boxes are never moved or touched again until dropped.
+        let node: *const GhostCell<'brand, T> = &**nodes.last().unwrap();
+        // SAFETY: The node is only freed when the arena is dropped, which can't
+        // happen while `self` is borrowed.
+        unsafe { &*node }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Default for Arena<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for Arena<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Arena").field("len", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ghost_cell::GhostToken;
+
+    struct GraphNode<'arena, 'brand> {
+        value: u32,
+        edges: Vec<&'arena GhostCell<'brand, GraphNode<'arena, 'brand>>>,
+    }
+
+    #[test]
+    fn test_cyclic_graph() {
+        GhostToken::new(|mut token| {
+            let arena = Arena::new();
+            let new_node = |value| {
+                arena.alloc(GraphNode {
+                    value,
+                    edges: Vec::new(),
+                })
+            };
+            let (a, b, c) = (new_node(1), new_node(2), new_node(3));
+
+            a.borrow_mut(&mut token).edges.push(b);
+            b.borrow_mut(&mut token).edges.push(c);
+            c.borrow_mut(&mut token).edges.push(a);
+            // Bump every node through the cycle.
+            let mut node = a;
+            for _ in 0..3 {
+                node.borrow_mut(&mut token).value += 10;
+                node = node.borrow(&token).edges[0];
+            }
+
+            let values: Vec<u32> = [a, b, c].iter().map(|n| n.borrow(&token).value).collect();
+            assert_eq!(values, vec![11, 12, 13]);
+            assert_eq!(arena.len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_drop_frees_nodes() {
+        let rc = std::rc::Rc::new(());
+        GhostToken::new(|_token| {
+            let arena = Arena::new();
+            arena.alloc(rc.clone());
+            arena.alloc(rc.clone());
+        });
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+}
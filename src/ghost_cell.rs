@@ -0,0 +1,285 @@
+//! Interior mutability checked entirely at compile time, after the GhostCell paper.
+//!
+//! Every `GhostCell<'brand, T>` belongs to the one `GhostToken<'brand>` with the
+//! same brand. Reading a cell needs `&token`, writing needs `&mut token`, so the
+//! borrow checker does for all cells of a brand at once what `RefCell` does per
+//! cell at runtime. The brand is an invariant lifetime that `GhostToken::new`
+//! makes unique by only handing the token to a closure generic over it, or that
+//! `with_brand!` makes unique to the current scope.
+
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+
+use crate::qcell::impl_rw2_rw3;
+use crate::unsafe_cell::UnsafeCell;
+
+/// Keeps `'brand` from being shortened or lengthened to match another brand.
+type InvariantLifetime<'brand> = PhantomData<fn(&'brand ()) -> &'brand ()>;
+
+/// The key to all `GhostCell`s of its brand.
+pub struct GhostToken<'brand> {
+    _brand: InvariantLifetime<'brand>,
+}
+
+impl<'brand> GhostToken<'brand> {
+    /// Runs `f` with a token whose brand no other token shares.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new<R, F>(f: F) -> R
+    where
+        F: for<'new_brand> FnOnce(GhostToken<'new_brand>) -> R,
+    {
+        f(GhostToken {
+            _brand: PhantomData,
+        })
+    }
+
+    /// Used by `with_brand!`.
+    ///
+    /// # Safety
+    ///
+    /// `place` must be borrowed by a `BrandGuard` that lives until the end of the
+    /// scope and no other token may be created from it.
+    #[doc(hidden)]
+    pub unsafe fn from_place(_place: &'brand BrandPlace<'brand>) -> Self {
+        Self {
+            _brand: PhantomData,
+        }
+    }
+
+    impl_rw2_rw3!(GhostCell<'brand>);
+}
+
+/// Declares `let mut $token: GhostToken<'_>` with a brand unique to the enclosing scope.
+///
+/// ```ignore
+/// with_brand!(token);
+/// let cell = GhostCell::new(1);
+/// *cell.borrow_mut(&mut token) += 1;
+/// ```
+///
+/// The brand borrows a hidden local, and a guard with a `Drop` impl keeps that
+/// borrow alive until the end of the scope. Were two brands in one scope the same
+/// lifetime, the guard declared first would outlive the local borrowed by the
+/// second and the program would be rejected.
+#[macro_export]
+macro_rules! with_brand {
+    ($token:ident) => {
+        let place = $crate::ghost_cell::BrandPlace::new();
+        let _guard = $crate::ghost_cell::BrandGuard::new(&place);
+        #[allow(unused_mut)]
+        let mut $token = unsafe {
+            // SAFETY: `place` and `_guard` make the brand unique to this scope.
+            $crate::ghost_cell::GhostToken::from_place(&place)
+        };
+    };
+}
+
+#[doc(hidden)]
+pub struct BrandPlace<'brand> {
+    _brand: InvariantLifetime<'brand>,
+}
+
+#[doc(hidden)]
+impl<'brand> BrandPlace<'brand> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            _brand: PhantomData,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub struct BrandGuard<'brand> {
+    _place: PhantomData<&'brand BrandPlace<'brand>>,
+}
+
+#[doc(hidden)]
+impl<'brand> BrandGuard<'brand> {
+    pub fn new(_place: &'brand BrandPlace<'brand>) -> Self {
+        Self {
+            _place: PhantomData,
+        }
+    }
+}
+
+// Makes `'brand` live until the guard goes out of scope.
+impl Drop for BrandGuard<'_> {
+    fn drop(&mut self) {}
+}
+
+impl Debug for GhostToken<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("GhostToken")
+    }
+}
+
+/// A cell that is borrowed through its brand's `GhostToken` instead of runtime flags.
+#[repr(transparent)]
+pub struct GhostCell<'brand, T: ?Sized> {
+    _brand: InvariantLifetime<'brand>,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: Access requires the token, which is borrowed like the value itself would
+// be: sharing the cell shares `&T` (T: Sync) and `&mut token` on another thread
+// gives `&mut T` there (T: Send).
+unsafe impl<T: ?Sized + Send + Sync> Sync for GhostCell<'_, T> {}
+
+impl<'brand, T> GhostCell<'brand, T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            _brand: PhantomData,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    pub fn replace(&self, value: T, token: &mut GhostToken<'brand>) -> T {
+        std::mem::replace(self.borrow_mut(token), value)
+    }
+}
+
+impl<'brand, T: ?Sized> GhostCell<'brand, T> {
+    pub fn borrow<'a>(&'a self, _token: &'a GhostToken<'brand>) -> &'a T {
+        // SAFETY: Writing needs `&mut token`, which can't exist while `token` is borrowed.
+        unsafe { &*self.value.get() }
+    }
+
+    pub fn borrow_mut<'a>(&'a self, _token: &'a mut GhostToken<'brand>) -> &'a mut T {
+        // SAFETY: We hold the only token of this brand exclusively, so nothing else
+        // can borrow any cell of the brand while the result lives.
+        unsafe { &mut *self.value.get() }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn from_mut(value: &mut T) -> &mut Self {
+        // SAFETY: GhostCell is repr(transparent) over UnsafeCell<T>, which is over T.
+        unsafe { &mut *(value as *mut T as *mut Self) }
+    }
+
+    pub fn as_ptr(&self) -> *mut T {
+        self.value.get()
+    }
+}
+
+impl<T: Default> Default for GhostCell<'_, T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: ?Sized> Debug for GhostCell<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Without the token we can't look inside.
+        f.debug_struct("GhostCell").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_borrow_through_token() {
+        GhostToken::new(|mut token| {
+            let a = GhostCell::new(1);
+            let b = GhostCell::new(String::from("b"));
+
+            *a.borrow_mut(&mut token) += 1;
+            b.borrow_mut(&mut token).push('!');
+
+            assert_eq!(*a.borrow(&token), 2);
+            assert_eq!(b.borrow(&token), "b!");
+            assert_eq!(a.replace(3, &mut token), 2);
+            assert_eq!(a.into_inner(), 3);
+        });
+    }
+
+    #[test]
+    fn test_many_shared_borrows() {
+        GhostToken::new(|token| {
+            let cells: Vec<GhostCell<'_, i32>> = (0..3).map(GhostCell::new).collect();
+
+            let refs: Vec<&i32> = cells.iter().map(|cell| cell.borrow(&token)).collect();
+
+            assert_eq!(refs, vec![&0, &1, &2]);
+        });
+    }
+
+    #[test]
+    fn test_from_mut_and_get_mut() {
+        let mut value = 1;
+        *GhostCell::from_mut(&mut value).get_mut() = 2;
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_token_and_cell_are_shareable() {
+        assert_sync::<GhostToken<'static>>();
+        assert_sync::<GhostCell<'static, Vec<i32>>>();
+        assert_not_impl!(GhostCell<'static, std::cell::Cell<i32>>: Sync);
+    }
+
+    #[test]
+    fn test_threads_take_turns_with_token() {
+        GhostToken::new(|mut token| {
+            let counter = GhostCell::new(0);
+
+            std::thread::scope(|s| {
+                s.spawn(|| *counter.borrow_mut(&mut token) += 1);
+            });
+            *counter.borrow_mut(&mut token) += 1;
+
+            assert_eq!(*counter.borrow(&token), 2);
+        });
+    }
+
+    #[test]
+    fn test_rw2_rw3() {
+        GhostToken::new(|mut token| {
+            let (a, b, c) = (GhostCell::new(1), GhostCell::new(2), GhostCell::new(3));
+
+            let (x, y) = token.rw2(&a, &b);
+            std::mem::swap(x, y);
+            let (x, y, z) = token.rw3(&a, &b, &c);
+            *z += *x + *y;
+
+            assert_eq!(
+                (*a.borrow(&token), *b.borrow(&token), *c.borrow(&token)),
+                (2, 1, 6)
+            );
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "Illegal to borrow the same cell twice in rw2/rw3")]
+    fn test_rw2_same_cell_panics() {
+        GhostToken::new(|mut token| {
+            let cell = GhostCell::new(1);
+            token.rw2(&cell, &cell);
+        });
+    }
+
+    #[test]
+    fn test_with_brand() {
+        crate::with_brand!(token);
+        crate::with_brand!(other);
+        let cell = GhostCell::new(vec![1]);
+        let other_cell = GhostCell::new(0);
+
+        cell.borrow_mut(&mut token).push(2);
+        *other_cell.borrow_mut(&mut other) += 1;
+
+        assert_eq!(cell.borrow(&token), &vec![1, 2]);
+        assert_eq!(*other_cell.borrow(&other), 1);
+    }
+}
@@ -0,0 +1,293 @@
+use std::fmt::{self, Debug};
+
+use crate::atomic::AtomicUsize;
+use crate::ghost_arena::Arena;
+use crate::ghost_cell::{GhostCell, GhostToken};
+
+/// A node of a `GhostList`, allocated in the list's arena.
+pub struct Node<'arena, 'brand, T> {
+    value: T,
+    prev: Option<NodeRef<'arena, 'brand, T>>,
+    next: Option<NodeRef<'arena, 'brand, T>>,
+    // The id of the list the node is linked into, `UNLINKED` once removed.
+    list: usize,
+}
+
+const UNLINKED: usize = 0;
+
+/// Lists of one arena can hand each other their nodes, so every list gets an id.
+static NEXT_LIST_ID: AtomicUsize = AtomicUsize::new(UNLINKED + 1);
+
+pub type NodeRef<'arena, 'brand, T> = &'arena GhostCell<'brand, Node<'arena, 'brand, T>>;
+
+impl<T> Node<'_, '_, T> {
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    pub fn value_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+/// A doubly-linked list built from `GhostCell`s in an `Arena`, the example of a
+/// cyclic structure that needs no `Rc`, `Weak`, or runtime borrow checks.
+///
+/// Unlinked nodes stay in the arena until it's dropped.
+pub struct GhostList<'arena, 'brand, T> {
+    arena: &'arena Arena<'brand, Node<'arena, 'brand, T>>,
+    head: Option<NodeRef<'arena, 'brand, T>>,
+    tail: Option<NodeRef<'arena, 'brand, T>>,
+    len: usize,
+    id: usize,
+}
+
+impl<'arena, 'brand, T> GhostList<'arena, 'brand, T> {
+    pub fn new(arena: &'arena Arena<'brand, Node<'arena, 'brand, T>>) -> Self {
+        Self {
+            arena,
+            head: None,
+            tail: None,
+            len: 0,
+            id: NEXT_LIST_ID.fetch_add_relaxed(1),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn front(&self) -> Option<NodeRef<'arena, 'brand, T>> {
+        self.head
+    }
+
+    pub fn back(&self) -> Option<NodeRef<'arena, 'brand, T>> {
+        self.tail
+    }
+
+    pub fn push_back(
+        &mut self,
+        value: T,
+        token: &mut GhostToken<'brand>,
+    ) -> NodeRef<'arena, 'brand, T> {
+        let node = self.arena.alloc(Node {
+            value,
+            prev: self.tail,
+            next: None,
+            list: self.id,
+        });
+        match self.tail {
+            Some(tail) => tail.borrow_mut(token).next = Some(node),
+            None => self.head = Some(node),
+        }
+        self.tail = Some(node);
+        self.len += 1;
+        node
+    }
+
+    pub fn push_front(
+        &mut self,
+        value: T,
+        token: &mut GhostToken<'brand>,
+    ) -> NodeRef<'arena, 'brand, T> {
+        let node = self.arena.alloc(Node {
+            value,
+            prev: None,
+            next: self.head,
+            list: self.id,
+        });
+        match self.head {
+            Some(head) => head.borrow_mut(token).prev = Some(node),
+            None => self.tail = Some(node),
+        }
+        self.head = Some(node);
+        self.len += 1;
+        node
+    }
+
+    /// Unlinks `node` if it is part of this list, returns whether it was.
+    pub fn remove(
+        &mut self,
+        node: NodeRef<'arena, 'brand, T>,
+        token: &mut GhostToken<'brand>,
+    ) -> bool {
+        let (prev, next) = {
+            let node = node.borrow_mut(token);
+            if node.list != self.id {
+                return false;
+            }
+            node.list = UNLINKED;
+            (node.prev.take(), node.next.take())
+        };
+        match prev {
+            Some(prev) => prev.borrow_mut(token).next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => next.borrow_mut(token).prev = prev,
+            None => self.tail = prev,
+        }
+        self.len -= 1;
+        true
+    }
+
+    pub fn pop_front(
+        &mut self,
+        token: &mut GhostToken<'brand>,
+    ) -> Option<NodeRef<'arena, 'brand, T>> {
+        let head = self.head?;
+        self.remove(head, token);
+        Some(head)
+    }
+
+    pub fn pop_back(
+        &mut self,
+        token: &mut GhostToken<'brand>,
+    ) -> Option<NodeRef<'arena, 'brand, T>> {
+        let tail = self.tail?;
+        self.remove(tail, token);
+        Some(tail)
+    }
+
+    pub fn iter<'a>(&self, token: &'a GhostToken<'brand>) -> Iter<'a, 'arena, 'brand, T> {
+        Iter {
+            front: self.head,
+            back: self.tail,
+            remaining: self.len,
+            token,
+        }
+    }
+}
+
+impl<T> Debug for GhostList<'_, '_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GhostList").field("len", &self.len).finish()
+    }
+}
+
+pub struct Iter<'a, 'arena, 'brand, T> {
+    front: Option<NodeRef<'arena, 'brand, T>>,
+    back: Option<NodeRef<'arena, 'brand, T>>,
+    remaining: usize,
+    token: &'a GhostToken<'brand>,
+}
+
+impl<'a, 'arena: 'a, T> Iterator for Iter<'a, 'arena, '_, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front?.borrow(self.token);
+        self.front = node.next;
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+}
+
+impl<'a, 'arena: 'a, T> DoubleEndedIterator for Iter<'a, 'arena, '_, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back?.borrow(self.token);
+        self.back = node.prev;
+        self.remaining -= 1;
+        Some(&node.value)
+    }
+}
+
+impl<T> Debug for Iter<'_, '_, '_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Iter")
+            .field("remaining", &self.remaining)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_iterate_both_ways() {
+        GhostToken::new(|mut token| {
+            let arena = Arena::new();
+            let mut list = GhostList::new(&arena);
+            list.push_back(2, &mut token);
+            list.push_back(3, &mut token);
+            list.push_front(1, &mut token);
+
+            assert_eq!(
+                list.iter(&token).copied().collect::<Vec<_>>(),
+                vec![1, 2, 3]
+            );
+            assert_eq!(
+                list.iter(&token).rev().copied().collect::<Vec<_>>(),
+                vec![3, 2, 1]
+            );
+            assert_eq!(list.len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_remove_from_middle_and_pop() {
+        GhostToken::new(|mut token| {
+            let arena = Arena::new();
+            let mut list = GhostList::new(&arena);
+            list.push_back("a", &mut token);
+            let b = list.push_back("b", &mut token);
+            list.push_back("c", &mut token);
+
+            list.remove(b, &mut token);
+            assert_eq!(
+                list.iter(&token).copied().collect::<Vec<_>>(),
+                vec!["a", "c"]
+            );
+
+            let front = list.pop_front(&mut token).unwrap();
+            assert_eq!(front.borrow(&token).value(), &"a");
+            let back = list.pop_back(&mut token).unwrap();
+            assert_eq!(back.borrow(&token).value(), &"c");
+            assert!(list.is_empty());
+            assert!(list.pop_front(&mut token).is_none());
+        });
+    }
+
+    #[test]
+    fn test_mutate_through_node_refs() {
+        GhostToken::new(|mut token| {
+            let arena = Arena::new();
+            let mut list = GhostList::new(&arena);
+            let node = list.push_back(String::from("x"), &mut token);
+
+            node.borrow_mut(&mut token).value_mut().push('y');
+
+            assert_eq!(list.front().unwrap().borrow(&token).value(), "xy");
+        });
+    }
+
+    #[test]
+    fn test_remove_foreign_or_unlinked_node() {
+        GhostToken::new(|mut token| {
+            let arena = Arena::new();
+            let mut list = GhostList::new(&arena);
+            let mut other = GhostList::new(&arena);
+            let a = list.push_back(1, &mut token);
+            list.push_back(2, &mut token);
+            let foreign = other.push_back(3, &mut token);
+
+            assert!(list.remove(a, &mut token));
+            assert!(!list.remove(a, &mut token));
+            assert!(!list.remove(foreign, &mut token));
+
+            assert_eq!(list.iter(&token).copied().collect::<Vec<_>>(), vec![2]);
+            assert_eq!(other.iter(&token).copied().collect::<Vec<_>>(), vec![3]);
+        });
+    }
+}
@@ -0,0 +1,227 @@
+//! Hazard pointers, the other way to free values that lock-free code unlinked
+//! while other threads may still read them.
+//!
+//! A reader publishes the pointer it is about to use in a [`HazardPointer`]
+//! slot. Unlinked values are handed to [`retire`], which frees them once no
+//! slot holds their address. Unlike with [`epoch`](crate::epoch), a stalled
+//! reader keeps only the values it protects alive, not everything retired
+//! after it pinned, but every protected load costs a fence.
+
+use std::fmt::{self, Debug};
+use std::mem;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+use crate::atomic::{self, AtomicPtr};
+use crate::registry::{Entry, Registry};
+use crate::sync::{ignore_poison, Mutex};
+
+/// How many retired values pile up before `retire` scans the slots.
+const RETIRED_BEFORE_RECLAIM: usize = 64;
+
+/// Every slot ever handed out, they are reused once their hazard pointer drops.
+static SLOTS: Registry<Slot> = Registry::new();
+
+static RETIRED: Mutex<Vec<Retired>> = Mutex::new(Vec::new());
+
+struct Slot {
+    protected: AtomicPtr<u8>,
+}
+
+struct Retired {
+    ptr: SendPtr<u8>,
+    // Drops the box `ptr` came from, as its original type.
+    drop: unsafe fn(*mut u8),
+}
+
+/// A retired pointer, freed by whichever thread reclaims it.
+struct SendPtr<T>(*mut T);
+
+// SAFETY: `retire` only takes values that are Send.
+unsafe impl<T: Send> Send for SendPtr<T> {}
+
+/// A slot announcing the one pointer its owner is using, values retired
+/// meanwhile at that address aren't freed.
+pub struct HazardPointer {
+    slot: &'static Entry<Slot>,
+}
+
+impl HazardPointer {
+    pub fn new() -> Self {
+        Self {
+            slot: SLOTS.acquire(|| Slot {
+                protected: AtomicPtr::new(ptr::null_mut()),
+            }),
+        }
+    }
+
+    /// Loads `src` and protects what it points to, until the next `protect` or
+    /// `reset`. Returns a pointer that stays valid that long, unless it is null.
+    pub fn protect<T>(&mut self, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load_relaxed();
+        loop {
+            self.slot.protected.store(ptr.cast(), Ordering::SeqCst);
+            // The store must be visible to threads scanning before we check
+            // `src` again, a store followed by a load needs SeqCst.
+            atomic::fence(Ordering::SeqCst);
+            // Acquire pairs with the Release that published the value. If `src`
+            // still holds it, it wasn't retired before the scan can see our slot.
+            let current = src.load_acquire();
+            if current == ptr {
+                return ptr;
+            }
+            ptr = current;
+        }
+    }
+
+    /// Stops protecting the last pointer.
+    pub fn reset(&mut self) {
+        // Release: our reads through the pointer happen before whoever frees
+        // it sees the slot cleared.
+        self.slot.protected.store_release(ptr::null_mut());
+    }
+}
+
+impl Default for HazardPointer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for HazardPointer {
+    fn drop(&mut self) {
+        self.reset();
+        self.slot.release();
+    }
+}
+
+impl Debug for HazardPointer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HazardPointer")
+            .field("protected", &self.slot.protected.load_relaxed())
+            .finish()
+    }
+}
+
+fn retired() -> crate::sync::MutexGuard<'static, Vec<Retired>> {
+    ignore_poison(RETIRED.lock())
+}
+
+/// Drops the boxed value behind `ptr` once no hazard pointer protects it.
+///
+/// # Safety
+///
+/// `ptr` comes from `Box::into_raw`, is already unreachable for loads that
+/// start from now on, and isn't freed otherwise.
+pub unsafe fn retire<T: Send + 'static>(ptr: *mut T) {
+    unsafe fn drop_box<T>(ptr: *mut u8) {
+        drop(Box::from_raw(ptr.cast::<T>()));
+    }
+
+    let pending = {
+        let mut retired = retired();
+        retired.push(Retired {
+            ptr: SendPtr(ptr.cast()),
+            drop: drop_box::<T>,
+        });
+        retired.len()
+    };
+    if pending >= RETIRED_BEFORE_RECLAIM {
+        reclaim();
+    }
+}
+
+/// Frees the retired values that no hazard pointer protects. `retire` does this
+/// every so often, tests call it directly.
+pub fn reclaim() {
+    let candidates = mem::take(&mut *retired());
+    // Pairs with the fence in protect: either we see a reader's slot, or it
+    // sees the value unlinked and protects something else.
+    atomic::fence(Ordering::SeqCst);
+    let mut protected: Vec<usize> = SLOTS
+        .iter()
+        .map(|slot| slot.protected.load_acquire().addr())
+        .filter(|&addr| addr != 0)
+        .collect();
+    protected.sort_unstable();
+    let (ready, kept): (Vec<_>, Vec<_>) = candidates
+        .into_iter()
+        .partition(|retired| protected.binary_search(&retired.ptr.0.addr()).is_err());
+    retired().extend(kept);
+    for retired in ready {
+        // SAFETY: `retire` was handed the pointer of a box, nobody protects it.
+        unsafe { (retired.drop)(retired.ptr.0) };
+    }
+}
+
+/// Reclaims until `done` holds, another thread's scan may hold on to retired
+/// values briefly.
+#[cfg(test)]
+pub(crate) fn reclaim_until(done: impl Fn() -> bool) -> bool {
+    for _ in 0..10_000 {
+        if done() {
+            return true;
+        }
+        reclaim();
+        std::thread::yield_now();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reclaim, reclaim_until, retire, HazardPointer};
+    use crate::atomic::AtomicPtr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    struct Counted(Arc<AtomicUsize>);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_protected_value_survives_reclaim() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let src = AtomicPtr::new(Box::into_raw(Box::new(Counted(drops.clone()))));
+
+        let mut hazard = HazardPointer::new();
+        let ptr = hazard.protect(&src);
+        src.store_release(std::ptr::null_mut());
+        // SAFETY: Unlinked above, retired once.
+        unsafe { retire(ptr) };
+        reclaim();
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+
+        hazard.reset();
+        assert!(reclaim_until(|| drops.load(Ordering::Relaxed) == 1));
+    }
+
+    #[test]
+    fn test_protect_follows_updates() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let first = Box::into_raw(Box::new(Counted(drops.clone())));
+        let src = AtomicPtr::new(first);
+        let mut hazard = HazardPointer::new();
+
+        assert_eq!(hazard.protect(&src), first);
+        let second = Box::into_raw(Box::new(Counted(drops.clone())));
+        src.store_release(second);
+        assert_eq!(hazard.protect(&src), second);
+        // SAFETY: Unlinked above, retired once.
+        unsafe { retire(first) };
+        // The hazard pointer moved on, nothing keeps the first value.
+        assert!(reclaim_until(|| drops.load(Ordering::Relaxed) == 1));
+
+        src.store_release(std::ptr::null_mut());
+        // SAFETY: Unlinked above, retired once.
+        unsafe { retire(second) };
+        reclaim();
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        drop(hazard);
+        assert!(reclaim_until(|| drops.load(Ordering::Relaxed) == 2));
+    }
+}
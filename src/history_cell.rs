@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+
+use crate::ref_cell::RefCell;
+use crate::refs::Ref;
+
+/// A cell that keeps snapshots of its previous values so mutations can be undone.
+///
+/// Every mutation records the value it replaces, keeping at most `depth`
+/// snapshots. Undone values can be redone until the next mutation.
+pub struct HistoryCell<T: Clone> {
+    value: RefCell<T>,
+    undo: RefCell<VecDeque<T>>,
+    redo: RefCell<Vec<T>>,
+    depth: usize,
+}
+
+impl<T: Clone> HistoryCell<T> {
+    pub fn new(value: T, depth: usize) -> Self {
+        Self {
+            value: RefCell::new(value),
+            undo: RefCell::new(VecDeque::new()),
+            redo: RefCell::new(Vec::new()),
+            depth,
+        }
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    pub fn get(&self) -> T {
+        self.borrow().clone()
+    }
+
+    pub fn set(&self, value: T) {
+        self.update(|current| *current = value);
+    }
+
+    /// Records a snapshot and lets `f` mutate the value.
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let mut value = self.value.borrow_mut();
+        self.record(value.clone());
+        self.redo.borrow_mut().clear();
+        f(&mut value)
+    }
+
+    /// Goes back to the previous snapshot, returns false if there is none.
+    pub fn undo(&self) -> bool {
+        let previous = match self.undo.borrow_mut().pop_back() {
+            Some(previous) => previous,
+            None => return false,
+        };
+        let current = self.value.replace(previous);
+        self.redo.borrow_mut().push(current);
+        true
+    }
+
+    /// Reapplies the last undone change, returns false if there is none.
+    pub fn redo(&self) -> bool {
+        let next = match self.redo.borrow_mut().pop() {
+            Some(next) => next,
+            None => return false,
+        };
+        let current = self.value.replace(next);
+        self.record(current);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.borrow().is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.borrow().is_empty()
+    }
+
+    /// The recorded snapshots, oldest first, not including the current value.
+    pub fn history(&self) -> Vec<T> {
+        self.undo.borrow().iter().cloned().collect()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn record(&self, snapshot: T) {
+        if self.depth == 0 {
+            return;
+        }
+        let mut undo = self.undo.borrow_mut();
+        if undo.len() == self.depth {
+            undo.pop_front();
+        }
+        undo.push_back(snapshot);
+    }
+}
+
+impl<T: Clone + Debug> Debug for HistoryCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HistoryCell")
+            .field("value", &self.value)
+            .field("depth", &self.depth)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_redo() {
+        let cell = HistoryCell::new(String::from("a"), 10);
+        cell.set(String::from("ab"));
+        cell.update(|text| text.push('c'));
+
+        assert!(cell.undo());
+        assert_eq!(cell.get(), "ab");
+        assert!(cell.undo());
+        assert_eq!(cell.get(), "a");
+        assert!(!cell.undo());
+
+        assert!(cell.redo());
+        assert!(cell.redo());
+        assert_eq!(cell.get(), "abc");
+        assert!(!cell.redo());
+    }
+
+    #[test]
+    fn test_mutation_discards_redo() {
+        let cell = HistoryCell::new(1, 10);
+        cell.set(2);
+        cell.undo();
+        assert!(cell.can_redo());
+
+        cell.set(3);
+
+        assert!(!cell.can_redo());
+        assert_eq!(cell.history(), vec![1]);
+    }
+
+    #[test]
+    fn test_depth_limits_history() {
+        let cell = HistoryCell::new(0, 2);
+        for i in 1..=5 {
+            cell.set(i);
+        }
+
+        assert_eq!(cell.history(), vec![3, 4]);
+
+        let none = HistoryCell::new(0, 0);
+        none.set(1);
+        assert!(!none.can_undo());
+    }
+}
@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::mem::MaybeUninit;
+
+use crate::cell::Cell;
+use crate::unsafe_cell::UnsafeCell;
+
+/// A value that is filled in after its owner was constructed, e.g. a back-pointer
+/// that can only be set once the owner has reached its final place.
+///
+/// Unlike `OnceCell`, the value can be mutated through `&mut` and taken out again
+/// after which the cell can be initialized anew.
+pub struct InitCell<T> {
+    initialized: Cell<bool>,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// Returned when an `InitCell` is read before it was initialized.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Uninitialized;
+
+impl Display for Uninitialized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("InitCell used before initialization")
+    }
+}
+
+impl Error for Uninitialized {}
+
+impl<T> InitCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            initialized: Cell::new(false),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub fn is_initialized(&self) -> bool {
+        self.initialized.get()
+    }
+
+    /// Initializes the cell, hands the value back if it was already initialized.
+    pub fn set_once(&self, value: T) -> Result<(), T> {
+        if self.is_initialized() {
+            return Err(value);
+        }
+        unsafe {
+            // SAFETY: No references to the uninitialized value can exist.
+            (*self.value.get()).write(value);
+        }
+        self.initialized.set(true);
+        Ok(())
+    }
+
+    #[track_caller]
+    pub fn get(&self) -> &T {
+        self.try_get().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_get(&self) -> Result<&T, Uninitialized> {
+        if self.is_initialized() {
+            Ok(unsafe {
+                // SAFETY: The value is initialized and only mutated through `&mut self`.
+                (*self.value.get()).assume_init_ref()
+            })
+        } else {
+            Err(Uninitialized)
+        }
+    }
+
+    #[track_caller]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.try_get_mut().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_get_mut(&mut self) -> Result<&mut T, Uninitialized> {
+        if self.is_initialized() {
+            Ok(unsafe {
+                // SAFETY: The value is initialized.
+                self.value.get_mut().assume_init_mut()
+            })
+        } else {
+            Err(Uninitialized)
+        }
+    }
+
+    /// Takes the value out, leaving the cell uninitialized.
+    pub fn take(&mut self) -> Option<T> {
+        if !self.initialized.replace(false) {
+            return None;
+        }
+        Some(unsafe {
+            // SAFETY: The value was initialized and the flag cleared, so it won't be read or dropped again.
+            self.value.get_mut().assume_init_read()
+        })
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        self.take()
+    }
+}
+
+impl<T> Drop for InitCell<T> {
+    fn drop(&mut self) {
+        drop(self.take());
+    }
+}
+
+impl<T> Default for InitCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Debug> Debug for InitCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_get() {
+            Ok(value) => f.debug_tuple("InitCell").field(value).finish(),
+            Err(_) => f.write_str("InitCell(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_set_once_then_get() {
+        let cell = InitCell::new();
+        assert_eq!(cell.try_get(), Err(Uninitialized));
+
+        assert_eq!(cell.set_once(1), Ok(()));
+        assert_eq!(cell.set_once(2), Err(2));
+
+        assert!(cell.is_initialized());
+        assert_eq!(*cell.get(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "InitCell used before initialization")]
+    fn test_get_before_init_panics() {
+        InitCell::<i32>::new().get();
+    }
+
+    #[test]
+    fn test_back_pointer_setup() {
+        struct Node {
+            parent: InitCell<Rc<str>>,
+        }
+
+        let node = Node {
+            parent: InitCell::new(),
+        };
+        let parent: Rc<str> = Rc::from("root");
+        node.parent.set_once(parent.clone()).unwrap();
+
+        assert_eq!(&**node.parent.get(), "root");
+    }
+
+    #[test]
+    fn test_take_allows_reinit_and_drop_is_safe() {
+        let rc = Rc::new(());
+        let mut cell = InitCell::new();
+        cell.set_once(rc.clone()).unwrap();
+
+        assert!(cell.take().is_some());
+        assert!(!cell.is_initialized());
+        cell.set_once(rc.clone()).unwrap();
+        drop(cell);
+
+        assert_eq!(Rc::strong_count(&rc), 1);
+        drop(InitCell::<Rc<()>>::new());
+    }
+}
@@ -0,0 +1,118 @@
+use std::fmt::{self, Debug};
+use std::ops::Deref;
+
+use crate::cell::Cell;
+use crate::once_cell::OnceCell;
+
+/// A value initialized on first access.
+pub struct LazyCell<T, F = fn() -> T> {
+    cell: OnceCell<T>,
+    init: Cell<Option<F>>,
+}
+
+impl<T, F: FnOnce() -> T> LazyCell<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceCell::new(),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// Forces the evaluation and returns a reference to the value.
+    ///
+    /// Panics if a previous initialization attempt panicked.
+    pub fn force(this: &LazyCell<T, F>) -> &T {
+        this.cell.get_or_init(|| match this.init.take() {
+            Some(init) => init(),
+            None => panic!("LazyCell instance has previously been poisoned"),
+        })
+    }
+
+    /// Returns the value if it was initialized, or the initializer otherwise.
+    pub fn into_value(this: LazyCell<T, F>) -> Result<T, F> {
+        let LazyCell { cell, init } = this;
+        match cell.into_inner() {
+            Some(value) => Ok(value),
+            None => Err(init
+                .into_inner()
+                .expect("LazyCell instance has previously been poisoned")),
+        }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyCell<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        LazyCell::force(self)
+    }
+}
+
+impl<T: Default> Default for LazyCell<T> {
+    fn default() -> Self {
+        LazyCell::new(T::default)
+    }
+}
+
+impl<T: Debug, F> Debug for LazyCell<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cell.get() {
+            Some(value) => f.debug_tuple("LazyCell").field(value).finish(),
+            None => f.write_str("LazyCell(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initializes_on_first_access() {
+        let calls = Cell::new(0);
+        let lazy = LazyCell::new(|| {
+            calls.set(calls.get() + 1);
+            42
+        });
+        assert_eq!(calls.get(), 0);
+
+        assert_eq!(*lazy, 42);
+        assert_eq!(*LazyCell::force(&lazy), 42);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_into_value() {
+        let lazy = LazyCell::new(|| 42);
+        assert!(LazyCell::into_value(lazy).is_err());
+
+        let lazy = LazyCell::new(|| 42);
+        LazyCell::force(&lazy);
+        assert_eq!(LazyCell::into_value(lazy).ok(), Some(42));
+    }
+
+    #[test]
+    fn test_poisoned_after_panic() {
+        let lazy: LazyCell<i32> = LazyCell::new(|| panic!("boom"));
+
+        let first = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy));
+        let second = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy));
+
+        assert!(first.is_err());
+        let message = second.unwrap_err();
+        assert_eq!(
+            message.downcast_ref::<&str>(),
+            Some(&"LazyCell instance has previously been poisoned")
+        );
+    }
+
+    #[test]
+    fn test_fn_pointer_default_type() {
+        fn answer() -> i32 {
+            42
+        }
+        let lazy: LazyCell<i32> = LazyCell::new(answer);
+        assert_eq!(*lazy, 42);
+        assert_eq!(*LazyCell::<Vec<i32>>::default(), Vec::<i32>::new());
+    }
+}
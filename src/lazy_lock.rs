@@ -0,0 +1,127 @@
+use std::fmt::{self, Debug};
+use std::ops::Deref;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+use crate::once_lock::OnceLock;
+use crate::unsafe_cell::UnsafeCell;
+
+/// A thread-safe value initialized on first access, usable in `static` items.
+pub struct LazyLock<T, F = fn() -> T> {
+    once: OnceLock<T>,
+    init: UnsafeCell<Option<F>>,
+}
+
+// SAFETY: `init` is only touched by the single thread running the OnceLock initializer
+// (which may be any thread, hence `F: Send`), after that only `&T` is shared.
+unsafe impl<T: Sync + Send, F: Send> Sync for LazyLock<T, F> {}
+
+impl<T: RefUnwindSafe + UnwindSafe, F: UnwindSafe> RefUnwindSafe for LazyLock<T, F> {}
+
+impl<T, F: FnOnce() -> T> LazyLock<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            once: OnceLock::new(),
+            init: UnsafeCell::new(Some(init)),
+        }
+    }
+
+    /// Forces the evaluation and returns a reference to the value.
+    ///
+    /// Panics if a previous initialization attempt panicked.
+    pub fn force(this: &LazyLock<T, F>) -> &T {
+        this.once.get_or_init(|| {
+            let init = unsafe {
+                // SAFETY: OnceLock runs at most one initializer at a time.
+                (*this.init.get()).take()
+            };
+            match init {
+                Some(init) => init(),
+                None => panic!("LazyLock instance has previously been poisoned"),
+            }
+        })
+    }
+
+    /// Returns the value if it was initialized, or the initializer otherwise.
+    pub fn into_value(this: LazyLock<T, F>) -> Result<T, F> {
+        let LazyLock { once, init } = this;
+        match once.into_inner() {
+            Some(value) => Ok(value),
+            None => Err(init
+                .into_inner()
+                .expect("LazyLock instance has previously been poisoned")),
+        }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for LazyLock<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        LazyLock::force(self)
+    }
+}
+
+impl<T: Default> Default for LazyLock<T> {
+    fn default() -> Self {
+        LazyLock::new(T::default)
+    }
+}
+
+impl<T: Debug, F> Debug for LazyLock<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.once.get() {
+            Some(value) => f.debug_tuple("LazyLock").field(value).finish(),
+            None => f.write_str("LazyLock(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+    static CONFIG: LazyLock<HashMap<&str, i32>> = LazyLock::new(|| {
+        INITS.fetch_add(1, Ordering::Relaxed);
+        let mut config = HashMap::new();
+        config.insert("answer", 42);
+        config
+    });
+
+    #[test]
+    fn test_static_initialized_once_across_threads() {
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| assert_eq!(CONFIG["answer"], 42));
+            }
+        });
+
+        assert_eq!(INITS.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_into_value() {
+        let lazy = LazyLock::new(|| 42);
+        assert!(LazyLock::into_value(lazy).is_err());
+
+        let lazy = LazyLock::new(|| 42);
+        assert_eq!(*lazy, 42);
+        assert_eq!(LazyLock::into_value(lazy).ok(), Some(42));
+    }
+
+    #[test]
+    fn test_poisoned_after_panic() {
+        let lazy: LazyLock<i32> = LazyLock::new(|| panic!("boom"));
+
+        assert!(std::panic::catch_unwind(|| *lazy).is_err());
+        let message = std::panic::catch_unwind(|| *lazy).unwrap_err();
+
+        assert_eq!(
+            message.downcast_ref::<&str>(),
+            Some(&"LazyLock instance has previously been poisoned")
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Accounting of the live `Rc` and `Arc` allocations, to hunt down leaks and
+//! reference cycles in long-running programs. Only with the `leak-check` feature.
+//!
+//! An allocation counts as live until it is freed, so an `Rc` only kept alive by
+//! a cycle shows up here with the place it was created at.
+
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::panic::Location;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+struct Allocation {
+    kind: &'static str,
+    type_name: &'static str,
+    location: &'static Location<'static>,
+}
+
+/// Live allocations by address.
+static LIVE: Mutex<Option<HashMap<usize, Allocation>>> = Mutex::new(None);
+
+fn live() -> MutexGuard<'static, Option<HashMap<usize, Allocation>>> {
+    // A panic while holding the lock can't leave the map half updated.
+    LIVE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub(crate) fn register(
+    address: usize,
+    kind: &'static str,
+    type_name: &'static str,
+    location: &'static Location<'static>,
+) {
+    let allocation = Allocation {
+        kind,
+        type_name,
+        location,
+    };
+    live()
+        .get_or_insert_with(HashMap::new)
+        .insert(address, allocation);
+}
+
+pub(crate) fn unregister(address: usize) {
+    if let Some(live) = live().as_mut() {
+        live.remove(&address);
+    }
+}
+
+/// The live allocations of one type created at one place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveAllocations {
+    /// `"Rc"` or `"Arc"`.
+    pub kind: &'static str,
+    pub type_name: &'static str,
+    pub location: &'static Location<'static>,
+    pub count: usize,
+}
+
+impl Display for LiveAllocations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} x {}<{}> created at {}",
+            self.count, self.kind, self.type_name, self.location
+        )
+    }
+}
+
+/// The live allocations grouped by type and creation site, the largest groups first.
+pub fn live_allocations() -> Vec<LiveAllocations> {
+    let mut groups: HashMap<_, usize> = HashMap::new();
+    if let Some(live) = live().as_ref() {
+        for allocation in live.values() {
+            let key = (allocation.kind, allocation.type_name, allocation.location);
+            *groups.entry(key).or_default() += 1;
+        }
+    }
+    let mut groups: Vec<_> = groups
+        .into_iter()
+        .map(|((kind, type_name, location), count)| LiveAllocations {
+            kind,
+            type_name,
+            location,
+            count,
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then_with(|| a.location.to_string().cmp(&b.location.to_string()))
+    });
+    groups
+}
+
+/// Prints the live allocations to stderr.
+pub fn dump_live_allocations() {
+    let groups = live_allocations();
+    eprintln!("{} live Rc/Arc allocation sites:", groups.len());
+    for group in groups {
+        eprintln!("  {}", group);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::live_allocations;
+    use crate::arc::Arc;
+    use crate::rc::Rc;
+    use crate::ref_cell::RefCell;
+
+    struct Node {
+        next: RefCell<Option<Rc<Node>>>,
+    }
+
+    fn count_at(line: u32) -> usize {
+        live_allocations()
+            .iter()
+            .filter(|group| group.location.file() == file!() && group.location.line() == line)
+            .map(|group| group.count)
+            .sum()
+    }
+
+    #[test]
+    fn test_freed_allocations_are_forgotten() {
+        let line = line!() + 1;
+        let values = vec![Arc::new(0), Arc::new(1), Arc::new(2)];
+        let weak = Arc::downgrade(&values[0]);
+
+        assert_eq!(count_at(line), 3);
+        drop(values);
+        // The Weak keeps the allocation, though not the value.
+        assert_eq!(count_at(line), 1);
+        drop(weak);
+        assert_eq!(count_at(line), 0);
+    }
+
+    #[test]
+    fn test_cycle_shows_up() {
+        let line = line!() + 1;
+        let a = Rc::new(Node {
+            next: RefCell::new(None),
+        });
+        let b = Rc::new(Node {
+            next: RefCell::new(Some(a.clone())),
+        });
+        *a.next.borrow_mut() = Some(b.clone());
+        drop((a, b));
+
+        let group = live_allocations()
+            .into_iter()
+            .find(|group| group.location.line() == line)
+            .unwrap();
+        assert_eq!(group.count, 1);
+        assert!(group
+            .to_string()
+            .starts_with("1 x Rc<rsplay::leak_check::tests::Node> created at src/leak_check.rs:"));
+    }
+}
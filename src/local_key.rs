@@ -0,0 +1,282 @@
+//! Per-thread storage for the crate's cells.
+//!
+//! Every thread gets one registry (the only thing kept in a std `thread_local!`)
+//! that maps each `LocalKey` to this thread's value. Values are created on first
+//! access and dropped together with the registry when the thread exits.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+
+/// Declares `static` items of type `LocalKey<T>`, each thread sees its own value.
+///
+/// ```ignore
+/// local_cell! {
+///     static COUNTER: Cell<u32> = Cell::new(0);
+/// }
+///
+/// COUNTER.set(COUNTER.get() + 1);
+/// ```
+#[macro_export]
+macro_rules! local_cell {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::local_cell!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::local_cell!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])*
+        $vis static $name: $crate::local_key::LocalKey<$t> = {
+            fn init() -> $t {
+                $init
+            }
+            $crate::local_key::LocalKey::new(init)
+        };
+    };
+}
+
+/// Returned when a `LocalKey` is accessed after this thread's values were dropped,
+/// e.g. from the destructor of another thread-local value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AccessError;
+
+impl Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("cannot access a thread-local value during or after destruction")
+    }
+}
+
+impl Error for AccessError {}
+
+/// A key to a per-thread value, declared with `local_cell!`.
+pub struct LocalKey<T: 'static> {
+    init: fn() -> T,
+}
+
+impl<T: 'static> LocalKey<T> {
+    /// Used by `local_cell!`, the key must live in a `static` to have a stable identity.
+    pub const fn new(init: fn() -> T) -> Self {
+        Self { init }
+    }
+
+    /// Panics if this thread's values were already dropped.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.try_with(f)
+            .expect("cannot access a thread-local value during or after destruction")
+    }
+
+    pub fn try_with<F, R>(&'static self, f: F) -> Result<R, AccessError>
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let value = REGISTRY
+            .try_with(|registry| registry.get_or_init(self.id(), self.init))
+            .map_err(|_| AccessError)?;
+        // SAFETY: The value is boxed, so it doesn't move when the registry grows, and it's
+        // only dropped with the registry at thread exit, which can't happen while `f` runs.
+        Ok(f(unsafe { &*value }))
+    }
+
+    fn id(&'static self) -> usize {
+        self as *const Self as usize
+    }
+}
+
+impl<T: 'static> Debug for LocalKey<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+impl<T: 'static> LocalKey<Cell<T>> {
+    pub fn get(&'static self) -> T
+    where
+        T: Copy,
+    {
+        self.with(Cell::get)
+    }
+
+    pub fn set(&'static self, value: T) {
+        self.with(|cell| cell.set(value))
+    }
+
+    pub fn replace(&'static self, value: T) -> T {
+        self.with(|cell| cell.replace(value))
+    }
+
+    pub fn take(&'static self) -> T
+    where
+        T: Default,
+    {
+        self.with(Cell::take)
+    }
+}
+
+impl<T: 'static> LocalKey<RefCell<T>> {
+    pub fn with_borrow<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        self.with(|cell| f(&cell.borrow()))
+    }
+
+    pub fn with_borrow_mut<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        self.with(|cell| f(&mut cell.borrow_mut()))
+    }
+
+    pub fn set(&'static self, value: T) {
+        drop(self.replace(value))
+    }
+
+    pub fn replace(&'static self, value: T) -> T {
+        self.with(|cell| cell.replace(value))
+    }
+
+    pub fn take(&'static self) -> T
+    where
+        T: Default,
+    {
+        self.with(RefCell::take)
+    }
+}
+
+thread_local! {
+    static REGISTRY: Registry = Registry {
+        values: RefCell::new(HashMap::new()),
+    };
+}
+
+/// This thread's values, `None` marks a value whose initializer is running.
+struct Registry {
+    values: RefCell<HashMap<usize, Option<Box<dyn Any>>>>,
+}
+
+impl Registry {
+    fn get_or_init<T: 'static>(&self, id: usize, init: fn() -> T) -> *const T {
+        match self.values.borrow().get(&id) {
+            Some(Some(value)) => return Self::downcast(value),
+            Some(None) => panic!("LocalKey initialized re-entrantly"),
+            None => {}
+        }
+        self.values.borrow_mut().insert(id, None);
+
+        // Forgets the placeholder if `init` panics, so the next access tries again.
+        struct Reset<'a>(&'a Registry, usize);
+
+        impl Drop for Reset<'_> {
+            fn drop(&mut self) {
+                self.0.values.borrow_mut().remove(&self.1);
+            }
+        }
+
+        let reset = Reset(self, id);
+        let value: Box<dyn Any> = Box::new(init());
+        std::mem::forget(reset);
+        let ptr = Self::downcast(&value);
+        self.values.borrow_mut().insert(id, Some(value));
+        ptr
+    }
+
+    fn downcast<T: 'static>(value: &Box<dyn Any>) -> *const T {
+        value
+            .downcast_ref::<T>()
+            .expect("LocalKey values are keyed by their own LocalKey<T>")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    crate::local_cell! {
+        static COUNTER: Cell<u32> = Cell::new(0);
+        static NAMES: RefCell<Vec<&'static str>> = RefCell::new(Vec::new());
+    }
+
+    #[test]
+    fn test_values_are_per_thread() {
+        COUNTER.set(5);
+
+        thread::spawn(|| {
+            assert_eq!(COUNTER.get(), 0);
+            COUNTER.set(10);
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(COUNTER.replace(6), 5);
+    }
+
+    #[test]
+    fn test_ref_cell_helpers() {
+        NAMES.with_borrow_mut(|names| names.push("a"));
+        NAMES.with_borrow_mut(|names| names.push("b"));
+
+        assert_eq!(NAMES.with_borrow(|names| names.len()), 2);
+        assert_eq!(NAMES.take(), vec!["a", "b"]);
+    }
+
+    static INITS: AtomicUsize = AtomicUsize::new(0);
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Tracked;
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    crate::local_cell! {
+        static TRACKED: Tracked = {
+            INITS.fetch_add(1, Ordering::Relaxed);
+            Tracked
+        };
+    }
+
+    #[test]
+    fn test_lazy_init_and_drop_at_thread_exit() {
+        thread::spawn(|| {
+            // Never touched, so never created.
+        })
+        .join()
+        .unwrap();
+        assert_eq!(INITS.load(Ordering::Relaxed), 0);
+
+        thread::spawn(|| {
+            TRACKED.with(|_| {});
+            TRACKED.with(|_| {});
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(INITS.load(Ordering::Relaxed), 1);
+        assert_eq!(DROPS.load(Ordering::Relaxed), 1);
+    }
+
+    crate::local_cell! {
+        static REENTRANT: u32 = REENTRANT.with(|value| *value);
+    }
+
+    #[test]
+    fn test_reentrant_init_panics() {
+        let message = thread::spawn(|| REENTRANT.with(|_| {})).join().unwrap_err();
+
+        assert_eq!(
+            message.downcast_ref::<&str>(),
+            Some(&"LocalKey initialized re-entrantly")
+        );
+    }
+}
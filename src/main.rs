@@ -1,7 +1,74 @@
+#![cfg_attr(feature = "nightly", feature(coerce_unsized, set_ptr_value, unsize))]
+
+/// Fails to compile when `$t` implements `$trait`, used to keep auto traits in check.
+///
+/// Both impls of the helper trait apply when the bound holds, which makes the
+/// type inference of `_` ambiguous.
+#[cfg(test)]
+macro_rules! assert_not_impl {
+    ($t:ty: $trait:path) => {{
+        trait AmbiguousIfImpl<A> {
+            fn check() {}
+        }
+        impl<T: ?Sized> AmbiguousIfImpl<()> for T {}
+        #[allow(dead_code)]
+        struct Invalid;
+        impl<T: ?Sized + $trait> AmbiguousIfImpl<Invalid> for T {}
+        <$t as AmbiguousIfImpl<_>>::check();
+    }};
+}
+
+pub mod arc;
+pub mod arc_swap;
+pub mod atomic;
+pub mod atomic_cell;
+pub mod atomic_ref_cell;
+pub mod cc_rc;
 pub mod cell;
+pub mod cell_fields;
+pub mod cell_slice;
+pub mod counter_cell;
+pub mod double_buffer_cell;
+pub mod epoch;
+pub mod exclusive;
+pub mod flag_cell;
+pub mod gc;
+pub mod generational_cell;
+pub mod ghost_arena;
+pub mod ghost_cell;
+pub mod ghost_list;
+pub mod hazard;
+pub mod history_cell;
+pub mod init_cell;
+pub mod lazy_cell;
+pub mod lazy_lock;
+#[cfg(feature = "leak-check")]
+pub mod leak_check;
+pub mod local_key;
+pub mod memo_cell;
+pub mod observable_cell;
+pub mod once_cell;
+pub mod once_lock;
+pub mod pin_cell;
+pub mod qcell;
+pub mod race;
+pub mod rc;
+pub mod rcu_cell;
+pub mod rc_ref;
 pub mod ref_cell;
 pub mod refs;
+mod registry;
+pub mod rw_cell;
+pub mod send_cell;
+pub mod sharded_cell;
+pub mod shared;
+pub mod split_cell;
+pub mod stm;
+pub mod sync;
+pub mod take_cell;
+pub mod tcell;
 pub mod unsafe_cell;
+pub mod versioned_cell;
 
 fn main() {
     println!("Hello, world!");
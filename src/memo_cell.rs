@@ -0,0 +1,101 @@
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+
+/// Caches a value computed on demand until it's invalidated.
+///
+/// Every invalidation bumps a generation counter, so a value whose computation
+/// raced with an invalidation (e.g. the closure itself invalidated the cell)
+/// is returned but not cached.
+pub struct MemoCell<T> {
+    value: RefCell<Option<T>>,
+    generation: Cell<u64>,
+}
+
+impl<T> MemoCell<T> {
+    pub fn new() -> Self {
+        Self {
+            value: RefCell::new(None),
+            generation: Cell::new(0),
+        }
+    }
+
+    pub fn get_or_compute<F>(&self, f: F) -> T
+    where
+        T: Clone,
+        F: FnOnce() -> T,
+    {
+        if let Some(value) = &*self.value.borrow() {
+            return value.clone();
+        }
+        let generation = self.generation.get();
+        let value = f();
+        if self.generation.get() == generation {
+            *self.value.borrow_mut() = Some(value.clone());
+        }
+        value
+    }
+
+    pub fn invalidate(&self) {
+        self.generation.set(self.generation.get() + 1);
+        let stale = self.value.borrow_mut().take();
+        drop(stale);
+    }
+
+    pub fn is_cached(&self) -> bool {
+        self.value.borrow().is_some()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.get()
+    }
+}
+
+impl<T> Default for MemoCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_computes_once() {
+        let memo = MemoCell::new();
+        let calls = Cell::new(0);
+        let compute = || {
+            calls.set(calls.get() + 1);
+            42
+        };
+
+        assert_eq!(memo.get_or_compute(compute), 42);
+        assert_eq!(memo.get_or_compute(compute), 42);
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_invalidate_forces_recompute() {
+        let memo = MemoCell::new();
+        memo.get_or_compute(|| 42);
+
+        memo.invalidate();
+
+        assert!(!memo.is_cached());
+        assert_eq!(memo.generation(), 1);
+        assert_eq!(memo.get_or_compute(|| 43), 43);
+    }
+
+    #[test]
+    fn test_value_invalidated_during_compute_is_not_cached() {
+        let memo = MemoCell::new();
+
+        let value = memo.get_or_compute(|| {
+            memo.invalidate();
+            42
+        });
+
+        assert_eq!(value, 42);
+        assert!(!memo.is_cached());
+    }
+}
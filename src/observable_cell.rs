@@ -0,0 +1,165 @@
+use std::fmt::{self, Debug};
+use std::rc::Rc;
+
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+use crate::refs::Ref;
+
+type Callback<T> = Rc<dyn Fn(&T)>;
+
+/// A cell that notifies subscribers after every `set` or `update`.
+///
+/// Callbacks see the new value through a shared borrow, so they may read the
+/// cell and (un)subscribe, but mutating it from a callback panics.
+pub struct ObservableCell<T> {
+    value: RefCell<T>,
+    subscribers: RefCell<Vec<(u64, Callback<T>)>>,
+    next_id: Cell<u64>,
+}
+
+/// Keeps a callback registered, dropping it unsubscribes.
+#[must_use = "dropping the subscription unsubscribes immediately"]
+pub struct Subscription<'cell, T> {
+    cell: &'cell ObservableCell<T>,
+    id: u64,
+}
+
+impl<T> Drop for Subscription<'_, T> {
+    fn drop(&mut self) {
+        self.cell
+            .subscribers
+            .borrow_mut()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
+
+impl<T> Debug for Subscription<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<T> ObservableCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: RefCell::new(value),
+            subscribers: RefCell::new(Vec::new()),
+            next_id: Cell::new(0),
+        }
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    pub fn on_change<F>(&self, callback: F) -> Subscription<'_, T>
+    where
+        F: Fn(&T) + 'static,
+    {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.subscribers.borrow_mut().push((id, Rc::new(callback)));
+        Subscription { cell: self, id }
+    }
+
+    pub fn set(&self, value: T) {
+        self.update(|current| *current = value);
+    }
+
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        let result = f(&mut self.value.borrow_mut());
+        self.notify();
+        result
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.borrow().len()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn notify(&self) {
+        // A snapshot, so callbacks can (un)subscribe while we iterate.
+        let subscribers: Vec<Callback<T>> = self
+            .subscribers
+            .borrow()
+            .iter()
+            .map(|(_, callback)| callback.clone())
+            .collect();
+        let value = self.value.borrow();
+        for callback in subscribers {
+            callback(&value);
+        }
+    }
+}
+
+impl<T: Clone> ObservableCell<T> {
+    pub fn get(&self) -> T {
+        self.borrow().clone()
+    }
+}
+
+impl<T: Debug> Debug for ObservableCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObservableCell")
+            .field("value", &self.value)
+            .field("subscribers", &self.subscriber_count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_callbacks_fire_after_mutation() {
+        let cell = ObservableCell::new(1);
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let sink = seen.clone();
+        let _subscription = cell.on_change(move |value| sink.borrow_mut().push(*value));
+        cell.set(2);
+        cell.update(|value| *value *= 10);
+
+        assert_eq!(*seen.borrow(), vec![2, 20]);
+    }
+
+    #[test]
+    fn test_dropping_subscription_unsubscribes() {
+        let cell = ObservableCell::new(1);
+        let calls = Rc::new(Cell::new(0));
+
+        let counter = calls.clone();
+        let subscription = cell.on_change(move |_| counter.set(counter.get() + 1));
+        cell.set(2);
+        drop(subscription);
+        cell.set(3);
+
+        assert_eq!(calls.get(), 1);
+        assert_eq!(cell.subscriber_count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value already borrowed")]
+    fn test_mutating_from_callback_panics() {
+        let cell = Rc::new(ObservableCell::new(1));
+
+        let inner = Rc::downgrade(&cell);
+        let subscription = cell.on_change(move |_| {
+            if let Some(cell) = inner.upgrade() {
+                cell.set(0);
+            }
+        });
+        cell.set(2);
+        drop(subscription);
+    }
+}
@@ -0,0 +1,160 @@
+use std::convert::Infallible;
+use std::fmt::{self, Debug};
+use std::mem;
+
+use crate::unsafe_cell::UnsafeCell;
+
+/// A cell that can be written to only once, after which shared references to
+/// the value can be handed out freely.
+pub struct OnceCell<T> {
+    inner: UnsafeCell<Option<T>>,
+}
+
+impl<T> OnceCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            inner: UnsafeCell::new(None),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        unsafe {
+            // SAFETY: Once the value is set it is never mutated through `&self` again.
+            (*self.inner.get()).as_ref()
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.inner.get_mut().as_mut()
+    }
+
+    /// Initializes the cell, hands the value back if it was already initialized.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        if self.get().is_some() {
+            return Err(value);
+        }
+        unsafe {
+            // SAFETY: The cell is empty so there are no references to its contents,
+            // and OnceCell is not Sync so nobody else is writing concurrently.
+            *self.inner.get() = Some(value);
+        }
+        Ok(())
+    }
+
+    /// Panics if `f` initializes the cell re-entrantly.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> T,
+    {
+        match self.get_or_try_init(|| Ok::<T, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like `get_or_init`, but the cell stays empty if `f` fails.
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let value = f()?;
+        assert!(self.set(value).is_ok(), "OnceCell initialized re-entrantly");
+        Ok(self.get().unwrap())
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        mem::take(self.inner.get_mut())
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.inner.into_inner()
+    }
+}
+
+impl<T> Default for OnceCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T> {
+    fn from(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(Some(value)),
+        }
+    }
+}
+
+impl<T: Debug> Debug for OnceCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceCell").field(value).finish(),
+            None => f.write_str("OnceCell(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_once() {
+        let cell = OnceCell::new();
+        assert_eq!(cell.get(), None);
+
+        assert_eq!(cell.set(42), Ok(()));
+        assert_eq!(cell.set(43), Err(43));
+        assert_eq!(cell.get(), Some(&42));
+    }
+
+    #[test]
+    fn test_get_or_init() {
+        let cell = OnceCell::new();
+        let first = cell.get_or_init(|| String::from("first"));
+        let second = cell.get_or_init(|| unreachable!());
+
+        assert_eq!(first, "first");
+        assert!(std::ptr::eq(first, second));
+    }
+
+    #[test]
+    fn test_get_or_try_init_leaves_cell_empty_on_error() {
+        let cell: OnceCell<i32> = OnceCell::new();
+
+        assert_eq!(cell.get_or_try_init(|| Err("nope")), Err("nope"));
+        assert_eq!(cell.get(), None);
+        assert_eq!(cell.get_or_try_init(|| Ok::<_, ()>(42)), Ok(&42));
+    }
+
+    #[test]
+    #[should_panic(expected = "OnceCell initialized re-entrantly")]
+    fn test_reentrant_init_panics() {
+        let cell = OnceCell::new();
+        cell.get_or_init(|| {
+            cell.set(1).unwrap();
+            2
+        });
+    }
+
+    #[test]
+    fn test_take_and_into_inner() {
+        let mut cell = OnceCell::from(42);
+        *cell.get_mut().unwrap() += 1;
+
+        assert_eq!(cell.take(), Some(43));
+        assert_eq!(cell.get(), None);
+        assert_eq!(OnceCell::from(44).into_inner(), Some(44));
+    }
+
+    #[test]
+    fn test_debug() {
+        let cell = OnceCell::new();
+        assert_eq!(format!("{:?}", cell), "OnceCell(<uninit>)");
+        cell.set(42).unwrap();
+        assert_eq!(format!("{:?}", cell), "OnceCell(42)");
+    }
+}
@@ -0,0 +1,227 @@
+use std::convert::Infallible;
+use std::fmt::{self, Debug};
+use std::mem::{self, MaybeUninit};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+
+use crate::sync::Once;
+use crate::unsafe_cell::UnsafeCell;
+
+/// A thread-safe cell that can be written to only once.
+///
+/// Threads racing to initialize the cell wait for the winner. If the initializer
+/// panics or fails, the cell stays empty and another thread gets to try.
+pub struct OnceLock<T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+// SAFETY: Sharing the lock shares `&T` between threads (needs Sync) and lets any
+// thread initialize it with a value created there (needs Send).
+unsafe impl<T: Sync + Send> Sync for OnceLock<T> {}
+unsafe impl<T: Send> Send for OnceLock<T> {}
+
+// A panicking initializer leaves the lock empty, never half-initialized.
+impl<T: RefUnwindSafe + UnwindSafe> RefUnwindSafe for OnceLock<T> {}
+impl<T: UnwindSafe> UnwindSafe for OnceLock<T> {}
+
+impl<T> OnceLock<T> {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        // Completing the Once makes the write of the value visible.
+        if self.once.is_completed() {
+            Some(unsafe {
+                // SAFETY: The value was initialized and is never mutated through `&self` again.
+                (*self.value.get()).assume_init_ref()
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        if self.once.is_completed() {
+            Some(unsafe {
+                // SAFETY: The value was initialized.
+                self.value.get_mut().assume_init_mut()
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Initializes the cell, hands the value back if it was already initialized.
+    pub fn set(&self, value: T) -> Result<(), T> {
+        let mut value = Some(value);
+        self.get_or_init(|| value.take().unwrap());
+        match value {
+            None => Ok(()),
+            Some(value) => Err(value),
+        }
+    }
+
+    /// Blocks while another thread is initializing the cell. Initializing the
+    /// cell re-entrantly from `f` deadlocks.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> T,
+    {
+        match self.get_or_try_init(|| Ok::<T, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        self.initialize(f)?;
+        Ok(self.get().unwrap())
+    }
+
+    fn initialize<F, E>(&self, f: F) -> Result<(), E>
+    where
+        F: FnOnce() -> Result<T, E>,
+    {
+        let mut result = Ok(());
+        // A panicking or failing `f` leaves the cell empty for the next thread to
+        // try, so a poisoned Once is no reason to give up.
+        self.once.call_once_force(|state| match f() {
+            Ok(value) => unsafe {
+                // SAFETY: Only the thread running the Once writes the value,
+                // nobody reads it until the Once completes.
+                (*self.value.get()).write(value);
+            },
+            Err(err) => {
+                result = Err(err);
+                state.retry();
+            }
+        });
+        result
+    }
+
+    pub fn take(&mut self) -> Option<T> {
+        mem::take(self).into_inner()
+    }
+
+    pub fn into_inner(mut self) -> Option<T> {
+        if !self.once.is_completed() {
+            return None;
+        }
+        self.once = Once::new();
+        Some(unsafe {
+            // SAFETY: The value was initialized and the state reset, so Drop won't drop it again.
+            self.value.get_mut().assume_init_read()
+        })
+    }
+}
+
+impl<T> Drop for OnceLock<T> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe {
+                // SAFETY: The value was initialized.
+                self.value.get_mut().assume_init_drop();
+            }
+        }
+    }
+}
+
+impl<T> Default for OnceLock<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceLock<T> {
+    fn from(value: T) -> Self {
+        Self {
+            once: Once::completed(),
+            value: UnsafeCell::new(MaybeUninit::new(value)),
+        }
+    }
+}
+
+impl<T: Debug> Debug for OnceLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceLock").field(value).finish(),
+            None => f.write_str("OnceLock(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_set_once() {
+        let lock = OnceLock::new();
+
+        assert_eq!(lock.set(42), Ok(()));
+        assert_eq!(lock.set(43), Err(43));
+        assert_eq!(lock.get(), Some(&42));
+    }
+
+    #[test]
+    fn test_racing_threads_initialize_once() {
+        let lock = OnceLock::new();
+        let inits = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for i in 0..8 {
+                let (lock, inits) = (&lock, &inits);
+                s.spawn(move || {
+                    let value = lock.get_or_init(|| {
+                        inits.fetch_add(1, Ordering::Relaxed);
+                        vec![i; 100]
+                    });
+                    assert_eq!(value.len(), 100);
+                });
+            }
+        });
+
+        assert_eq!(inits.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_failed_init_can_be_retried() {
+        let lock = OnceLock::new();
+
+        assert_eq!(lock.get_or_try_init(|| Err("nope")), Err("nope"));
+        let panicked = std::panic::catch_unwind(|| lock.get_or_init(|| panic!("boom")));
+        assert!(panicked.is_err());
+
+        assert_eq!(lock.get_or_init(|| 42), &42);
+    }
+
+    #[test]
+    fn test_usable_in_statics() {
+        static LOCK: OnceLock<String> = OnceLock::new();
+        assert_eq!(LOCK.get_or_init(|| String::from("static")), "static");
+    }
+
+    #[test]
+    fn test_take_and_drop() {
+        let rc = std::rc::Rc::new(());
+        let mut lock = OnceLock::from(rc.clone());
+        assert!(lock.take().is_some());
+        assert_eq!(lock.get(), None);
+
+        let _ = lock.set(rc.clone());
+        drop(lock);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+}
@@ -0,0 +1,145 @@
+use std::fmt::{self, Debug};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+
+use crate::ref_cell::{BorrowError, BorrowMutError, RefCell};
+use crate::refs::{Ref, RefMut};
+
+/// A `RefCell` that keeps its contents structurally pinned: mutable access
+/// is only available through a pinned reference to the cell, as `Pin<&mut T>`.
+pub struct PinCell<T> {
+    inner: RefCell<T>,
+}
+
+impl<T> PinCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: RefCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        self.inner.try_borrow()
+    }
+
+    #[track_caller]
+    pub fn borrow_mut(self: Pin<&Self>) -> PinMut<'_, T> {
+        self.try_borrow_mut().expect("Value already borrowed")
+    }
+
+    #[track_caller]
+    pub fn try_borrow_mut(self: Pin<&Self>) -> Result<PinMut<'_, T>, BorrowMutError> {
+        let inner = self.get_ref().inner.try_borrow_mut()?;
+        Ok(PinMut { inner })
+    }
+}
+
+impl<T: Debug> Debug for PinCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinCell")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+/// A mutable borrow of a pinned `PinCell`.
+pub struct PinMut<'cell, T> {
+    inner: RefMut<'cell, T>,
+}
+
+impl<T> PinMut<'_, T> {
+    /// Gets a pinned mutable reference to the borrowed value.
+    ///
+    /// This is an associated function so that it doesn't shadow methods of `T`.
+    pub fn as_mut(orig: &mut Self) -> Pin<&mut T> {
+        unsafe {
+            // SAFETY: The PinCell was pinned when the borrow was created, and PinCell
+            // never hands out `&mut T` for !Unpin values, so the value never moves.
+            Pin::new_unchecked(&mut *orig.inner)
+        }
+    }
+}
+
+impl<T> Deref for PinMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl<T: Unpin> DerefMut for PinMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::marker::PhantomPinned;
+
+    struct Pinned {
+        value: i32,
+        _pin: PhantomPinned,
+    }
+
+    impl Pinned {
+        fn bump(self: Pin<&mut Self>) {
+            unsafe {
+                // SAFETY: We don't move out of the reference.
+                self.get_unchecked_mut().value += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn test_borrow_mut_gives_pinned_access() {
+        let cell = Box::pin(PinCell::new(Pinned {
+            value: 41,
+            _pin: PhantomPinned,
+        }));
+
+        {
+            let mut borrow = cell.as_ref().borrow_mut();
+            PinMut::as_mut(&mut borrow).bump();
+        }
+
+        assert_eq!(cell.borrow().value, 42);
+    }
+
+    #[test]
+    fn test_unpin_values_deref_mut() {
+        let cell = PinCell::new(41);
+        let cell = Pin::new(&cell);
+
+        *cell.borrow_mut() += 1;
+
+        assert_eq!(*cell.borrow(), 42);
+    }
+
+    #[test]
+    fn test_try_borrow_mut_is_err_when_readers() {
+        let cell = PinCell::new(42);
+        let cell = Pin::new(&cell);
+
+        let _borrow = cell.borrow();
+        assert!(cell.try_borrow_mut().is_err());
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let cell = PinCell::new(42);
+        assert_eq!(cell.into_inner(), 42);
+    }
+}
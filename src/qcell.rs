@@ -0,0 +1,242 @@
+//! Cells borrowed through an owner object that is checked at runtime by id.
+//!
+//! Instead of a borrow flag per cell, every `QCell` remembers the id of its
+//! `QCellOwner`. Reading needs `&owner` and writing `&mut owner`, so the borrow
+//! checker tracks all cells of an owner through the owner, and a runtime check
+//! only makes sure the right owner was passed.
+
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::unsafe_cell::UnsafeCell;
+
+static NEXT_OWNER_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Panics if `a` and `b` are the same cell, used by the owners' `rw2`/`rw3`.
+///
+/// Distinct cells can't overlap: reaching a cell nested in another's value
+/// needs a borrow of the owner, which `rw2` borrows mutably.
+#[track_caller]
+pub(crate) fn assert_distinct<A: ?Sized, B: ?Sized>(a: &A, b: &B) {
+    assert!(
+        a as *const A as *const u8 != b as *const B as *const u8,
+        "Illegal to borrow the same cell twice in rw2/rw3"
+    );
+}
+
+/// Implements `rw2` and `rw3` in an owner's impl block, for cells of type
+/// `$cell` followed by the value's type parameter. Owners that check at runtime
+/// whether a cell is theirs pass the cell method that does, it runs first.
+macro_rules! impl_rw2_rw3 {
+    ($cell:ident $(<$($param:tt),+>)? $(, $check:ident)?) => {
+        /// Borrows two cells mutably at once, panics if they are the same cell.
+        #[track_caller]
+        pub fn rw2<'a, T: ?Sized, U: ?Sized>(
+            &'a mut self,
+            c1: &'a $cell<$($($param,)+)? T>,
+            c2: &'a $cell<$($($param,)+)? U>,
+        ) -> (&'a mut T, &'a mut U) {
+            $(
+                c1.$check(self);
+                c2.$check(self);
+            )?
+            $crate::qcell::assert_distinct(c1, c2);
+            // SAFETY: The owner is borrowed exclusively, so none of its other
+            // cells is borrowed, and the cells are distinct.
+            unsafe { (&mut *c1.value.get(), &mut *c2.value.get()) }
+        }
+
+        /// Borrows three cells mutably at once, panics if any of them are the
+        /// same cell.
+        #[track_caller]
+        pub fn rw3<'a, T: ?Sized, U: ?Sized, V: ?Sized>(
+            &'a mut self,
+            c1: &'a $cell<$($($param,)+)? T>,
+            c2: &'a $cell<$($($param,)+)? U>,
+            c3: &'a $cell<$($($param,)+)? V>,
+        ) -> (&'a mut T, &'a mut U, &'a mut V) {
+            $(
+                c1.$check(self);
+                c2.$check(self);
+                c3.$check(self);
+            )?
+            $crate::qcell::assert_distinct(c1, c2);
+            $crate::qcell::assert_distinct(c1, c3);
+            $crate::qcell::assert_distinct(c2, c3);
+            // SAFETY: As in `rw2`.
+            unsafe {
+                (
+                    &mut *c1.value.get(),
+                    &mut *c2.value.get(),
+                    &mut *c3.value.get(),
+                )
+            }
+        }
+    };
+}
+pub(crate) use impl_rw2_rw3;
+
+/// The key to all `QCell`s created with it.
+pub struct QCellOwner {
+    id: u64,
+}
+
+impl QCellOwner {
+    pub fn new() -> Self {
+        Self {
+            id: NEXT_OWNER_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn cell<T>(&self, value: T) -> QCell<T> {
+        QCell::new(self, value)
+    }
+
+    #[track_caller]
+    pub fn ro<'a, T: ?Sized>(&'a self, cell: &'a QCell<T>) -> &'a T {
+        cell.check_owner(self);
+        // SAFETY: Writing needs `&mut self`, which can't exist while we're borrowed.
+        unsafe { &*cell.value.get() }
+    }
+
+    #[track_caller]
+    pub fn rw<'a, T: ?Sized>(&'a mut self, cell: &'a QCell<T>) -> &'a mut T {
+        cell.check_owner(self);
+        // SAFETY: We are borrowed exclusively, so no other cell of ours is borrowed.
+        unsafe { &mut *cell.value.get() }
+    }
+
+    impl_rw2_rw3!(QCell, check_owner);
+}
+
+impl Default for QCellOwner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for QCellOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QCellOwner").field("id", &self.id).finish()
+    }
+}
+
+/// A cell that is borrowed through its `QCellOwner`.
+pub struct QCell<T: ?Sized> {
+    owner: u64,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: Same reasoning as for GhostCell, the owner is borrowed in place of the value.
+unsafe impl<T: ?Sized + Send + Sync> Sync for QCell<T> {}
+
+impl<T> QCell<T> {
+    pub fn new(owner: &QCellOwner, value: T) -> Self {
+        Self {
+            owner: owner.id,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: ?Sized> QCell<T> {
+    #[track_caller]
+    pub fn ro<'a>(&'a self, owner: &'a QCellOwner) -> &'a T {
+        owner.ro(self)
+    }
+
+    #[track_caller]
+    pub fn rw<'a>(&'a self, owner: &'a mut QCellOwner) -> &'a mut T {
+        owner.rw(self)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    pub fn is_owned_by(&self, owner: &QCellOwner) -> bool {
+        self.owner == owner.id
+    }
+
+    #[track_caller]
+    fn check_owner(&self, owner: &QCellOwner) {
+        assert!(
+            self.is_owned_by(owner),
+            "QCell accessed with the wrong owner"
+        );
+    }
+}
+
+impl<T: ?Sized> Debug for QCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QCell")
+            .field("owner", &self.owner)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_borrow_through_owner() {
+        let mut owner = QCellOwner::new();
+        let a = owner.cell(1);
+        let b = QCell::new(&owner, vec![1]);
+
+        *owner.rw(&a) += 1;
+        b.rw(&mut owner).push(2);
+
+        assert_eq!((owner.ro(&a), b.ro(&owner)), (&2, &vec![1, 2]));
+        assert_eq!(a.into_inner(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "QCell accessed with the wrong owner")]
+    fn test_wrong_owner_panics() {
+        let owner = QCellOwner::new();
+        let other = QCellOwner::new();
+        let cell = owner.cell(1);
+
+        assert!(!cell.is_owned_by(&other));
+        other.ro(&cell);
+    }
+
+    #[test]
+    fn test_cell_is_shareable_across_threads() {
+        let mut owner = QCellOwner::new();
+        let cell = owner.cell(0);
+
+        std::thread::scope(|s| {
+            s.spawn(|| *cell.rw(&mut owner) += 1);
+        });
+
+        assert_eq!(*cell.ro(&owner), 1);
+    }
+
+    #[test]
+    fn test_rw2_rw3() {
+        let mut owner = QCellOwner::new();
+        let (a, b, c) = (owner.cell(1), owner.cell(2), owner.cell(3));
+
+        let (x, y) = owner.rw2(&a, &b);
+        std::mem::swap(x, y);
+        let (x, y, z) = owner.rw3(&a, &b, &c);
+        *z += *x + *y;
+
+        assert_eq!((*a.ro(&owner), *b.ro(&owner), *c.ro(&owner)), (2, 1, 6));
+    }
+
+    #[test]
+    #[should_panic(expected = "Illegal to borrow the same cell twice in rw2/rw3")]
+    fn test_rw2_same_cell_panics() {
+        let mut owner = QCellOwner::new();
+        let cell = owner.cell(1);
+        owner.rw2(&cell, &cell);
+    }
+}
@@ -0,0 +1,481 @@
+//! Lock-free once-cells for when blocking isn't an option.
+//!
+//! Unlike `OnceLock`, threads racing to initialize these never wait for each other.
+//! Each computes its own value and the first one to be stored wins, the others
+//! get the winner's value back and throw away their own.
+
+use std::convert::Infallible;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::num::NonZeroUsize;
+use std::ops::Deref;
+use std::ptr::{self, NonNull};
+use std::sync::atomic::Ordering;
+
+use crate::atomic::{AtomicPtr, AtomicUsize};
+
+/// A `&'a T` that can be set once.
+pub struct OnceRef<'a, T> {
+    inner: AtomicPtr<T>,
+    // Makes OnceRef Send and Sync exactly when &'a T is.
+    _marker: PhantomData<Option<&'a T>>,
+}
+
+impl<'a, T> OnceRef<'a, T> {
+    pub const fn new() -> Self {
+        Self {
+            inner: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> Option<&'a T> {
+        let ptr = self.inner.load_acquire();
+        // SAFETY: Only pointers created from a `&'a T` are ever stored.
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Sets the reference, hands it back if the cell was already set.
+    pub fn set(&self, value: &'a T) -> Result<(), &'a T> {
+        let ptr = value as *const T as *mut T;
+        self.inner
+            .compare_exchange(ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire)
+            .map(|_| ())
+            .map_err(|_| value)
+    }
+
+    /// `f` may run on several threads at once, all but one of the results are discarded.
+    pub fn get_or_init<F>(&self, f: F) -> &'a T
+    where
+        F: FnOnce() -> &'a T,
+    {
+        match self.get_or_try_init(|| Ok::<&'a T, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&'a T, E>
+    where
+        F: FnOnce() -> Result<&'a T, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let value = f()?;
+        let ptr = value as *const T as *mut T;
+        match self
+            .inner
+            .compare_exchange(ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => Ok(value),
+            // SAFETY: Somebody else won the race with a pointer from a `&'a T`.
+            Err(winner) => Ok(unsafe { &*winner }),
+        }
+    }
+}
+
+impl<T> Default for OnceRef<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Debug> Debug for OnceRef<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceRef").field(value).finish(),
+            None => f.write_str("OnceRef(<uninit>)"),
+        }
+    }
+}
+
+/// A `Box<T>` that can be set once.
+pub struct OnceBox<T> {
+    inner: AtomicPtr<T>,
+    // We own a Box<T>, so we are Send when it is.
+    _marker: PhantomData<Option<Box<T>>>,
+}
+
+// SAFETY: Sharing the cell shares `&T` between threads (needs Sync) and lets any
+// thread store a box created there (needs Send).
+unsafe impl<T: Sync + Send> Sync for OnceBox<T> {}
+
+impl<T> OnceBox<T> {
+    pub const fn new() -> Self {
+        Self {
+            inner: AtomicPtr::new(ptr::null_mut()),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get(&self) -> Option<&T> {
+        let ptr = self.inner.load_acquire();
+        // SAFETY: A stored box is never freed or mutated while we are borrowed.
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Stores the box, hands it back if the cell was already set.
+    pub fn set(&self, value: Box<T>) -> Result<(), Box<T>> {
+        let ptr = Box::into_raw(value);
+        match self
+            .inner
+            .compare_exchange(ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => Ok(()),
+            // SAFETY: The pointer came from Box::into_raw and was not stored.
+            Err(_) => Err(unsafe { Box::from_raw(ptr) }),
+        }
+    }
+
+    /// `f` may run on several threads at once, all but one of the results are dropped.
+    pub fn get_or_init<F>(&self, f: F) -> &T
+    where
+        F: FnOnce() -> Box<T>,
+    {
+        match self.get_or_try_init(|| Ok::<Box<T>, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<&T, E>
+    where
+        F: FnOnce() -> Result<Box<T>, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let ptr = Box::into_raw(f()?);
+        match self
+            .inner
+            .compare_exchange(ptr::null_mut(), ptr, Ordering::AcqRel, Ordering::Acquire)
+        {
+            // SAFETY: The box is now owned by the cell and lives as long as it.
+            Ok(_) => Ok(unsafe { &*ptr }),
+            Err(winner) => {
+                // SAFETY: Our box lost the race and was never shared, the winner's is owned by the cell.
+                unsafe {
+                    drop(Box::from_raw(ptr));
+                    Ok(&*winner)
+                }
+            }
+        }
+    }
+
+    pub fn into_inner(mut self) -> Option<Box<T>> {
+        let ptr = NonNull::new(std::mem::replace(self.inner.get_mut(), ptr::null_mut()))?;
+        // SAFETY: The pointer came from Box::into_raw and we took it out of the cell.
+        Some(unsafe { Box::from_raw(ptr.as_ptr()) })
+    }
+}
+
+impl<T> Drop for OnceBox<T> {
+    fn drop(&mut self) {
+        let ptr = *self.inner.get_mut();
+        if !ptr.is_null() {
+            // SAFETY: The pointer came from Box::into_raw and is owned by the cell.
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T> Default for OnceBox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Debug> Debug for OnceBox<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.get() {
+            Some(value) => f.debug_tuple("OnceBox").field(value).finish(),
+            None => f.write_str("OnceBox(<uninit>)"),
+        }
+    }
+}
+
+/// A `NonZeroUsize` that can be set once, without any allocation.
+#[derive(Default, Debug)]
+pub struct OnceNonZeroUsize {
+    inner: AtomicUsize,
+}
+
+impl OnceNonZeroUsize {
+    pub const fn new() -> Self {
+        Self {
+            inner: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get(&self) -> Option<NonZeroUsize> {
+        NonZeroUsize::new(self.inner.load_acquire())
+    }
+
+    /// Sets the value, hands it back if the cell was already set.
+    pub fn set(&self, value: NonZeroUsize) -> Result<(), NonZeroUsize> {
+        self.inner
+            .compare_exchange(0, value.get(), Ordering::AcqRel, Ordering::Acquire)
+            .map(|_| ())
+            .map_err(|_| value)
+    }
+
+    /// `f` may run on several threads at once, all but one of the results are discarded.
+    pub fn get_or_init<F>(&self, f: F) -> NonZeroUsize
+    where
+        F: FnOnce() -> NonZeroUsize,
+    {
+        match self.get_or_try_init(|| Ok::<NonZeroUsize, Infallible>(f())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<NonZeroUsize, E>
+    where
+        F: FnOnce() -> Result<NonZeroUsize, E>,
+    {
+        if let Some(value) = self.get() {
+            return Ok(value);
+        }
+        let value = f()?;
+        match self.set(value) {
+            Ok(()) => Ok(value),
+            Err(_) => Ok(self.get().unwrap()),
+        }
+    }
+}
+
+/// A `bool` that can be set once, without any allocation.
+#[derive(Default, Debug)]
+pub struct OnceBool {
+    inner: OnceNonZeroUsize,
+}
+
+impl OnceBool {
+    pub const fn new() -> Self {
+        Self {
+            inner: OnceNonZeroUsize::new(),
+        }
+    }
+
+    pub fn get(&self) -> Option<bool> {
+        self.inner.get().map(Self::from_usize)
+    }
+
+    /// Sets the value, hands it back if the cell was already set.
+    pub fn set(&self, value: bool) -> Result<(), bool> {
+        self.inner.set(Self::to_usize(value)).map_err(|_| value)
+    }
+
+    /// `f` may run on several threads at once, all but one of the results are discarded.
+    pub fn get_or_init<F>(&self, f: F) -> bool
+    where
+        F: FnOnce() -> bool,
+    {
+        Self::from_usize(self.inner.get_or_init(|| Self::to_usize(f())))
+    }
+
+    pub fn get_or_try_init<F, E>(&self, f: F) -> Result<bool, E>
+    where
+        F: FnOnce() -> Result<bool, E>,
+    {
+        self.inner
+            .get_or_try_init(|| f().map(Self::to_usize))
+            .map(Self::from_usize)
+    }
+
+    fn from_usize(value: NonZeroUsize) -> bool {
+        value.get() == 1
+    }
+
+    fn to_usize(value: bool) -> NonZeroUsize {
+        NonZeroUsize::new(if value { 1 } else { 2 }).unwrap()
+    }
+}
+
+/// A value initialized on first access, where racing threads don't wait for each other.
+///
+/// Every thread that finds the value missing runs `init` and tries to store its
+/// result, the first store wins. So `init` must be `Fn` and it's fine for it to run
+/// more than once. A panicking `init` leaves the value missing and the next
+/// access simply tries again, there is no poisoning.
+pub struct RacyLazy<T, F = fn() -> T> {
+    cell: OnceBox<T>,
+    init: F,
+}
+
+impl<T, F: Fn() -> T> RacyLazy<T, F> {
+    pub const fn new(init: F) -> Self {
+        Self {
+            cell: OnceBox::new(),
+            init,
+        }
+    }
+
+    /// Forces the evaluation and returns a reference to the value.
+    pub fn force(this: &RacyLazy<T, F>) -> &T {
+        this.cell.get_or_init(|| Box::new((this.init)()))
+    }
+
+    pub fn get(this: &RacyLazy<T, F>) -> Option<&T> {
+        this.cell.get()
+    }
+
+    /// Returns the value if it was initialized, or the initializer otherwise.
+    pub fn into_value(this: RacyLazy<T, F>) -> Result<T, F> {
+        let RacyLazy { cell, init } = this;
+        match cell.into_inner() {
+            Some(value) => Ok(*value),
+            None => Err(init),
+        }
+    }
+}
+
+impl<T, F: Fn() -> T> Deref for RacyLazy<T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        RacyLazy::force(self)
+    }
+}
+
+impl<T: Default> Default for RacyLazy<T> {
+    fn default() -> Self {
+        RacyLazy::new(T::default)
+    }
+}
+
+impl<T: Debug, F> Debug for RacyLazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cell.get() {
+            Some(value) => f.debug_tuple("RacyLazy").field(value).finish(),
+            None => f.write_str("RacyLazy(<uninit>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn test_once_ref_set_once() {
+        let (a, b) = (1, 2);
+        let cell = OnceRef::new();
+
+        assert_eq!(cell.set(&a), Ok(()));
+        assert_eq!(cell.set(&b), Err(&2));
+        assert_eq!(cell.get_or_init(|| &b), &1);
+    }
+
+    #[test]
+    fn test_once_ref_outlives_borrow_of_cell() {
+        let value = String::from("interned");
+        let found = {
+            let cell = OnceRef::new();
+            cell.get_or_init(|| &value)
+        };
+        assert_eq!(found, "interned");
+    }
+
+    #[test]
+    fn test_once_box_set_once() {
+        let cell = OnceBox::new();
+
+        assert_eq!(cell.set(Box::new(1)), Ok(()));
+        assert_eq!(cell.set(Box::new(2)), Err(Box::new(2)));
+        assert_eq!(cell.get(), Some(&1));
+        assert_eq!(cell.into_inner(), Some(Box::new(1)));
+    }
+
+    #[test]
+    fn test_once_box_racing_threads_agree() {
+        let cell = OnceBox::new();
+        let inits = AtomicUsize::new(0);
+
+        let seen: Vec<usize> = thread::scope(|s| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let (cell, inits) = (&cell, &inits);
+                    s.spawn(move || {
+                        *cell.get_or_init(|| {
+                            inits.fetch_add(1, Ordering::Relaxed);
+                            Box::new(i)
+                        })
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert!(inits.load(Ordering::Relaxed) >= 1);
+        assert!(seen.iter().all(|&v| v == seen[0]));
+    }
+
+    #[test]
+    fn test_once_box_drops_value() {
+        let rc = std::rc::Rc::new(());
+        let cell = OnceBox::new();
+        cell.get_or_init(|| Box::new(rc.clone()));
+        // The loser of a race is dropped right away.
+        let _ = cell.get_or_try_init(|| Ok::<_, ()>(Box::new(rc.clone())));
+        assert_eq!(std::rc::Rc::strong_count(&rc), 2);
+
+        drop(cell);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+
+    #[test]
+    fn test_once_bool() {
+        let cell = OnceBool::new();
+
+        assert_eq!(cell.get(), None);
+        assert!(!cell.get_or_init(|| false));
+        assert_eq!(cell.set(true), Err(true));
+        assert_eq!(cell.get(), Some(false));
+    }
+
+    #[test]
+    fn test_once_non_zero_usize() {
+        let cell = OnceNonZeroUsize::new();
+        let one = NonZeroUsize::new(1).unwrap();
+
+        assert_eq!(cell.get_or_try_init(|| Err("nope")), Err("nope"));
+        assert_eq!(cell.get_or_init(|| one), one);
+        assert_eq!(
+            cell.set(NonZeroUsize::new(2).unwrap()).map_err(|v| v.get()),
+            Err(2)
+        );
+    }
+
+    static RACY: RacyLazy<Vec<u32>> = RacyLazy::new(|| (0..10).collect());
+
+    #[test]
+    fn test_racy_lazy_static() {
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| assert_eq!(RACY.len(), 10));
+            }
+        });
+        assert!(RacyLazy::get(&RACY).is_some());
+    }
+
+    #[test]
+    fn test_racy_lazy_retries_after_panic() {
+        let attempts = AtomicUsize::new(0);
+        let lazy = RacyLazy::new(|| {
+            if attempts.fetch_add(1, Ordering::Relaxed) == 0 {
+                panic!("boom");
+            }
+            42
+        });
+
+        assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| *lazy)).is_err());
+
+        assert_eq!(*lazy, 42);
+        assert_eq!(RacyLazy::into_value(lazy).ok(), Some(42));
+    }
+}
@@ -0,0 +1,1176 @@
+use std::alloc::{self, Layout};
+use std::borrow::Borrow;
+use std::fmt::{self, Debug, Display};
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem::{self, ManuallyDrop};
+use std::ops::{Deref, DerefMut};
+use std::pin::Pin;
+use std::ptr::{self, NonNull};
+
+use crate::cell::Cell;
+
+/// The heap allocation shared by all clones of an `Rc` and its `Weak`s.
+///
+/// The value is dropped when the last `Rc` goes away, the allocation itself once
+/// there are no `Weak`s left either. All `Rc`s together hold one weak reference,
+/// so `weak` only reaches zero when both are gone.
+///
+/// `repr(C)` pins the counts in front of the value, so the layout for an unsized
+/// value can be computed at runtime, see `Rc::from_box` and `Rc::from_raw`.
+#[repr(C)]
+struct RcBox<T: ?Sized> {
+    strong: Cell<usize>,
+    weak: Cell<usize>,
+    value: ManuallyDrop<T>,
+}
+
+impl<T: ?Sized> RcBox<T> {
+    fn inc_strong(&self) {
+        increment(&self.strong);
+    }
+
+    fn dec_strong(&self) {
+        self.strong.set(self.strong.get() - 1);
+    }
+
+    fn inc_weak(&self) {
+        increment(&self.weak);
+    }
+}
+
+fn increment(count: &Cell<usize>) {
+    // Leaking clones in a loop could overflow the count and lead to a use after free.
+    count.set(count.get().checked_add(1).expect("Rc count overflowed"));
+}
+
+/// Points a possibly fat pointer at `address`, keeping its metadata (slice
+/// length or vtable) but taking the provenance of `address`.
+#[cfg(feature = "nightly")]
+unsafe fn with_address<T: ?Sized>(ptr: *mut T, address: *mut u8) -> *mut T {
+    address.with_metadata_of(ptr)
+}
+
+/// Stable Rust can only keep the provenance of `ptr` (`ptr.with_addr`), which
+/// would still point into the Box the value came from. So the address is written
+/// over the first word of the pointer, where every pointer keeps it.
+#[cfg(not(feature = "nightly"))]
+unsafe fn with_address<T: ?Sized>(mut ptr: *mut T, address: *mut u8) -> *mut T {
+    ptr::write(&mut ptr as *mut *mut T as *mut *mut u8, address);
+    ptr
+}
+
+/// Records a new allocation with the `leak-check` feature.
+#[cfg(feature = "leak-check")]
+#[track_caller]
+fn register<T: ?Sized>(inner: *mut RcBox<T>) {
+    crate::leak_check::register(
+        inner as *mut u8 as usize,
+        "Rc",
+        std::any::type_name::<T>(),
+        std::panic::Location::caller(),
+    );
+}
+
+/// The layout of an `RcBox` around a value with the given layout.
+fn rc_box_layout(value_layout: Layout) -> Layout {
+    Layout::new::<RcBox<()>>()
+        .extend(value_layout)
+        .expect("Rc allocation too large")
+        .0
+        .pad_to_align()
+}
+
+/// The offset of a value with the given alignment in its RcBox.
+fn value_offset(align: usize) -> usize {
+    let value = Layout::from_size_align(0, align).unwrap();
+    Layout::new::<RcBox<()>>().extend(value).unwrap().1
+}
+
+/// A single-threaded reference-counted pointer, the companion of `RefCell` for
+/// shared mutable state: `Rc<RefCell<T>>`.
+///
+/// `T` can be unsized, an `Rc<str>`, `Rc<[T]>` or `Rc<dyn Trait>` is created from
+/// the corresponding `Box` or, with the `nightly` feature, by unsizing coercion.
+pub struct Rc<T: ?Sized> {
+    ptr: NonNull<RcBox<T>>,
+    // Tells dropck that we own an RcBox<T>.
+    _marker: PhantomData<RcBox<T>>,
+}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<Rc<U>> for Rc<T> {}
+
+impl<T> Rc<T> {
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        let boxed = Box::new(RcBox {
+            strong: Cell::new(1),
+            weak: Cell::new(1),
+            value: ManuallyDrop::new(value),
+        });
+        let ptr = NonNull::from(Box::leak(boxed));
+        #[cfg(feature = "leak-check")]
+        register(ptr.as_ptr());
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    /// A pinned `Rc`, for values that must not move once they are shared.
+    ///
+    /// The value is never moved by an `Rc`, and a `Pin<Rc<T>>` doesn't give access
+    /// to `get_mut`, `make_mut` or `try_unwrap` which could move it.
+    #[track_caller]
+    pub fn pin(value: T) -> Pin<Self> {
+        // SAFETY: See above, the value stays in place until it is dropped.
+        unsafe { Pin::new_unchecked(Rc::new(value)) }
+    }
+
+    /// Moves the value out if this is the only `Rc`, outstanding `Weak`s don't matter.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        if this.inner().strong.get() != 1 {
+            return Err(this);
+        }
+        this.inner().dec_strong();
+        let this = ManuallyDrop::new(this);
+        let value = unsafe {
+            // SAFETY: We were the last Rc, with the count at zero Weaks can't
+            // upgrade and nobody will drop the value again.
+            ManuallyDrop::take(&mut (*this.ptr.as_ptr()).value)
+        };
+        // The weak reference held by all the Rcs together.
+        drop(Weak { ptr: this.ptr });
+        Ok(value)
+    }
+
+    /// Returns the value if this is the only `Rc`, otherwise just drops this one.
+    pub fn into_inner(this: Self) -> Option<T> {
+        Rc::try_unwrap(this).ok()
+    }
+
+    /// Clone-on-write: clones the value into a fresh `Rc` unless this is the only
+    /// one. Outstanding `Weak`s are left with the old allocation and won't upgrade.
+    #[track_caller]
+    pub fn make_mut(this: &mut Self) -> &mut T
+    where
+        T: Clone,
+    {
+        if this.inner().strong.get() != 1 {
+            *this = Rc::new((**this).clone());
+        } else if this.inner().weak.get() != 1 {
+            // Moves the value out, which leaves the Weaks behind on an empty allocation.
+            unsafe {
+                // SAFETY: `this` is overwritten without being dropped, and nothing in
+                // between can panic: we are the only Rc, so try_unwrap succeeds.
+                let value = Rc::try_unwrap(ptr::read(this)).ok().unwrap();
+                ptr::write(this, Rc::new(value));
+            }
+        }
+        Rc::get_mut(this).unwrap()
+    }
+}
+
+impl<T: ?Sized> Rc<T> {
+    /// Moves a boxed, possibly unsized, value into a new `Rc`.
+    #[track_caller]
+    fn from_box(boxed: Box<T>) -> Self {
+        let value = Box::into_raw(boxed);
+        // SAFETY: `value` comes from a live Box.
+        let value_layout = Layout::for_value(unsafe { &*value });
+        let layout = rc_box_layout(value_layout);
+        unsafe {
+            // SAFETY: The layout is never zero-sized, it contains the counts.
+            let memory = alloc::alloc(layout);
+            if memory.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            // SAFETY: `memory` fits an RcBox<T> with the metadata of `value`, whose
+            // bytes are moved over before its allocation is freed without dropping.
+            let inner = with_address(value as *mut RcBox<T>, memory);
+            ptr::addr_of_mut!((*inner).strong).write(Cell::new(1));
+            ptr::addr_of_mut!((*inner).weak).write(Cell::new(1));
+            ptr::copy_nonoverlapping(
+                value as *const u8,
+                ptr::addr_of_mut!((*inner).value) as *mut u8,
+                value_layout.size(),
+            );
+            if value_layout.size() != 0 {
+                alloc::dealloc(value as *mut u8, value_layout);
+            }
+            #[cfg(feature = "leak-check")]
+            register(inner);
+            Self {
+                ptr: NonNull::new_unchecked(inner),
+                _marker: PhantomData,
+            }
+        }
+    }
+
+    /// A mutable reference to the value if there are no other `Rc`s or `Weak`s.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        if Rc::is_unique(this) {
+            // SAFETY: Nobody else can reach the value while `this` is borrowed mutably.
+            Some(unsafe { &mut (*this.ptr.as_ptr()).value })
+        } else {
+            None
+        }
+    }
+
+    /// A pointer to the value, valid as long as some `Rc` keeps it alive.
+    pub fn as_ptr(this: &Self) -> *const T {
+        // Not through Deref, the pointer keeps the provenance of the whole allocation.
+        unsafe { ptr::addr_of_mut!((*this.ptr.as_ptr()).value) as *const T }
+    }
+
+    /// Turns the `Rc` into a pointer to its value, for example to pass it
+    /// through a `void*` user-data argument. The reference is leaked until it comes
+    /// back through `Rc::from_raw`.
+    pub fn into_raw(this: Self) -> *const T {
+        let this = ManuallyDrop::new(this);
+        Rc::as_ptr(&this)
+    }
+
+    /// Takes back a reference given out by `Rc::into_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Rc::into_raw` of an `Rc<U>` where `U` has the same
+    /// layout as `T`, and each such pointer may only be taken back once.
+    pub unsafe fn from_raw(ptr: *const T) -> Self {
+        let offset = value_offset(mem::align_of_val(&*ptr));
+        // Moves the address back to the start of the RcBox, keeping the metadata.
+        let inner = (ptr as *const RcBox<T>).byte_sub(offset) as *mut RcBox<T>;
+        Self {
+            ptr: NonNull::new_unchecked(inner),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Adds a reference to the value behind a pointer from `Rc::into_raw`,
+    /// to be released with `Rc::decrement_strong_count` or `Rc::from_raw`.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Rc::into_raw` and its value still be alive.
+    pub unsafe fn increment_strong_count(ptr: *const T) {
+        let this = ManuallyDrop::new(Rc::from_raw(ptr));
+        mem::forget(Rc::clone(&this));
+    }
+
+    /// Releases a reference to the value behind a pointer from `Rc::into_raw`,
+    /// dropping the value if it was the last one.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must come from `Rc::into_raw` and the reference it stands for
+    /// not have been released yet.
+    pub unsafe fn decrement_strong_count(ptr: *const T) {
+        drop(Rc::from_raw(ptr));
+    }
+
+    /// Whether both point to the same allocation, rather than to equal values.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        // Only the addresses, vtables of the same type may differ between codegen units.
+        ptr::addr_eq(this.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    pub fn strong_count(this: &Self) -> usize {
+        this.inner().strong.get()
+    }
+
+    pub fn weak_count(this: &Self) -> usize {
+        this.inner().weak.get() - 1
+    }
+
+    fn is_unique(this: &Self) -> bool {
+        // A weak count of one is the weak reference held by the Rcs.
+        this.inner().strong.get() == 1 && this.inner().weak.get() == 1
+    }
+
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        this.inner().inc_weak();
+        Weak { ptr: this.ptr }
+    }
+
+    fn inner(&self) -> &RcBox<T> {
+        // SAFETY: The box is alive as long as there is an Rc pointing to it.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> Rc<[T]> {
+    /// Allocates an `RcBox<[T]>` with the counts set and `len` uninitialized elements.
+    #[track_caller]
+    fn allocate_for_slice(len: usize) -> PartialSlice<T> {
+        let layout = rc_box_layout(Layout::array::<T>(len).expect("Rc allocation too large"));
+        unsafe {
+            // SAFETY: The layout is never zero-sized, it contains the counts.
+            let memory = alloc::alloc(layout);
+            if memory.is_null() {
+                alloc::handle_alloc_error(layout);
+            }
+            // The slice metadata carries over to the RcBox, so this has length `len`.
+            let inner = ptr::slice_from_raw_parts_mut(memory as *mut T, len) as *mut RcBox<[T]>;
+            ptr::addr_of_mut!((*inner).strong).write(Cell::new(1));
+            ptr::addr_of_mut!((*inner).weak).write(Cell::new(1));
+            #[cfg(feature = "leak-check")]
+            register(inner);
+            PartialSlice {
+                inner,
+                layout,
+                written: 0,
+            }
+        }
+    }
+
+    /// Collects `iter` into a single allocation if it yields exactly `len`
+    /// elements. If its `size_hint` was wrong, the elements go through a `Vec`.
+    #[track_caller]
+    fn from_exact_iter<I: Iterator<Item = T>>(mut iter: I, len: usize) -> Self {
+        let mut slice = Rc::allocate_for_slice(len);
+        while slice.written < len {
+            match iter.next() {
+                Some(element) => slice.push(element),
+                None => return Rc::from(slice.into_vec()),
+            }
+        }
+        match iter.next() {
+            None => slice.finish(),
+            Some(element) => {
+                let mut elements = slice.into_vec();
+                elements.push(element);
+                elements.extend(iter);
+                Rc::from(elements)
+            }
+        }
+    }
+}
+
+/// An `Rc<[T]>` under construction, dropping it cleans up after a panicking
+/// `clone()` or iterator.
+struct PartialSlice<T> {
+    inner: *mut RcBox<[T]>,
+    layout: Layout,
+    written: usize,
+}
+
+impl<T> PartialSlice<T> {
+    fn elements(&self) -> *mut T {
+        // SAFETY: `inner` is allocated, only the address of the value is taken.
+        unsafe { ptr::addr_of_mut!((*self.inner).value) as *mut T }
+    }
+
+    fn push(&mut self, element: T) {
+        // SAFETY: The callers never push more than the allocated length.
+        unsafe { ptr::write(self.elements().add(self.written), element) };
+        self.written += 1;
+    }
+
+    /// Moves the elements written so far into a `Vec` and frees the allocation.
+    fn into_vec(mut self) -> Vec<T> {
+        let mut elements = Vec::with_capacity(self.written);
+        unsafe {
+            // SAFETY: The first `written` elements are initialized, they are moved
+            // out and the allocation is freed without dropping them.
+            ptr::copy_nonoverlapping(self.elements(), elements.as_mut_ptr(), self.written);
+            elements.set_len(self.written);
+        }
+        self.written = 0;
+        elements
+    }
+
+    fn finish(self) -> Rc<[T]> {
+        let this = ManuallyDrop::new(self);
+        Rc {
+            // SAFETY: Allocated by allocate_for_slice, all elements are written.
+            ptr: unsafe { NonNull::new_unchecked(this.inner) },
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Drop for PartialSlice<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // SAFETY: Exactly the first `written` elements are initialized.
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.elements(), self.written));
+            #[cfg(feature = "leak-check")]
+            crate::leak_check::unregister(self.inner as *mut u8 as usize);
+            alloc::dealloc(self.inner as *mut u8, self.layout);
+        }
+    }
+}
+
+// Moving an Rc never moves its value.
+impl<T: ?Sized> Unpin for Rc<T> {}
+
+impl<T: ?Sized> Clone for Rc<T> {
+    fn clone(&self) -> Self {
+        self.inner().inc_strong();
+        Self {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> Drop for Rc<T> {
+    fn drop(&mut self) {
+        self.inner().dec_strong();
+        if self.inner().strong.get() != 0 {
+            return;
+        }
+        unsafe {
+            // SAFETY: We were the last Rc, nobody can reach the value anymore.
+            ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).value);
+        }
+        // The weak reference held by all the Rcs together.
+        drop(Weak { ptr: self.ptr });
+    }
+}
+
+impl<T: ?Sized> Deref for Rc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T: ?Sized> AsRef<T> for Rc<T> {
+    fn as_ref(&self) -> &T {
+        self
+    }
+}
+
+impl<T: ?Sized> Borrow<T> for Rc<T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+impl<T: Default> Default for Rc<T> {
+    #[track_caller]
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Rc<T> {
+    #[track_caller]
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: ?Sized> From<Box<T>> for Rc<T> {
+    #[track_caller]
+    fn from(boxed: Box<T>) -> Self {
+        Self::from_box(boxed)
+    }
+}
+
+impl<T: Clone> From<&[T]> for Rc<[T]> {
+    #[track_caller]
+    fn from(value: &[T]) -> Self {
+        Rc::from_exact_iter(value.iter().cloned(), value.len())
+    }
+}
+
+impl<T> From<Vec<T>> for Rc<[T]> {
+    #[track_caller]
+    fn from(mut value: Vec<T>) -> Self {
+        let mut slice = Rc::allocate_for_slice(value.len());
+        unsafe {
+            // SAFETY: The elements are moved, the Vec forgets them and frees only
+            // its buffer.
+            ptr::copy_nonoverlapping(value.as_ptr(), slice.elements(), value.len());
+            slice.written = value.len();
+            value.set_len(0);
+        }
+        slice.finish()
+    }
+}
+
+impl<T> FromIterator<T> for Rc<[T]> {
+    /// Writes straight into the `Rc` when the iterator knows its exact length,
+    /// otherwise collects into a `Vec` first.
+    #[track_caller]
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => Rc::from_exact_iter(iter, lower),
+            _ => Rc::from(iter.collect::<Vec<T>>()),
+        }
+    }
+}
+
+impl From<&str> for Rc<str> {
+    #[track_caller]
+    fn from(value: &str) -> Self {
+        let bytes = ManuallyDrop::new(Rc::<[u8]>::from(value.as_bytes()));
+        // SAFETY: str has the layout of [u8] and the bytes are valid UTF-8.
+        let ptr = unsafe { NonNull::new_unchecked(bytes.ptr.as_ptr() as *mut RcBox<str>) };
+        // Replaces the record of the [u8] allocation.
+        #[cfg(feature = "leak-check")]
+        register(ptr.as_ptr());
+        Rc {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl From<String> for Rc<str> {
+    #[track_caller]
+    fn from(value: String) -> Self {
+        Rc::from(value.as_str())
+    }
+}
+
+impl<T: ?Sized + PartialEq> PartialEq for Rc<T> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized + Eq> Eq for Rc<T> {}
+
+impl<T: ?Sized + Debug> Debug for Rc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized + Display> Display for Rc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+/// A non-owning reference to the value of an `Rc`, which doesn't keep the value
+/// alive and so can be used to break reference cycles.
+pub struct Weak<T: ?Sized> {
+    // Points to usize::MAX for Weak::new(), which has no allocation. No RcBox can
+    // live there, it is at least two words large.
+    ptr: NonNull<RcBox<T>>,
+}
+
+#[cfg(feature = "nightly")]
+impl<T: ?Sized + std::marker::Unsize<U>, U: ?Sized> std::ops::CoerceUnsized<Weak<U>> for Weak<T> {}
+
+impl<T> Weak<T> {
+    /// A Weak that never upgrades, without allocating anything.
+    pub fn new() -> Self {
+        Self {
+            ptr: NonNull::new(ptr::without_provenance_mut(usize::MAX)).unwrap(),
+        }
+    }
+}
+
+impl<T: ?Sized> Weak<T> {
+    /// Returns an `Rc` to the value unless it was already dropped.
+    pub fn upgrade(&self) -> Option<Rc<T>> {
+        let inner = self.inner()?;
+        if inner.strong.get() == 0 {
+            return None;
+        }
+        increment(inner.strong);
+        Some(Rc {
+            ptr: self.ptr,
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        ptr::addr_eq(self.ptr.as_ptr(), other.ptr.as_ptr())
+    }
+
+    /// The number of `Rc`s, zero once the value was dropped.
+    pub fn strong_count(&self) -> usize {
+        self.inner().map_or(0, |inner| inner.strong.get())
+    }
+
+    /// The number of `Weak`s, or zero once the value was dropped.
+    pub fn weak_count(&self) -> usize {
+        match self.inner() {
+            Some(inner) if inner.strong.get() > 0 => inner.weak.get() - 1,
+            _ => 0,
+        }
+    }
+
+    /// The counts, never a reference to the whole `RcBox`: a Weak may be used
+    /// while the value is being dropped, for example by the value itself.
+    fn inner(&self) -> Option<WeakInner<'_>> {
+        if (self.ptr.as_ptr() as *mut u8).addr() == usize::MAX {
+            return None;
+        }
+        let inner = self.ptr.as_ptr();
+        // SAFETY: The allocation lives as long as there are Weaks pointing to it,
+        // only the fields of the counts are borrowed.
+        unsafe {
+            Some(WeakInner {
+                strong: &*ptr::addr_of!((*inner).strong),
+                weak: &*ptr::addr_of!((*inner).weak),
+            })
+        }
+    }
+}
+
+/// The counts of the allocation behind a `Weak`.
+struct WeakInner<'a> {
+    strong: &'a Cell<usize>,
+    weak: &'a Cell<usize>,
+}
+
+impl<T: ?Sized> Clone for Weak<T> {
+    fn clone(&self) -> Self {
+        if let Some(inner) = self.inner() {
+            increment(inner.weak);
+        }
+        Self { ptr: self.ptr }
+    }
+}
+
+impl<T: ?Sized> Drop for Weak<T> {
+    fn drop(&mut self) {
+        let inner = match self.inner() {
+            Some(inner) => inner,
+            None => return,
+        };
+        inner.weak.set(inner.weak.get() - 1);
+        if inner.weak.get() == 0 {
+            // SAFETY: No Rc or Weak is left and the value was already dropped, so
+            // nothing else borrows the box. The layout is the one it was allocated
+            // with, by Box or by Rc::from_box.
+            unsafe {
+                let layout = Layout::for_value(self.ptr.as_ref());
+                #[cfg(feature = "leak-check")]
+                crate::leak_check::unregister(self.ptr.as_ptr() as *mut u8 as usize);
+                alloc::dealloc(self.ptr.as_ptr() as *mut u8, layout);
+            }
+        }
+    }
+}
+
+impl<T> Default for Weak<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ?Sized> Debug for Weak<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(Weak)")
+    }
+}
+
+/// An `Rc` under construction: mutable, and already handing out `Weak`s which
+/// start upgrading once it becomes an `Rc` with `into_rc`. Cyclic structures can
+/// be set up in several steps this way.
+///
+/// It keeps the strong count at zero, so the `Weak`s can't upgrade meanwhile.
+pub struct UniqueRc<T: ?Sized> {
+    ptr: NonNull<RcBox<T>>,
+    // Tells dropck that we own an RcBox<T>.
+    _marker: PhantomData<RcBox<T>>,
+}
+
+impl<T> UniqueRc<T> {
+    #[track_caller]
+    pub fn new(value: T) -> Self {
+        let boxed = Box::new(RcBox {
+            strong: Cell::new(0),
+            // The weak reference that the Rcs hold together later.
+            weak: Cell::new(1),
+            value: ManuallyDrop::new(value),
+        });
+        let ptr = NonNull::from(Box::leak(boxed));
+        #[cfg(feature = "leak-check")]
+        register(ptr.as_ptr());
+        Self {
+            ptr,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T: ?Sized> UniqueRc<T> {
+    /// A Weak that upgrades once this became an `Rc`.
+    pub fn downgrade(this: &Self) -> Weak<T> {
+        this.inner().inc_weak();
+        Weak { ptr: this.ptr }
+    }
+
+    pub fn into_rc(this: Self) -> Rc<T> {
+        let this = ManuallyDrop::new(this);
+        this.inner().strong.set(1);
+        Rc {
+            ptr: this.ptr,
+            _marker: PhantomData,
+        }
+    }
+
+    fn inner(&self) -> &RcBox<T> {
+        // SAFETY: The box is alive as long as the UniqueRc.
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T: ?Sized> Drop for UniqueRc<T> {
+    fn drop(&mut self) {
+        unsafe {
+            // SAFETY: With the strong count at zero nobody else reaches the value.
+            ManuallyDrop::drop(&mut (*self.ptr.as_ptr()).value);
+        }
+        // The weak reference the Rcs would have held.
+        drop(Weak { ptr: self.ptr });
+    }
+}
+
+impl<T: ?Sized> Deref for UniqueRc<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.inner().value
+    }
+}
+
+impl<T: ?Sized> DerefMut for UniqueRc<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: There are no Rcs and the Weaks can't upgrade, `self` is borrowed
+        // mutably.
+        unsafe { &mut (*self.ptr.as_ptr()).value }
+    }
+}
+
+impl<T: ?Sized + Debug> Debug for UniqueRc<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // Not a glob import, the Borrow trait would shadow RefCell::borrow.
+    use super::{Rc, UniqueRc, Weak};
+    use crate::cell::Cell;
+    use crate::ref_cell::RefCell;
+
+    struct DropCounter<'a>(&'a Cell<usize>);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_clones_share_value() {
+        let a = Rc::new(RefCell::new(vec![1]));
+        let b = a.clone();
+
+        b.borrow_mut().push(2);
+
+        assert_eq!(*a.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_dropped_with_last_clone() {
+        let drops = Cell::new(0);
+        let a = Rc::new(DropCounter(&drops));
+        let b = a.clone();
+
+        drop(a);
+        assert_eq!(drops.get(), 0);
+        drop(b);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_formatting_and_comparison() {
+        let rc = Rc::new(5);
+        assert_eq!(format!("{} {:?}", rc, rc), "5 5");
+        assert_eq!(rc, Rc::from(5));
+        assert_not_impl!(Rc<i32>: Send);
+        assert_not_impl!(Rc<i32>: Sync);
+    }
+
+    #[test]
+    fn test_upgrade_while_alive() {
+        let rc = Rc::new(1);
+        let weak = Rc::downgrade(&rc);
+
+        assert_eq!(weak.upgrade(), Some(Rc::new(1)));
+        drop(rc);
+        assert_eq!(weak.upgrade(), None);
+        assert!(Weak::<i32>::new().upgrade().is_none());
+    }
+
+    #[test]
+    fn test_value_dropped_before_allocation() {
+        let drops = Cell::new(0);
+        let rc = Rc::new(DropCounter(&drops));
+        let weak = Rc::downgrade(&rc);
+        let weak2 = weak.clone();
+
+        drop(rc);
+        assert_eq!(drops.get(), 1);
+        drop(weak);
+        drop(weak2);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_weak_breaks_cycle() {
+        struct Node<'a> {
+            parent: RefCell<Weak<Node<'a>>>,
+            children: RefCell<Vec<Rc<Node<'a>>>>,
+            _counter: DropCounter<'a>,
+        }
+
+        let drops = Cell::new(0);
+        let new_node = || {
+            Rc::new(Node {
+                parent: RefCell::new(Weak::new()),
+                children: RefCell::new(Vec::new()),
+                _counter: DropCounter(&drops),
+            })
+        };
+        let parent = new_node();
+        let child = new_node();
+        *child.parent.borrow_mut() = Rc::downgrade(&parent);
+        parent.children.borrow_mut().push(child.clone());
+        drop(child);
+
+        let child = parent.children.borrow()[0].clone();
+        assert!(child.parent.borrow().upgrade().is_some());
+        drop(child);
+        drop(parent);
+
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let rc = Rc::new(String::from("unique"));
+        let clone = rc.clone();
+
+        let rc = Rc::try_unwrap(rc).unwrap_err();
+        drop(clone);
+        let weak = Rc::downgrade(&rc);
+
+        assert_eq!(Rc::try_unwrap(rc), Ok(String::from("unique")));
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let drops = Cell::new(0);
+        let rc = Rc::new(DropCounter(&drops));
+        let clone = rc.clone();
+
+        assert!(Rc::into_inner(rc).is_none());
+        assert_eq!(drops.get(), 0);
+        let value = Rc::into_inner(clone);
+        assert!(value.is_some());
+        assert_eq!(drops.get(), 0);
+
+        drop(value);
+        assert_eq!(drops.get(), 1);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut rc = Rc::new(1);
+        *Rc::get_mut(&mut rc).unwrap() += 1;
+
+        let clone = rc.clone();
+        assert!(Rc::get_mut(&mut rc).is_none());
+        drop(clone);
+
+        let weak = Rc::downgrade(&rc);
+        assert!(Rc::get_mut(&mut rc).is_none());
+        drop(weak);
+
+        assert_eq!(Rc::get_mut(&mut rc), Some(&mut 2));
+    }
+
+    #[test]
+    fn test_make_mut_clones_when_shared() {
+        let mut rc = Rc::new(vec![1]);
+        let other = rc.clone();
+
+        Rc::make_mut(&mut rc).push(2);
+        Rc::make_mut(&mut rc).push(3);
+
+        assert_eq!(*rc, vec![1, 2, 3]);
+        assert_eq!(*other, vec![1]);
+    }
+
+    #[test]
+    fn test_make_mut_disassociates_weaks() {
+        let mut rc = Rc::new(1);
+        let weak = Rc::downgrade(&rc);
+
+        *Rc::make_mut(&mut rc) += 1;
+
+        assert!(weak.upgrade().is_none());
+        assert_eq!(*rc, 2);
+    }
+
+    #[test]
+    fn test_counts_and_identity() {
+        let rc = Rc::new(1);
+        let clone = rc.clone();
+        let weak = Rc::downgrade(&rc);
+
+        assert!(Rc::ptr_eq(&rc, &clone));
+        assert!(!Rc::ptr_eq(&rc, &Rc::new(1)));
+        assert!(weak.ptr_eq(&Rc::downgrade(&clone)));
+        assert_eq!((Rc::strong_count(&rc), Rc::weak_count(&rc)), (2, 1));
+        assert_eq!((weak.strong_count(), weak.weak_count()), (2, 1));
+
+        drop((rc, clone));
+        assert_eq!((weak.strong_count(), weak.weak_count()), (0, 0));
+        assert_eq!(Weak::<i32>::new().strong_count(), 0);
+    }
+
+    #[test]
+    fn test_str_and_slice() {
+        let name: Rc<str> = Rc::from("rsplay");
+        let owned: Rc<str> = Rc::from(String::from("rsplay"));
+        let slice: Rc<[u16]> = Rc::from(vec![1, 2, 3].into_boxed_slice());
+
+        assert_eq!(name, owned);
+        assert_eq!(&*name, "rsplay");
+        assert_eq!(&*slice, &[1, 2, 3]);
+        assert_eq!(Rc::<[u8]>::from(Box::from([])).len(), 0);
+    }
+
+    #[test]
+    fn test_trait_object() {
+        let drops = Cell::new(0);
+        let boxed: Box<dyn Fn() -> usize + '_> = {
+            let counter = DropCounter(&drops);
+            Box::new(move || counter.0.get() + 10)
+        };
+        let rc: Rc<dyn Fn() -> usize + '_> = Rc::from(boxed);
+        let weak = Rc::downgrade(&rc);
+
+        assert_eq!(weak.upgrade().unwrap()(), 10);
+        drop(rc);
+        assert_eq!(drops.get(), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_over_aligned_unsized_value() {
+        #[repr(align(64))]
+        struct Aligned(u8);
+
+        let rc: Rc<[Aligned]> = Rc::from(vec![Aligned(1), Aligned(2)].into_boxed_slice());
+
+        assert_eq!(rc.as_ptr() as usize % 64, 0);
+        assert_eq!(rc[1].0, 2);
+    }
+
+    #[test]
+    fn test_slice_from_vec_and_slice() {
+        let drops = Cell::new(0);
+        let counters: Rc<[DropCounter]> = Rc::from(vec![DropCounter(&drops), DropCounter(&drops)]);
+        let names: Rc<[String]> = Rc::from(&["a".to_string(), "b".to_string()][..]);
+
+        assert_eq!(drops.get(), 0);
+        assert_eq!(counters.len(), 2);
+        assert_eq!(&*names, &["a", "b"]);
+        drop(counters);
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn test_slice_from_iter() {
+        let exact: Rc<[i32]> = (1..=3).collect();
+        let filtered: Rc<[i32]> = (1..=6).filter(|n| n % 2 == 0).collect();
+
+        assert_eq!(&*exact, &[1, 2, 3]);
+        assert_eq!(&*filtered, &[2, 4, 6]);
+    }
+
+    #[test]
+    fn test_slice_cleaned_up_after_panic() {
+        let drops = Cell::new(0);
+        let elements = (0..4).map(|i| {
+            if i == 2 {
+                panic!("boom");
+            }
+            DropCounter(&drops)
+        });
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            elements.collect::<Rc<[DropCounter]>>()
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(drops.get(), 2);
+    }
+
+    #[test]
+    fn test_slice_from_lying_iter() {
+        struct Liar {
+            left: usize,
+            claimed: usize,
+        }
+
+        impl Iterator for Liar {
+            type Item = usize;
+
+            fn next(&mut self) -> Option<usize> {
+                self.left = self.left.checked_sub(1)?;
+                Some(self.left)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                (self.claimed, Some(self.claimed))
+            }
+        }
+
+        let fewer: Rc<[usize]> = Liar {
+            left: 3,
+            claimed: 4,
+        }
+        .collect();
+        assert_eq!(*fewer, [2, 1, 0]);
+
+        let more: Rc<[usize]> = Liar {
+            left: 3,
+            claimed: 1,
+        }
+        .collect();
+        assert_eq!(*more, [2, 1, 0]);
+        assert_eq!(Rc::strong_count(&more), 1);
+    }
+
+    #[test]
+    fn test_raw_round_trip_through_c_callback() {
+        use std::ffi::c_void;
+
+        extern "C" fn on_event(user_data: *mut c_void) {
+            // Borrows the reference the caller handed out, without releasing it.
+            unsafe { Rc::increment_strong_count(user_data as *const Cell<usize>) };
+            let events = unsafe { Rc::from_raw(user_data as *const Cell<usize>) };
+            events.set(events.get() + 1);
+        }
+
+        let events = Rc::new(Cell::new(0usize));
+        let user_data = Rc::into_raw(events.clone()) as *mut c_void;
+
+        on_event(user_data);
+        on_event(user_data);
+        assert_eq!(Rc::strong_count(&events), 2);
+        unsafe { Rc::decrement_strong_count(user_data as *const Cell<usize>) };
+
+        assert_eq!(events.get(), 2);
+        assert_eq!(Rc::strong_count(&events), 1);
+    }
+
+    #[test]
+    fn test_raw_unsized() {
+        let name: Rc<str> = Rc::from("rsplay");
+        let ptr = Rc::as_ptr(&name);
+
+        let raw = Rc::into_raw(name);
+        assert_eq!(raw, ptr);
+        let name = unsafe { Rc::from_raw(raw) };
+
+        assert_eq!(&*name, "rsplay");
+        assert_eq!(Rc::strong_count(&name), 1);
+    }
+
+    #[test]
+    fn test_pinned_value_stays_in_place() {
+        use std::marker::PhantomPinned;
+        use std::pin::Pin;
+
+        // An intrusive node that records its own address.
+        struct Node {
+            address: Cell<usize>,
+            _pinned: PhantomPinned,
+        }
+
+        impl Node {
+            fn register(self: Pin<&Self>) {
+                self.address.set(&*self as *const Node as usize);
+            }
+        }
+
+        let node = Rc::pin(Node {
+            address: Cell::new(0),
+            _pinned: PhantomPinned,
+        });
+        node.as_ref().register();
+        let moved = Box::new(node.clone());
+
+        assert_eq!(moved.address.get(), &*node as *const Node as usize);
+        fn assert_unpin<T: Unpin>() {}
+        assert_unpin::<Rc<PhantomPinned>>();
+    }
+
+    #[test]
+    fn test_unique_rc_builds_cycle() {
+        struct Parent {
+            name: String,
+            children: Vec<Rc<Child>>,
+        }
+
+        struct Child {
+            parent: Weak<Parent>,
+        }
+
+        let mut parent = UniqueRc::new(Parent {
+            name: String::from("parent"),
+            children: Vec::new(),
+        });
+        for _ in 0..2 {
+            let child = Rc::new(Child {
+                parent: UniqueRc::downgrade(&parent),
+            });
+            assert!(child.parent.upgrade().is_none());
+            parent.children.push(child);
+        }
+        parent.name.push('!');
+        let parent = UniqueRc::into_rc(parent);
+
+        assert_eq!(Rc::weak_count(&parent), 2);
+        for child in &parent.children {
+            assert_eq!(child.parent.upgrade().unwrap().name, "parent!");
+        }
+    }
+
+    #[test]
+    fn test_unique_rc_dropped_before_sharing() {
+        let drops = Cell::new(0);
+        let unique = UniqueRc::new(DropCounter(&drops));
+        let weak = UniqueRc::downgrade(&unique);
+
+        drop(unique);
+
+        assert_eq!(drops.get(), 1);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn test_unsizing_coercion() {
+        let rc: Rc<[i32]> = Rc::new([1, 2, 3]);
+        let debug: Rc<dyn std::fmt::Debug> = Rc::new(5);
+        let weak = Rc::downgrade(&Rc::new(1));
+        let weak: super::Weak<dyn std::fmt::Debug> = weak;
+
+        assert_eq!(&*rc, &[1, 2, 3]);
+        assert_eq!(format!("{:?}", debug), "5");
+        assert!(weak.upgrade().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::{self, Debug, Display};
+use std::ops::Deref;
+use std::ptr::NonNull;
+
+use crate::rc::Rc;
+
+/// An `Rc<T>` together with a reference into its value, a shared handle to part
+/// of a shared value:
+///
+/// ```ignore
+/// let config = Rc::new(Config { name: String::from("prod"), .. });
+/// let name: RcRef<Config, str> = RcRef::map(config, |config| config.name.as_str());
+/// ```
+///
+/// The value behind an `Rc` never moves and the `RcRef` keeps it alive, so the
+/// reference stays valid without a lifetime.
+pub struct RcRef<T: ?Sized, U: ?Sized = T> {
+    rc: Rc<T>,
+    // Borrowed from the value in `rc`.
+    projected: NonNull<U>,
+}
+
+impl<T: ?Sized> RcRef<T> {
+    pub fn new(rc: Rc<T>) -> Self {
+        let projected = NonNull::from(&*rc);
+        Self { rc, projected }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> RcRef<T, U> {
+    /// Narrows the reference down further, `this` may also be a plain `Rc<T>`.
+    pub fn map<V: ?Sized>(this: impl Into<Self>, f: impl FnOnce(&U) -> &V) -> RcRef<T, V> {
+        let this = this.into();
+        let projected = NonNull::from(f(&*this));
+        RcRef {
+            rc: this.rc,
+            projected,
+        }
+    }
+
+    /// Like `map`, but keeps `this` if there is nothing to project to.
+    pub fn try_map<V: ?Sized>(
+        this: impl Into<Self>,
+        f: impl FnOnce(&U) -> Option<&V>,
+    ) -> Result<RcRef<T, V>, Self> {
+        let this = this.into();
+        match f(&*this).map(NonNull::from) {
+            Some(projected) => Ok(RcRef {
+                rc: this.rc,
+                projected,
+            }),
+            None => Err(this),
+        }
+    }
+
+    /// The `Rc` to the whole value.
+    pub fn as_rc(this: &Self) -> &Rc<T> {
+        &this.rc
+    }
+
+    pub fn into_rc(this: Self) -> Rc<T> {
+        this.rc
+    }
+}
+
+impl<T: ?Sized> From<Rc<T>> for RcRef<T> {
+    fn from(rc: Rc<T>) -> Self {
+        Self::new(rc)
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> Clone for RcRef<T, U> {
+    fn clone(&self) -> Self {
+        Self {
+            rc: self.rc.clone(),
+            projected: self.projected,
+        }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized> Deref for RcRef<T, U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        // SAFETY: Points into the value of `self.rc`, which is alive and doesn't
+        // move. Nobody can get at it mutably while we share it.
+        unsafe { self.projected.as_ref() }
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + PartialEq> PartialEq for RcRef<T, U> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Debug> Debug for RcRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl<T: ?Sized, U: ?Sized + Display> Display for RcRef<T, U> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RcRef;
+    use crate::rc::Rc;
+
+    struct Config {
+        name: String,
+        ports: Vec<u16>,
+    }
+
+    fn config() -> Rc<Config> {
+        Rc::new(Config {
+            name: String::from("prod"),
+            ports: vec![80, 443],
+        })
+    }
+
+    // Returns part of the value without tying it to a borrow.
+    fn name_of(config: &Rc<Config>) -> RcRef<Config, str> {
+        RcRef::map(config.clone(), |config| config.name.as_str())
+    }
+
+    #[test]
+    fn test_projection_keeps_value_alive() {
+        let config = config();
+        let name = name_of(&config);
+        drop(config);
+
+        assert_eq!(&*name, "prod");
+        assert_eq!(name.to_string(), "prod");
+        assert_eq!(Rc::strong_count(RcRef::as_rc(&name)), 1);
+    }
+
+    #[test]
+    fn test_nested_projections() {
+        let ports = RcRef::map(config(), |config| &config.ports[..]);
+        let https = RcRef::map(ports.clone(), |ports| &ports[1]);
+
+        assert_eq!(*https, 443);
+        assert_eq!(&*ports, &[80, 443]);
+        assert_eq!(RcRef::into_rc(https).name, "prod");
+    }
+
+    #[test]
+    fn test_try_map() {
+        let ports = RcRef::map(config(), |config| &config.ports);
+
+        let missing = RcRef::try_map(ports, |ports| ports.get(2)).unwrap_err();
+        let first = RcRef::try_map(missing, |ports| ports.first()).unwrap();
+
+        assert_eq!(*first, 80);
+    }
+}
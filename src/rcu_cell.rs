@@ -0,0 +1,299 @@
+//! Read-copy-update: readers see a snapshot without ever waiting, writers
+//! install new versions and retire the old ones, through
+//! [`epoch`](crate::epoch) or [`hazard`](crate::hazard) pointers.
+
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::ops::Deref;
+
+use crate::atomic::AtomicPtr;
+use crate::epoch;
+use crate::hazard::{self, HazardPointer};
+use crate::sync::{ignore_poison, Mutex};
+
+/// How an [`RcuCell`] keeps retired versions alive while readers use them.
+///
+/// # Safety
+///
+/// A pointer returned by `protect` stays valid while its guard lives, even if
+/// it is passed to `retire` meanwhile.
+pub unsafe trait Reclaim {
+    type Guard;
+
+    /// Loads `current` with Acquire and protects what it points to.
+    fn protect<T>(current: &AtomicPtr<T>) -> (Self::Guard, *mut T);
+
+    /// Drops the boxed value once no guard protects it.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` comes from `Box::into_raw`, is unreachable for new readers, and
+    /// isn't freed otherwise.
+    unsafe fn retire<T: Send + 'static>(ptr: *mut T);
+}
+
+/// Epoch-based reclamation, the default: a read pins the thread, which is
+/// cheap, but a reader that stalls holds back every version retired meanwhile.
+#[derive(Debug)]
+pub enum Epoch {}
+
+// SAFETY: A version retired while the guard pins the thread is deferred until
+// it unpins.
+unsafe impl Reclaim for Epoch {
+    type Guard = epoch::Guard;
+
+    fn protect<T>(current: &AtomicPtr<T>) -> (epoch::Guard, *mut T) {
+        let guard = epoch::pin();
+        (guard, current.load_acquire())
+    }
+
+    unsafe fn retire<T: Send + 'static>(ptr: *mut T) {
+        epoch::pin().defer_destroy(ptr);
+    }
+}
+
+/// Hazard pointers: a read costs a fence, but a stalled reader holds back only
+/// the version it reads.
+#[derive(Debug)]
+pub enum Hazard {}
+
+// SAFETY: The hazard pointer protects the version until it is dropped.
+unsafe impl Reclaim for Hazard {
+    type Guard = HazardPointer;
+
+    fn protect<T>(current: &AtomicPtr<T>) -> (HazardPointer, *mut T) {
+        let mut hazard = HazardPointer::new();
+        let ptr = hazard.protect(current);
+        (hazard, ptr)
+    }
+
+    unsafe fn retire<T: Send + 'static>(ptr: *mut T) {
+        hazard::retire(ptr);
+    }
+}
+
+/// A value read far more often than it changes, like a configuration.
+///
+/// `read` loads a pointer and protects it, it never blocks. Writers replace the
+/// whole value, a version stays alive until the last reader that could have
+/// seen it is done. `R` picks how, see [`Reclaim`].
+pub struct RcuCell<T, R: Reclaim = Epoch> {
+    current: AtomicPtr<T>,
+    // Serializes writers, so that `update` doesn't lose concurrent writes.
+    writer: Mutex<()>,
+    reclaim: PhantomData<R>,
+}
+
+// SAFETY: Readers share `&T` across threads, and any thread may drop a version
+// another thread created.
+unsafe impl<T: Send + Sync, R: Reclaim> Sync for RcuCell<T, R> {}
+unsafe impl<T: Send, R: Reclaim> Send for RcuCell<T, R> {}
+
+/// A snapshot of an [`RcuCell`], keeps the version alive.
+pub struct RcuReadGuard<'a, T, R: Reclaim = Epoch> {
+    value: &'a T,
+    _guard: R::Guard,
+}
+
+impl<T, R: Reclaim> Deref for RcuReadGuard<'_, T, R> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: Debug, R: Reclaim> Debug for RcuReadGuard<'_, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self.value, f)
+    }
+}
+
+impl<T: Send + Sync + 'static> RcuCell<T> {
+    pub fn new(value: T) -> Self {
+        Self::with_reclaim(value)
+    }
+}
+
+impl<T: Send + Sync + 'static, R: Reclaim> RcuCell<T, R> {
+    /// A cell using `R` for reclamation, as in
+    /// `RcuCell::<_, Hazard>::with_reclaim(value)`.
+    pub fn with_reclaim(value: T) -> Self {
+        Self {
+            current: AtomicPtr::new(Box::into_raw(Box::new(value))),
+            writer: Mutex::new(()),
+            reclaim: PhantomData,
+        }
+    }
+
+    /// The current version. Writers don't wait for the guard, they replace the
+    /// value next to it.
+    pub fn read(&self) -> RcuReadGuard<'_, T, R> {
+        let (guard, ptr) = R::protect(&self.current);
+        // SAFETY: The version isn't freed before the guard is dropped.
+        let value = unsafe { &*ptr };
+        RcuReadGuard {
+            value,
+            _guard: guard,
+        }
+    }
+
+    pub fn write(&self, value: T) {
+        let _writer = self.lock_writer();
+        self.replace(value);
+    }
+
+    /// Installs `f` of the current version, other writers wait meanwhile.
+    pub fn update(&self, f: impl FnOnce(&T) -> T) {
+        let _writer = self.lock_writer();
+        let value = f(&self.read());
+        self.replace(value);
+    }
+
+    fn lock_writer(&self) -> crate::sync::MutexGuard<'_, ()> {
+        ignore_poison(self.writer.lock())
+    }
+
+    fn replace(&self, value: T) {
+        // Release publishes the new version, Acquire gets the old one's writes
+        // before we retire it.
+        let old = self.current.swap_acq_rel(Box::into_raw(Box::new(value)));
+        // SAFETY: `old` came from Box::into_raw and is unreachable now.
+        unsafe { R::retire(old) };
+    }
+}
+
+impl<T, R: Reclaim> Drop for RcuCell<T, R> {
+    fn drop(&mut self) {
+        // SAFETY: Readers borrow the cell, there are none left. Old versions
+        // were retired, only the current one is still ours.
+        unsafe { drop(Box::from_raw(*self.current.get_mut())) };
+    }
+}
+
+impl<T: Default + Send + Sync + 'static, R: Reclaim> Default for RcuCell<T, R> {
+    fn default() -> Self {
+        Self::with_reclaim(T::default())
+    }
+}
+
+impl<T: Debug + Send + Sync + 'static, R: Reclaim> Debug for RcuCell<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RcuCell").field(&*self.read()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Hazard, RcuCell, Reclaim};
+    use crate::epoch::{self, collect_until};
+    use crate::hazard::reclaim_until;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    fn assert_sync<T: Sync>() {}
+
+    struct Version {
+        number: usize,
+        drops: Arc<AtomicUsize>,
+    }
+
+    impl Drop for Version {
+        fn drop(&mut self) {
+            self.drops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_auto_traits() {
+        assert_sync::<RcuCell<Vec<i32>>>();
+        assert_not_impl!(RcuCell<std::cell::Cell<i32>>: Sync);
+    }
+
+    #[test]
+    fn test_read_write_update() {
+        let cell = RcuCell::new(vec![1]);
+
+        cell.write(vec![1, 2]);
+        cell.update(|value| value.iter().map(|x| x * 10).collect());
+
+        assert_eq!(*cell.read(), [10, 20]);
+        assert_eq!(format!("{:?}", cell), "RcuCell([10, 20])");
+    }
+
+    #[test]
+    fn test_old_version_outlives_its_readers() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let version = |number| Version {
+            number,
+            drops: drops.clone(),
+        };
+        let cell = RcuCell::new(version(1));
+
+        let snapshot = cell.read();
+        cell.write(version(2));
+        for _ in 0..10 {
+            epoch::collect();
+        }
+        assert_eq!(snapshot.number, 1);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(snapshot);
+
+        assert!(collect_until(|| drops.load(Ordering::Relaxed) == 1));
+        assert_eq!(cell.read().number, 2);
+        drop(cell);
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_hazard_pointer_keeps_its_version() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let version = |number| Version {
+            number,
+            drops: drops.clone(),
+        };
+        let cell = RcuCell::<_, Hazard>::with_reclaim(version(1));
+
+        let snapshot = cell.read();
+        cell.write(version(2));
+        cell.write(version(3));
+        // Only the version still read survives.
+        assert!(reclaim_until(|| drops.load(Ordering::Relaxed) == 1));
+        assert_eq!(snapshot.number, 1);
+        drop(snapshot);
+
+        assert!(reclaim_until(|| drops.load(Ordering::Relaxed) == 2));
+        assert_eq!(cell.read().number, 3);
+    }
+
+    fn count_across_threads<R: Reclaim>() {
+        let cell = RcuCell::<_, R>::with_reclaim(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        cell.update(|value| value + 1);
+                    }
+                });
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..1000 {
+                        let value = *cell.read();
+                        assert!(value >= last);
+                        last = value;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*cell.read(), 400);
+    }
+
+    #[test]
+    fn test_updates_across_threads() {
+        count_across_threads::<super::Epoch>();
+        count_across_threads::<Hazard>();
+    }
+}
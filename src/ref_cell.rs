@@ -1,16 +1,88 @@
+use std::backtrace::Backtrace;
+use std::error::Error;
 use std::fmt::{self, Debug, Display};
+use std::mem;
+use std::panic::Location;
+use std::sync::Arc;
 
-use crate::{cell::Cell, refs::RefMut};
-use crate::refs::{Ref, State};
-use crate::unsafe_cell::UnsafeCell;
+use crate::refs::{Ref, RefMut};
+#[cfg(not(feature = "std-backend"))]
+use crate::{cell::Cell, refs::State, unsafe_cell::UnsafeCell};
 
 pub struct RefCell<T> {
+    #[cfg(not(feature = "std-backend"))]
     value: UnsafeCell<T>,
+    #[cfg(not(feature = "std-backend"))]
     state: Cell<State>,
+    // Where the borrow that took the cell out of the unused state was made.
+    #[cfg(all(feature = "debug-borrows", not(feature = "std-backend")))]
+    borrowed_at: Cell<Option<&'static Location<'static>>>,
+    #[cfg(all(feature = "borrow-backtraces", not(feature = "std-backend")))]
+    borrowed_backtrace: Cell<Option<Arc<Backtrace>>>,
+    // With the `std-backend` feature all the bookkeeping is delegated to `std::cell::RefCell`.
+    #[cfg(feature = "std-backend")]
+    inner: std::cell::RefCell<T>,
+    name: Option<&'static str>,
+}
+
+/// Why a borrow failed.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorrowErrorKind {
+    /// The cell is borrowed immutably.
+    BlockedBySharedBorrows,
+    /// The cell is borrowed mutably.
+    BlockedByExclusiveBorrow,
+}
+
+/// The outstanding borrow that made another borrow fail.
+struct ConflictingBorrow {
+    location: &'static Location<'static>,
+    backtrace: Option<Arc<Backtrace>>,
 }
 
 /// An error returned by [`RefCell::try_borrow`].
-pub struct BorrowError {}
+#[non_exhaustive]
+pub struct BorrowError {
+    name: Option<&'static str>,
+    location: &'static Location<'static>,
+    conflicting: Option<ConflictingBorrow>,
+}
+
+impl BorrowError {
+    pub(crate) fn new(name: Option<&'static str>, location: &'static Location<'static>) -> Self {
+        Self {
+            name,
+            location,
+            conflicting: None,
+        }
+    }
+
+    /// Shared borrows can only ever be blocked by an exclusive one.
+    pub fn kind(&self) -> BorrowErrorKind {
+        BorrowErrorKind::BlockedByExclusiveBorrow
+    }
+
+    /// The name of the cell, see [`RefCell::new_named`].
+    pub fn cell_name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Where the failed borrow was attempted.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Where the outstanding mutable borrow was made, only tracked with the `debug-borrows` feature.
+    pub fn conflicting_borrow_location(&self) -> Option<&'static Location<'static>> {
+        self.conflicting.as_ref().map(|c| c.location)
+    }
+
+    /// The backtrace of the conflicting borrow, only captured with the `borrow-backtraces` feature.
+    pub fn conflicting_borrow_backtrace(&self) -> Option<&Backtrace> {
+        self.conflicting.as_ref()?.backtrace.as_deref()
+    }
+}
 
 impl Debug for BorrowError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -20,12 +92,70 @@ impl Debug for BorrowError {
 
 impl Display for BorrowError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Already mutably borrowed").finish()
+        f.write_str("Already mutably borrowed")?;
+        if let Some(name) = self.name {
+            write!(f, " `{}`", name)?;
+        }
+        write!(f, " (at {})", self.location)?;
+        if let Some(conflicting) = self.conflicting_borrow_location() {
+            write!(f, ", mutable borrow taken at {}", conflicting)?;
+        }
+        Ok(())
     }
 }
 
-/// An error returned by [`RefCell::try_borrow`].
-pub struct BorrowMutError {}
+impl Error for BorrowError {}
+
+/// An error returned by [`RefCell::try_borrow_mut`].
+#[non_exhaustive]
+pub struct BorrowMutError {
+    kind: BorrowErrorKind,
+    name: Option<&'static str>,
+    location: &'static Location<'static>,
+    conflicting: Option<ConflictingBorrow>,
+}
+
+impl BorrowMutError {
+    pub(crate) fn new(
+        kind: BorrowErrorKind,
+        name: Option<&'static str>,
+        location: &'static Location<'static>,
+    ) -> Self {
+        Self {
+            kind,
+            name,
+            location,
+            conflicting: None,
+        }
+    }
+
+    pub fn kind(&self) -> BorrowErrorKind {
+        self.kind
+    }
+
+    /// The name of the cell, see [`RefCell::new_named`].
+    pub fn cell_name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Where the failed borrow was attempted.
+    pub fn location(&self) -> &'static Location<'static> {
+        self.location
+    }
+
+    /// Where the outstanding borrow was made, only tracked with the `debug-borrows` feature.
+    ///
+    /// With several readers this is the first one, the cell only remembers where it
+    /// stopped being unused.
+    pub fn conflicting_borrow_location(&self) -> Option<&'static Location<'static>> {
+        self.conflicting.as_ref().map(|c| c.location)
+    }
+
+    /// The backtrace of the conflicting borrow, only captured with the `borrow-backtraces` feature.
+    pub fn conflicting_borrow_backtrace(&self) -> Option<&Backtrace> {
+        self.conflicting.as_ref()?.backtrace.as_deref()
+    }
+}
 
 impl Debug for BorrowMutError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -35,35 +165,157 @@ impl Debug for BorrowMutError {
 
 impl Display for BorrowMutError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Already borrowed").finish()
+        f.write_str("Already borrowed")?;
+        if let Some(name) = self.name {
+            write!(f, " `{}`", name)?;
+        }
+        write!(f, " (at {})", self.location)?;
+        if let Some(conflicting) = self.conflicting_borrow_location() {
+            write!(f, ", conflicting borrow taken at {}", conflicting)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BorrowMutError {}
+
+/// Either of the borrow errors, for code that both reads and writes and wants to use `?`.
+#[derive(Debug)]
+pub enum TryBorrowError {
+    Borrow(BorrowError),
+    BorrowMut(BorrowMutError),
+}
+
+impl TryBorrowError {
+    pub fn kind(&self) -> BorrowErrorKind {
+        match self {
+            TryBorrowError::Borrow(err) => err.kind(),
+            TryBorrowError::BorrowMut(err) => err.kind(),
+        }
+    }
+
+    pub fn location(&self) -> &'static Location<'static> {
+        match self {
+            TryBorrowError::Borrow(err) => err.location(),
+            TryBorrowError::BorrowMut(err) => err.location(),
+        }
+    }
+}
+
+impl From<BorrowError> for TryBorrowError {
+    fn from(err: BorrowError) -> Self {
+        TryBorrowError::Borrow(err)
+    }
+}
+
+impl From<BorrowMutError> for TryBorrowError {
+    fn from(err: BorrowMutError) -> Self {
+        TryBorrowError::BorrowMut(err)
+    }
+}
+
+impl Display for TryBorrowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TryBorrowError::Borrow(err) => Display::fmt(err, f),
+            TryBorrowError::BorrowMut(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl Error for TryBorrowError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TryBorrowError::Borrow(err) => Some(err),
+            TryBorrowError::BorrowMut(err) => Some(err),
+        }
     }
 }
 
 impl<T> RefCell<T> {
-    pub fn new(value: T) -> Self {
+    pub const fn new(value: T) -> Self {
+        Self::with_name(value, None)
+    }
+
+    /// Creates a cell whose name shows up in its Debug output, borrow errors and panics.
+    pub const fn new_named(value: T, name: &'static str) -> Self {
+        Self::with_name(value, Some(name))
+    }
+
+    #[cfg(not(feature = "std-backend"))]
+    const fn with_name(value: T, name: Option<&'static str>) -> Self {
         Self {
             value: UnsafeCell::new(value),
             state: Cell::new(State::Unused),
+            #[cfg(feature = "debug-borrows")]
+            borrowed_at: Cell::new(None),
+            #[cfg(feature = "borrow-backtraces")]
+            borrowed_backtrace: Cell::new(None),
+            name,
         }
     }
 
+    #[cfg(feature = "std-backend")]
+    const fn with_name(value: T, name: Option<&'static str>) -> Self {
+        Self {
+            inner: std::cell::RefCell::new(value),
+            name,
+        }
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    #[cfg(not(feature = "std-backend"))]
     pub fn into_inner(self) -> T {
         self.value.into_inner()
     }
 
-    // pub fn replace(&self, newval: T) -> T {
-    //     self.value.replace(newval)
-    // }
+    #[cfg(feature = "std-backend")]
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+
+    /// Replaces the value, panics if the value is currently borrowed.
+    #[track_caller]
+    pub fn replace(&self, newval: T) -> T {
+        mem::replace(&mut *self.borrow_mut(), newval)
+    }
 
-    pub fn borrow(&self) -> Ref<T> {
-        self.try_borrow()
-            .expect("Value borrowed mutably, can't borrow.")
+    #[track_caller]
+    pub fn take(&self) -> T
+    where
+        T: Default,
+    {
+        self.replace(T::default())
     }
 
-    pub fn try_borrow(&self) -> Result<Ref<T>, BorrowError> {
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        match self.try_borrow() {
+            Ok(borrow) => borrow,
+            Err(err) => panic!("Value borrowed mutably, can't borrow: {}", err),
+        }
+    }
+
+    #[cfg(feature = "std-backend")]
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
+        let location = Location::caller();
+        self.inner
+            .try_borrow()
+            .map(Ref::from_std)
+            .map_err(|_| BorrowError::new(self.name, location))
+    }
+
+    #[cfg(not(feature = "std-backend"))]
+    #[track_caller]
+    pub fn try_borrow(&self) -> Result<Ref<'_, T>, BorrowError> {
         match self.state.get() {
             State::Unused => {
                 self.state.set(State::HasReaders(1));
+                self.track_borrow(Location::caller());
                 Ok(unsafe {
                     // SAFETY: This is safe because we have no pending borrows.
                     Ref::new(&self.state, &*self.value.get())
@@ -76,26 +328,95 @@ impl<T> RefCell<T> {
                     Ref::new(&self.state, &*self.value.get())
                 })
             }
-            State::HasWriter => Err(BorrowError {}),
+            State::HasWriter => Err(BorrowError {
+                name: self.name,
+                location: Location::caller(),
+                conflicting: self.conflicting_borrow(),
+            }),
         }
     }
 
-    pub fn try_borrow_mut(&self) -> Result<RefMut<T>, BorrowMutError> {
+    #[cfg(feature = "std-backend")]
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
+        let location = Location::caller();
+        self.inner
+            .try_borrow_mut()
+            .map(RefMut::from_std)
+            .map_err(|_| {
+                // std doesn't tell us, but a shared borrow only succeeds when there's no writer.
+                let kind = match self.inner.try_borrow() {
+                    Ok(_) => BorrowErrorKind::BlockedBySharedBorrows,
+                    Err(_) => BorrowErrorKind::BlockedByExclusiveBorrow,
+                };
+                BorrowMutError::new(kind, self.name, location)
+            })
+    }
+
+    #[cfg(not(feature = "std-backend"))]
+    #[track_caller]
+    pub fn try_borrow_mut(&self) -> Result<RefMut<'_, T>, BorrowMutError> {
         match self.state.get() {
             State::Unused => {
                 self.state.set(State::HasWriter);
+                self.track_borrow(Location::caller());
                 Ok(unsafe {
                     // SAFETY: This is safe because we have no pending borrows.
                     RefMut::new(&self.state, &mut *self.value.get())
                 })
             }
-            State::HasReaders(_) => Err(BorrowMutError {}),
-            State::HasWriter => Err(BorrowMutError {}),
+            State::HasReaders(_) => Err(BorrowMutError {
+                kind: BorrowErrorKind::BlockedBySharedBorrows,
+                name: self.name,
+                location: Location::caller(),
+                conflicting: self.conflicting_borrow(),
+            }),
+            State::HasWriter => Err(BorrowMutError {
+                kind: BorrowErrorKind::BlockedByExclusiveBorrow,
+                name: self.name,
+                location: Location::caller(),
+                conflicting: self.conflicting_borrow(),
+            }),
         }
     }
 
-    pub fn borrow_mut(&self) -> RefMut<T> {
-        self.try_borrow_mut().expect("Value already borrowed")
+    #[cfg(not(feature = "std-backend"))]
+    fn track_borrow(&self, _location: &'static Location<'static>) {
+        #[cfg(feature = "debug-borrows")]
+        self.borrowed_at.set(Some(_location));
+        #[cfg(feature = "borrow-backtraces")]
+        self.borrowed_backtrace
+            .set(Some(Arc::new(Backtrace::force_capture())));
+    }
+
+    #[cfg(not(feature = "std-backend"))]
+    fn conflicting_borrow(&self) -> Option<ConflictingBorrow> {
+        #[cfg(feature = "debug-borrows")]
+        return self.borrowed_at.get().map(|location| ConflictingBorrow {
+            location,
+            #[cfg(feature = "borrow-backtraces")]
+            backtrace: self.borrowed_backtrace.with_taken(|b| b.clone()),
+            #[cfg(not(feature = "borrow-backtraces"))]
+            backtrace: None,
+        });
+        #[cfg(not(feature = "debug-borrows"))]
+        None
+    }
+
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(borrow) => borrow,
+            Err(err) => panic!("Value already borrowed: {}", err),
+        }
+    }
+
+    fn debug_struct<'a, 'b>(&self, f: &'a mut fmt::Formatter<'b>) -> fmt::DebugStruct<'a, 'b> {
+        let mut debug = f.debug_struct("RefCell");
+        if let Some(name) = self.name {
+            debug.field("name", &name);
+        }
+        debug
     }
 }
 
@@ -105,7 +426,7 @@ where
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self.try_borrow() {
-            Ok(borrow) => f.debug_struct("RefCell").field("value", &borrow).finish(),
+            Ok(borrow) => self.debug_struct(f).field("value", &borrow).finish(),
             Err(_) => {
                 struct Placeholder;
 
@@ -115,14 +436,18 @@ where
                     }
                 }
 
-                f.debug_struct("RefCell")
-                    .field("value", &Placeholder)
-                    .finish()
+                self.debug_struct(f).field("value", &Placeholder).finish()
             }
         }
     }
 }
 
+impl<T: PartialEq> PartialEq for RefCell<T> {
+    fn eq(&self, other: &RefCell<T>) -> bool {
+        *self.borrow() == *other.borrow()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -133,13 +458,28 @@ mod tests {
         assert_eq!(cell.into_inner(), 42);
     }
 
-    // #[test]
-    // fn test_replace() {
-    //     let cell = RefCell::new(5);
-    //     let old_value = cell.replace(6);
-    //     assert_eq!(old_value, 5);
-    //     assert_eq!(cell, RefCell::new(6));
-    // }
+    #[test]
+    fn test_replace() {
+        let cell = RefCell::new(5);
+        let old_value = cell.replace(6);
+        assert_eq!(old_value, 5);
+        assert_eq!(cell, RefCell::new(6));
+    }
+
+    #[test]
+    fn test_take() {
+        let cell = RefCell::new(5);
+        assert_eq!(cell.take(), 5);
+        assert_eq!(cell.into_inner(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value already borrowed")]
+    fn test_replace_panics_when_borrowed() {
+        let cell = RefCell::new(5);
+        let _borrow = cell.borrow();
+        cell.replace(6);
+    }
 
     #[test]
     fn test_borrow() {
@@ -193,26 +533,195 @@ mod tests {
 
     #[test]
     fn test_borrow_error_debug() {
-        let err = BorrowError {};
+        let err = BorrowError {
+            name: None,
+            location: Location::caller(),
+            conflicting: None,
+        };
         assert_eq!(format!("{:?}", err), "BorrowError");
     }
 
     #[test]
     fn test_borrow_error_display() {
-        let err = BorrowError {};
-        assert_eq!(format!("{}", err), "Already mutably borrowed");
+        let cell = RefCell::new(42);
+        let _mut_borrow = cell.borrow_mut();
+        let err = cell.try_borrow().unwrap_err();
+        // With `debug-borrows` the conflicting borrow is appended.
+        assert!(format!("{}", err)
+            .starts_with(&format!("Already mutably borrowed (at {})", err.location())));
     }
 
     #[test]
     fn test_borrow_mut_error_debug() {
-        let err = BorrowMutError {};
+        let err = BorrowMutError {
+            kind: BorrowErrorKind::BlockedBySharedBorrows,
+            name: None,
+            location: Location::caller(),
+            conflicting: None,
+        };
         assert_eq!(format!("{:?}", err), "BorrowMutError");
     }
 
     #[test]
     fn test_borrow_mut_error_display() {
-        let err = BorrowMutError {};
-        assert_eq!(format!("{}", err), "Already borrowed");
+        let cell = RefCell::new(42);
+        let _borrow = cell.borrow();
+        let err = cell.try_borrow_mut().unwrap_err();
+        // With `debug-borrows` the conflicting borrow is appended.
+        assert!(
+            format!("{}", err).starts_with(&format!("Already borrowed (at {})", err.location()))
+        );
+    }
+
+    #[test]
+    fn test_errors_box_into_dyn_error() {
+        let cell = RefCell::new(42);
+        let _mut_borrow = cell.borrow_mut();
+
+        let read = || -> Result<i32, Box<dyn Error>> { Ok(*cell.try_borrow()?) };
+        let write = || -> Result<(), Box<dyn Error>> {
+            *cell.try_borrow_mut()? = 43;
+            Ok(())
+        };
+
+        assert!(read()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Already mutably borrowed"));
+        assert!(write()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Already borrowed"));
+    }
+
+    #[test]
+    fn test_error_location_points_to_caller() {
+        let cell = RefCell::new(42);
+        let _borrow = cell.borrow();
+
+        let (err, line) = (cell.try_borrow_mut().unwrap_err(), line!());
+
+        assert_eq!(err.location().file(), file!());
+        assert_eq!(err.location().line(), line);
+    }
+
+    #[cfg(all(feature = "debug-borrows", not(feature = "std-backend")))]
+    #[test]
+    fn test_errors_report_conflicting_borrow() {
+        let cell = RefCell::new(42);
+        let (borrow, line) = (cell.borrow(), line!());
+        let second_borrow = cell.borrow();
+
+        let err = cell.try_borrow_mut().unwrap_err();
+        let conflicting = err.conflicting_borrow_location().unwrap();
+        assert_eq!((conflicting.file(), conflicting.line()), (file!(), line));
+        assert!(err
+            .to_string()
+            .ends_with(&format!(", conflicting borrow taken at {}", conflicting)));
+
+        drop(borrow);
+        drop(second_borrow);
+        let (_mut_borrow, line) = (cell.borrow_mut(), line!());
+        let err = cell.try_borrow().unwrap_err();
+        assert_eq!(err.conflicting_borrow_location().unwrap().line(), line);
+    }
+
+    #[cfg(all(feature = "borrow-backtraces", not(feature = "std-backend")))]
+    #[test]
+    fn test_errors_capture_conflicting_backtrace() {
+        let cell = RefCell::new(42);
+        let _borrow = cell.borrow();
+
+        let err = cell.try_borrow_mut().unwrap_err();
+
+        let backtrace = err.conflicting_borrow_backtrace().unwrap();
+        assert_eq!(
+            backtrace.status(),
+            std::backtrace::BacktraceStatus::Captured
+        );
+    }
+
+    #[cfg(not(feature = "debug-borrows"))]
+    #[test]
+    fn test_errors_do_not_track_conflicting_borrow() {
+        let cell = RefCell::new(42);
+        let _borrow = cell.borrow();
+        assert!(cell
+            .try_borrow_mut()
+            .unwrap_err()
+            .conflicting_borrow_location()
+            .is_none());
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let cell = RefCell::new(42);
+        {
+            let _borrow = cell.borrow();
+            assert_eq!(
+                cell.try_borrow_mut().unwrap_err().kind(),
+                BorrowErrorKind::BlockedBySharedBorrows
+            );
+        }
+
+        let _mut_borrow = cell.borrow_mut();
+        assert_eq!(
+            cell.try_borrow_mut().unwrap_err().kind(),
+            BorrowErrorKind::BlockedByExclusiveBorrow
+        );
+        assert_eq!(
+            cell.try_borrow().unwrap_err().kind(),
+            BorrowErrorKind::BlockedByExclusiveBorrow
+        );
+    }
+
+    #[test]
+    fn test_try_borrow_error_conversions() {
+        let source = RefCell::new(42);
+        let target = RefCell::new(0);
+        let copy = || -> Result<(), TryBorrowError> {
+            *target.try_borrow_mut()? = *source.try_borrow()?;
+            Ok(())
+        };
+
+        {
+            let _mut_borrow = source.borrow_mut();
+            assert!(matches!(copy(), Err(TryBorrowError::Borrow(_))));
+        }
+        {
+            let _borrow = target.borrow();
+            let err = copy().unwrap_err();
+            assert!(matches!(err, TryBorrowError::BorrowMut(_)));
+            assert_eq!(err.kind(), BorrowErrorKind::BlockedBySharedBorrows);
+            assert!(err.source().is_some());
+        }
+        assert!(copy().is_ok());
+        assert_eq!(*target.borrow(), 42);
+    }
+
+    #[test]
+    fn test_named_cell_in_errors_and_debug() {
+        let cell = RefCell::new_named(42, "answer");
+        assert_eq!(cell.name(), Some("answer"));
+        assert_eq!(
+            format!("{:?}", cell),
+            "RefCell { name: \"answer\", value: Ref { value: 42 } }"
+        );
+
+        let _borrow = cell.borrow();
+        let err = cell.try_borrow_mut().unwrap_err();
+        assert_eq!(err.cell_name(), Some("answer"));
+        assert!(err
+            .to_string()
+            .starts_with("Already borrowed `answer` (at "));
+    }
+
+    #[test]
+    #[should_panic(expected = "Value already borrowed: Already borrowed `answer`")]
+    fn test_named_cell_in_panics() {
+        let cell = RefCell::new_named(42, "answer");
+        let _borrow = cell.borrow();
+        cell.borrow_mut();
     }
 
     #[test]
@@ -1,5 +1,8 @@
 use std::{borrow::{Borrow, BorrowMut}, ops::{Deref, DerefMut}};
 use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::ptr::NonNull;
 
 use crate::cell::Cell;
 
@@ -9,51 +12,74 @@ pub enum State {
     HasReaders(usize),
     HasWriter,
 }
-pub struct Ref<'cell, T> {
-    state: &'cell Cell<State>,
-    value: &'cell T
-}
 
-impl<'cell, T> Ref<'cell, T> {
-    pub fn new(state: &'cell Cell<State>, value: &'cell T) -> Self {
-        Self { state, value }
-    }
+/// The bookkeeping of a cell's outstanding borrows, guards release their borrow through it when dropped.
+pub trait BorrowFlag {
+    fn release_shared(&self);
+    fn release_exclusive(&self);
 }
 
-impl<'cell, T> Drop for Ref<'cell, T> {
-    fn drop(&mut self) {
-        let state = self.state.get();
+impl BorrowFlag for Cell<State> {
+    fn release_shared(&self) {
+        let state = self.get();
         if let State::HasReaders(n) = state {
             if n == 1 {
-                self.state.set(State::Unused);
+                self.set(State::Unused);
             } else {
-                self.state.set(State::HasReaders(n - 1));
+                self.set(State::HasReaders(n - 1));
             }
         } else {
             unreachable!("Cannot have a Ref instance when the cell is not in the HasReaders state");
         }
     }
+
+    fn release_exclusive(&self) {
+        let state = self.get();
+        if let State::HasWriter = state {
+            self.set(State::Unused);
+        } else {
+            unreachable!("Cannot have a RefMut instance when the cell is not in the HasWriter state");
+        }
+    }
 }
 
-impl<T> Borrow<T> for Ref<'_, T> {
+/// A shared borrow of a cell whose borrows are tracked by `F`.
+pub struct FlagRef<'cell, T, F: BorrowFlag> {
+    flag: &'cell F,
+    value: &'cell T
+}
+
+impl<'cell, T, F: BorrowFlag> FlagRef<'cell, T, F> {
+    pub fn new(flag: &'cell F, value: &'cell T) -> Self {
+        Self { flag, value }
+    }
+}
+
+impl<T, F: BorrowFlag> Drop for FlagRef<'_, T, F> {
+    fn drop(&mut self) {
+        self.flag.release_shared();
+    }
+}
+
+impl<T, F: BorrowFlag> Borrow<T> for FlagRef<'_, T, F> {
     fn borrow(&self) -> &T {
         self.value
     }
 }
 
-impl<T: Debug> Debug for Ref<'_, T> {
+impl<T: Debug, F: BorrowFlag> Debug for FlagRef<'_, T, F> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Ref").field("value", self.value).finish()
     }
 }
 
-impl<T: PartialEq> PartialEq for Ref<'_, T> {
+impl<T: PartialEq, F: BorrowFlag> PartialEq for FlagRef<'_, T, F> {
     fn eq(&self, other: &Self) -> bool {
         self.value.eq(other.value)
     }
 }
 
-impl<T> Deref for Ref<'_, T> {
+impl<T, F: BorrowFlag> Deref for FlagRef<'_, T, F> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
@@ -61,50 +87,177 @@ impl<T> Deref for Ref<'_, T> {
     }
 }
 
-pub struct RefMut<'cell, T> {
-    state: &'cell Cell<State>,
-    value: &'cell mut T
+/// An exclusive borrow of a cell whose borrows are tracked by `F`.
+///
+/// The value is held by pointer, not `&mut T`: a guard passed by value to a
+/// function that releases the borrow, like `Condvar::wait`, would otherwise keep
+/// the value borrowed for the whole call while another thread takes it.
+pub struct FlagRefMut<'cell, T, F: BorrowFlag> {
+    flag: &'cell F,
+    value: NonNull<T>,
+    _marker: PhantomData<&'cell mut T>,
 }
 
-impl<'cell, T> RefMut<'cell, T> {
-    pub fn new(state: &'cell Cell<State>, value: &'cell mut T) -> Self {
-        Self { state, value }
+// SAFETY: The same as for the `(&'cell F, &'cell mut T)` the guard stands for.
+unsafe impl<T: Send, F: BorrowFlag + Sync> Send for FlagRefMut<'_, T, F> {}
+unsafe impl<T: Sync, F: BorrowFlag + Sync> Sync for FlagRefMut<'_, T, F> {}
+
+impl<'cell, T, F: BorrowFlag> FlagRefMut<'cell, T, F> {
+    pub fn new(flag: &'cell F, value: &'cell mut T) -> Self {
+        Self { flag, value: NonNull::from(value), _marker: PhantomData }
+    }
+
+    /// Like `new`, but keeps the pointer as it is instead of going through a
+    /// `&mut T`, which the next borrow of the cell would invalidate.
+    ///
+    /// SAFETY: `flag` must hold the exclusive borrow of the value at `value`
+    /// for `'cell`.
+    pub(crate) unsafe fn from_raw(flag: &'cell F, value: NonNull<T>) -> Self {
+        Self { flag, value, _marker: PhantomData }
+    }
+
+    /// Gives up the guard without releasing the borrow, the caller takes over.
+    pub(crate) fn into_parts(this: Self) -> (&'cell F, NonNull<T>) {
+        let this = ManuallyDrop::new(this);
+        (this.flag, this.value)
     }
 }
 
-impl<T> Drop for RefMut<'_, T> {
+impl<T, F: BorrowFlag> Drop for FlagRefMut<'_, T, F> {
     fn drop(&mut self) {
-        let state = self.state.get();
-        if let State::HasWriter = state {
-            self.state.set(State::Unused);
-        } else {
-            unreachable!("Cannot have a RefMut instance when the cell is not in the HasWriter state");
-        }
+        self.flag.release_exclusive();
+    }
+}
+
+impl<T, F: BorrowFlag> Borrow<T> for FlagRefMut<'_, T, F> {
+    fn borrow(&self) -> &T {
+        self
     }
 }
 
+impl<T: Debug, F: BorrowFlag> Debug for FlagRefMut<'_, T, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RefMut").field("value", &**self).finish()
+    }
+}
+
+impl<T, F: BorrowFlag> BorrowMut<T> for FlagRefMut<'_, T, F> {
+    fn borrow_mut(&mut self) -> &mut T {
+        self
+    }
+}
+
+impl<T, F: BorrowFlag> Deref for FlagRefMut<'_, T, F> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: The guard holds the exclusive borrow of the value.
+        unsafe { self.value.as_ref() }
+    }
+}
+
+impl<T, F: BorrowFlag> DerefMut for FlagRefMut<'_, T, F> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: As in `deref`, and the guard is borrowed mutably.
+        unsafe { self.value.as_mut() }
+    }
+}
+
+#[cfg(not(feature = "std-backend"))]
+pub type Ref<'cell, T> = FlagRef<'cell, T, Cell<State>>;
+
+#[cfg(not(feature = "std-backend"))]
+pub type RefMut<'cell, T> = FlagRefMut<'cell, T, Cell<State>>;
+
+// With the `std-backend` feature RefCell hands out wrappers of the std guards instead.
+
+#[cfg(feature = "std-backend")]
+pub struct Ref<'cell, T> {
+    inner: std::cell::Ref<'cell, T>,
+}
+
+#[cfg(feature = "std-backend")]
+impl<'cell, T> Ref<'cell, T> {
+    pub(crate) fn from_std(inner: std::cell::Ref<'cell, T>) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "std-backend")]
+impl<T> Borrow<T> for Ref<'_, T> {
+    fn borrow(&self) -> &T {
+        self
+    }
+}
+
+#[cfg(feature = "std-backend")]
+impl<T: Debug> Debug for Ref<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ref").field("value", &**self).finish()
+    }
+}
+
+#[cfg(feature = "std-backend")]
+impl<T: PartialEq> PartialEq for Ref<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        (**self).eq(&**other)
+    }
+}
+
+#[cfg(feature = "std-backend")]
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+#[cfg(feature = "std-backend")]
+pub struct RefMut<'cell, T> {
+    inner: std::cell::RefMut<'cell, T>,
+}
+
+#[cfg(feature = "std-backend")]
+impl<'cell, T> RefMut<'cell, T> {
+    pub(crate) fn from_std(inner: std::cell::RefMut<'cell, T>) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(feature = "std-backend")]
 impl<T> Borrow<T> for RefMut<'_, T> {
     fn borrow(&self) -> &T {
-        self.value
+        self
     }
 }
 
+#[cfg(feature = "std-backend")]
+impl<T: Debug> Debug for RefMut<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RefMut").field("value", &**self).finish()
+    }
+}
+
+#[cfg(feature = "std-backend")]
 impl<T> BorrowMut<T> for RefMut<'_, T> {
     fn borrow_mut(&mut self) -> &mut T {
-        self.value
+        self
     }
 }
 
+#[cfg(feature = "std-backend")]
 impl<T> Deref for RefMut<'_, T> {
     type Target = T;
 
     fn deref(&self) -> &Self::Target {
-        self.value
+        &self.inner
     }
 }
 
+#[cfg(feature = "std-backend")]
 impl<T> DerefMut for RefMut<'_, T> {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        self.value
+        &mut self.inner
     }
-}
\ No newline at end of file
+}
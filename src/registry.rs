@@ -0,0 +1,114 @@
+//! A lock-free list of records that threads check out and give back, the
+//! per-thread state of [`epoch`](crate::epoch) and the slots of
+//! [`hazard`](crate::hazard) pointers.
+//!
+//! Entries are leaked, never freed, so references to them stay valid forever
+//! and anyone can walk the list without locking.
+
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::ptr;
+use std::sync::atomic::Ordering;
+
+use crate::atomic::{AtomicBool, AtomicPtr};
+
+pub(crate) struct Registry<T: 'static> {
+    head: AtomicPtr<Entry<T>>,
+    // Entries are handed out to every thread by reference.
+    _entries: PhantomData<&'static Entry<T>>,
+}
+
+pub(crate) struct Entry<T> {
+    value: T,
+    in_use: AtomicBool,
+    // Set before the entry is published, never changed after.
+    next: *mut Entry<T>,
+}
+
+// SAFETY: Threads only share entries by reference, `next` is never written
+// once other threads can see it.
+unsafe impl<T: Sync> Sync for Entry<T> {}
+
+impl<T: 'static> Registry<T> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            _entries: PhantomData,
+        }
+    }
+
+    /// Every entry ever published, whether in use or not.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &'static Entry<T>> {
+        let mut next = self.head.load_acquire();
+        std::iter::from_fn(move || {
+            // SAFETY: Entries are leaked, never freed.
+            let entry = unsafe { next.as_ref()? };
+            next = entry.next;
+            Some(entry)
+        })
+    }
+
+    /// Checks out an entry nobody uses, or publishes a new one made by `init`.
+    pub(crate) fn acquire(&self, init: impl FnOnce() -> T) -> &'static Entry<T> {
+        for entry in self.iter() {
+            if entry.in_use.compare_exchange_acquire(false, true).is_ok() {
+                return entry;
+            }
+        }
+        let entry = Box::leak(Box::new(Entry {
+            value: init(),
+            in_use: AtomicBool::new(true),
+            next: ptr::null_mut(),
+        }));
+        let mut head = self.head.load_relaxed();
+        loop {
+            entry.next = head;
+            // Release publishes the entry's fields to the threads iterating.
+            match self
+                .head
+                .compare_exchange_weak(head, entry, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => return entry,
+                Err(actual) => head = actual,
+            }
+        }
+    }
+}
+
+impl<T> Entry<T> {
+    /// Gives the entry back for the next `acquire` to reuse.
+    pub(crate) fn release(&self) {
+        self.in_use.store_release(false);
+    }
+}
+
+impl<T> Deref for Entry<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Registry;
+    use crate::atomic::AtomicUsize;
+
+    #[test]
+    fn test_released_entries_are_reused() {
+        static REGISTRY: Registry<AtomicUsize> = Registry::new();
+
+        let first = REGISTRY.acquire(|| AtomicUsize::new(1));
+        let second = REGISTRY.acquire(|| AtomicUsize::new(2));
+        assert_eq!(REGISTRY.iter().count(), 2);
+
+        first.release();
+        let reused = REGISTRY.acquire(|| unreachable!());
+        assert!(std::ptr::eq(reused, first));
+        assert_eq!(reused.load_relaxed(), 1);
+        second.release();
+        reused.release();
+        assert_eq!(REGISTRY.iter().count(), 2);
+    }
+}
@@ -0,0 +1,128 @@
+//! The "build, then freeze" pattern as types.
+//!
+//! A `RwCell` is mutated through `&mut` while the value is being built, `freeze`
+//! turns it into a `RoCell` that can only be read, so it can be shared freely
+//! without any guards or flags. Both are plain wrappers, all checks happen at
+//! compile time.
+
+use std::fmt::{self, Debug};
+use std::ops::Deref;
+
+/// The mutable, exclusively owned state.
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct RwCell<T> {
+    value: T,
+}
+
+impl<T> RwCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+
+    pub fn set(&mut self, value: T) {
+        self.value = value;
+    }
+
+    /// Builder-style mutation, `RwCell::new(..).update(..).freeze()`.
+    pub fn update<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut T),
+    {
+        f(&mut self.value);
+        self
+    }
+
+    /// Ends the mutable phase.
+    pub fn freeze(self) -> RoCell<T> {
+        RoCell { value: self.value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T: Debug> Debug for RwCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RwCell").field(&self.value).finish()
+    }
+}
+
+/// The frozen, read-only state.
+#[derive(Clone, PartialEq, Eq)]
+pub struct RoCell<T> {
+    value: T,
+}
+
+impl<T> RoCell<T> {
+    pub fn get(&self) -> &T {
+        &self.value
+    }
+
+    /// Back to the mutable state, which needs ownership so no readers are left.
+    pub fn thaw(self) -> RwCell<T> {
+        RwCell { value: self.value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+}
+
+impl<T> Deref for RoCell<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Debug> Debug for RoCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RoCell").field(&self.value).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::thread;
+
+    #[test]
+    fn test_build_then_freeze() {
+        let mut config = RwCell::new(HashMap::new());
+        config.get_mut().insert("threads", 4);
+        let config = config
+            .update(|config| {
+                config.insert("retries", 3);
+            })
+            .freeze();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| assert_eq!(config["threads"], 4));
+            }
+        });
+
+        assert_eq!(config.get().len(), 2);
+    }
+
+    #[test]
+    fn test_thaw() {
+        let frozen = RwCell::new(1).freeze();
+        let mut cell = frozen.thaw();
+
+        cell.set(2);
+
+        assert_eq!(cell.freeze().into_inner(), 2);
+    }
+}
@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::mem::{self, ManuallyDrop};
+use std::thread::{self, ThreadId};
+
+/// Makes any `T` `Send` by remembering the thread that created it and panicking
+/// when the value is used or dropped anywhere else.
+///
+/// This allows passing e.g. GUI objects through other threads as long as they
+/// come back home before anybody looks inside.
+pub struct SendCell<T> {
+    value: ManuallyDrop<T>,
+    owner: ThreadId,
+}
+
+// SAFETY: The value is only ever accessed and dropped on the owner thread, moving
+// the cell (or sharing a reference to it) never lets another thread touch it.
+unsafe impl<T> Send for SendCell<T> {}
+unsafe impl<T> Sync for SendCell<T> {}
+
+/// Returned when a `SendCell` is accessed from a thread that doesn't own it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidThreadAccess;
+
+impl Display for InvalidThreadAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendCell value accessed from a different thread")
+    }
+}
+
+impl Error for InvalidThreadAccess {}
+
+impl<T> SendCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: ManuallyDrop::new(value),
+            owner: thread::current().id(),
+        }
+    }
+
+    /// Whether the current thread may access the value.
+    pub fn is_valid(&self) -> bool {
+        thread::current().id() == self.owner
+    }
+
+    #[track_caller]
+    pub fn get(&self) -> &T {
+        self.try_get().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    #[track_caller]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.try_get_mut().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    pub fn try_get(&self) -> Result<&T, InvalidThreadAccess> {
+        if self.is_valid() {
+            Ok(&self.value)
+        } else {
+            Err(InvalidThreadAccess)
+        }
+    }
+
+    pub fn try_get_mut(&mut self) -> Result<&mut T, InvalidThreadAccess> {
+        if self.is_valid() {
+            Ok(&mut self.value)
+        } else {
+            Err(InvalidThreadAccess)
+        }
+    }
+
+    #[track_caller]
+    pub fn into_inner(self) -> T {
+        self.try_into_inner()
+            .unwrap_or_else(|_| panic!("{}", InvalidThreadAccess))
+    }
+
+    /// Hands the cell back if called on the wrong thread.
+    pub fn try_into_inner(self) -> Result<T, Self> {
+        if !self.is_valid() {
+            return Err(self);
+        }
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never used or dropped again.
+        Ok(unsafe { ManuallyDrop::take(&mut this.value) })
+    }
+}
+
+impl<T> Drop for SendCell<T> {
+    fn drop(&mut self) {
+        if !mem::needs_drop::<T>() {
+            return;
+        }
+        if self.is_valid() {
+            // SAFETY: The value is dropped exactly once, here.
+            unsafe { ManuallyDrop::drop(&mut self.value) };
+        } else if !thread::panicking() {
+            panic!("SendCell value dropped on a different thread");
+        }
+        // Otherwise leak, panicking while unwinding would abort.
+    }
+}
+
+impl<T: Debug> Debug for SendCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_get() {
+            Ok(value) => f.debug_tuple("SendCell").field(value).finish(),
+            Err(_) => f.write_str("SendCell(<other thread>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ref_cell::RefCell;
+    use std::rc::Rc;
+
+    fn assert_send<T: Send>() {}
+
+    #[test]
+    fn test_makes_non_send_types_send() {
+        assert_not_impl!(Rc<RefCell<i32>>: Send);
+        assert_send::<SendCell<Rc<RefCell<i32>>>>();
+    }
+
+    #[test]
+    fn test_round_trip_through_other_thread() {
+        let cell = SendCell::new(Rc::new(RefCell::new(1)));
+
+        let cell = thread::spawn(move || {
+            assert!(!cell.is_valid());
+            assert_eq!(cell.try_get().err(), Some(InvalidThreadAccess));
+            assert_eq!(format!("{:?}", cell), "SendCell(<other thread>)");
+            cell
+        })
+        .join()
+        .unwrap();
+
+        *cell.get().borrow_mut() += 1;
+        assert_eq!(*cell.into_inner().borrow(), 2);
+    }
+
+    #[test]
+    fn test_access_from_other_thread_panics() {
+        let cell = SendCell::new(Rc::new(1));
+
+        let cell = thread::spawn(move || {
+            let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| **cell.get()));
+            assert!(panicked.is_err());
+            match cell.try_into_inner() {
+                Ok(_) => panic!("moved out on the wrong thread"),
+                Err(cell) => cell,
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(*cell.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_drop_on_other_thread_panics() {
+        let cell = SendCell::new(Rc::new(1));
+
+        let message = thread::spawn(move || drop(cell)).join().unwrap_err();
+
+        assert_eq!(
+            message.downcast_ref::<&str>(),
+            Some(&"SendCell value dropped on a different thread")
+        );
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt::{self, Debug};
+use std::ptr;
+use std::sync::atomic::Ordering;
+use std::thread::{self, ThreadId};
+
+use crate::atomic::AtomicPtr;
+
+/// Gives every thread its own `T`, e.g. a `Cell<u64>` counter, so threads never
+/// contend while accumulating. The shards are combined with `fold` once the
+/// threads are done.
+///
+/// Shards live in a push-only list, a thread walks it to find its own shard and
+/// only touches the list head when it uses the cell for the first time.
+pub struct ShardedCell<T> {
+    head: AtomicPtr<Shard<T>>,
+    init: fn() -> T,
+}
+
+struct Shard<T> {
+    owner: ThreadId,
+    value: T,
+    next: *mut Shard<T>,
+}
+
+// SAFETY: A shard is only ever handed out to the thread that created it, so `T`
+// doesn't need to be Sync. It may be created on one thread and folded or dropped
+// on another, hence `T: Send`.
+unsafe impl<T: Send> Sync for ShardedCell<T> {}
+unsafe impl<T: Send> Send for ShardedCell<T> {}
+
+impl<T: Default> ShardedCell<T> {
+    pub const fn new() -> Self {
+        Self::with_init(T::default)
+    }
+}
+
+impl<T: Default> Default for ShardedCell<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ShardedCell<T> {
+    /// Creates a cell whose shards start out as `init()`.
+    pub const fn with_init(init: fn() -> T) -> Self {
+        Self {
+            head: AtomicPtr::new(ptr::null_mut()),
+            init,
+        }
+    }
+
+    /// Runs `f` with the current thread's shard, creating it if needed.
+    pub fn with_local<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let owner = thread::current().id();
+        let mut head = self.head.load_acquire();
+        let mut shard = head;
+        while !shard.is_null() {
+            // SAFETY: Shards are only freed when the cell is dropped.
+            let current = unsafe { &*shard };
+            if current.owner == owner {
+                return f(&current.value);
+            }
+            shard = current.next;
+        }
+
+        let shard = Box::into_raw(Box::new(Shard {
+            owner,
+            value: (self.init)(),
+            next: head,
+        }));
+        // Other threads only ever prepend their own shards, so ours can't be among them.
+        while let Err(actual) =
+            self.head
+                .compare_exchange_weak(head, shard, Ordering::AcqRel, Ordering::Acquire)
+        {
+            head = actual;
+            // SAFETY: We haven't published the shard yet.
+            unsafe { (*shard).next = head };
+        }
+        // SAFETY: The shard is published and lives as long as the cell.
+        f(unsafe { &(*shard).value })
+    }
+
+    /// Combines all shards, the exclusive borrow guarantees no thread is using them.
+    pub fn fold<B, F>(&mut self, init: B, mut f: F) -> B
+    where
+        F: FnMut(B, &mut T) -> B,
+    {
+        let mut acc = init;
+        let mut shard = *self.head.get_mut();
+        while !shard.is_null() {
+            // SAFETY: We have exclusive access to the whole list.
+            let current = unsafe { &mut *shard };
+            acc = f(acc, &mut current.value);
+            shard = current.next;
+        }
+        acc
+    }
+
+    /// Drops all shards, threads will start over from `init()`.
+    pub fn clear(&mut self) {
+        let mut shard = std::mem::replace(self.head.get_mut(), ptr::null_mut());
+        while !shard.is_null() {
+            // SAFETY: The shard came from Box::into_raw and was just unlinked.
+            let current = unsafe { Box::from_raw(shard) };
+            shard = current.next;
+        }
+    }
+}
+
+impl<T> Drop for ShardedCell<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T: Debug> Debug for ShardedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Other threads' shards may be in use, so only show ours.
+        self.with_local(|value| f.debug_struct("ShardedCell").field("local", value).finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cell::Cell;
+    use crate::ref_cell::RefCell;
+
+    #[test]
+    fn test_counts_per_thread() {
+        let mut counter = ShardedCell::with_init(|| Cell::new(0u64));
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        counter.with_local(|count| count.set(count.get() + 1));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(counter.fold(0, |sum, count| sum + count.get()), 8000);
+        assert_eq!(counter.fold(0, |shards, _| shards + 1), 8);
+    }
+
+    #[test]
+    fn test_shard_is_reused_on_same_thread() {
+        let mut log = ShardedCell::with_init(|| RefCell::new(vec![0]));
+
+        log.with_local(|log| log.borrow_mut().push(1));
+        log.with_local(|log| log.borrow_mut().push(2));
+
+        let all = log.fold(Vec::new(), |mut all, log| {
+            all.extend(log.borrow_mut().drain(..));
+            all
+        });
+        assert_eq!(all, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_clear_and_drop_free_shards() {
+        let rc = std::rc::Rc::new(());
+        let mut cell = ShardedCell::with_init(|| Cell::new(None));
+        cell.with_local(|slot| slot.set(Some(rc.clone())));
+
+        cell.clear();
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+
+        cell.with_local(|slot| slot.set(Some(rc.clone())));
+        drop(cell);
+        assert_eq!(std::rc::Rc::strong_count(&rc), 1);
+    }
+}
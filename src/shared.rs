@@ -0,0 +1,267 @@
+use std::fmt::{self, Debug};
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+use crate::rc::{Rc, Weak};
+use crate::ref_cell::RefCell;
+use crate::refs::{Ref, RefMut};
+
+/// Shared mutable state, the `Rc<RefCell<T>>` every program ends up wrapping.
+///
+/// Clones are cheap handles to the same value. Borrows are checked at runtime
+/// like with `RefCell`, `with` and `with_mut` keep them short.
+pub struct Shared<T> {
+    inner: Rc<RefCell<T>>,
+}
+
+impl<T> Shared<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(value)),
+        }
+    }
+
+    #[track_caller]
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> R {
+        f(&self.inner.borrow())
+    }
+
+    #[track_caller]
+    pub fn with_mut<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        f(&mut self.inner.borrow_mut())
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.inner.borrow()
+    }
+
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.inner.borrow_mut()
+    }
+
+    /// Moves the value out if this is the only handle.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        match Rc::try_unwrap(this.inner) {
+            Ok(cell) => Ok(cell.into_inner()),
+            Err(inner) => Err(Self { inner }),
+        }
+    }
+
+    pub fn downgrade(this: &Self) -> WeakShared<T> {
+        WeakShared {
+            inner: Rc::downgrade(&this.inner),
+        }
+    }
+
+    /// Whether both are handles to the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rc::ptr_eq(&this.inner, &other.inner)
+    }
+}
+
+impl<T: 'static> Shared<T> {
+    /// A shared borrow that keeps its own handle, so it can outlive `self`.
+    #[track_caller]
+    pub fn borrow_owned(&self) -> OwnedRef<T> {
+        let inner = self.inner.clone();
+        // SAFETY: The guard only borrows the RefCell, which `inner` keeps alive
+        // and which the guard releases before dropping `inner`.
+        let guard = unsafe { mem::transmute::<Ref<'_, T>, Ref<'static, T>>(inner.borrow()) };
+        OwnedRef {
+            guard,
+            _inner: inner,
+        }
+    }
+
+    /// An exclusive borrow that keeps its own handle, so it can outlive `self`.
+    #[track_caller]
+    pub fn borrow_mut_owned(&self) -> OwnedRefMut<T> {
+        let inner = self.inner.clone();
+        // SAFETY: As in borrow_owned.
+        let guard =
+            unsafe { mem::transmute::<RefMut<'_, T>, RefMut<'static, T>>(inner.borrow_mut()) };
+        OwnedRefMut {
+            guard,
+            _inner: inner,
+        }
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T: Default> Default for Shared<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Shared<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for Shared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.inner.try_borrow() {
+            Ok(value) => f.debug_tuple("Shared").field(&*value).finish(),
+            Err(_) => f.write_str("Shared(<borrowed>)"),
+        }
+    }
+}
+
+/// A handle that doesn't keep the value alive, from `Shared::downgrade`.
+pub struct WeakShared<T> {
+    inner: Weak<RefCell<T>>,
+}
+
+impl<T> WeakShared<T> {
+    pub fn new() -> Self {
+        Self { inner: Weak::new() }
+    }
+
+    pub fn upgrade(&self) -> Option<Shared<T>> {
+        self.inner.upgrade().map(|inner| Shared { inner })
+    }
+}
+
+impl<T> Clone for WeakShared<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<T> Default for WeakShared<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Debug for WeakShared<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(WeakShared)")
+    }
+}
+
+/// A shared borrow from `Shared::borrow_owned`.
+pub struct OwnedRef<T: 'static> {
+    // Declared first, the borrow must end before the handle is dropped.
+    guard: Ref<'static, T>,
+    _inner: Rc<RefCell<T>>,
+}
+
+impl<T: 'static> Deref for OwnedRef<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: Debug + 'static> Debug for OwnedRef<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+/// An exclusive borrow from `Shared::borrow_mut_owned`.
+pub struct OwnedRefMut<T: 'static> {
+    // Declared first, the borrow must end before the handle is dropped.
+    guard: RefMut<'static, T>,
+    _inner: Rc<RefCell<T>>,
+}
+
+impl<T: 'static> Deref for OwnedRefMut<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T: 'static> DerefMut for OwnedRefMut<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T: Debug + 'static> Debug for OwnedRefMut<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Shared, WeakShared};
+
+    #[test]
+    fn test_handles_share_value() {
+        let counter = Shared::new(0);
+        let handle = counter.clone();
+
+        handle.with_mut(|n| *n += 1);
+        *counter.borrow_mut() += 1;
+
+        assert_eq!(counter.with(|n| *n), 2);
+        assert!(Shared::ptr_eq(&counter, &handle));
+        assert_eq!(format!("{:?}", counter), "Shared(2)");
+    }
+
+    #[test]
+    fn test_owned_guards_outlive_handle() {
+        let log = Shared::new(vec![1]);
+
+        let mut writer = log.borrow_mut_owned();
+        drop(log.clone());
+        writer.push(2);
+        assert_eq!(format!("{:?}", log), "Shared(<borrowed>)");
+        drop(writer);
+
+        let reader = log.borrow_owned();
+        drop(log);
+        assert_eq!(*reader, [1, 2]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_owned_guard_still_checks_borrows() {
+        let shared = Shared::new(0);
+        let _writer = shared.borrow_mut_owned();
+
+        shared.with(|_| {});
+    }
+
+    #[test]
+    fn test_try_unwrap() {
+        let shared = Shared::new(String::from("x"));
+        let handle = shared.clone();
+
+        let shared = Shared::try_unwrap(shared).unwrap_err();
+        drop(handle);
+
+        assert_eq!(Shared::try_unwrap(shared).unwrap(), "x");
+    }
+
+    #[test]
+    fn test_weak_handle() {
+        let shared = Shared::new(1);
+        let weak = Shared::downgrade(&shared);
+
+        assert_eq!(weak.upgrade().unwrap().with(|n| *n), 1);
+        drop(shared);
+
+        assert!(weak.upgrade().is_none());
+        assert!(WeakShared::<i32>::default().upgrade().is_none());
+    }
+}
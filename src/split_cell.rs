@@ -0,0 +1,118 @@
+//! Borrowing the fields of one struct independently.
+//!
+//! A struct behind a single `RefCell` can't have one field read while another is
+//! mutated. `#[derive(SplitBorrow)]` generates a `FooFields` struct that gives
+//! every field its own borrow flag, with `field()`/`field_mut()` methods that hand
+//! out per-field guards. `SplitCell` keeps a struct in that form.
+//!
+//! ```ignore
+//! #[derive(SplitBorrow)]
+//! struct Game {
+//!     players: Vec<Player>,
+//!     log: Vec<String>,
+//! }
+//!
+//! let game = SplitCell::new(Game { players, log: Vec::new() });
+//! for player in game.players().iter() {
+//!     game.log_mut().push(player.name.clone());
+//! }
+//! ```
+
+use std::fmt::{self, Debug};
+use std::ops::{Deref, DerefMut};
+
+pub use cell_fields_derive::SplitBorrow;
+
+/// Implemented by `#[derive(SplitBorrow)]`.
+pub trait SplitBorrow: Sized {
+    type Fields;
+
+    fn split(value: Self) -> Self::Fields;
+
+    fn join(fields: Self::Fields) -> Self;
+}
+
+/// A struct stored field by field, each behind its own borrow flag.
+pub struct SplitCell<T: SplitBorrow> {
+    fields: T::Fields,
+}
+
+impl<T: SplitBorrow> SplitCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            fields: T::split(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        T::join(self.fields)
+    }
+}
+
+impl<T: SplitBorrow> Deref for SplitCell<T> {
+    type Target = T::Fields;
+
+    fn deref(&self) -> &T::Fields {
+        &self.fields
+    }
+}
+
+impl<T: SplitBorrow> DerefMut for SplitCell<T> {
+    fn deref_mut(&mut self) -> &mut T::Fields {
+        &mut self.fields
+    }
+}
+
+impl<T: SplitBorrow> Debug for SplitCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplitCell").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[derive(SplitBorrow, Debug, PartialEq)]
+    struct Game {
+        players: Vec<&'static str>,
+        scores: HashMap<&'static str, u32>,
+        pub log: Vec<String>,
+    }
+
+    fn game() -> Game {
+        Game {
+            players: vec!["ann", "bob"],
+            scores: HashMap::new(),
+            log: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_fields_borrow_independently() {
+        let game = SplitCell::new(game());
+
+        for player in game.players().iter() {
+            *game.scores_mut().entry(player).or_default() += 1;
+            game.log_mut().push(format!("{} scored", player));
+        }
+
+        let game = game.into_inner();
+        assert_eq!(game.scores["ann"], 1);
+        assert_eq!(game.log, vec!["ann scored", "bob scored"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value borrowed mutably, can't borrow")]
+    fn test_same_field_still_checked() {
+        let game = SplitCell::new(game());
+        let _log = game.log_mut();
+        game.log();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(SplitCell::new(game()).into_inner(), game());
+    }
+}
@@ -0,0 +1,314 @@
+//! Software transactional memory over `TVar`s.
+//!
+//! `atomically` runs a closure against a transaction log: writes are buffered and
+//! reads remember the version of the variable they saw. On commit the log is
+//! validated and either applied in one go or, if another transaction committed a
+//! conflicting change in the meantime, thrown away and the closure run again.
+//!
+//! Versions come from a global clock (the TL2 scheme): a transaction notes the
+//! clock when it starts and refuses to read anything committed after that, so it
+//! never observes a mix of old and new values. Commits are serialized by a lock.
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+static CLOCK: AtomicU64 = AtomicU64::new(0);
+static COMMIT_LOCK: Mutex<()> = Mutex::new(());
+
+/// A variable that can only be changed inside a transaction.
+pub struct TVar<T> {
+    // The version of the transaction that wrote the value.
+    slot: Mutex<(u64, T)>,
+}
+
+impl<T: Clone + Send + Sync + 'static> TVar<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            slot: Mutex::new((0, value)),
+        }
+    }
+
+    /// Reads the latest committed value outside of any transaction.
+    pub fn load(&self) -> T {
+        self.read_versioned().1
+    }
+
+    pub fn into_inner(self) -> T {
+        self.slot.into_inner().unwrap_or_else(|e| e.into_inner()).1
+    }
+
+    fn read_versioned(&self) -> (u64, T) {
+        // Values are only replaced wholesale, a poisoned lock can't hold a torn one.
+        let slot = self.slot.lock().unwrap_or_else(|e| e.into_inner());
+        (slot.0, slot.1.clone())
+    }
+}
+
+impl<T: Clone + Send + Sync + Debug + 'static> Debug for TVar<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TVar").field("value", &self.load()).finish()
+    }
+}
+
+/// The type-erased part of a `TVar` a transaction needs to validate and commit.
+trait AnyTVar: Sync {
+    fn version(&self) -> u64;
+    fn commit(&self, value: Box<dyn Any + Send>, version: u64);
+}
+
+impl<T: Clone + Send + Sync + 'static> AnyTVar for TVar<T> {
+    fn version(&self) -> u64 {
+        self.slot.lock().unwrap_or_else(|e| e.into_inner()).0
+    }
+
+    fn commit(&self, value: Box<dyn Any + Send>, version: u64) {
+        let value = *value
+            .downcast::<T>()
+            .expect("TVar writes are keyed by the TVar itself");
+        *self.slot.lock().unwrap_or_else(|e| e.into_inner()) = (version, value);
+    }
+}
+
+/// Returned from `Transaction::read` when the transaction can't succeed anymore.
+/// Propagate it with `?`, `atomically` will run the transaction again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Conflict;
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Transaction conflicted with a concurrent commit")
+    }
+}
+
+impl Error for Conflict {}
+
+pub type StmResult<T> = Result<T, Conflict>;
+
+/// The log of a running transaction.
+pub struct Transaction<'a> {
+    start: u64,
+    reads: Vec<(&'a dyn AnyTVar, u64)>,
+    writes: Vec<(&'a dyn AnyTVar, Box<dyn Any + Send>)>,
+    conflicted: bool,
+}
+
+impl<'a> Transaction<'a> {
+    fn new() -> Self {
+        Self {
+            start: CLOCK.load(Ordering::Acquire),
+            reads: Vec::new(),
+            writes: Vec::new(),
+            conflicted: false,
+        }
+    }
+
+    /// Reads the variable, seeing this transaction's own writes.
+    pub fn read<T: Clone + Send + Sync + 'static>(&mut self, var: &'a TVar<T>) -> StmResult<T> {
+        if let Some((_, value)) = self
+            .writes
+            .iter()
+            .find(|(written, _)| Self::same(*written, var))
+        {
+            return Ok(value.downcast_ref::<T>().unwrap().clone());
+        }
+        let (version, value) = var.read_versioned();
+        if version > self.start {
+            self.conflicted = true;
+            return Err(Conflict);
+        }
+        self.reads.push((var, version));
+        Ok(value)
+    }
+
+    /// Buffers a write, it becomes visible to others once the transaction commits.
+    pub fn write<T: Clone + Send + Sync + 'static>(&mut self, var: &'a TVar<T>, value: T) {
+        match self
+            .writes
+            .iter_mut()
+            .find(|(written, _)| Self::same(*written, var))
+        {
+            Some((_, slot)) => *slot = Box::new(value),
+            None => self.writes.push((var, Box::new(value))),
+        }
+    }
+
+    pub fn modify<T, F>(&mut self, var: &'a TVar<T>, f: F) -> StmResult<()>
+    where
+        T: Clone + Send + Sync + 'static,
+        F: FnOnce(T) -> T,
+    {
+        let value = self.read(var)?;
+        self.write(var, f(value));
+        Ok(())
+    }
+
+    fn same<T>(a: &dyn AnyTVar, b: &TVar<T>) -> bool {
+        a as *const dyn AnyTVar as *const () == b as *const TVar<T> as *const ()
+    }
+
+    fn commit(self) -> bool {
+        let _lock = COMMIT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        if self
+            .reads
+            .iter()
+            .any(|(var, version)| var.version() != *version)
+        {
+            return false;
+        }
+        if self.writes.is_empty() {
+            return true;
+        }
+        let version = CLOCK.load(Ordering::Relaxed) + 1;
+        for (var, value) in self.writes {
+            var.commit(value, version);
+        }
+        // Published only after all writes, so a transaction that starts at this
+        // version sees every one of them.
+        CLOCK.store(version, Ordering::Release);
+        true
+    }
+}
+
+impl Debug for Transaction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("start", &self.start)
+            .field("reads", &self.reads.len())
+            .field("writes", &self.writes.len())
+            .finish()
+    }
+}
+
+/// Runs `f` as a transaction, retrying until it commits without conflicts.
+pub fn atomically<'a, R, F>(mut f: F) -> R
+where
+    F: FnMut(&mut Transaction<'a>) -> StmResult<R>,
+{
+    loop {
+        // A Conflict that `f` made up itself is treated as a request to retry.
+        if let Ok(result) = try_atomically(&mut f) {
+            return result;
+        }
+    }
+}
+
+/// Like `atomically`, but `f` may fail with its own error, which rolls the
+/// transaction back. Panics roll it back as well since nothing was written yet.
+pub fn try_atomically<'a, R, E, F>(mut f: F) -> Result<R, E>
+where
+    F: FnMut(&mut Transaction<'a>) -> Result<R, E>,
+{
+    loop {
+        let mut tx = Transaction::new();
+        match f(&mut tx) {
+            Ok(result) => {
+                if tx.commit() {
+                    return Ok(result);
+                }
+            }
+            Err(err) if !tx.conflicted => return Err(err),
+            Err(_) => {}
+        }
+        thread::yield_now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reads_see_own_writes() {
+        let var = TVar::new(1);
+
+        let seen = atomically(|tx| {
+            tx.write(&var, 2);
+            tx.modify(&var, |value| value * 10)?;
+            tx.read(&var)
+        });
+
+        assert_eq!(seen, 20);
+        assert_eq!(var.load(), 20);
+    }
+
+    #[test]
+    fn test_error_rolls_back() {
+        let var = TVar::new(String::from("before"));
+
+        let result: Result<(), &str> = try_atomically(|tx| {
+            tx.write(&var, String::from("after"));
+            Err("nope")
+        });
+
+        assert_eq!(result, Err("nope"));
+        assert_eq!(var.load(), "before");
+    }
+
+    #[test]
+    fn test_panic_rolls_back() {
+        let var = TVar::new(1);
+
+        let panicked = std::panic::catch_unwind(|| {
+            atomically(|tx| {
+                tx.write(&var, 2);
+                if tx.read(&var)? == 2 {
+                    panic!("boom");
+                }
+                Ok(())
+            })
+        });
+
+        assert!(panicked.is_err());
+        assert_eq!(var.load(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_transfers_preserve_total() {
+        let accounts: Vec<TVar<i64>> = (0..4).map(|_| TVar::new(1000)).collect();
+
+        thread::scope(|s| {
+            for t in 0..8 {
+                let accounts = &accounts;
+                s.spawn(move || {
+                    for i in 0..200 {
+                        let from = &accounts[(t + i) % 4];
+                        let to = &accounts[(t + i + 1) % 4];
+                        atomically(|tx| {
+                            tx.modify(from, |balance| balance - 7)?;
+                            tx.modify(to, |balance| balance + 7)
+                        });
+                    }
+                });
+            }
+        });
+
+        let total: i64 = accounts.iter().map(TVar::load).sum();
+        assert_eq!(total, 4000);
+    }
+
+    #[test]
+    fn test_concurrent_readers_see_consistent_snapshots() {
+        let (a, b) = (TVar::new(0), TVar::new(0));
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    atomically(|tx| {
+                        tx.modify(&a, |x| x + 1)?;
+                        tx.modify(&b, |x| x + 1)
+                    });
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    let (x, y) = atomically(|tx| Ok((tx.read(&a)?, tx.read(&b)?)));
+                    assert_eq!(x, y);
+                }
+            });
+        });
+    }
+}
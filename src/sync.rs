@@ -0,0 +1,47 @@
+//! Blocking synchronization primitives built on atomics, with threads parking
+//! while they wait. Only `SpinLock` waits by spinning instead.
+
+mod barrier;
+mod condvar;
+pub mod mpmc;
+pub mod mpsc;
+mod mutex;
+mod once;
+mod parking_lot;
+mod poison;
+mod reentrant_mutex;
+mod rw_lock;
+mod semaphore;
+mod seq_lock;
+mod spin_lock;
+mod wait_group;
+mod wait_queue;
+mod word_mutex;
+
+pub use crate::arc::{Arc, Weak};
+
+pub use self::barrier::{Barrier, BarrierWaitResult};
+pub use self::condvar::{Condvar, WaitTimeoutResult};
+pub use self::mutex::{Mutex, MutexGuard, RawMutex};
+pub use self::once::{Once, OnceState};
+pub(crate) use self::poison::ignore_poison;
+pub use self::poison::{LockResult, PoisonError, TryLockError, TryLockResult};
+pub use self::reentrant_mutex::{
+    RawReentrantMutex, ReentrantMutex, ReentrantMutexGuard, ReentrantRefCell,
+};
+pub use self::rw_lock::{
+    Fairness, RawRwLock, RwLock, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard,
+};
+pub use self::semaphore::{Semaphore, SemaphorePermit};
+pub use self::seq_lock::SeqLock;
+pub use self::spin_lock::{RawSpinLock, SpinLock, SpinLockGuard};
+pub use self::wait_group::WaitGroup;
+pub use self::word_mutex::{RawWordMutex, WordMutex, WordMutexGuard};
+
+/// [`WordMutex`] under the names of [`Mutex`], to pick the compact lock by
+/// changing an import. Its `lock` returns the guard without a `LockResult`.
+pub mod compact {
+    pub use super::word_mutex::{
+        RawWordMutex as RawMutex, WordMutex as Mutex, WordMutexGuard as MutexGuard,
+    };
+}
@@ -0,0 +1,134 @@
+use std::fmt::{self, Debug};
+
+use crate::sync::{ignore_poison, Condvar, Mutex};
+
+/// Lets a fixed number of threads wait for each other, for example for all
+/// workers to be set up before any of them starts. It can be reused, the next
+/// `n` calls to `wait` form the next round.
+pub struct Barrier {
+    n: usize,
+    state: Mutex<BarrierState>,
+    all_arrived: Condvar,
+}
+
+struct BarrierState {
+    arrived: usize,
+    // Counts finished rounds, a woken thread knows it's its round that finished
+    // and not a spurious wake-up.
+    round: usize,
+}
+
+/// Returned by [`Barrier::wait`].
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult {
+    /// True for exactly one thread of every round, the last one to arrive.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
+
+impl Debug for BarrierWaitResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BarrierWaitResult")
+            .field("is_leader", &self.is_leader())
+            .finish()
+    }
+}
+
+impl Barrier {
+    /// A barrier for `n` threads. With `n` of 0 or 1, `wait` never blocks.
+    pub const fn new(n: usize) -> Self {
+        Self {
+            n,
+            state: Mutex::new(BarrierState {
+                arrived: 0,
+                round: 0,
+            }),
+            all_arrived: Condvar::new(),
+        }
+    }
+
+    /// Blocks until `n` threads are waiting, then releases all of them.
+    pub fn wait(&self) -> BarrierWaitResult {
+        let mut state = ignore_poison(self.state.lock());
+        state.arrived += 1;
+        if state.arrived < self.n {
+            let round = state.round;
+            let _state = ignore_poison(
+                self.all_arrived
+                    .wait_while(state, |state| state.round == round),
+            );
+            BarrierWaitResult(false)
+        } else {
+            state.arrived = 0;
+            state.round = state.round.wrapping_add(1);
+            self.all_arrived.notify_all();
+            BarrierWaitResult(true)
+        }
+    }
+}
+
+impl Debug for Barrier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Barrier").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Barrier;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_threads_wait_for_each_other() {
+        let barrier = Barrier::new(8);
+        let arrived = AtomicUsize::new(0);
+
+        let leaders = thread::scope(|s| {
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    s.spawn(|| {
+                        arrived.fetch_add(1, Ordering::Relaxed);
+                        let result = barrier.wait();
+                        assert_eq!(arrived.load(Ordering::Relaxed), 8);
+                        result.is_leader()
+                    })
+                })
+                .collect();
+            threads
+                .into_iter()
+                .map(|t| t.join().unwrap())
+                .filter(|&leader| leader)
+                .count()
+        });
+
+        assert_eq!(leaders, 1);
+    }
+
+    #[test]
+    fn test_reusable() {
+        let barrier = Barrier::new(4);
+        let counter = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for round in 0..10 {
+                        counter.fetch_add(1, Ordering::Relaxed);
+                        barrier.wait();
+                        assert_eq!(counter.load(Ordering::Relaxed), (round + 1) * 4);
+                        barrier.wait();
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_single_thread_is_leader() {
+        assert!(Barrier::new(1).wait().is_leader());
+        assert!(Barrier::new(0).wait().is_leader());
+    }
+}
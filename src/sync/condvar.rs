@@ -0,0 +1,245 @@
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::sync::mutex::{self, MutexGuard};
+use crate::sync::poison::{self, LockResult};
+use crate::sync::wait_queue::WaitQueue;
+
+/// Lets threads sleep until another thread changes the value behind a
+/// [`Mutex`](super::Mutex) and notifies them.
+///
+/// A waiter can wake up without a notification, and another thread may change
+/// the value again before the waiter relocks the mutex, so it always checks its
+/// condition in a loop, or lets `wait_while` do it:
+///
+/// ```ignore
+/// let mut queue = condvar.wait_while(mutex.lock().unwrap(), |queue| queue.is_empty()).unwrap();
+/// let job = queue.pop_front().unwrap();
+/// ```
+pub struct Condvar {
+    // Bumped by every notification, a waiter only sleeps if it hasn't changed
+    // since it released the mutex, so notifications in between aren't lost.
+    notifications: AtomicU32,
+    waiters: WaitQueue,
+}
+
+/// Whether a timed wait returned because the timeout passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WaitTimeoutResult(bool);
+
+impl WaitTimeoutResult {
+    pub fn timed_out(&self) -> bool {
+        self.0
+    }
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            notifications: AtomicU32::new(0),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Releases the mutex, sleeps until notified (or spuriously), then locks it
+    /// again.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        let notifications = self.notifications.load(Ordering::Relaxed);
+        poison::map_result(
+            mutex::unlocked(guard, || {
+                self.waiters.wait(&self.notifications, notifications)
+            }),
+            |(guard, ())| guard,
+        )
+    }
+
+    /// Waits for as long as `condition` holds.
+    pub fn wait_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> LockResult<MutexGuard<'a, T>> {
+        while condition(&mut *guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    /// Like `wait`, but also returns when `timeout` passes.
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        timeout: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        self.wait_until(guard, Instant::now() + timeout)
+    }
+
+    /// Waits for as long as `condition` holds, but at most `timeout`. The
+    /// result says whether it timed out with the condition still holding.
+    pub fn wait_timeout_while<'a, T>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        timeout: Duration,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        let deadline = Instant::now() + timeout;
+        while condition(&mut *guard) {
+            let (next, result) = self.wait_until(guard, deadline)?;
+            guard = next;
+            if result.timed_out() {
+                let timed_out = condition(&mut *guard);
+                return Ok((guard, WaitTimeoutResult(timed_out)));
+            }
+        }
+        Ok((guard, WaitTimeoutResult(false)))
+    }
+
+    fn wait_until<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        deadline: Instant,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        let notifications = self.notifications.load(Ordering::Relaxed);
+        poison::map_result(
+            mutex::unlocked(guard, || {
+                self.waiters
+                    .wait_until(&self.notifications, notifications, Some(deadline))
+            }),
+            |(guard, woken)| (guard, WaitTimeoutResult(!woken)),
+        )
+    }
+
+    /// Wakes up one waiting thread, if there is any.
+    pub fn notify_one(&self) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+        self.waiters.wake_one(&self.notifications);
+    }
+
+    /// Wakes up all waiting threads. They then take turns locking the mutex.
+    pub fn notify_all(&self) {
+        self.notifications.fetch_add(1, Ordering::Relaxed);
+        self.waiters.wake_all(&self.notifications);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Condvar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Condvar { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Condvar;
+    use crate::sync::Mutex;
+    use std::collections::VecDeque;
+    use std::panic;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_producer_consumer() {
+        let queue = Mutex::new(VecDeque::new());
+        let not_empty = Condvar::new();
+
+        let received: Vec<i32> = thread::scope(|s| {
+            let consumer = s.spawn(|| {
+                let mut received = Vec::new();
+                while received.last() != Some(&99) {
+                    let mut queue = not_empty
+                        .wait_while(queue.lock().unwrap(), |queue| queue.is_empty())
+                        .unwrap();
+                    received.extend(queue.drain(..));
+                }
+                received
+            });
+            for i in 0..100 {
+                queue.lock().unwrap().push_back(i);
+                not_empty.notify_one();
+            }
+            consumer.join().unwrap()
+        });
+
+        assert_eq!(received, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_notify_all() {
+        let started = Mutex::new(false);
+        let start = Condvar::new();
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut started = started.lock().unwrap();
+                    // Checked in a loop, the wake-up may be spurious.
+                    while !*started {
+                        started = start.wait(started).unwrap();
+                    }
+                });
+            }
+            thread::sleep(Duration::from_millis(10));
+            *started.lock().unwrap() = true;
+            start.notify_all();
+        });
+    }
+
+    #[test]
+    fn test_wait_timeout() {
+        let ready = Mutex::new(false);
+        let condvar = Condvar::new();
+
+        let (guard, result) = condvar
+            .wait_timeout(ready.lock().unwrap(), Duration::from_millis(10))
+            .unwrap();
+        assert!(result.timed_out());
+        assert!(!*guard);
+
+        let (guard, result) = condvar
+            .wait_timeout_while(guard, Duration::from_millis(10), |ready| !*ready)
+            .unwrap();
+        assert!(result.timed_out());
+        drop(guard);
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                *ready.lock().unwrap() = true;
+                condvar.notify_one();
+            });
+            let (guard, result) = condvar
+                .wait_timeout_while(ready.lock().unwrap(), Duration::from_secs(60), |ready| {
+                    !*ready
+                })
+                .unwrap();
+            assert!(!result.timed_out());
+            assert!(*guard);
+        });
+    }
+
+    #[test]
+    fn test_wait_reports_poison() {
+        let mutex = Mutex::new(0);
+        let condvar = Condvar::new();
+
+        thread::scope(|s| {
+            let guard = mutex.lock().unwrap();
+            s.spawn(|| {
+                let _ = panic::catch_unwind(|| {
+                    let _guard = mutex.lock().unwrap();
+                    panic!("poisoning");
+                });
+                condvar.notify_one();
+            });
+            let result = condvar.wait_while(guard, |_| !mutex.is_poisoned());
+            assert!(result.is_err());
+        });
+    }
+}
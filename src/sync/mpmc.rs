@@ -0,0 +1,469 @@
+//! A bounded channel any number of threads send to and receive from, on a
+//! lock-free ring buffer like crossbeam's `ArrayQueue`. Uncontended sends and
+//! receives touch no lock, only blocking ones park.
+
+use std::fmt::{self, Debug};
+use std::mem::MaybeUninit;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use crate::atomic::{self, AtomicBool, AtomicUsize};
+use crate::sync::mpsc::{RecvError, SendError, TryRecvError, TrySendError};
+use crate::sync::spin_lock::Backoff;
+use crate::sync::wait_queue::WaitQueue;
+use crate::unsafe_cell::UnsafeCell;
+
+/// Keeps `head` and `tail` on separate cache lines, senders and receivers
+/// would otherwise invalidate each other's.
+#[repr(align(128))]
+struct CachePadded<T>(T);
+
+impl<T> Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// A slot is ready to be written when its stamp equals the tail position, and
+/// ready to be read when it is one past the head position.
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A position is a lap count in the upper bits and an index below `one_lap`.
+struct ArrayQueue<T> {
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    buffer: Box<[Slot<T>]>,
+    one_lap: usize,
+}
+
+// SAFETY: A slot's value is accessed only by the thread whose CAS claimed it.
+unsafe impl<T: Send> Sync for ArrayQueue<T> {}
+
+impl<T> ArrayQueue<T> {
+    fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "capacity must be positive");
+        Self {
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+            buffer: (0..capacity)
+                .map(|i| Slot {
+                    stamp: AtomicUsize::new(i),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            one_lap: (capacity + 1).next_power_of_two(),
+        }
+    }
+
+    /// The position after `pos`, wrapping into the next lap at the end.
+    fn next(&self, pos: usize) -> usize {
+        let index = pos & (self.one_lap - 1);
+        if index + 1 < self.buffer.len() {
+            pos + 1
+        } else {
+            (pos & !(self.one_lap - 1)).wrapping_add(self.one_lap)
+        }
+    }
+
+    fn push(&self, value: T) -> Result<(), T> {
+        let mut backoff = Backoff::new();
+        let mut tail = self.tail.load_relaxed();
+        loop {
+            let slot = &self.buffer[tail & (self.one_lap - 1)];
+            // Acquire pairs with the Release of the receiver that emptied it.
+            let stamp = slot.stamp.load_acquire();
+            if stamp == tail {
+                match self.tail.compare_exchange_weak(
+                    tail,
+                    self.next(tail),
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The CAS made the slot ours until we stamp it.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.stamp.store_release(tail + 1);
+                        return Ok(());
+                    }
+                    Err(actual) => tail = actual,
+                }
+            } else if stamp.wrapping_add(self.one_lap) == tail + 1 {
+                // The slot still holds last lap's value: full, unless a
+                // receiver moved on meanwhile.
+                atomic::fence(Ordering::SeqCst);
+                if self.head.load_relaxed().wrapping_add(self.one_lap) == tail {
+                    return Err(value);
+                }
+                backoff.spin();
+                tail = self.tail.load_relaxed();
+            } else {
+                // Another sender claimed the slot and hasn't stamped it yet.
+                backoff.spin();
+                tail = self.tail.load_relaxed();
+            }
+        }
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut backoff = Backoff::new();
+        let mut head = self.head.load_relaxed();
+        loop {
+            let slot = &self.buffer[head & (self.one_lap - 1)];
+            // Acquire pairs with the Release of the sender that filled it.
+            let stamp = slot.stamp.load_acquire();
+            if stamp == head + 1 {
+                match self.head.compare_exchange_weak(
+                    head,
+                    self.next(head),
+                    Ordering::SeqCst,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: The CAS made the slot ours, the sender
+                        // initialized it before stamping.
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.stamp.store_release(head.wrapping_add(self.one_lap));
+                        return Some(value);
+                    }
+                    Err(actual) => head = actual,
+                }
+            } else if stamp == head {
+                // Not written this lap: empty, unless a sender moved on
+                // meanwhile.
+                atomic::fence(Ordering::SeqCst);
+                if self.tail.load_relaxed() == head {
+                    return None;
+                }
+                backoff.spin();
+                head = self.head.load_relaxed();
+            } else {
+                backoff.spin();
+                head = self.head.load_relaxed();
+            }
+        }
+    }
+}
+
+impl<T> Drop for ArrayQueue<T> {
+    fn drop(&mut self) {
+        while self.pop().is_some() {}
+    }
+}
+
+/// Threads blocked on one side of the channel, woken by the other side.
+struct Waiters {
+    // Bumped on every wake, so a waiter notices wakes since it last checked.
+    wakes: AtomicU32,
+    waiting: AtomicUsize,
+    queue: WaitQueue,
+}
+
+impl Waiters {
+    const fn new() -> Self {
+        Self {
+            wakes: AtomicU32::new(0),
+            waiting: AtomicUsize::new(0),
+            queue: WaitQueue::new(),
+        }
+    }
+
+    /// Retries `attempt` until it returns `Some`, sleeping in between.
+    fn block_on<R>(&self, mut attempt: impl FnMut() -> Option<R>) -> R {
+        loop {
+            let wakes = self.wakes.load(Ordering::Acquire);
+            self.waiting.fetch_add_relaxed(1);
+            // Pairs with the fence in wake: either the other side sees us
+            // waiting, or we see what it did before waking.
+            atomic::fence(Ordering::SeqCst);
+            let result = attempt();
+            if result.is_none() {
+                self.queue.wait(&self.wakes, wakes);
+            }
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+
+    fn wake(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.waiting.load_relaxed() > 0 {
+            self.wakes.fetch_add(1, Ordering::Release);
+            self.queue.wake_all(&self.wakes);
+        }
+    }
+}
+
+struct Channel<T> {
+    queue: ArrayQueue<T>,
+    senders: AtomicUsize,
+    receivers: AtomicUsize,
+    // Set when either side is gone.
+    disconnected: AtomicBool,
+    blocked_senders: Waiters,
+    blocked_receivers: Waiters,
+}
+
+impl<T> Channel<T> {
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        if self.disconnected.load_acquire() {
+            return Err(TrySendError::Disconnected(value));
+        }
+        self.queue.push(value).map_err(TrySendError::Full)?;
+        self.blocked_receivers.wake();
+        Ok(())
+    }
+
+    fn try_recv(&self) -> Result<T, TryRecvError> {
+        let value = match self.queue.pop() {
+            Some(value) => value,
+            // Check again, values sent before the last sender left count.
+            None if self.disconnected.load_acquire() => {
+                self.queue.pop().ok_or(TryRecvError::Disconnected)?
+            }
+            None => return Err(TryRecvError::Empty),
+        };
+        self.blocked_senders.wake();
+        Ok(value)
+    }
+
+    fn disconnect(&self) {
+        self.disconnected.store_release(true);
+        self.blocked_senders.wake();
+        self.blocked_receivers.wake();
+    }
+}
+
+/// Creates a channel holding at most `capacity` values.
+///
+/// # Panics
+///
+/// If `capacity` is 0, there is no rendezvous flavor.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    let channel = Arc::new(Channel {
+        queue: ArrayQueue::new(capacity),
+        senders: AtomicUsize::new(1),
+        receivers: AtomicUsize::new(1),
+        disconnected: AtomicBool::new(false),
+        blocked_senders: Waiters::new(),
+        blocked_receivers: Waiters::new(),
+    });
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver { channel },
+    )
+}
+
+/// The sending half of a [`bounded`] channel, clone it for more senders.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Fails with `Full` instead of blocking.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(value)
+    }
+
+    /// Blocks while the channel is full, fails once all receivers are gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut value = Some(value);
+        self.channel.blocked_senders.block_on(|| {
+            match self.channel.try_send(value.take().unwrap()) {
+                Ok(()) => Some(Ok(())),
+                Err(TrySendError::Full(rejected)) => {
+                    value = Some(rejected);
+                    None
+                }
+                Err(TrySendError::Disconnected(rejected)) => Some(Err(SendError(rejected))),
+            }
+        })
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.senders.fetch_add_relaxed(1);
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.channel.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.disconnect();
+        }
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sender { .. }")
+    }
+}
+
+/// The receiving half of a [`bounded`] channel, clone it for more receivers.
+/// Every value goes to exactly one of them.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Receiver<T> {
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.try_recv()
+    }
+
+    /// Blocks until a value arrives, fails once the channel is empty and all
+    /// senders are gone.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.channel
+            .blocked_receivers
+            .block_on(|| match self.channel.try_recv() {
+                Ok(value) => Some(Ok(value)),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Err(RecvError)),
+            })
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        self.channel.receivers.fetch_add_relaxed(1);
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        if self.channel.receivers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.channel.disconnect();
+        }
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Receiver { .. }")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bounded, Receiver, Sender};
+    use crate::sync::mpsc::{TryRecvError, TrySendError};
+    use crate::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<Sender<i32>>();
+        assert_sync::<Receiver<i32>>();
+        assert_not_impl!(Receiver<std::rc::Rc<i32>>: Send);
+    }
+
+    #[test]
+    fn test_try_send_and_try_recv() {
+        let (tx, rx) = bounded(2);
+
+        tx.try_send(1).unwrap();
+        tx.try_send(2).unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(rx.try_recv(), Ok(1));
+        // The ring wraps around.
+        tx.try_send(3).unwrap();
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (tx, rx) = bounded(4);
+        tx.send(String::from("queued")).unwrap();
+        drop(tx);
+
+        assert_eq!(rx.recv().unwrap(), "queued");
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(rx.recv().is_err());
+
+        let (tx, rx) = bounded(1);
+        drop(rx.clone());
+        tx.send(1).unwrap();
+        drop(rx);
+        assert_eq!(tx.try_send(2), Err(TrySendError::Disconnected(2)));
+        assert!(tx.send(2).is_err());
+    }
+
+    #[test]
+    fn test_blocking_send_waits_for_room() {
+        let (tx, rx) = bounded(1);
+        tx.send(1).unwrap();
+
+        thread::scope(|s| {
+            s.spawn(|| tx.send(2).unwrap());
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(rx.recv(), Ok(1));
+            assert_eq!(rx.recv(), Ok(2));
+        });
+    }
+
+    #[test]
+    fn test_values_left_behind_are_dropped() {
+        let value = std::sync::Arc::new(());
+        let (tx, rx) = bounded(4);
+
+        tx.send(value.clone()).unwrap();
+        tx.send(value.clone()).unwrap();
+        drop((tx, rx));
+
+        assert_eq!(std::sync::Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn test_many_senders_many_receivers() {
+        let (tx, rx) = bounded(4);
+        let received = Mutex::new(Vec::new());
+
+        thread::scope(|s| {
+            for i in 0..4 {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for j in 0..1000 {
+                        tx.send(i * 1000 + j).unwrap();
+                    }
+                });
+            }
+            drop(tx);
+            for _ in 0..4 {
+                let rx = rx.clone();
+                let received = &received;
+                s.spawn(move || {
+                    while let Ok(value) = rx.recv() {
+                        received.lock().unwrap().push(value);
+                    }
+                });
+            }
+        });
+
+        let mut received = received.into_inner().unwrap();
+        received.sort_unstable();
+        assert_eq!(received, (0..4000).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,610 @@
+//! Channels passing values from any number of senders to one receiver, with the
+//! API of `std::sync::mpsc`, built on [`Mutex`] and [`Condvar`].
+
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::sync::{ignore_poison, Condvar, Mutex, MutexGuard};
+
+struct Channel<T> {
+    state: Mutex<State<T>>,
+    // Receivers wait here for a value or for the last sender to leave.
+    not_empty: Condvar,
+    // Bounded senders wait here for room, rendezvous senders for their value to
+    // be taken.
+    not_full: Condvar,
+    bound: Option<usize>,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    senders: usize,
+    receiver: bool,
+    waiting_receivers: usize,
+    sent: u64,
+    received: u64,
+}
+
+impl<T> Channel<T> {
+    fn new(bound: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                senders: 1,
+                receiver: true,
+                waiting_receivers: 0,
+                sent: 0,
+                received: 0,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            bound,
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        ignore_poison(self.state.lock())
+    }
+
+    /// Whether a sender has to wait for room, a rendezvous channel holds one
+    /// value while its sender waits for a receiver.
+    fn is_full(&self, state: &State<T>) -> bool {
+        match self.bound {
+            Some(bound) => state.queue.len() >= bound.max(1),
+            None => false,
+        }
+    }
+
+    fn push(&self, state: &mut State<T>, value: T) -> u64 {
+        state.queue.push_back(value);
+        state.sent += 1;
+        self.not_empty.notify_one();
+        state.sent
+    }
+
+    fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let state = self.lock();
+        let mut state = ignore_poison(
+            self.not_full
+                .wait_while(state, |state| state.receiver && self.is_full(state)),
+        );
+        if !state.receiver {
+            return Err(SendError(value));
+        }
+        let ticket = self.push(&mut state, value);
+        if self.bound == Some(0) {
+            state = ignore_poison(
+                self.not_full
+                    .wait_while(state, |state| state.receiver && state.received < ticket),
+            );
+            if state.received < ticket {
+                // The receiver left, our value is the only one queued.
+                let value = state.queue.pop_back().unwrap();
+                return Err(SendError(value));
+            }
+        }
+        Ok(())
+    }
+
+    fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        let mut state = self.lock();
+        if !state.receiver {
+            return Err(TrySendError::Disconnected(value));
+        }
+        let full = match self.bound {
+            // Only a receiver already waiting can take the value right away.
+            Some(0) => state.waiting_receivers <= state.queue.len(),
+            _ => self.is_full(&state),
+        };
+        if full {
+            return Err(TrySendError::Full(value));
+        }
+        self.push(&mut state, value);
+        Ok(())
+    }
+
+    fn recv(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        let mut state = self.lock();
+        loop {
+            match self.pop(&mut state) {
+                Err(TryRecvError::Empty) => {}
+                result => return result.map_err(|_| RecvTimeoutError::Disconnected),
+            }
+            let timeout = match deadline {
+                None => None,
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(timeout) if timeout > Duration::ZERO => Some(timeout),
+                    _ => return Err(RecvTimeoutError::Timeout),
+                },
+            };
+            state.waiting_receivers += 1;
+            state = match timeout {
+                None => ignore_poison(self.not_empty.wait(state)),
+                Some(timeout) => ignore_poison(self.not_empty.wait_timeout(state, timeout)).0,
+            };
+            state.waiting_receivers -= 1;
+        }
+    }
+
+    fn pop(&self, state: &mut State<T>) -> Result<T, TryRecvError> {
+        match state.queue.pop_front() {
+            Some(value) => {
+                state.received += 1;
+                if self.bound.is_some() {
+                    // Wakes senders waiting for room and rendezvous senders
+                    // alike, only the latter check `received`.
+                    self.not_full.notify_all();
+                }
+                Ok(value)
+            }
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    fn add_sender(&self) {
+        self.lock().senders += 1;
+    }
+
+    fn remove_sender(&self) {
+        let mut state = self.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            self.not_empty.notify_all();
+        }
+    }
+}
+
+/// Creates a channel without bound, `send` never blocks.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let channel = Channel::new(None);
+    (
+        Sender {
+            channel: channel.clone(),
+        },
+        Receiver::new(channel),
+    )
+}
+
+/// Creates a channel holding at most `bound` values, `send` blocks while it is
+/// full. With a bound of 0, every `send` waits until its value is received.
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, Receiver<T>) {
+    let channel = Channel::new(Some(bound));
+    (
+        SyncSender {
+            channel: channel.clone(),
+        },
+        Receiver::new(channel),
+    )
+}
+
+/// The sending half of a [`channel`], clone it for more senders.
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> Sender<T> {
+    /// Fails, handing the value back, once the receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.send(value)
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.channel.add_sender();
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.channel.remove_sender();
+    }
+}
+
+impl<T> Debug for Sender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sender { .. }")
+    }
+}
+
+/// The sending half of a [`sync_channel`], clone it for more senders.
+pub struct SyncSender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+impl<T> SyncSender<T> {
+    /// Blocks while the channel is full, fails once the receiver is gone.
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        self.channel.send(value)
+    }
+
+    /// Fails with `Full` instead of blocking.
+    pub fn try_send(&self, value: T) -> Result<(), TrySendError<T>> {
+        self.channel.try_send(value)
+    }
+}
+
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        self.channel.add_sender();
+        Self {
+            channel: self.channel.clone(),
+        }
+    }
+}
+
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.channel.remove_sender();
+    }
+}
+
+impl<T> Debug for SyncSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SyncSender { .. }")
+    }
+}
+
+/// The receiving half of a channel. Values queued when it is dropped are
+/// dropped with the channel.
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+    // There is one receiver, it may move to another thread but not be shared.
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+impl<T> Receiver<T> {
+    fn new(channel: Arc<Channel<T>>) -> Self {
+        Self {
+            channel,
+            _not_sync: PhantomData,
+        }
+    }
+
+    /// Blocks until a value arrives, fails once the queue is empty and all
+    /// senders are gone.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.channel.recv(None).map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.channel.pop(&mut self.channel.lock())
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.channel.recv(Some(Instant::now() + timeout))
+    }
+
+    /// Receives values until all senders are gone.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { receiver: self }
+    }
+
+    /// Receives the values already queued.
+    pub fn try_iter(&self) -> TryIter<'_, T> {
+        TryIter { receiver: self }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.lock().receiver = false;
+        self.channel.not_full.notify_all();
+    }
+}
+
+impl<T> Debug for Receiver<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Receiver { .. }")
+    }
+}
+
+/// Returned by [`Receiver::iter`].
+#[derive(Debug)]
+pub struct Iter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for Iter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Returned by [`Receiver::try_iter`].
+#[derive(Debug)]
+pub struct TryIter<'a, T> {
+    receiver: &'a Receiver<T>,
+}
+
+impl<T> Iterator for TryIter<'_, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+/// Returned by `Receiver::into_iter`.
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Receiver<T> {
+    type Item = T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> IntoIterator for Receiver<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> IntoIter<T> {
+        IntoIter { receiver: self }
+    }
+}
+
+/// The receiver is gone, the value comes back.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub struct SendError<T>(pub T);
+
+impl<T> Debug for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError { .. }")
+    }
+}
+
+impl<T> Display for SendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending on a closed channel")
+    }
+}
+
+impl<T> Error for SendError<T> {}
+
+/// Why a `try_send` didn't send, the value comes back.
+#[derive(PartialEq, Eq, Clone, Copy)]
+pub enum TrySendError<T> {
+    Full(T),
+    Disconnected(T),
+}
+
+impl<T> From<SendError<T>> for TrySendError<T> {
+    fn from(err: SendError<T>) -> Self {
+        TrySendError::Disconnected(err.0)
+    }
+}
+
+impl<T> Debug for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Disconnected(_) => f.write_str("Disconnected(..)"),
+        }
+    }
+}
+
+impl<T> Display for TrySendError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending on a full channel"),
+            TrySendError::Disconnected(_) => f.write_str("sending on a closed channel"),
+        }
+    }
+}
+
+impl<T> Error for TrySendError<T> {}
+
+/// The channel is empty and all senders are gone.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct RecvError;
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("receiving on a closed channel")
+    }
+}
+
+impl Error for RecvError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TryRecvError {
+    Empty,
+    /// Empty, and all senders are gone.
+    Disconnected,
+}
+
+impl From<RecvError> for TryRecvError {
+    fn from(_: RecvError) -> Self {
+        TryRecvError::Disconnected
+    }
+}
+
+impl Display for TryRecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryRecvError::Empty => f.write_str("receiving on an empty channel"),
+            TryRecvError::Disconnected => f.write_str("receiving on a closed channel"),
+        }
+    }
+}
+
+impl Error for TryRecvError {}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum RecvTimeoutError {
+    Timeout,
+    /// Empty, and all senders are gone.
+    Disconnected,
+}
+
+impl From<RecvError> for RecvTimeoutError {
+    fn from(_: RecvError) -> Self {
+        RecvTimeoutError::Disconnected
+    }
+}
+
+impl Display for RecvTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvTimeoutError::Timeout => f.write_str("timed out waiting on channel"),
+            RecvTimeoutError::Disconnected => {
+                f.write_str("channel is empty and sending half is closed")
+            }
+        }
+    }
+}
+
+impl Error for RecvTimeoutError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{channel, sync_channel, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+    use super::{Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<Sender<i32>>();
+        assert_sync::<Sender<i32>>();
+        assert_send::<Receiver<i32>>();
+        assert_not_impl!(Receiver<i32>: Sync);
+        assert_not_impl!(Sender<std::rc::Rc<i32>>: Send);
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        let (tx, rx) = channel();
+
+        tx.send(1).unwrap();
+        tx.send(2).unwrap();
+
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_many_senders() {
+        let (tx, rx) = channel();
+
+        thread::scope(|s| {
+            for i in 0..4 {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for j in 0..100 {
+                        tx.send(i * 100 + j).unwrap();
+                    }
+                });
+            }
+            drop(tx);
+
+            let mut received: Vec<i32> = rx.iter().collect();
+            received.sort_unstable();
+            assert_eq!(received, (0..400).collect::<Vec<_>>());
+        });
+    }
+
+    #[test]
+    fn test_disconnect() {
+        let (tx, rx) = channel();
+        tx.send(1).unwrap();
+        drop(tx);
+
+        // Queued values are still delivered.
+        assert_eq!(rx.recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert!(rx.recv().is_err());
+
+        let (tx, rx) = channel();
+        drop(rx);
+        assert_eq!(tx.send(2), Err(SendError(2)));
+    }
+
+    #[test]
+    fn test_recv_timeout() {
+        let (tx, rx) = channel();
+
+        assert_eq!(
+            rx.recv_timeout(Duration::from_millis(10)),
+            Err(RecvTimeoutError::Timeout)
+        );
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                tx.send(1).unwrap();
+            });
+            assert_eq!(rx.recv_timeout(Duration::from_secs(60)), Ok(1));
+        });
+        drop(tx);
+        assert_eq!(
+            rx.recv_timeout(Duration::from_secs(60)),
+            Err(RecvTimeoutError::Disconnected)
+        );
+    }
+
+    #[test]
+    fn test_bounded_channel_blocks_when_full() {
+        let (tx, rx) = sync_channel(2);
+
+        tx.try_send(1).unwrap();
+        tx.send(2).unwrap();
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+
+        thread::scope(|s| {
+            s.spawn(|| tx.send(3).unwrap());
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(rx.recv(), Ok(1));
+        });
+        assert_eq!(rx.try_iter().collect::<Vec<_>>(), [2, 3]);
+
+        drop(rx);
+        assert_eq!(tx.try_send(4), Err(TrySendError::Disconnected(4)));
+    }
+
+    #[test]
+    fn test_rendezvous() {
+        let (tx, rx) = sync_channel(0);
+
+        assert_eq!(tx.try_send(1), Err(TrySendError::Full(1)));
+        thread::scope(|s| {
+            let sender = s.spawn(|| {
+                tx.send(1).unwrap();
+                tx.send(2)
+            });
+            assert_eq!(rx.recv(), Ok(1));
+            thread::sleep(Duration::from_millis(10));
+            // The second send waits for a receiver that never comes.
+            drop(rx);
+            assert_eq!(sender.join().unwrap(), Err(SendError(2)));
+        });
+    }
+}
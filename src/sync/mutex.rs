@@ -0,0 +1,388 @@
+use std::fmt::{self, Debug};
+use std::hint;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::refs::{BorrowFlag, FlagRefMut};
+use crate::sync::poison::{self, LockResult, TryLockError, TryLockResult};
+use crate::sync::wait_queue::WaitQueue;
+use crate::unsafe_cell::UnsafeCell;
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+// Locked, and threads may be waiting for it.
+const CONTENDED: u32 = 2;
+
+/// How often `lock` retries before going to sleep, a lock is often held briefly.
+const SPINS: u32 = 100;
+
+/// The lock state of a [`Mutex`], released by its guard.
+pub struct RawMutex {
+    state: AtomicU32,
+    waiters: WaitQueue,
+    poison: poison::Flag,
+}
+
+impl RawMutex {
+    pub(crate) const fn new() -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            waiters: WaitQueue::new(),
+            poison: poison::Flag::new(),
+        }
+    }
+
+    pub(crate) fn try_lock(&self) -> bool {
+        // Acquire pairs with the Release in unlock, we see what the last owner wrote.
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    pub(crate) fn lock(&self) {
+        if !self.try_lock() {
+            self.lock_contended(None);
+        }
+    }
+
+    fn try_lock_until(&self, deadline: Instant) -> bool {
+        self.try_lock() || self.lock_contended(Some(deadline))
+    }
+
+    /// Returns false if the deadline passed first.
+    #[cold]
+    fn lock_contended(&self, deadline: Option<Instant>) -> bool {
+        for _ in 0..SPINS {
+            // Spins on a load, retrying the CAS would keep the cache line busy.
+            if self.state.load(Ordering::Relaxed) == UNLOCKED && self.try_lock() {
+                return true;
+            }
+            hint::spin_loop();
+        }
+        // From here on we don't know whether there are other waiters, so we take
+        // the lock as CONTENDED: unlocking it then wakes the next one just in case.
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            if !self.waiters.wait_until(&self.state, CONTENDED, deadline) {
+                return false;
+            }
+        }
+        true
+    }
+
+    pub(crate) fn unlock(&self) {
+        // Release pairs with the Acquire of the next owner.
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            self.waiters.wake_one(&self.state);
+        }
+    }
+}
+
+impl BorrowFlag for RawMutex {
+    fn release_shared(&self) {
+        unreachable!("A Mutex is never borrowed shared");
+    }
+
+    fn release_exclusive(&self) {
+        self.poison.unlock();
+        self.unlock();
+    }
+}
+
+/// Unlocks the mutex when dropped.
+pub type MutexGuard<'a, T> = FlagRefMut<'a, T, RawMutex>;
+
+/// Releases the lock of `guard` while `f` runs and takes it again, for
+/// [`Condvar`](super::Condvar).
+pub(crate) fn unlocked<'a, T, R>(
+    guard: MutexGuard<'a, T>,
+    f: impl FnOnce() -> R,
+) -> LockResult<(MutexGuard<'a, T>, R)> {
+    let (raw, value) = FlagRefMut::into_parts(guard);
+    raw.release_exclusive();
+    let result = f();
+    raw.lock();
+    raw.poison.lock();
+    // SAFETY: We hold the lock again, `value` points into the mutex.
+    raw.poison
+        .result((unsafe { FlagRefMut::from_raw(raw, value) }, result))
+}
+
+/// A lock protecting a value, threads wait for their turn to access it.
+///
+/// Waiting threads spin briefly, then park until the lock is released.
+///
+/// Like `std::sync::Mutex`, a thread panicking while it holds the guard poisons
+/// the mutex: locking it afterwards returns a
+/// [`PoisonError`](super::PoisonError), which still hands out the guard for code
+/// that can repair the value.
+pub struct Mutex<T> {
+    raw: RawMutex,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The lock hands out one `&mut T` at a time, possibly on another thread.
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+// Poisoning reports a value left behind by a panic.
+impl<T> UnwindSafe for Mutex<T> {}
+impl<T> RefUnwindSafe for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            raw: RawMutex::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Blocks until the lock is free. Locking it again on the same thread
+    /// deadlocks.
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        self.raw.lock();
+        // SAFETY: We hold the lock.
+        unsafe { self.guard() }
+    }
+
+    /// Fails with `WouldBlock` instead of blocking if the lock is taken.
+    pub fn try_lock(&self) -> TryLockResult<MutexGuard<'_, T>> {
+        if self.raw.try_lock() {
+            // SAFETY: We hold the lock.
+            Ok(unsafe { self.guard() }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks for at most `timeout`, then fails with `WouldBlock`.
+    pub fn try_lock_for(&self, timeout: Duration) -> TryLockResult<MutexGuard<'_, T>> {
+        self.try_lock_until(Instant::now() + timeout)
+    }
+
+    /// Blocks until `deadline` at the latest, then fails with `WouldBlock`.
+    pub fn try_lock_until(&self, deadline: Instant) -> TryLockResult<MutexGuard<'_, T>> {
+        if self.raw.try_lock_until(deadline) {
+            // SAFETY: We hold the lock.
+            Ok(unsafe { self.guard() }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.poison.get()
+    }
+
+    /// Marks the value as repaired, locking succeeds again.
+    pub fn clear_poison(&self) {
+        self.raw.poison.clear();
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let Mutex { raw, value } = self;
+        raw.poison.result(value.into_inner())
+    }
+
+    /// No locking needed, `&mut self` guarantees there are no guards.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.raw.poison.result(self.value.get_mut())
+    }
+
+    /// SAFETY: The lock must be held, the guard releases it.
+    unsafe fn guard(&self) -> LockResult<MutexGuard<'_, T>> {
+        self.raw.poison.lock();
+        let value = NonNull::new_unchecked(self.value.get());
+        self.raw
+            .poison
+            .result(FlagRefMut::from_raw(&self.raw, value))
+    }
+}
+
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for Mutex<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("Mutex");
+        match self.try_lock() {
+            Ok(value) => d.field("value", &*value),
+            Err(TryLockError::Poisoned(err)) => d.field("value", &**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("value", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mutex;
+    use crate::sync::TryLockError;
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<Mutex<std::cell::Cell<i32>>>();
+        assert_sync::<Mutex<std::cell::Cell<i32>>>();
+        assert_not_impl!(Mutex<std::rc::Rc<i32>>: Sync);
+    }
+
+    #[test]
+    fn test_lock_and_try_lock() {
+        let mutex = Mutex::new(vec![1]);
+
+        let mut guard = mutex.lock().unwrap();
+        guard.push(2);
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::WouldBlock)));
+        assert_eq!(
+            format!("{:?}", mutex),
+            "Mutex { value: <locked>, poisoned: false }"
+        );
+        drop(guard);
+
+        assert_eq!(*mutex.try_lock().unwrap(), [1, 2]);
+        assert_eq!(
+            format!("{:?}", mutex),
+            "Mutex { value: [1, 2], poisoned: false }"
+        );
+    }
+
+    #[test]
+    fn test_get_mut_and_into_inner() {
+        let mut mutex = Mutex::new(1);
+
+        *mutex.get_mut().unwrap() += 1;
+
+        assert_eq!(mutex.into_inner().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_counter_across_threads() {
+        let counter = Mutex::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *counter.lock().unwrap() += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(counter.into_inner().unwrap(), 8000);
+    }
+
+    #[test]
+    fn test_waiter_parks_until_unlock() {
+        let mutex = Mutex::new(0);
+        let locked = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let mut guard = mutex.lock().unwrap();
+            s.spawn(|| {
+                locked.store(true, Ordering::Relaxed);
+                *mutex.lock().unwrap() += 1;
+            });
+            while !locked.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            // Long enough for the other thread to stop spinning and park.
+            thread::sleep(Duration::from_millis(20));
+            *guard += 1;
+        });
+
+        assert_eq!(mutex.into_inner().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_try_lock_for() {
+        let mutex = Mutex::new(0);
+
+        thread::scope(|s| {
+            let mut guard = mutex.lock().unwrap();
+            s.spawn(|| {
+                let timed_out = mutex.try_lock_for(Duration::from_millis(10));
+                assert!(matches!(timed_out, Err(TryLockError::WouldBlock)));
+            })
+            .join()
+            .unwrap();
+
+            let waiter = s.spawn(|| {
+                *mutex.try_lock_for(Duration::from_secs(60)).unwrap() += 1;
+            });
+            thread::sleep(Duration::from_millis(20));
+            *guard += 1;
+            drop(guard);
+            waiter.join().unwrap();
+        });
+
+        assert_eq!(mutex.into_inner().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_panic_poisons() {
+        let mutex = Mutex::new(vec![1]);
+
+        let result = panic::catch_unwind(|| {
+            let mut guard = mutex.lock().unwrap();
+            guard.push(2);
+            panic!("interrupted update");
+        });
+
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+        assert_eq!(
+            format!("{:?}", mutex),
+            "Mutex { value: [1, 2], poisoned: true }"
+        );
+        assert!(matches!(mutex.try_lock(), Err(TryLockError::Poisoned(_))));
+        // The value is still reachable, for code that can repair it.
+        let mut guard = mutex.lock().unwrap_err().into_inner();
+        guard.pop();
+        drop(guard);
+        assert!(mutex.is_poisoned());
+
+        mutex.clear_poison();
+        assert_eq!(*mutex.lock().unwrap(), [1]);
+        assert_eq!(mutex.into_inner().unwrap(), [1]);
+    }
+
+    #[test]
+    fn test_locking_while_panicking_does_not_poison() {
+        struct LockOnDrop<'a>(&'a Mutex<i32>);
+
+        impl Drop for LockOnDrop<'_> {
+            fn drop(&mut self) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+
+        let mutex = Mutex::new(0);
+
+        let result = panic::catch_unwind(|| {
+            let _lock_on_drop = LockOnDrop(&mutex);
+            panic!("unrelated");
+        });
+
+        assert!(result.is_err());
+        assert!(!mutex.is_poisoned());
+        assert_eq!(mutex.into_inner().unwrap(), 1);
+    }
+}
@@ -0,0 +1,235 @@
+use std::cell::Cell;
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::sync::wait_queue::WaitQueue;
+
+const INCOMPLETE: u32 = 0;
+// A closure panicked, the next `call_once` panics too.
+const POISONED: u32 = 1;
+const RUNNING: u32 = 2;
+// Running, and threads may be waiting for it.
+const QUEUED: u32 = 3;
+const COMPLETE: u32 = 4;
+
+/// Runs a one-time initialization, for example of a global, exactly once.
+///
+/// This is the primitive beneath [`OnceLock`](crate::once_lock::OnceLock) and
+/// [`LazyLock`](crate::lazy_lock::LazyLock): threads that come while the closure
+/// runs park until it's done, then see everything it wrote.
+pub struct Once {
+    state: AtomicU32,
+    waiters: WaitQueue,
+}
+
+/// Passed to the closure of [`Once::call_once_force`].
+pub struct OnceState {
+    poisoned: bool,
+    set_state_to: Cell<u32>,
+}
+
+impl OnceState {
+    /// Whether an earlier closure panicked.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Leaves the `Once` incomplete without poisoning it, for initializers that
+    /// fail without panicking.
+    pub(crate) fn retry(&self) {
+        self.set_state_to.set(INCOMPLETE);
+    }
+}
+
+impl Debug for OnceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnceState")
+            .field("poisoned", &self.poisoned)
+            .finish()
+    }
+}
+
+impl Once {
+    pub const fn new() -> Self {
+        Self::with_state(INCOMPLETE)
+    }
+
+    /// A `Once` that has already run.
+    pub(crate) const fn completed() -> Self {
+        Self::with_state(COMPLETE)
+    }
+
+    const fn with_state(state: u32) -> Self {
+        Self {
+            state: AtomicU32::new(state),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    /// Runs `f` if no closure has completed yet, otherwise blocks until it has.
+    ///
+    /// Panics if an earlier closure panicked, the initialization may be half
+    /// done. Calling it re-entrantly from `f` deadlocks.
+    pub fn call_once<F: FnOnce()>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        let mut f = Some(f);
+        self.call(false, &mut |_| f.take().unwrap()());
+    }
+
+    /// Like `call_once`, but also runs `f` after an earlier closure panicked.
+    /// `f` gets to know through the [`OnceState`] and can repair what's left.
+    pub fn call_once_force<F: FnOnce(&OnceState)>(&self, f: F) {
+        if self.is_completed() {
+            return;
+        }
+        let mut f = Some(f);
+        self.call(true, &mut |state| f.take().unwrap()(state));
+    }
+
+    pub fn is_completed(&self) -> bool {
+        // Acquire pairs with the Release when completing, what the closure wrote
+        // is visible to the caller.
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+
+    // Not generic, so that it's compiled once rather than per closure.
+    #[cold]
+    fn call(&self, ignore_poison: bool, f: &mut dyn FnMut(&OnceState)) {
+        let mut state = self.state.load(Ordering::Acquire);
+        loop {
+            match state {
+                COMPLETE => return,
+                POISONED if !ignore_poison => {
+                    panic!("Once instance has previously been poisoned");
+                }
+                INCOMPLETE | POISONED => {
+                    if let Err(actual) = self.state.compare_exchange_weak(
+                        state,
+                        RUNNING,
+                        Ordering::Acquire,
+                        Ordering::Acquire,
+                    ) {
+                        state = actual;
+                        continue;
+                    }
+                    // Poisons the Once if `f` panics.
+                    let mut complete = Completion {
+                        once: self,
+                        set_state_to: POISONED,
+                    };
+                    let once_state = OnceState {
+                        poisoned: state == POISONED,
+                        set_state_to: Cell::new(COMPLETE),
+                    };
+                    f(&once_state);
+                    complete.set_state_to = once_state.set_state_to.get();
+                    return;
+                }
+                RUNNING => {
+                    // Tell the running thread to wake us up.
+                    if let Err(actual) = self.state.compare_exchange_weak(
+                        RUNNING,
+                        QUEUED,
+                        Ordering::Relaxed,
+                        Ordering::Acquire,
+                    ) {
+                        state = actual;
+                        continue;
+                    }
+                    self.waiters.wait(&self.state, QUEUED);
+                    state = self.state.load(Ordering::Acquire);
+                }
+                _ => {
+                    self.waiters.wait(&self.state, QUEUED);
+                    state = self.state.load(Ordering::Acquire);
+                }
+            }
+        }
+    }
+}
+
+struct Completion<'a> {
+    once: &'a Once,
+    set_state_to: u32,
+}
+
+impl Drop for Completion<'_> {
+    fn drop(&mut self) {
+        // Release pairs with the Acquire of everyone who sees the new state.
+        if self.once.state.swap(self.set_state_to, Ordering::Release) == QUEUED {
+            self.once.waiters.wake_all(&self.once.state);
+        }
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once")
+            .field("completed", &self.is_completed())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Once;
+    use std::panic;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_runs_once() {
+        let once = Once::new();
+        let calls = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    once.call_once(|| {
+                        // Long enough for the others to park.
+                        thread::sleep(Duration::from_millis(10));
+                        calls.fetch_add(1, Ordering::Relaxed);
+                    });
+                    assert!(once.is_completed());
+                    assert_eq!(calls.load(Ordering::Relaxed), 1);
+                });
+            }
+        });
+
+        assert_eq!(format!("{:?}", once), "Once { completed: true }");
+    }
+
+    #[test]
+    fn test_usable_in_statics() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {});
+        assert!(INIT.is_completed());
+    }
+
+    #[test]
+    fn test_panic_poisons() {
+        let once = Once::new();
+
+        let result = panic::catch_unwind(|| once.call_once(|| panic!("init failed")));
+        assert!(result.is_err());
+        assert!(!once.is_completed());
+
+        let result = panic::catch_unwind(|| once.call_once(|| {}));
+        assert!(result.is_err());
+
+        let mut saw_poison = false;
+        once.call_once_force(|state| saw_poison = state.is_poisoned());
+        assert!(saw_poison);
+        assert!(once.is_completed());
+        once.call_once(|| unreachable!());
+    }
+}
@@ -0,0 +1,85 @@
+//! Parked threads keyed by address, for primitives too small to keep a queue of
+//! their own, like [`WordMutex`](super::WordMutex).
+//!
+//! Waiters live in a fixed table of buckets picked by the address, each a short
+//! spin-locked queue. Because the caller's checks and updates run with the
+//! bucket locked, a primitive can keep a "threads are parked" bit exact.
+
+use std::collections::VecDeque;
+use std::thread::{self, Thread};
+
+use crate::atomic::AtomicBool;
+use crate::sync::SpinLock;
+
+const BUCKET_COUNT: usize = 64;
+
+static BUCKETS: [SpinLock<VecDeque<Parked>>; BUCKET_COUNT] =
+    [const { SpinLock::new(VecDeque::new()) }; BUCKET_COUNT];
+
+struct ThreadData {
+    thread: Thread,
+    unparked: AtomicBool,
+}
+
+thread_local! {
+    static THREAD_DATA: ThreadData = ThreadData {
+        thread: thread::current(),
+        unparked: AtomicBool::new(false),
+    };
+}
+
+struct Parked {
+    key: usize,
+    // Valid while queued, the thread stays parked until it's taken out.
+    thread: *const ThreadData,
+}
+
+// SAFETY: The ThreadData is only touched through atomics and a `Thread`, which
+// is Sync.
+unsafe impl Send for Parked {}
+
+fn bucket(key: usize) -> &'static SpinLock<VecDeque<Parked>> {
+    // Drops the low bits, which are the same for all aligned addresses.
+    &BUCKETS[(key >> 3) % BUCKET_COUNT]
+}
+
+/// Parks the current thread in the queue for `key`, unless `validate`, called
+/// with the bucket locked, returns false. May return spuriously.
+pub(crate) fn park(key: usize, validate: impl FnOnce() -> bool) {
+    THREAD_DATA.with(|me| {
+        {
+            let mut queue = bucket(key).lock();
+            if !validate() {
+                return;
+            }
+            me.unparked.store_relaxed(false);
+            queue.push_back(Parked { key, thread: me });
+        }
+        // Acquire pairs with the Release in unpark_one.
+        while !me.unparked.load_acquire() {
+            thread::park();
+        }
+    });
+}
+
+/// Wakes the longest parked thread for `key`. Before that, with the bucket
+/// still locked, calls `callback` with whether more threads stay parked.
+pub(crate) fn unpark_one(key: usize, callback: impl FnOnce(bool)) {
+    let waiter = {
+        let mut queue = bucket(key).lock();
+        let waiter = queue
+            .iter()
+            .position(|parked| parked.key == key)
+            .and_then(|index| queue.remove(index));
+        callback(queue.iter().any(|parked| parked.key == key));
+        waiter
+    };
+    if let Some(waiter) = waiter {
+        // SAFETY: The thread is parked until we set `unparked`.
+        let data = unsafe { &*waiter.thread };
+        // Cloned first, the thread may exit as soon as `unparked` is set.
+        let thread = data.thread.clone();
+        data.unparked.store_release(true);
+        thread.unpark();
+    }
+}
@@ -0,0 +1,145 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::thread;
+
+use crate::atomic::AtomicBool;
+
+/// Records whether a thread panicked while holding a lock exclusively.
+pub(crate) struct Flag {
+    poisoned: AtomicBool,
+    // Only touched by the exclusive owner, the lock orders the accesses.
+    panicking_on_lock: AtomicBool,
+}
+
+impl Flag {
+    pub(crate) const fn new() -> Self {
+        Self {
+            poisoned: AtomicBool::new(false),
+            panicking_on_lock: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn get(&self) -> bool {
+        // Relaxed, the lock itself orders the accesses to the value.
+        self.poisoned.load_relaxed()
+    }
+
+    pub(crate) fn clear(&self) {
+        self.poisoned.store_relaxed(false);
+    }
+
+    /// Called by the new exclusive owner. A thread that is already panicking (and
+    /// locks in a destructor) doesn't poison the lock, its panic didn't interrupt
+    /// an update.
+    pub(crate) fn lock(&self) {
+        self.panicking_on_lock.store_relaxed(thread::panicking());
+    }
+
+    /// Called by the exclusive owner before unlocking, poisons the lock if the
+    /// owner started panicking while holding it.
+    pub(crate) fn unlock(&self) {
+        if !self.panicking_on_lock.load_relaxed() && thread::panicking() {
+            self.poisoned.store_relaxed(true);
+        }
+    }
+
+    /// Wraps `value` into an error if the lock is poisoned.
+    pub(crate) fn result<T>(&self, value: T) -> LockResult<T> {
+        if self.get() {
+            Err(PoisonError::new(value))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// The value was left by a thread that panicked while holding the lock, and may
+/// be in an inconsistent state. The error still carries it, for recovery.
+pub struct PoisonError<T> {
+    value: T,
+}
+
+impl<T> PoisonError<T> {
+    pub fn new(value: T) -> Self {
+        Self { value }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.value
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T> Debug for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PoisonError { .. }")
+    }
+}
+
+impl<T> Display for PoisonError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("poisoned lock: another thread panicked while holding it")
+    }
+}
+
+impl<T> Error for PoisonError<T> {}
+
+/// Why a `try_lock` didn't return a guard.
+pub enum TryLockError<T> {
+    Poisoned(PoisonError<T>),
+    /// The lock is held by somebody else.
+    WouldBlock,
+}
+
+impl<T> From<PoisonError<T>> for TryLockError<T> {
+    fn from(err: PoisonError<T>) -> Self {
+        TryLockError::Poisoned(err)
+    }
+}
+
+impl<T> Debug for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::Poisoned(err) => f.debug_tuple("Poisoned").field(err).finish(),
+            TryLockError::WouldBlock => f.write_str("WouldBlock"),
+        }
+    }
+}
+
+impl<T> Display for TryLockError<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TryLockError::Poisoned(err) => Display::fmt(err, f),
+            TryLockError::WouldBlock => {
+                f.write_str("try_lock failed because the operation would block")
+            }
+        }
+    }
+}
+
+impl<T> Error for TryLockError<T> {}
+
+pub type LockResult<T> = Result<T, PoisonError<T>>;
+
+/// Unwraps `result` whether or not the lock was poisoned, for the crate's own
+/// types whose state stays consistent even if a thread panics while holding it.
+pub(crate) fn ignore_poison<T>(result: LockResult<T>) -> T {
+    result.unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Maps the value, keeping a poisoned result poisoned.
+pub(crate) fn map_result<T, U>(result: LockResult<T>, f: impl FnOnce(T) -> U) -> LockResult<U> {
+    match result {
+        Ok(value) => Ok(f(value)),
+        Err(err) => Err(PoisonError::new(f(err.into_inner()))),
+    }
+}
+
+pub type TryLockResult<T> = Result<T, TryLockError<T>>;
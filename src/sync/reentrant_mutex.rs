@@ -0,0 +1,244 @@
+use std::fmt::{self, Debug};
+
+use crate::atomic::AtomicUsize;
+use crate::ref_cell::RefCell;
+use crate::refs::{BorrowFlag, FlagRef};
+use crate::sync::RawMutex;
+use crate::unsafe_cell::UnsafeCell;
+
+/// A value unique to the current thread for as long as it runs, never 0.
+fn current_thread() -> usize {
+    thread_local! {
+        static ID: u8 = const { 0 };
+    }
+    ID.with(|id| id as *const u8 as usize)
+}
+
+/// The lock state of a [`ReentrantMutex`], released by its guards.
+pub struct RawReentrantMutex {
+    mutex: RawMutex,
+    // The thread holding `mutex`, or 0.
+    owner: AtomicUsize,
+    // Only touched by the owner.
+    count: UnsafeCell<usize>,
+}
+
+impl RawReentrantMutex {
+    const fn new() -> Self {
+        Self {
+            mutex: RawMutex::new(),
+            owner: AtomicUsize::new(0),
+            count: UnsafeCell::new(0),
+        }
+    }
+
+    fn lock(&self) {
+        if !self.lock_again() {
+            self.mutex.lock();
+            self.acquired();
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        if self.lock_again() {
+            return true;
+        }
+        if self.mutex.try_lock() {
+            self.acquired();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Counts one more lock if the current thread already holds it.
+    fn lock_again(&self) -> bool {
+        // Relaxed is enough, only the current thread can have stored its own id.
+        if self.owner.load_relaxed() != current_thread() {
+            return false;
+        }
+        // SAFETY: We're the owner.
+        let count = unsafe { &mut *self.count.get() };
+        *count = count
+            .checked_add(1)
+            .expect("ReentrantMutex locked too often");
+        true
+    }
+
+    fn acquired(&self) {
+        self.owner.store_relaxed(current_thread());
+        // SAFETY: We're the owner.
+        unsafe { *self.count.get() = 1 };
+    }
+}
+
+impl BorrowFlag for RawReentrantMutex {
+    fn release_shared(&self) {
+        // SAFETY: Guards only exist on the owner's thread.
+        let count = unsafe { &mut *self.count.get() };
+        *count -= 1;
+        if *count == 0 {
+            self.owner.store_relaxed(0);
+            self.mutex.unlock();
+        }
+    }
+
+    fn release_exclusive(&self) {
+        unreachable!("A ReentrantMutex only hands out shared guards");
+    }
+}
+
+/// Unlocks the mutex when the last guard of the owning thread is dropped.
+pub type ReentrantMutexGuard<'a, T> = FlagRef<'a, T, RawReentrantMutex>;
+
+/// A mutex the thread holding it can lock again, for code that may call back
+/// into itself while holding a lock, like std's `Stdout`.
+///
+/// Its guards only give `&T`: the same thread could hold two of them. To
+/// change the value put it into a [`RefCell`], see [`ReentrantRefCell`]. The
+/// RefCell then catches a callback trying to borrow it mutably while an outer
+/// call holds a borrow, which would otherwise deadlock or alias.
+pub struct ReentrantMutex<T> {
+    raw: RawReentrantMutex,
+    value: T,
+}
+
+// SAFETY: Only the owning thread accesses the value, through `&T`, so it's as
+// if the value was sent there.
+unsafe impl<T: Send> Send for ReentrantMutex<T> {}
+unsafe impl<T: Send> Sync for ReentrantMutex<T> {}
+
+/// The usual way to mutate what a [`ReentrantMutex`] protects:
+///
+/// ```ignore
+/// let out: ReentrantRefCell<Vec<u8>> = ReentrantMutex::new(RefCell::new(Vec::new()));
+/// let guard = out.lock();
+/// guard.borrow_mut().extend(b"hello");
+/// ```
+pub type ReentrantRefCell<T> = ReentrantMutex<RefCell<T>>;
+
+impl<T> ReentrantMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            raw: RawReentrantMutex::new(),
+            value,
+        }
+    }
+
+    /// Blocks until no other thread holds the lock. Doesn't block if the
+    /// current thread holds it already.
+    pub fn lock(&self) -> ReentrantMutexGuard<'_, T> {
+        self.raw.lock();
+        FlagRef::new(&self.raw, &self.value)
+    }
+
+    pub fn try_lock(&self) -> Option<ReentrantMutexGuard<'_, T>> {
+        if self.raw.try_lock() {
+            Some(FlagRef::new(&self.raw, &self.value))
+        } else {
+            None
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// No locking needed, `&mut self` guarantees there are no guards.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: Default> Default for ReentrantMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for ReentrantMutex<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for ReentrantMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(value) => f
+                .debug_struct("ReentrantMutex")
+                .field("value", &*value)
+                .finish(),
+            None => f.write_str("ReentrantMutex { <locked> }"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReentrantMutex, ReentrantRefCell};
+    use crate::ref_cell::RefCell;
+    use std::thread;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<ReentrantRefCell<i32>>();
+        assert_sync::<ReentrantRefCell<i32>>();
+        assert_not_impl!(ReentrantMutex<std::rc::Rc<i32>>: Sync);
+    }
+
+    #[test]
+    fn test_owner_locks_again() {
+        let mutex = ReentrantMutex::new(1);
+
+        let outer = mutex.lock();
+        let inner = mutex.lock();
+        assert_eq!(*outer + *inner, 2);
+        assert_eq!(format!("{:?}", mutex), "ReentrantMutex { value: 1 }");
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                assert!(mutex.try_lock().is_none());
+            });
+        });
+        drop(outer);
+        thread::scope(|s| {
+            s.spawn(|| assert!(mutex.try_lock().is_none()));
+        });
+        drop(inner);
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(*mutex.try_lock().unwrap(), 1));
+        });
+    }
+
+    #[test]
+    fn test_ref_cell_regains_mutability() {
+        fn log(out: &ReentrantRefCell<Vec<String>>, message: &str, depth: u32) {
+            let guard = out.lock();
+            guard.borrow_mut().push(message.to_string());
+            if depth > 0 {
+                // Calls back into itself while holding the lock.
+                log(out, message, depth - 1);
+            }
+        }
+
+        let out = ReentrantMutex::new(RefCell::new(Vec::new()));
+
+        thread::scope(|s| {
+            for i in 0..4 {
+                let out = &out;
+                s.spawn(move || log(out, &i.to_string(), 2));
+            }
+        });
+
+        let lines = out.into_inner().into_inner();
+        assert_eq!(lines.len(), 12);
+        // Every thread's lines came out together.
+        for chunk in lines.chunks(3) {
+            assert!(chunk.iter().all(|line| *line == chunk[0]));
+        }
+    }
+}
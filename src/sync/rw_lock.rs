@@ -0,0 +1,912 @@
+use std::fmt::{self, Debug};
+use std::mem;
+use std::ops::Deref;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::refs::{BorrowFlag, FlagRef, FlagRefMut};
+use crate::sync::poison::{self, LockResult, TryLockError, TryLockResult};
+use crate::sync::wait_queue::WaitQueue;
+use crate::unsafe_cell::UnsafeCell;
+
+// The state counts readers in steps of two, the lowest bit says a writer is
+// waiting. Unless the lock prefers readers, new readers queue up behind a
+// waiting writer, so a steady stream of readers can't starve it.
+const READER: u32 = 2;
+const WRITER_WAITING: u32 = 1;
+const WRITE_LOCKED: u32 = u32::MAX;
+
+/// Who goes first when readers and writers compete for a [`RwLock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fairness {
+    /// New readers wait while a writer waits. Writers can't be starved, but a
+    /// stream of writers keeps readers waiting.
+    #[default]
+    WriterPreferring,
+    /// Readers only wait while a writer holds the lock. Reads have the lowest
+    /// latency, but overlapping readers can keep a writer out forever.
+    ReaderPreferring,
+    /// Like `WriterPreferring`, but when a writer unlocks, the readers that had
+    /// to wait for it go before the next writer. Reads and writes alternate in
+    /// phases under contention, neither side starves.
+    PhaseFair,
+}
+
+/// The lock state of a [`RwLock`], released by its guards.
+pub struct RawRwLock {
+    fairness: Fairness,
+    state: AtomicU32,
+    // Bumped whenever a waiting writer may be able to proceed, writers wait for
+    // it to change rather than on `state`, which readers keep changing.
+    writer_wake: AtomicU32,
+    // Bumped whenever waiting readers may be able to proceed. Waiting on `state`
+    // could miss the end of a PhaseFair write phase: a writer waiting in the next
+    // one can bring `state` back to the value the reader saw.
+    reader_wake: AtomicU32,
+    readers: WaitQueue,
+    writers: WaitQueue,
+    // 1 while an upgradable reader exists, it also counts as a reader in `state`.
+    upgradable: AtomicU32,
+    upgradable_waiters: WaitQueue,
+    // The upgradable reader waiting in `upgrade` for the other readers to leave,
+    // bumped when it may be able to proceed.
+    upgrade_wake: AtomicU32,
+    upgrading: WaitQueue,
+    // For PhaseFair: the number of write phases in the upper half, the readers
+    // waiting in the current phase in the lower half. Ending a write phase moves
+    // its readers to `granted_readers`, which writers wait to drop to zero.
+    waiting_readers: AtomicU64,
+    granted_readers: AtomicU32,
+    poison: poison::Flag,
+}
+
+impl RawRwLock {
+    const fn new(fairness: Fairness) -> Self {
+        Self {
+            fairness,
+            state: AtomicU32::new(0),
+            writer_wake: AtomicU32::new(0),
+            reader_wake: AtomicU32::new(0),
+            readers: WaitQueue::new(),
+            writers: WaitQueue::new(),
+            upgradable: AtomicU32::new(0),
+            upgradable_waiters: WaitQueue::new(),
+            upgrade_wake: AtomicU32::new(0),
+            upgrading: WaitQueue::new(),
+            waiting_readers: AtomicU64::new(0),
+            granted_readers: AtomicU32::new(0),
+            poison: poison::Flag::new(),
+        }
+    }
+
+    fn try_read(&self) -> bool {
+        self.try_read_granted(false)
+    }
+
+    /// A granted reader may pass waiting writers.
+    fn try_read_granted(&self, granted: bool) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while self.admits_reader(state, granted) {
+            assert!(state < WRITE_LOCKED - READER, "Too many readers");
+            // Acquire pairs with the Release in write_unlock.
+            match self.state.compare_exchange_weak(
+                state,
+                state + READER,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => state = actual,
+            }
+        }
+        false
+    }
+
+    fn admits_reader(&self, state: u32, granted: bool) -> bool {
+        state != WRITE_LOCKED
+            && (state & WRITER_WAITING == 0
+                || granted
+                || self.fairness == Fairness::ReaderPreferring)
+    }
+
+    /// Returns false if the deadline passed first.
+    fn read(&self, deadline: Option<Instant>) -> bool {
+        if self.try_read() {
+            return true;
+        }
+        let phase = self.register_reader();
+        let acquired = loop {
+            // Loaded before checking, any unlock after the check changes it.
+            let wake = self.reader_wake.load(Ordering::Acquire);
+            let granted = phase.is_some_and(|phase| self.phase() != phase);
+            if self.try_read_granted(granted) {
+                break true;
+            }
+            if !self.readers.wait_until(&self.reader_wake, wake, deadline) {
+                break false;
+            }
+        };
+        if let Some(phase) = phase {
+            self.unregister_reader(phase);
+        }
+        acquired
+    }
+
+    /// Counts a waiting reader for PhaseFair, returns the phase it waits in.
+    fn register_reader(&self) -> Option<u32> {
+        if self.fairness != Fairness::PhaseFair {
+            return None;
+        }
+        let waiting = self.waiting_readers.fetch_add(1, Ordering::AcqRel);
+        Some((waiting >> 32) as u32)
+    }
+
+    fn phase(&self) -> u32 {
+        (self.waiting_readers.load(Ordering::Acquire) >> 32) as u32
+    }
+
+    fn unregister_reader(&self, phase: u32) {
+        let mut waiting = self.waiting_readers.load(Ordering::Acquire);
+        // Still waiting in the same phase, nobody counts on us yet.
+        while (waiting >> 32) as u32 == phase {
+            match self.waiting_readers.compare_exchange_weak(
+                waiting,
+                waiting - 1,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return,
+                Err(actual) => waiting = actual,
+            }
+        }
+        // A write phase ended and granted us to go before the next writer.
+        if self.granted_readers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.wake_writer();
+        }
+    }
+
+    /// Whether a writer has to let granted readers go first.
+    fn readers_granted(&self) -> bool {
+        self.fairness == Fairness::PhaseFair && self.granted_readers.load(Ordering::Acquire) > 0
+    }
+
+    fn try_write(&self) -> bool {
+        // Acquire: seeing the unlocked state of a write_unlock, we see what it
+        // granted.
+        let mut state = self.state.load(Ordering::Acquire);
+        if self.readers_granted() {
+            return false;
+        }
+        // No readers, only maybe a waiting writer which we get ahead of.
+        while state <= WRITER_WAITING {
+            // Acquire pairs with the Release in read_unlock and write_unlock.
+            match self.state.compare_exchange_weak(
+                state,
+                WRITE_LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => state = actual,
+            }
+        }
+        false
+    }
+
+    /// Returns false if the deadline passed first.
+    fn write(&self, deadline: Option<Instant>) -> bool {
+        while !self.try_write() {
+            let state = self.state.load(Ordering::Relaxed);
+            // Announce ourselves so that no new readers come in.
+            if state & WRITER_WAITING == 0
+                && self
+                    .state
+                    .compare_exchange(
+                        state,
+                        state | WRITER_WAITING,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            let wake = self.writer_wake.load(Ordering::Acquire);
+            if (self.state.load(Ordering::Relaxed) > WRITER_WAITING || self.readers_granted())
+                && !self.writers.wait_until(&self.writer_wake, wake, deadline)
+            {
+                self.abandon_write();
+                return false;
+            }
+        }
+        true
+    }
+
+    /// A writer giving up must not leave WRITER_WAITING behind, readers would wait
+    /// for it forever. Other waiting writers are woken to set it again.
+    fn abandon_write(&self) {
+        // A write-locked state clears the bit on write_unlock anyway.
+        let _ = self
+            .state
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+                (state != WRITE_LOCKED).then_some(state & !WRITER_WAITING)
+            });
+        self.wake_writer();
+        self.wake_readers();
+    }
+
+    fn try_upgradable_read(&self) -> bool {
+        if self
+            .upgradable
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        if self.try_read() {
+            true
+        } else {
+            self.release_upgradable();
+            false
+        }
+    }
+
+    fn upgradable_read(&self) {
+        // Only one upgradable reader at a time, two could wait for each other in
+        // `upgrade` forever.
+        while self.upgradable.swap(1, Ordering::Acquire) != 0 {
+            self.upgradable_waiters.wait(&self.upgradable, 1);
+        }
+        self.read(None);
+    }
+
+    fn upgradable_read_unlock(&self) {
+        self.read_unlock();
+        self.release_upgradable();
+    }
+
+    /// Turns the upgradable reader's read lock into the write lock.
+    fn upgrade(&self) {
+        loop {
+            let state = self.state.load(Ordering::Relaxed);
+            if state & !WRITER_WAITING == READER {
+                // Only our own read lock is left. Acquire pairs with the Release
+                // in read_unlock.
+                if self
+                    .state
+                    .compare_exchange(state, WRITE_LOCKED, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+                {
+                    break;
+                }
+                continue;
+            }
+            // Keep new readers out while we wait for the current ones to leave.
+            if state & WRITER_WAITING == 0
+                && self
+                    .state
+                    .compare_exchange(
+                        state,
+                        state | WRITER_WAITING,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            let wake = self.upgrade_wake.load(Ordering::Acquire);
+            if self.state.load(Ordering::Relaxed) > READER | WRITER_WAITING {
+                self.upgrading.wait(&self.upgrade_wake, wake);
+            }
+        }
+        // Writers are out anyway, the next upgradable reader may start waiting.
+        self.release_upgradable();
+    }
+
+    fn release_upgradable(&self) {
+        self.upgradable.store(0, Ordering::Release);
+        self.upgradable_waiters.wake_one(&self.upgradable);
+    }
+
+    fn read_unlock(&self) {
+        match self.state.fetch_sub(READER, Ordering::Release) {
+            // The last reader leaving while a writer waits lets it in.
+            previous if previous == READER | WRITER_WAITING => self.wake_writer(),
+            // The only reader left may be the upgradable one, waiting in upgrade.
+            previous if previous == (2 * READER) | WRITER_WAITING => {
+                self.upgrade_wake.fetch_add(1, Ordering::Release);
+                self.upgrading.wake_one(&self.upgrade_wake);
+            }
+            _ => {}
+        }
+    }
+
+    fn write_unlock(&self) {
+        if self.fairness == Fairness::PhaseFair {
+            // Ends the phase, the readers that waited in it go first.
+            let waiting = self
+                .waiting_readers
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |waiting| {
+                    Some((waiting & !u64::from(u32::MAX)).wrapping_add(1 << 32))
+                })
+                .unwrap();
+            self.granted_readers
+                .fetch_add(waiting as u32, Ordering::Release);
+        }
+        // Release pairs with the Acquire of the next owners.
+        self.state.store(0, Ordering::Release);
+        // A waiting writer had the WRITER_WAITING bit set, we cleared it: wake one
+        // writer to set it again, and all readers, whoever is first wins.
+        self.wake_writer();
+        self.wake_readers();
+    }
+
+    fn wake_writer(&self) {
+        self.writer_wake.fetch_add(1, Ordering::Release);
+        self.writers.wake_one(&self.writer_wake);
+    }
+
+    fn wake_readers(&self) {
+        self.reader_wake.fetch_add(1, Ordering::Release);
+        self.readers.wake_all(&self.reader_wake);
+    }
+}
+
+impl BorrowFlag for RawRwLock {
+    fn release_shared(&self) {
+        self.read_unlock();
+    }
+
+    fn release_exclusive(&self) {
+        self.poison.unlock();
+        self.write_unlock();
+    }
+}
+
+/// Releases the shared lock when dropped.
+pub type RwLockReadGuard<'a, T> = FlagRef<'a, T, RawRwLock>;
+
+/// Releases the exclusive lock when dropped.
+pub type RwLockWriteGuard<'a, T> = FlagRefMut<'a, T, RawRwLock>;
+
+/// A read lock that can be turned into the write lock without letting a writer
+/// in between, for code that checks the value before deciding to change it.
+///
+/// It shares the value with plain readers, but excludes writers and other
+/// upgradable readers.
+pub struct RwLockUpgradableReadGuard<'a, T> {
+    lock: &'a RwLock<T>,
+}
+
+impl<'a, T> RwLockUpgradableReadGuard<'a, T> {
+    /// Blocks until the plain readers are gone. New readers wait meanwhile.
+    pub fn upgrade(this: Self) -> RwLockWriteGuard<'a, T> {
+        let lock = this.lock;
+        mem::forget(this);
+        lock.raw.upgrade();
+        lock.raw.poison.lock();
+        // SAFETY: We hold the write lock. The lock can't have been poisoned since
+        // we took the upgradable read lock, writers were excluded.
+        unsafe { FlagRefMut::new(&lock.raw, &mut *lock.value.get()) }
+    }
+}
+
+impl<T> Deref for RwLockUpgradableReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: Writers are excluded while we hold a read lock.
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for RwLockUpgradableReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.raw.upgradable_read_unlock();
+    }
+}
+
+impl<T: Debug> Debug for RwLockUpgradableReadGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RwLockUpgradableReadGuard")
+            .field("value", &**self)
+            .finish()
+    }
+}
+
+/// A lock that lets many threads read the value at once, or one thread write it.
+///
+/// By default waiting writers block new readers, so writers aren't starved, see
+/// [`Fairness`] for the alternatives. Panicking while
+/// holding a write guard poisons the lock, as with [`Mutex`](super::Mutex).
+pub struct RwLock<T> {
+    raw: RawRwLock,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: Writers get a `&mut T`, possibly on another thread. Readers share a
+// `&T` across threads.
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+// Poisoning reports a value left behind by a panic.
+impl<T> UnwindSafe for RwLock<T> {}
+impl<T> RefUnwindSafe for RwLock<T> {}
+
+impl<T> RwLock<T> {
+    /// A writer-preferring lock.
+    pub const fn new(value: T) -> Self {
+        Self::with_fairness(value, Fairness::WriterPreferring)
+    }
+
+    pub const fn with_fairness(value: T, fairness: Fairness) -> Self {
+        Self {
+            raw: RawRwLock::new(fairness),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn fairness(&self) -> Fairness {
+        self.raw.fairness
+    }
+
+    /// Blocks while a writer holds or waits for the lock. Taking a read lock
+    /// again on the same thread can deadlock if a writer waits in between.
+    pub fn read(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        self.raw.read(None);
+        // SAFETY: We hold a read lock.
+        unsafe { self.read_guard() }
+    }
+
+    /// Fails with `WouldBlock` instead of blocking.
+    pub fn try_read(&self) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        if self.raw.try_read() {
+            // SAFETY: We hold a read lock.
+            Ok(unsafe { self.read_guard() }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks for at most `timeout`, then fails with `WouldBlock`.
+    pub fn try_read_for(&self, timeout: Duration) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        self.try_read_until(Instant::now() + timeout)
+    }
+
+    /// Blocks until `deadline` at the latest, then fails with `WouldBlock`.
+    pub fn try_read_until(&self, deadline: Instant) -> TryLockResult<RwLockReadGuard<'_, T>> {
+        if self.raw.read(Some(deadline)) {
+            // SAFETY: We hold a read lock.
+            Ok(unsafe { self.read_guard() }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks until all other guards are gone.
+    pub fn write(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        self.raw.write(None);
+        // SAFETY: We hold the write lock.
+        unsafe { self.write_guard() }
+    }
+
+    /// Fails with `WouldBlock` instead of blocking.
+    pub fn try_write(&self) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        if self.raw.try_write() {
+            // SAFETY: We hold the write lock.
+            Ok(unsafe { self.write_guard() }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks for at most `timeout`, then fails with `WouldBlock`.
+    pub fn try_write_for(&self, timeout: Duration) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        self.try_write_until(Instant::now() + timeout)
+    }
+
+    /// Blocks until `deadline` at the latest, then fails with `WouldBlock`.
+    pub fn try_write_until(&self, deadline: Instant) -> TryLockResult<RwLockWriteGuard<'_, T>> {
+        if self.raw.write(Some(deadline)) {
+            // SAFETY: We hold the write lock.
+            Ok(unsafe { self.write_guard() }?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    /// Blocks while a writer or another upgradable reader holds the lock, or a
+    /// writer waits for it.
+    pub fn upgradable_read(&self) -> LockResult<RwLockUpgradableReadGuard<'_, T>> {
+        self.raw.upgradable_read();
+        self.raw
+            .poison
+            .result(RwLockUpgradableReadGuard { lock: self })
+    }
+
+    /// Fails with `WouldBlock` instead of blocking.
+    pub fn try_upgradable_read(&self) -> TryLockResult<RwLockUpgradableReadGuard<'_, T>> {
+        if self.raw.try_upgradable_read() {
+            Ok(self
+                .raw
+                .poison
+                .result(RwLockUpgradableReadGuard { lock: self })?)
+        } else {
+            Err(TryLockError::WouldBlock)
+        }
+    }
+
+    pub fn is_poisoned(&self) -> bool {
+        self.raw.poison.get()
+    }
+
+    /// Marks the value as repaired, locking succeeds again.
+    pub fn clear_poison(&self) {
+        self.raw.poison.clear();
+    }
+
+    pub fn into_inner(self) -> LockResult<T> {
+        let RwLock { raw, value } = self;
+        raw.poison.result(value.into_inner())
+    }
+
+    /// No locking needed, `&mut self` guarantees there are no guards.
+    pub fn get_mut(&mut self) -> LockResult<&mut T> {
+        self.raw.poison.result(self.value.get_mut())
+    }
+
+    /// SAFETY: A read lock must be held, the guard releases it.
+    unsafe fn read_guard(&self) -> LockResult<RwLockReadGuard<'_, T>> {
+        self.raw
+            .poison
+            .result(FlagRef::new(&self.raw, &*self.value.get()))
+    }
+
+    /// SAFETY: The write lock must be held, the guard releases it.
+    unsafe fn write_guard(&self) -> LockResult<RwLockWriteGuard<'_, T>> {
+        self.raw.poison.lock();
+        self.raw
+            .poison
+            .result(FlagRefMut::new(&self.raw, &mut *self.value.get()))
+    }
+}
+
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for RwLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct("RwLock");
+        match self.try_read() {
+            Ok(value) => d.field("value", &*value),
+            Err(TryLockError::Poisoned(err)) => d.field("value", &**err.get_ref()),
+            Err(TryLockError::WouldBlock) => d.field("value", &format_args!("<locked>")),
+        };
+        d.field("poisoned", &self.is_poisoned()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Fairness, RwLock, RwLockUpgradableReadGuard};
+    use crate::sync::Mutex;
+    use crate::sync::TryLockError;
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<RwLock<std::cell::Cell<i32>>>();
+        assert_sync::<RwLock<i32>>();
+        assert_not_impl!(RwLock<std::cell::Cell<i32>>: Sync);
+    }
+
+    #[test]
+    fn test_readers_share_writer_excludes() {
+        let lock = RwLock::new(vec![1]);
+
+        let first = lock.read().unwrap();
+        let second = lock.try_read().unwrap();
+        assert_eq!((first.len(), second.len()), (1, 1));
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        drop((first, second));
+
+        let mut writer = lock.write().unwrap();
+        writer.push(2);
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        assert_eq!(
+            format!("{:?}", lock),
+            "RwLock { value: <locked>, poisoned: false }"
+        );
+        drop(writer);
+
+        assert_eq!(
+            format!("{:?}", lock),
+            "RwLock { value: [1, 2], poisoned: false }"
+        );
+        assert_eq!(lock.into_inner().unwrap(), [1, 2]);
+    }
+
+    #[test]
+    fn test_readers_run_concurrently() {
+        let lock = RwLock::new(0);
+        let inside = AtomicUsize::new(0);
+
+        // Every reader waits for all others to be inside at the same time.
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let _guard = lock.read().unwrap();
+                    inside.fetch_add(1, Ordering::SeqCst);
+                    while inside.load(Ordering::SeqCst) < 4 {
+                        thread::yield_now();
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_waiting_writer_blocks_new_readers() {
+        let lock = RwLock::new(0);
+        let writer_started = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let reader = lock.read().unwrap();
+            s.spawn(|| {
+                writer_started.store(true, Ordering::Relaxed);
+                *lock.write().unwrap() += 1;
+            });
+            while !writer_started.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            while lock.try_read().is_ok() {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(20));
+            drop(reader);
+            assert_eq!(*lock.read().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn test_counter_across_threads() {
+        let lock = RwLock::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *lock.write().unwrap() += 1;
+                    }
+                });
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        assert!(*lock.read().unwrap() <= 4000);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(lock.into_inner().unwrap(), 4000);
+    }
+
+    #[test]
+    fn test_panicking_writer_poisons() {
+        let lock = RwLock::new(1);
+
+        let _ = panic::catch_unwind(|| {
+            let _reader = lock.read().unwrap();
+            panic!("readers don't poison");
+        });
+        assert!(!lock.is_poisoned());
+
+        let _ = panic::catch_unwind(|| {
+            let mut writer = lock.write().unwrap();
+            *writer += 1;
+            panic!("interrupted update");
+        });
+        assert!(lock.is_poisoned());
+        assert_eq!(*lock.read().unwrap_err().into_inner(), 2);
+        assert!(matches!(lock.try_write(), Err(TryLockError::Poisoned(_))));
+
+        lock.clear_poison();
+        assert_eq!(*lock.write().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_upgradable_read_excludes_writers_not_readers() {
+        let lock = RwLock::new(1);
+
+        let upgradable = lock.upgradable_read().unwrap();
+        let reader = lock.try_read().unwrap();
+        assert_eq!(*upgradable + *reader, 2);
+        assert!(matches!(lock.try_write(), Err(TryLockError::WouldBlock)));
+        assert!(matches!(
+            lock.try_upgradable_read(),
+            Err(TryLockError::WouldBlock)
+        ));
+        drop(reader);
+
+        let mut writer = RwLockUpgradableReadGuard::upgrade(upgradable);
+        *writer += 1;
+        assert!(matches!(lock.try_read(), Err(TryLockError::WouldBlock)));
+        drop(writer);
+
+        assert_eq!(*lock.try_upgradable_read().unwrap(), 2);
+        assert_eq!(*lock.try_write().unwrap(), 2);
+    }
+
+    #[test]
+    fn test_upgrade_waits_for_readers() {
+        let lock = RwLock::new(0);
+        let upgrading = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let reader = lock.read().unwrap();
+            s.spawn(|| {
+                let upgradable = lock.upgradable_read().unwrap();
+                upgrading.store(true, Ordering::Relaxed);
+                *RwLockUpgradableReadGuard::upgrade(upgradable) += 1;
+            });
+            while !upgrading.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(*reader, 0);
+            drop(reader);
+        });
+
+        assert_eq!(lock.into_inner().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_check_then_write() {
+        let lock = RwLock::new(Vec::new());
+
+        // Every thread inserts the same keys, each must end up there once.
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for key in 0..100 {
+                        let keys = lock.upgradable_read().unwrap();
+                        if !keys.contains(&key) {
+                            RwLockUpgradableReadGuard::upgrade(keys).push(key);
+                        }
+                        assert!(lock.read().unwrap().contains(&key));
+                    }
+                });
+            }
+        });
+
+        assert_eq!(lock.into_inner().unwrap(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_timed_locks() {
+        let lock = RwLock::new(0);
+
+        thread::scope(|s| {
+            let reader = lock.read().unwrap();
+            s.spawn(|| {
+                let timed_out = lock.try_write_for(Duration::from_millis(10));
+                assert!(matches!(timed_out, Err(TryLockError::WouldBlock)));
+            })
+            .join()
+            .unwrap();
+            // The writer that gave up doesn't keep new readers out.
+            assert!(lock.try_read().is_ok());
+            drop(reader);
+
+            let writer = lock.write().unwrap();
+            s.spawn(|| {
+                let timed_out = lock.try_read_for(Duration::from_millis(10));
+                assert!(matches!(timed_out, Err(TryLockError::WouldBlock)));
+            })
+            .join()
+            .unwrap();
+            let waiter = s.spawn(|| *lock.try_read_for(Duration::from_secs(60)).unwrap());
+            thread::sleep(Duration::from_millis(20));
+            drop(writer);
+            assert_eq!(waiter.join().unwrap(), 0);
+        });
+    }
+
+    /// Holds a read lock while a writer queues up, returns whether a new reader
+    /// got in meanwhile.
+    fn reader_passes_waiting_writer(fairness: Fairness) -> bool {
+        let lock = RwLock::with_fairness(0, fairness);
+        let writer_started = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let reader = lock.read().unwrap();
+            s.spawn(|| {
+                writer_started.store(true, Ordering::Relaxed);
+                *lock.write().unwrap() += 1;
+            });
+            while !writer_started.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(20));
+            let passed = lock.try_read().is_ok();
+            drop(reader);
+            passed
+        })
+    }
+
+    #[test]
+    fn test_fairness_toward_waiting_writers() {
+        assert!(!reader_passes_waiting_writer(Fairness::WriterPreferring));
+        assert!(reader_passes_waiting_writer(Fairness::ReaderPreferring));
+        assert!(!reader_passes_waiting_writer(Fairness::PhaseFair));
+    }
+
+    #[test]
+    fn test_phase_fair_readers_go_before_next_writer() {
+        let lock = RwLock::with_fairness((), Fairness::PhaseFair);
+        let order = Mutex::new(Vec::new());
+
+        thread::scope(|s| {
+            let writer = lock.write().unwrap();
+            s.spawn(|| {
+                let _reader = lock.read().unwrap();
+                order.lock().unwrap().push("reader");
+            });
+            thread::sleep(Duration::from_millis(20));
+            s.spawn(|| {
+                let _writer = lock.write().unwrap();
+                order.lock().unwrap().push("writer");
+            });
+            thread::sleep(Duration::from_millis(20));
+            drop(writer);
+        });
+
+        assert_eq!(order.into_inner().unwrap(), ["reader", "writer"]);
+    }
+
+    #[test]
+    fn test_counter_with_every_fairness() {
+        for fairness in [
+            Fairness::WriterPreferring,
+            Fairness::ReaderPreferring,
+            Fairness::PhaseFair,
+        ] {
+            let lock = RwLock::with_fairness(0, fairness);
+            assert_eq!(lock.fairness(), fairness);
+
+            thread::scope(|s| {
+                for _ in 0..4 {
+                    s.spawn(|| {
+                        for _ in 0..1000 {
+                            *lock.write().unwrap() += 1;
+                        }
+                    });
+                    s.spawn(|| {
+                        for _ in 0..1000 {
+                            let _ = lock.try_read_for(Duration::from_micros(10));
+                            assert!(*lock.read().unwrap() <= 4000);
+                        }
+                    });
+                }
+            });
+
+            assert_eq!(lock.into_inner().unwrap(), 4000);
+        }
+    }
+}
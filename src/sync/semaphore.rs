@@ -0,0 +1,213 @@
+use std::fmt::{self, Debug};
+
+use crate::sync::{ignore_poison, Condvar, Mutex};
+
+/// A number of permits threads take and give back, for example to limit how
+/// many of them use a resource at once.
+///
+/// Waiting threads aren't queued in order: a thread waiting for many permits
+/// can be overtaken by threads taking few.
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Permits taken from a [`Semaphore`], given back when dropped.
+#[must_use = "the permits are given back right away if the guard is dropped"]
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until a permit is available.
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        self.acquire_many(1)
+    }
+
+    /// Blocks until `n` permits are available and takes them at once.
+    pub fn acquire_many(&self, n: usize) -> SemaphorePermit<'_> {
+        let permits = ignore_poison(self.permits.lock());
+        let mut permits = ignore_poison(self.released.wait_while(permits, |permits| *permits < n));
+        *permits -= n;
+        self.permit(n)
+    }
+
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        self.try_acquire_many(1)
+    }
+
+    /// Takes `n` permits if that many are available, none otherwise.
+    pub fn try_acquire_many(&self, n: usize) -> Option<SemaphorePermit<'_>> {
+        let mut permits = ignore_poison(self.permits.lock());
+        if *permits < n {
+            return None;
+        }
+        *permits -= n;
+        Some(self.permit(n))
+    }
+
+    /// Adds permits, for example ones a [`SemaphorePermit::forget`] kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of available permits would overflow a `usize`.
+    pub fn add_permits(&self, n: usize) {
+        let mut permits = ignore_poison(self.permits.lock());
+        match permits.checked_add(n) {
+            Some(sum) => *permits = sum,
+            None => {
+                drop(permits);
+                panic!("semaphore permits overflowed");
+            }
+        }
+        drop(permits);
+        // Waiters may need different numbers of permits, all of them check.
+        self.released.notify_all();
+    }
+
+    pub fn available_permits(&self) -> usize {
+        *ignore_poison(self.permits.lock())
+    }
+
+    fn permit(&self, permits: usize) -> SemaphorePermit<'_> {
+        SemaphorePermit {
+            semaphore: self,
+            permits,
+        }
+    }
+}
+
+impl Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Semaphore")
+            .field("permits", &self.available_permits())
+            .finish()
+    }
+}
+
+impl SemaphorePermit<'_> {
+    pub fn num_permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Keeps the permits taken, the semaphore has that many fewer from now on.
+    pub fn forget(mut self) {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        if self.permits > 0 {
+            self.semaphore.add_permits(self.permits);
+        }
+    }
+}
+
+impl Debug for SemaphorePermit<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SemaphorePermit")
+            .field("permits", &self.permits)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Semaphore;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<Semaphore>();
+        assert_sync::<Semaphore>();
+    }
+
+    #[test]
+    fn test_permits_are_given_back() {
+        let semaphore = Semaphore::new(3);
+
+        let one = semaphore.acquire();
+        let two = semaphore.try_acquire_many(2).unwrap();
+        assert_eq!(two.num_permits(), 2);
+        assert!(semaphore.try_acquire().is_none());
+        assert_eq!(format!("{:?}", semaphore), "Semaphore { permits: 0 }");
+
+        drop(two);
+        assert_eq!(semaphore.available_permits(), 2);
+        drop(one);
+        assert_eq!(semaphore.available_permits(), 3);
+    }
+
+    #[test]
+    fn test_forget_and_add_permits() {
+        let semaphore = Semaphore::new(2);
+
+        semaphore.acquire().forget();
+        assert_eq!(semaphore.available_permits(), 1);
+
+        semaphore.add_permits(2);
+        assert_eq!(semaphore.acquire_many(3).num_permits(), 3);
+    }
+
+    #[test]
+    fn test_add_permits_overflow_panics() {
+        let semaphore = Semaphore::new(usize::MAX);
+
+        let result = std::panic::catch_unwind(|| semaphore.add_permits(1));
+        let message = *result.unwrap_err().downcast::<&str>().unwrap();
+        assert_eq!(message, "semaphore permits overflowed");
+        assert_eq!(semaphore.available_permits(), usize::MAX);
+        assert!(semaphore.try_acquire().is_some());
+    }
+
+    #[test]
+    fn test_acquire_many_waits_for_all() {
+        let semaphore = Semaphore::new(2);
+
+        thread::scope(|s| {
+            let permit = semaphore.acquire();
+            let waiter = s.spawn(|| semaphore.acquire_many(2).num_permits());
+            thread::sleep(Duration::from_millis(10));
+            assert!(!waiter.is_finished());
+            drop(permit);
+            assert_eq!(waiter.join().unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_limits_concurrency() {
+        let semaphore = Semaphore::new(2);
+        let (running, max_running) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..50 {
+                        let _permit = semaphore.acquire();
+                        let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                        max_running.fetch_max(now, Ordering::SeqCst);
+                        thread::yield_now();
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                });
+            }
+        });
+
+        assert!(max_running.load(Ordering::SeqCst) <= 2);
+        assert_eq!(semaphore.available_permits(), 2);
+    }
+}
@@ -0,0 +1,250 @@
+use std::fmt::{self, Debug};
+use std::mem::{self, MaybeUninit};
+use std::panic::{RefUnwindSafe, UnwindSafe};
+// The bytes past the last whole word, which crate::atomic doesn't wrap.
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::atomic::{self, AtomicUsize};
+use crate::sync::spin_lock::Backoff;
+use crate::unsafe_cell::UnsafeCell;
+
+/// A lock for small `Copy` values that are read far more often than written,
+/// like timestamps or counters: readers never write to shared memory, so they
+/// don't slow each other down.
+///
+/// A sequence number is odd while a writer is in the middle of a write. Readers
+/// copy the value out without locking and check that the sequence was even and
+/// didn't change meanwhile, otherwise the copy may be torn and they retry. The
+/// value is copied word by word with relaxed atomic loads and stores, so the
+/// racing copies aren't data races, and only used once the sequence vouches for
+/// it.
+///
+/// Like in [`AtomicCell`](crate::atomic_cell::AtomicCell), the words are copied
+/// as raw bits, which assumes `T` has no padding bytes.
+pub struct SeqLock<T: Copy> {
+    seq: AtomicUsize,
+    value: UnsafeCell<Words<T>>,
+}
+
+/// Aligns the value for the word-sized atomics that copy it.
+#[repr(C, align(8))]
+struct Words<T>(T);
+
+const WORD: usize = mem::size_of::<usize>();
+
+/// Copies the value at `src` out with relaxed loads, by words and then bytes.
+///
+/// SAFETY: `src` must be valid for reads, writes to it must be atomic too.
+unsafe fn load_relaxed<T>(src: *const Words<T>) -> MaybeUninit<T> {
+    let mut value = MaybeUninit::<T>::uninit();
+    let src = src.cast::<u8>();
+    let dst = value.as_mut_ptr().cast::<u8>();
+    let words = mem::size_of::<T>() / WORD;
+    for i in 0..words {
+        let word = (*src.add(i * WORD).cast::<AtomicUsize>()).load_relaxed();
+        dst.add(i * WORD).cast::<usize>().write_unaligned(word);
+    }
+    for i in words * WORD..mem::size_of::<T>() {
+        dst.add(i)
+            .write((*src.add(i).cast::<AtomicU8>()).load(Ordering::Relaxed));
+    }
+    value
+}
+
+/// Copies `value` to `dst` with relaxed stores, by words and then bytes.
+///
+/// SAFETY: `dst` must be valid for writes, other accesses to it must be atomic.
+unsafe fn store_relaxed<T>(dst: *mut Words<T>, value: T) {
+    let src = (&value as *const T).cast::<u8>();
+    let dst = dst.cast::<u8>();
+    let words = mem::size_of::<T>() / WORD;
+    for i in 0..words {
+        let word = src.add(i * WORD).cast::<usize>().read_unaligned();
+        (*dst.add(i * WORD).cast::<AtomicUsize>()).store_relaxed(word);
+    }
+    for i in words * WORD..mem::size_of::<T>() {
+        (*dst.add(i).cast::<AtomicU8>()).store(src.add(i).read(), Ordering::Relaxed);
+    }
+}
+
+// SAFETY: Values are copied between threads, and writers exclude each other.
+unsafe impl<T: Copy + Send> Send for SeqLock<T> {}
+unsafe impl<T: Copy + Send> Sync for SeqLock<T> {}
+
+// An update that panics writes nothing, the value stays as it was.
+impl<T: Copy> UnwindSafe for SeqLock<T> {}
+impl<T: Copy> RefUnwindSafe for SeqLock<T> {}
+
+impl<T: Copy> SeqLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            seq: AtomicUsize::new(0),
+            value: UnsafeCell::new(Words(value)),
+        }
+    }
+
+    /// Copies the value out, retrying while a writer is busy.
+    pub fn read(&self) -> T {
+        let mut backoff = Backoff::new();
+        loop {
+            // Acquire pairs with the Release of the write that made it even.
+            let before = self.seq.load_acquire();
+            if before & 1 == 0 {
+                // SAFETY: May race with a writer, the copy stays uninit until the
+                // sequence shows it wasn't torn.
+                let value = unsafe { load_relaxed(self.value.get()) };
+                // Keeps the reads of the value before the second load of seq.
+                atomic::fence_acquire();
+                if self.seq.load_relaxed() == before {
+                    // SAFETY: No writer touched the value while we copied it.
+                    return unsafe { value.assume_init() };
+                }
+            }
+            backoff.spin();
+        }
+    }
+
+    /// Replaces the value, waiting for other writers.
+    pub fn write(&self, value: T) {
+        self.update(|_| value);
+    }
+
+    /// Replaces the value with `f` of the current one, other writers wait
+    /// meanwhile. Returns the new value.
+    pub fn update(&self, f: impl FnOnce(T) -> T) -> T {
+        let _writing = self.lock();
+        // SAFETY: We're the only writer, readers only copy the value out.
+        let value = f(unsafe { load_relaxed(self.value.get()).assume_init() });
+        // SAFETY: As above, readers racing with this discard what they read.
+        unsafe { store_relaxed(self.value.get(), value) };
+        value
+    }
+
+    /// Makes the sequence odd until the returned guard is dropped.
+    fn lock(&self) -> Writing<'_> {
+        let mut backoff = Backoff::new();
+        loop {
+            let seq = self.seq.load_relaxed();
+            // Acquire pairs with the Release of the previous writer.
+            if seq & 1 == 0 && self.seq.compare_exchange_weak_acquire(seq, seq + 1).is_ok() {
+                // Readers that see our writes to the value must also see the odd
+                // sequence, the writes can't move before it.
+                atomic::fence_release();
+                return Writing {
+                    seq: &self.seq,
+                    before: seq,
+                };
+            }
+            backoff.spin();
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner().0
+    }
+
+    /// No locking needed, `&mut self` guarantees there are no readers or writers.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.value.get_mut().0
+    }
+}
+
+/// Makes the sequence even again when dropped, also if the update panicked
+/// before writing anything.
+struct Writing<'a> {
+    seq: &'a AtomicUsize,
+    before: usize,
+}
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        // Release pairs with the Acquire of readers and the next writer.
+        self.seq.store_release(self.before.wrapping_add(2));
+    }
+}
+
+impl<T: Copy + Default> Default for SeqLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T: Copy> From<T> for SeqLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Copy + Debug> Debug for SeqLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeqLock")
+            .field("value", &self.read())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SeqLock;
+    use std::panic;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_read_write() {
+        let mut lock = SeqLock::new((1, 2));
+
+        lock.write((3, 4));
+        assert_eq!(lock.read(), (3, 4));
+        assert_eq!(lock.update(|(a, b)| (b, a)), (4, 3));
+        assert_eq!(format!("{:?}", lock), "SeqLock { value: (4, 3) }");
+
+        lock.get_mut().0 = 5;
+        assert_eq!(lock.into_inner(), (5, 3));
+    }
+
+    #[test]
+    fn test_reads_are_never_torn() {
+        // Big enough to take several stores to write.
+        let lock = SeqLock::new([0u64; 8]);
+        let done = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let value = lock.read();
+                        assert!(value.iter().all(|&x| x == value[0]), "{:?}", value);
+                    }
+                });
+            }
+            let writers: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        for _ in 0..10_000 {
+                            lock.update(|value| [value[0] + 1; 8]);
+                        }
+                    })
+                })
+                .collect();
+            for writer in writers {
+                writer.join().unwrap();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+
+        assert_eq!(lock.read(), [20_000; 8]);
+    }
+
+    #[test]
+    fn test_panicking_update_unlocks() {
+        let lock = SeqLock::new(1);
+
+        let result = panic::catch_unwind(|| lock.update(|_| panic!("interrupted update")));
+
+        assert!(result.is_err());
+        assert_eq!(lock.read(), 1);
+        lock.write(2);
+        assert_eq!(lock.read(), 2);
+    }
+}
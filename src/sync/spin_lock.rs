@@ -0,0 +1,195 @@
+use std::fmt::{self, Debug};
+use std::hint;
+
+use crate::atomic::AtomicBool;
+use crate::refs::{BorrowFlag, FlagRefMut};
+use crate::unsafe_cell::UnsafeCell;
+
+/// Spinning doubles up to 2^SPIN_LIMIT iterations between attempts.
+const SPIN_LIMIT: u32 = 6;
+
+/// Exponential backoff: after every failed attempt the thread waits twice as
+/// long before the next, so contending threads don't keep hammering the cache
+/// line they all want.
+pub(crate) struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    pub(crate) fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    pub(crate) fn spin(&mut self) {
+        for _ in 0..1 << self.step {
+            hint::spin_loop();
+        }
+        if self.step < SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+}
+
+/// The lock state of a [`SpinLock`], released by its guard.
+pub struct RawSpinLock {
+    locked: AtomicBool,
+}
+
+impl RawSpinLock {
+    const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        // Acquire pairs with the Release in unlock.
+        self.locked.compare_exchange_acquire(false, true).is_ok()
+    }
+
+    fn lock(&self) {
+        let mut backoff = Backoff::new();
+        while !self.try_lock() {
+            // Waits on a load, a failing CAS would take the cache line exclusive.
+            while self.locked.load_relaxed() {
+                backoff.spin();
+            }
+        }
+    }
+}
+
+impl BorrowFlag for RawSpinLock {
+    fn release_shared(&self) {
+        unreachable!("A SpinLock is never borrowed shared");
+    }
+
+    fn release_exclusive(&self) {
+        self.locked.store_release(false);
+    }
+}
+
+/// Unlocks the spin lock when dropped.
+pub type SpinLockGuard<'a, T> = FlagRefMut<'a, T, RawSpinLock>;
+
+/// A lock whose waiters never sleep, they spin until it's free.
+///
+/// It needs nothing from the OS, just an atomic flag, but burns CPU time while
+/// waiting and can stall for long if the holder gets descheduled. Only worth it
+/// for tiny critical sections, or where there's no scheduler to park threads
+/// with. Unlike [`Mutex`](super::Mutex) it doesn't track poisoning.
+pub struct SpinLock<T> {
+    raw: RawSpinLock,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The lock hands out one `&mut T` at a time, possibly on another thread.
+unsafe impl<T: Send> Send for SpinLock<T> {}
+unsafe impl<T: Send> Sync for SpinLock<T> {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            raw: RawSpinLock::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Spins until the lock is free. Locking it again on the same thread spins
+    /// forever.
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        self.raw.lock();
+        // SAFETY: We hold the lock.
+        unsafe { self.guard() }
+    }
+
+    pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
+        if self.raw.try_lock() {
+            // SAFETY: We hold the lock.
+            Some(unsafe { self.guard() })
+        } else {
+            None
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// No locking needed, `&mut self` guarantees there are no guards.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// SAFETY: The lock must be held, the guard releases it.
+    unsafe fn guard(&self) -> SpinLockGuard<'_, T> {
+        FlagRefMut::new(&self.raw, &mut *self.value.get())
+    }
+}
+
+impl<T: Default> Default for SpinLock<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for SpinLock<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for SpinLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(value) => f.debug_struct("SpinLock").field("value", &*value).finish(),
+            None => f.write_str("SpinLock { <locked> }"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpinLock;
+    use std::thread;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<SpinLock<std::cell::Cell<i32>>>();
+        assert_sync::<SpinLock<std::cell::Cell<i32>>>();
+        assert_not_impl!(SpinLock<std::rc::Rc<i32>>: Sync);
+    }
+
+    #[test]
+    fn test_lock_and_try_lock() {
+        let lock = SpinLock::new(vec![1]);
+
+        let mut guard = lock.lock();
+        guard.push(2);
+        assert!(lock.try_lock().is_none());
+        assert_eq!(format!("{:?}", lock), "SpinLock { <locked> }");
+        drop(guard);
+
+        assert_eq!(*lock.try_lock().unwrap(), [1, 2]);
+        assert_eq!(format!("{:?}", lock), "SpinLock { value: [1, 2] }");
+    }
+
+    #[test]
+    fn test_counter_across_threads() {
+        let counter = SpinLock::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(counter.into_inner(), 8000);
+    }
+}
@@ -0,0 +1,135 @@
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use crate::sync::{ignore_poison, Condvar, Mutex};
+
+/// Waits for a group of threads to finish: every thread holds a clone and drops
+/// it when done, `wait` returns once all clones are gone. Unlike a [`Barrier`],
+/// the number of threads needn't be known up front.
+///
+/// [`Barrier`]: super::Barrier
+pub struct WaitGroup {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    count: Mutex<usize>,
+    all_done: Condvar,
+}
+
+impl WaitGroup {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                count: Mutex::new(1),
+                all_done: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Drops this handle and blocks until all other clones are dropped too.
+    pub fn wait(self) {
+        let inner = self.inner.clone();
+        drop(self);
+        let count = ignore_poison(inner.count.lock());
+        let _count = ignore_poison(inner.all_done.wait_while(count, |count| *count > 0));
+    }
+}
+
+impl Clone for WaitGroup {
+    /// Registers one more thread to wait for.
+    fn clone(&self) -> Self {
+        *ignore_poison(self.inner.count.lock()) += 1;
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl Drop for WaitGroup {
+    fn drop(&mut self) {
+        let mut count = ignore_poison(self.inner.count.lock());
+        *count -= 1;
+        if *count == 0 {
+            self.inner.all_done.notify_all();
+        }
+    }
+}
+
+impl Default for WaitGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for WaitGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitGroup")
+            .field("count", &*ignore_poison(self.inner.count.lock()))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WaitGroup;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+
+    #[test]
+    fn test_auto_traits() {
+        assert_send::<WaitGroup>();
+        assert_sync::<WaitGroup>();
+    }
+
+    #[test]
+    fn test_wait_for_all_clones() {
+        let wg = WaitGroup::new();
+        let finished = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                let wg = wg.clone();
+                let finished = &finished;
+                s.spawn(move || {
+                    thread::sleep(Duration::from_millis(10));
+                    finished.fetch_add(1, Ordering::Relaxed);
+                    drop(wg);
+                });
+            }
+            wg.wait();
+            assert_eq!(finished.load(Ordering::Relaxed), 4);
+        });
+    }
+
+    #[test]
+    fn test_wait_alone_returns() {
+        let wg = WaitGroup::new();
+        let other = wg.clone();
+        assert_eq!(format!("{:?}", wg), "WaitGroup { count: 2 }");
+        drop(other);
+
+        wg.wait();
+    }
+
+    #[test]
+    fn test_waiters_can_nest() {
+        let outer = WaitGroup::new();
+
+        thread::scope(|s| {
+            let registered = outer.clone();
+            s.spawn(move || {
+                let inner = WaitGroup::new();
+                let worker = inner.clone();
+                thread::spawn(move || drop(worker));
+                inner.wait();
+                drop(registered);
+            });
+            outer.wait();
+        });
+    }
+}
@@ -0,0 +1,31 @@
+//! Where the primitives put threads to sleep until an atomic changes.
+//!
+//! On Linux that's the kernel's futex, which keys waiters by the atomic's
+//! address and needs no memory of its own. Elsewhere threads park in a queue the
+//! primitive keeps next to the atomic. Both have the same interface: every queue
+//! belongs to exactly one atomic, passed to all its functions.
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod futex;
+#[cfg_attr(
+    all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ),
+    allow(dead_code)
+)]
+mod parking;
+
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+pub(crate) use self::futex::WaitQueue;
+#[cfg(not(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+)))]
+pub(crate) use self::parking::WaitQueue;
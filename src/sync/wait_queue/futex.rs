@@ -0,0 +1,97 @@
+use std::convert::TryFrom;
+use std::io;
+use std::os::raw::{c_int, c_long};
+use std::ptr;
+use std::sync::atomic::AtomicU32;
+use std::time::Instant;
+
+extern "C" {
+    // libc's, which std links anyway.
+    fn syscall(number: c_long, ...) -> c_long;
+}
+
+#[cfg(target_arch = "x86_64")]
+const SYS_FUTEX: c_long = 202;
+#[cfg(target_arch = "aarch64")]
+const SYS_FUTEX: c_long = 98;
+
+// The private variants skip the bookkeeping for futexes shared across
+// processes.
+const FUTEX_WAIT_PRIVATE: c_int = 128;
+const FUTEX_WAKE_PRIVATE: c_int = 1 | 128;
+
+const ETIMEDOUT: i32 = 110;
+
+#[repr(C)]
+struct Timespec {
+    tv_sec: i64,
+    tv_nsec: c_long,
+}
+
+/// Threads waiting for an atomic to change, in the kernel's futex queue for
+/// the atomic's address.
+///
+/// The kernel checks that the atomic still holds the expected value while it
+/// holds the queue's lock, so a wake-up after changing the atomic can't get lost.
+pub(crate) struct WaitQueue;
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        WaitQueue
+    }
+
+    /// Blocks until woken up, unless `atomic` doesn't hold `expected` anymore.
+    /// May also return spuriously, callers check their condition in a loop.
+    pub(crate) fn wait(&self, atomic: &AtomicU32, expected: u32) {
+        self.wait_until(atomic, expected, None);
+    }
+
+    /// Like `wait`, but gives up at `deadline`. Returns false if it timed out.
+    pub(crate) fn wait_until(
+        &self,
+        atomic: &AtomicU32,
+        expected: u32,
+        deadline: Option<Instant>,
+    ) -> bool {
+        let timeout = deadline.map(|deadline| {
+            // Relative, FUTEX_WAIT measures it on the monotonic clock.
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            Timespec {
+                tv_sec: i64::try_from(timeout.as_secs()).unwrap_or(i64::MAX),
+                tv_nsec: timeout.subsec_nanos().into(),
+            }
+        });
+        let timeout = timeout
+            .as_ref()
+            .map_or(ptr::null(), |t| t as *const Timespec);
+        // SAFETY: The atomic and the timeout outlive the call.
+        let result = unsafe {
+            syscall(
+                SYS_FUTEX,
+                atomic.as_ptr(),
+                FUTEX_WAIT_PRIVATE,
+                expected,
+                timeout,
+            )
+        };
+        // Woken up (0), the value had changed (EAGAIN), or interrupted (EINTR),
+        // only a timeout tells the caller to give up.
+        result == 0 || io::Error::last_os_error().raw_os_error() != Some(ETIMEDOUT)
+    }
+
+    /// Wakes up the longest waiting thread, returns whether there was one.
+    pub(crate) fn wake_one(&self, atomic: &AtomicU32) -> bool {
+        wake(atomic, 1) > 0
+    }
+
+    /// Wakes up every waiting thread.
+    pub(crate) fn wake_all(&self, atomic: &AtomicU32) {
+        wake(atomic, c_int::MAX);
+    }
+}
+
+/// Returns the number of threads woken.
+fn wake(atomic: &AtomicU32, count: c_int) -> c_long {
+    // SAFETY: The atomic outlives the call.
+    unsafe { syscall(SYS_FUTEX, atomic.as_ptr(), FUTEX_WAKE_PRIVATE, count) }
+}
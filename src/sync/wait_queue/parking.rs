@@ -0,0 +1,209 @@
+use std::collections::VecDeque;
+use std::hint;
+use std::panic::RefUnwindSafe;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+use crate::atomic::AtomicBool;
+use crate::unsafe_cell::UnsafeCell;
+
+/// Threads waiting for an atomic to change, in a queue of parked threads.
+///
+/// It has the semantics of a futex: `wait` only goes to sleep if the atomic
+/// still holds the expected value, checked under the queue's lock, so a wake-up
+/// after changing the atomic can't get lost. The queue belongs to one atomic,
+/// so the wake functions don't need it.
+pub(crate) struct WaitQueue {
+    locked: AtomicBool,
+    waiters: UnsafeCell<VecDeque<Arc<Waiter>>>,
+}
+
+// SAFETY: `waiters` is only accessed with `locked` held.
+unsafe impl Sync for WaitQueue {}
+
+// No user code runs with the queue locked, a panic can't leave it inconsistent.
+impl RefUnwindSafe for WaitQueue {}
+
+struct Waiter {
+    thread: Thread,
+    woken: AtomicBool,
+}
+
+impl WaitQueue {
+    pub(crate) const fn new() -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            waiters: UnsafeCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Blocks until woken up, unless `atomic` doesn't hold `expected` anymore.
+    /// May also return spuriously, callers check their condition in a loop.
+    pub(crate) fn wait(&self, atomic: &AtomicU32, expected: u32) {
+        self.wait_until(atomic, expected, None);
+    }
+
+    /// Like `wait`, but gives up at `deadline`. Returns false if it timed out.
+    pub(crate) fn wait_until(
+        &self,
+        atomic: &AtomicU32,
+        expected: u32,
+        deadline: Option<Instant>,
+    ) -> bool {
+        let waiter = Arc::new(Waiter {
+            thread: thread::current(),
+            woken: AtomicBool::new(false),
+        });
+        {
+            let mut waiters = self.lock();
+            // The thread changing the atomic wakes us after that, taking the lock
+            // we hold: either we see the change here or it sees us in the queue.
+            if atomic.load(Ordering::Relaxed) != expected {
+                return true;
+            }
+            waiters.push_back(waiter.clone());
+        }
+        // Acquire pairs with the Release in wake, park can return spuriously.
+        while !waiter.woken.load_acquire() {
+            match deadline {
+                None => thread::park(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return self.cancel(&waiter);
+                    }
+                    thread::park_timeout(deadline - now);
+                }
+            }
+        }
+        true
+    }
+
+    /// Takes a timed out waiter out of the queue, so that a wake-up doesn't get
+    /// lost on it. Returns whether it was woken in the meantime after all.
+    fn cancel(&self, waiter: &Arc<Waiter>) -> bool {
+        let mut waiters = self.lock();
+        match waiters
+            .iter()
+            .position(|queued| Arc::ptr_eq(queued, waiter))
+        {
+            Some(index) => {
+                waiters.remove(index);
+                false
+            }
+            // Whoever dequeued us is waking us.
+            None => true,
+        }
+    }
+
+    /// Wakes up the longest waiting thread, returns whether there was one.
+    pub(crate) fn wake_one(&self, _atomic: &AtomicU32) -> bool {
+        let waiter = self.lock().pop_front();
+        match waiter {
+            Some(waiter) => {
+                wake(&waiter);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wakes up every waiting thread.
+    pub(crate) fn wake_all(&self, _atomic: &AtomicU32) {
+        let waiters = std::mem::take(&mut *self.lock());
+        for waiter in waiters {
+            wake(&waiter);
+        }
+    }
+
+    fn lock(&self) -> QueueGuard<'_> {
+        // Only held for a few instructions, spinning beats parking.
+        while self
+            .locked
+            .compare_exchange_weak_acquire(false, true)
+            .is_err()
+        {
+            hint::spin_loop();
+        }
+        QueueGuard { queue: self }
+    }
+}
+
+fn wake(waiter: &Waiter) {
+    waiter.woken.store_release(true);
+    waiter.thread.unpark();
+}
+
+struct QueueGuard<'a> {
+    queue: &'a WaitQueue,
+}
+
+impl std::ops::Deref for QueueGuard<'_> {
+    type Target = VecDeque<Arc<Waiter>>;
+
+    fn deref(&self) -> &Self::Target {
+        // SAFETY: We hold the lock.
+        unsafe { &*self.queue.waiters.get() }
+    }
+}
+
+impl std::ops::DerefMut for QueueGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        // SAFETY: We hold the lock.
+        unsafe { &mut *self.queue.waiters.get() }
+    }
+}
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.queue.locked.store_release(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WaitQueue;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    // Exercised directly, on Linux the primitives use the futex queue instead.
+
+    #[test]
+    fn test_wait_and_wake() {
+        let queue = WaitQueue::new();
+        let atomic = AtomicU32::new(0);
+
+        // Returns right away, the value changed already.
+        queue.wait(&atomic, 1);
+
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while atomic.load(Ordering::Relaxed) == 0 {
+                        queue.wait(&atomic, 0);
+                    }
+                });
+            }
+            thread::sleep(Duration::from_millis(10));
+            atomic.store(1, Ordering::Relaxed);
+            queue.wake_all(&atomic);
+        });
+
+        assert!(!queue.wake_one(&atomic));
+    }
+
+    #[test]
+    fn test_wait_times_out() {
+        let queue = WaitQueue::new();
+        let atomic = AtomicU32::new(0);
+
+        let deadline = Instant::now() + Duration::from_millis(10);
+        assert!(!queue.wait_until(&atomic, 0, Some(deadline)));
+        assert!(Instant::now() >= deadline);
+        // The timed out waiter left the queue.
+        assert!(!queue.wake_one(&atomic));
+    }
+}
@@ -0,0 +1,286 @@
+use std::fmt::{self, Debug};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::refs::{BorrowFlag, FlagRefMut};
+use crate::sync::parking_lot;
+use crate::sync::spin_lock::Backoff;
+use crate::unsafe_cell::UnsafeCell;
+
+const LOCKED: u8 = 1;
+// Threads are parked on the lock, unlocking must wake one.
+const PARKED: u8 = 2;
+
+/// How often `lock` backs off and retries before parking, only while nobody is
+/// parked yet.
+const SPINS: u32 = 10;
+
+/// The lock state of a [`WordMutex`], a single byte.
+pub struct RawWordMutex {
+    state: AtomicU8,
+}
+
+impl RawWordMutex {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(0),
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        let mut state = self.state.load(Ordering::Relaxed);
+        while state & LOCKED == 0 {
+            // Acquire pairs with the Release in unlock.
+            match self.state.compare_exchange_weak(
+                state,
+                state | LOCKED,
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => state = actual,
+            }
+        }
+        false
+    }
+
+    fn lock(&self) {
+        if self
+            .state
+            .compare_exchange(0, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            self.lock_slow();
+        }
+    }
+
+    #[cold]
+    fn lock_slow(&self) {
+        let mut backoff = Backoff::new();
+        let mut spins = 0;
+        loop {
+            if self.try_lock() {
+                return;
+            }
+            let state = self.state.load(Ordering::Relaxed);
+            // Spinning is pointless once others park, the lock is held long.
+            if state & PARKED == 0 && spins < SPINS {
+                spins += 1;
+                backoff.spin();
+                continue;
+            }
+            if state & PARKED == 0
+                && self
+                    .state
+                    .compare_exchange_weak(
+                        state,
+                        state | PARKED,
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    .is_err()
+            {
+                continue;
+            }
+            // Only parks if the lock wasn't released since, unlock clears the bit
+            // with the bucket locked.
+            parking_lot::park(self.key(), || {
+                self.state.load(Ordering::Relaxed) == LOCKED | PARKED
+            });
+            backoff = Backoff::new();
+            spins = 0;
+        }
+    }
+
+    fn unlock(&self) {
+        // Release pairs with the Acquire of the next owner.
+        if self
+            .state
+            .compare_exchange(LOCKED, 0, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            self.unlock_slow();
+        }
+    }
+
+    #[cold]
+    fn unlock_slow(&self) {
+        parking_lot::unpark_one(self.key(), |more_parked| {
+            let state = if more_parked { PARKED } else { 0 };
+            self.state.store(state, Ordering::Release);
+        });
+    }
+
+    fn key(&self) -> usize {
+        &self.state as *const AtomicU8 as usize
+    }
+}
+
+impl BorrowFlag for RawWordMutex {
+    fn release_shared(&self) {
+        unreachable!("A WordMutex is never borrowed shared");
+    }
+
+    fn release_exclusive(&self) {
+        self.unlock();
+    }
+}
+
+/// Unlocks the mutex when dropped.
+pub type WordMutexGuard<'a, T> = FlagRefMut<'a, T, RawWordMutex>;
+
+/// A mutex whose whole state is one byte, the small-and-fast alternative to
+/// [`Mutex`](super::Mutex) in the style of `parking_lot`.
+///
+/// Waiting threads spin for a while, then park in a global table keyed by the
+/// mutex's address instead of a queue in the mutex. It doesn't track poisoning,
+/// so `lock` returns the guard directly.
+pub struct WordMutex<T> {
+    raw: RawWordMutex,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: The lock hands out one `&mut T` at a time, possibly on another thread.
+unsafe impl<T: Send> Send for WordMutex<T> {}
+unsafe impl<T: Send> Sync for WordMutex<T> {}
+
+impl<T> WordMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            raw: RawWordMutex::new(),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// Blocks until the lock is free. Locking it again on the same thread
+    /// deadlocks.
+    pub fn lock(&self) -> WordMutexGuard<'_, T> {
+        self.raw.lock();
+        // SAFETY: We hold the lock.
+        unsafe { self.guard() }
+    }
+
+    pub fn try_lock(&self) -> Option<WordMutexGuard<'_, T>> {
+        if self.raw.try_lock() {
+            // SAFETY: We hold the lock.
+            Some(unsafe { self.guard() })
+        } else {
+            None
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// No locking needed, `&mut self` guarantees there are no guards.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// SAFETY: The lock must be held, the guard releases it.
+    unsafe fn guard(&self) -> WordMutexGuard<'_, T> {
+        FlagRefMut::new(&self.raw, &mut *self.value.get())
+    }
+}
+
+impl<T: Default> Default for WordMutex<T> {
+    fn default() -> Self {
+        Self::new(T::default())
+    }
+}
+
+impl<T> From<T> for WordMutex<T> {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<T: Debug> Debug for WordMutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.try_lock() {
+            Some(value) => f.debug_struct("WordMutex").field("value", &*value).finish(),
+            None => f.write_str("WordMutex { <locked> }"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WordMutex;
+    use std::mem;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_one_byte() {
+        assert_eq!(mem::size_of::<WordMutex<()>>(), 1);
+        assert_eq!(mem::size_of::<WordMutex<u32>>(), 8);
+    }
+
+    #[test]
+    fn test_selectable_as_mutex() {
+        use crate::sync::compact::{Mutex, MutexGuard};
+
+        let mutex = Mutex::new(1);
+        let mut guard: MutexGuard<'_, i32> = mutex.lock();
+        *guard += 1;
+        drop(guard);
+        assert_eq!(mutex.into_inner(), 2);
+    }
+
+    #[test]
+    fn test_lock_and_try_lock() {
+        let mutex = WordMutex::new(vec![1]);
+
+        let mut guard = mutex.lock();
+        guard.push(2);
+        assert!(mutex.try_lock().is_none());
+        assert_eq!(format!("{:?}", mutex), "WordMutex { <locked> }");
+        drop(guard);
+
+        assert_eq!(*mutex.try_lock().unwrap(), [1, 2]);
+        assert_eq!(format!("{:?}", mutex), "WordMutex { value: [1, 2] }");
+    }
+
+    #[test]
+    fn test_counter_across_threads() {
+        let counter = WordMutex::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        *counter.lock() += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(counter.into_inner(), 8000);
+    }
+
+    #[test]
+    fn test_waiters_park_until_unlock() {
+        let mutex = WordMutex::new(0);
+        let started = AtomicBool::new(false);
+
+        thread::scope(|s| {
+            let mut guard = mutex.lock();
+            for _ in 0..4 {
+                s.spawn(|| {
+                    started.store(true, Ordering::Relaxed);
+                    *mutex.lock() += 1;
+                });
+            }
+            while !started.load(Ordering::Relaxed) {
+                thread::yield_now();
+            }
+            // Long enough for the others to stop spinning and park.
+            thread::sleep(Duration::from_millis(20));
+            *guard += 1;
+        });
+
+        assert_eq!(mutex.into_inner(), 5);
+    }
+}
@@ -0,0 +1,60 @@
+use crate::cell::Cell;
+
+/// A cell whose value can be taken out exactly once through a shared reference.
+pub struct TakeCell<T> {
+    value: Cell<Option<T>>,
+}
+
+impl<T> TakeCell<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            value: Cell::new(Some(value)),
+        }
+    }
+
+    /// Takes the value out, subsequent calls return `None`.
+    pub fn take(&self) -> Option<T> {
+        self.value.take()
+    }
+
+    pub fn is_taken(&self) -> bool {
+        self.value.is_none()
+    }
+
+    pub fn get_mut(&mut self) -> Option<&mut T> {
+        self.value.get_mut().as_mut()
+    }
+
+    pub fn into_inner(self) -> Option<T> {
+        self.value.into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_take_once() {
+        let cell = TakeCell::new(String::from("handle"));
+        let (p1, p2) = (&cell, &cell);
+
+        assert_eq!(p1.take(), Some(String::from("handle")));
+        assert_eq!(p2.take(), None);
+        assert!(cell.is_taken());
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut cell = TakeCell::new(42);
+        *cell.get_mut().unwrap() = 43;
+        assert_eq!(cell.take(), Some(43));
+        assert_eq!(cell.get_mut(), None);
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let cell = TakeCell::new(42);
+        assert_eq!(cell.into_inner(), Some(42));
+    }
+}
@@ -0,0 +1,303 @@
+//! Owner cells keyed by a marker type instead of a runtime id.
+//!
+//! There is at most one `TCellOwner<Q>` in the whole program (and one
+//! `TLCellOwner<Q>` per thread), so any `TCell<Q, T>` can only be borrowed
+//! through that one owner. Creating a second owner for the same marker panics,
+//! after that there are no runtime checks at all.
+
+use std::any::TypeId;
+use std::fmt::{self, Debug};
+use std::marker::PhantomData;
+use std::sync::Mutex;
+
+use crate::qcell::impl_rw2_rw3;
+use crate::ref_cell::RefCell;
+use crate::unsafe_cell::UnsafeCell;
+
+// Marker types whose owner currently exists.
+static OWNERS: Mutex<Vec<TypeId>> = Mutex::new(Vec::new());
+
+thread_local! {
+    static THREAD_OWNERS: RefCell<Vec<TypeId>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Makes the marker part of a type without it affecting auto traits.
+type Marker<Q> = PhantomData<fn() -> Q>;
+
+/// The program-wide owner of all `TCell<Q, _>`s.
+pub struct TCellOwner<Q: 'static> {
+    _marker: Marker<Q>,
+}
+
+impl<Q: 'static> TCellOwner<Q> {
+    /// Panics if an owner for `Q` already exists.
+    // No Default, it shouldn't be able to panic.
+    #[allow(clippy::new_without_default)]
+    #[track_caller]
+    pub fn new() -> Self {
+        Self::try_new()
+            .expect("Illegal to create two TCellOwner instances for the same marker type")
+    }
+
+    pub fn try_new() -> Option<Self> {
+        let mut owners = OWNERS.lock().unwrap_or_else(|e| e.into_inner());
+        if owners.contains(&TypeId::of::<Q>()) {
+            return None;
+        }
+        owners.push(TypeId::of::<Q>());
+        Some(Self {
+            _marker: PhantomData,
+        })
+    }
+
+    pub fn cell<T>(&self, value: T) -> TCell<Q, T> {
+        TCell::new(value)
+    }
+
+    pub fn ro<'a, T: ?Sized>(&'a self, cell: &'a TCell<Q, T>) -> &'a T {
+        // SAFETY: We are the only owner of Q and writing needs `&mut self`.
+        unsafe { &*cell.value.get() }
+    }
+
+    pub fn rw<'a, T: ?Sized>(&'a mut self, cell: &'a TCell<Q, T>) -> &'a mut T {
+        // SAFETY: We are the only owner of Q and borrowed exclusively.
+        unsafe { &mut *cell.value.get() }
+    }
+
+    impl_rw2_rw3!(TCell<Q>);
+}
+
+impl<Q: 'static> Drop for TCellOwner<Q> {
+    fn drop(&mut self) {
+        let mut owners = OWNERS.lock().unwrap_or_else(|e| e.into_inner());
+        owners.retain(|id| *id != TypeId::of::<Q>());
+    }
+}
+
+impl<Q: 'static> Debug for TCellOwner<Q> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TCellOwner")
+    }
+}
+
+/// A cell borrowed through the program-wide `TCellOwner<Q>`.
+pub struct TCell<Q, T: ?Sized> {
+    _marker: Marker<Q>,
+    value: UnsafeCell<T>,
+}
+
+// SAFETY: Same reasoning as for GhostCell, the owner is borrowed in place of the value.
+unsafe impl<Q, T: ?Sized + Send + Sync> Sync for TCell<Q, T> {}
+
+impl<Q: 'static, T> TCell<Q, T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            _marker: PhantomData,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<Q: 'static, T: ?Sized> TCell<Q, T> {
+    pub fn ro<'a>(&'a self, owner: &'a TCellOwner<Q>) -> &'a T {
+        owner.ro(self)
+    }
+
+    pub fn rw<'a>(&'a self, owner: &'a mut TCellOwner<Q>) -> &'a mut T {
+        owner.rw(self)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<Q, T: ?Sized> Debug for TCell<Q, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TCell").finish_non_exhaustive()
+    }
+}
+
+/// The owner of all `TLCell<Q, _>`s on the current thread.
+pub struct TLCellOwner<Q: 'static> {
+    _marker: Marker<Q>,
+    // Owners are tied to the thread they were created on.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl<Q: 'static> TLCellOwner<Q> {
+    /// Panics if this thread already has an owner for `Q`.
+    // No Default, it shouldn't be able to panic.
+    #[allow(clippy::new_without_default)]
+    #[track_caller]
+    pub fn new() -> Self {
+        Self::try_new().expect(
+            "Illegal to create two TLCellOwner instances for the same marker type on one thread",
+        )
+    }
+
+    pub fn try_new() -> Option<Self> {
+        THREAD_OWNERS.with(|owners| {
+            let mut owners = owners.borrow_mut();
+            if owners.contains(&TypeId::of::<Q>()) {
+                return None;
+            }
+            owners.push(TypeId::of::<Q>());
+            Some(Self {
+                _marker: PhantomData,
+                _not_send: PhantomData,
+            })
+        })
+    }
+
+    pub fn cell<T>(&self, value: T) -> TLCell<Q, T> {
+        TLCell::new(value)
+    }
+
+    pub fn ro<'a, T: ?Sized>(&'a self, cell: &'a TLCell<Q, T>) -> &'a T {
+        // SAFETY: TLCells never leave their thread while shared, and we are this
+        // thread's only owner of Q.
+        unsafe { &*cell.value.get() }
+    }
+
+    pub fn rw<'a, T: ?Sized>(&'a mut self, cell: &'a TLCell<Q, T>) -> &'a mut T {
+        // SAFETY: As above, and we are borrowed exclusively.
+        unsafe { &mut *cell.value.get() }
+    }
+
+    impl_rw2_rw3!(TLCell<Q>);
+}
+
+impl<Q: 'static> Drop for TLCellOwner<Q> {
+    fn drop(&mut self) {
+        // The registry is gone if we are dropped during thread exit, nothing to clean up then.
+        let _ = THREAD_OWNERS.try_with(|owners| {
+            owners.borrow_mut().retain(|id| *id != TypeId::of::<Q>());
+        });
+    }
+}
+
+impl<Q: 'static> Debug for TLCellOwner<Q> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TLCellOwner")
+    }
+}
+
+/// A cell borrowed through the current thread's `TLCellOwner<Q>`.
+///
+/// Not `Sync`: other threads have owners of their own for the same marker.
+pub struct TLCell<Q, T: ?Sized> {
+    _marker: Marker<Q>,
+    value: UnsafeCell<T>,
+}
+
+impl<Q: 'static, T> TLCell<Q, T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            _marker: PhantomData,
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<Q: 'static, T: ?Sized> TLCell<Q, T> {
+    pub fn ro<'a>(&'a self, owner: &'a TLCellOwner<Q>) -> &'a T {
+        owner.ro(self)
+    }
+
+    pub fn rw<'a>(&'a self, owner: &'a mut TLCellOwner<Q>) -> &'a mut T {
+        owner.rw(self)
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+}
+
+impl<Q, T: ?Sized> Debug for TLCell<Q, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TLCell").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tcell_owner_is_a_singleton() {
+        struct Marker;
+
+        let mut owner = TCellOwner::<Marker>::new();
+        assert!(TCellOwner::<Marker>::try_new().is_none());
+
+        let cell = owner.cell(1);
+        *cell.rw(&mut owner) += 1;
+        assert_eq!(*owner.ro(&cell), 2);
+
+        drop(owner);
+        assert!(TCellOwner::<Marker>::try_new().is_some());
+    }
+
+    #[test]
+    fn test_tcell_shared_across_threads() {
+        struct Marker;
+
+        let mut owner = TCellOwner::<Marker>::new();
+        let cell = TCell::<Marker, _>::new(0);
+
+        std::thread::scope(|s| {
+            s.spawn(|| *owner.rw(&cell) += 1);
+        });
+
+        assert_eq!(cell.into_inner(), 1);
+    }
+
+    #[test]
+    fn test_tlcell_owner_per_thread() {
+        struct Marker;
+
+        let mut owner = TLCellOwner::<Marker>::new();
+        assert!(TLCellOwner::<Marker>::try_new().is_none());
+        std::thread::spawn(|| assert!(TLCellOwner::<Marker>::try_new().is_some()))
+            .join()
+            .unwrap();
+
+        let cell = owner.cell(String::from("a"));
+        cell.rw(&mut owner).push('b');
+        assert_eq!(cell.ro(&owner), "ab");
+    }
+
+    #[test]
+    fn test_tlcell_auto_traits() {
+        assert_not_impl!(TLCellOwner<()>: Send);
+        assert_not_impl!(TLCell<(), i32>: Sync);
+    }
+
+    #[test]
+    fn test_rw2_rw3() {
+        struct Marker;
+
+        let mut owner = TCellOwner::<Marker>::new();
+        let (a, b, c) = (owner.cell(1), owner.cell(2), owner.cell(3));
+        let (x, y) = owner.rw2(&a, &b);
+        std::mem::swap(x, y);
+        let (x, y, z) = owner.rw3(&a, &b, &c);
+        *z += *x + *y;
+        assert_eq!((a.into_inner(), b.into_inner(), c.into_inner()), (2, 1, 6));
+
+        let mut owner = TLCellOwner::<Marker>::new();
+        let (a, b) = (owner.cell(vec![1]), owner.cell(vec![2]));
+        let (x, y) = owner.rw2(&a, &b);
+        x.append(y);
+        assert_eq!((a.into_inner(), b.into_inner()), (vec![1, 2], vec![]));
+    }
+}
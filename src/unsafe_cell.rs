@@ -1,25 +1,100 @@
+use std::any;
+use std::fmt::{self, Debug};
+use std::mem;
+use std::ptr::{self, NonNull};
 
-// #[repr(transparent)]
-
-pub struct UnsafeCell<T> {
-    value: T,
+/// Only `core::cell::UnsafeCell` tells the compiler that data behind a shared
+/// reference may be mutated, a plain struct wrapping `T` would make every write
+/// through `get` undefined behavior. This type keeps the educational API surface
+/// and delegates the actual "unsafety" to the lang item, which also makes it
+/// (and every cell built on top of it) `!Sync`.
+#[repr(transparent)]
+pub struct UnsafeCell<T: ?Sized> {
+    value: core::cell::UnsafeCell<T>,
 }
 
 impl<T> UnsafeCell<T> {
-    pub fn new(value: T) -> Self {
-        Self { value }
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: core::cell::UnsafeCell::new(value),
+        }
+    }
+
+    pub const fn into_inner(self) -> T {
+        // Moving out of `self.value` isn't allowed in const fns for generic `T`,
+        // so the value is read out and the now-empty shell forgotten.
+        let value = unsafe {
+            // SAFETY: `self` is forgotten right after, so the value is not duplicated.
+            ptr::read(&self.value)
+        };
+        mem::forget(self);
+        value.into_inner()
+    }
+
+    /// Replaces the value, returning the old one.
+    ///
+    /// # Safety
+    ///
+    /// There must be no references to the value alive and no concurrent accesses to it.
+    pub unsafe fn replace(&self, val: T) -> T {
+        ptr::replace(self.get(), val)
+    }
+
+    /// Takes the value, leaving `Default::default()` in its place.
+    ///
+    /// # Safety
+    ///
+    /// Same as for [`UnsafeCell::replace`].
+    pub unsafe fn take(&self) -> T
+    where
+        T: Default,
+    {
+        self.replace(T::default())
     }
+}
 
-    pub fn into_inner(self) -> T {
-        self.value
+impl<T: ?Sized> UnsafeCell<T> {
+    pub fn from_mut(value: &mut T) -> &UnsafeCell<T> {
+        unsafe {
+            // SAFETY: UnsafeCell is repr(transparent) so it has the same layout as T,
+            // and the exclusive borrow guarantees there are no other references to the value.
+            &*(value as *mut T as *const UnsafeCell<T>)
+        }
     }
 
+    // All pointers are derived from `core::cell::UnsafeCell::get`/`raw_get`, or by
+    // `as` casts that keep provenance, never by round-tripping through integers,
+    // so the cell stays clean under `-Zmiri-strict-provenance`.
     pub fn get(&self) -> *mut T {
-        self as *const UnsafeCell<T> as *const T as *mut T
+        self.value.get()
+    }
+
+    pub fn get_non_null(&self) -> NonNull<T> {
+        unsafe {
+            // SAFETY: The pointer is derived from a reference, it can't be null.
+            NonNull::new_unchecked(self.get())
+        }
+    }
+
+    /// Like `get`, but works on a raw pointer to the cell without creating an intermediate reference.
+    pub fn raw_get(this: *const UnsafeCell<T>) -> *mut T {
+        // UnsafeCell is repr(transparent), so the cast to the inner cell is layout-preserving.
+        core::cell::UnsafeCell::raw_get(this as *const core::cell::UnsafeCell<T>)
     }
 
     pub fn get_mut(&mut self) -> &mut T {
-        &mut self.value
+        self.value.get_mut()
+    }
+}
+
+/// Never reads the contents (they may be mutably borrowed elsewhere), so this
+/// works for any `T` and only shows the type and the address.
+impl<T: ?Sized> Debug for UnsafeCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnsafeCell")
+            .field("type", &any::type_name::<T>())
+            .field("address", &self.get())
+            .finish_non_exhaustive()
     }
 }
 
@@ -33,12 +108,107 @@ mod tests {
         assert_eq!(cell.into_inner(), 42);
     }
 
+    #[test]
+    fn test_const_construction() {
+        struct Static(UnsafeCell<i32>);
+        // SAFETY: The test only ever reads the value.
+        unsafe impl Sync for Static {}
+
+        static CELL: Static = Static(UnsafeCell::new(42));
+        const INNER: i32 = UnsafeCell::new(42).into_inner();
+
+        assert_eq!(unsafe { *CELL.0.get() }, 42);
+        assert_eq!(INNER, 42);
+    }
+
     #[test]
     fn test_get() {
         let cell = UnsafeCell::new(42);
         assert_eq!(unsafe { *cell.get() }, 42);
     }
 
+    #[test]
+    fn test_from_mut() {
+        let mut value = 42;
+        let cell = UnsafeCell::from_mut(&mut value);
+        unsafe { *cell.get() = 43 };
+        assert_eq!(value, 43);
+    }
+
+    #[test]
+    fn test_raw_get() {
+        let cell = UnsafeCell::new(42);
+        let cell_ptr: *const UnsafeCell<i32> = &cell;
+        unsafe { *UnsafeCell::raw_get(cell_ptr) = 43 };
+        assert_eq!(cell.into_inner(), 43);
+    }
+
+    #[test]
+    fn test_debug_does_not_read_value() {
+        struct NoDebug;
+
+        let cell = UnsafeCell::new(NoDebug);
+        let debug = format!("{:?}", cell);
+
+        assert!(debug.starts_with("UnsafeCell { type: \"rsplay::unsafe_cell::tests::"));
+        assert!(debug.contains(&format!("address: {:?}, ..", cell.get())));
+    }
+
+    // Writing through a pointer obtained from a shared reference is only allowed
+    // because of `core::cell::UnsafeCell`, `cargo miri test` reports UB for this
+    // test when the cell stores a plain `T`.
+    #[test]
+    fn test_write_through_shared_reference() {
+        let cell = UnsafeCell::new(42);
+        let (p1, p2) = (&cell, &cell);
+
+        unsafe { *p1.get() = 43 };
+
+        assert_eq!(unsafe { *p2.get() }, 43);
+    }
+
+    #[test]
+    fn test_unsized_values() {
+        let cell: &UnsafeCell<[i32]> = &UnsafeCell::new([1, 2, 3]);
+        unsafe { (*cell.get())[1] = 42 };
+        assert_eq!(unsafe { &*cell.get() }, &[1, 42, 3]);
+
+        let mut text = String::from("hello");
+        let cell: &UnsafeCell<str> = UnsafeCell::from_mut(text.as_mut_str());
+        unsafe { (*cell.get()).make_ascii_uppercase() };
+        assert_eq!(text, "HELLO");
+
+        let cell: &mut UnsafeCell<dyn std::fmt::Display> = &mut UnsafeCell::new(42);
+        assert_eq!(cell.get_mut().to_string(), "42");
+    }
+
+    #[test]
+    fn test_replace_and_take() {
+        let cell = UnsafeCell::new(42);
+
+        assert_eq!(unsafe { cell.replace(43) }, 42);
+        assert_eq!(unsafe { cell.take() }, 43);
+        assert_eq!(cell.into_inner(), 0);
+    }
+
+    #[test]
+    fn test_not_sync() {
+        assert_not_impl!(UnsafeCell<i32>: Sync);
+        assert_not_impl!(crate::cell::Cell<i32>: Sync);
+        assert_not_impl!(crate::ref_cell::RefCell<i32>: Sync);
+    }
+
+    #[test]
+    fn test_get_non_null() {
+        let cell = UnsafeCell::new(42);
+        let ptr = cell.get_non_null();
+
+        unsafe { *ptr.as_ptr() = 43 };
+
+        assert_eq!(ptr.as_ptr(), cell.get());
+        assert_eq!(cell.into_inner(), 43);
+    }
+
     #[test]
     fn test_get_mut() {
         let mut cell = UnsafeCell::new(42);
@@ -0,0 +1,141 @@
+use std::fmt::{self, Debug};
+
+use crate::cell::Cell;
+use crate::ref_cell::RefCell;
+use crate::refs::{Ref, RefMut};
+
+/// A `RefCell` that counts its mutations.
+///
+/// Readers remember the `version()` they last saw and use `get_if_changed` to find
+/// out whether there is anything new, which is much cheaper than comparing values.
+/// Every mutable access counts as a change, whether it changed the value or not.
+pub struct VersionedCell<T> {
+    value: RefCell<T>,
+    version: Cell<u64>,
+}
+
+impl<T> VersionedCell<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            value: RefCell::new(value),
+            version: Cell::new(0),
+        }
+    }
+
+    pub fn version(&self) -> u64 {
+        self.version.get()
+    }
+
+    /// Borrows the value if it changed since the reader saw `since`.
+    #[track_caller]
+    pub fn get_if_changed(&self, since: u64) -> Option<Ref<'_, T>> {
+        if self.version() == since {
+            None
+        } else {
+            Some(self.value.borrow())
+        }
+    }
+
+    #[track_caller]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.value.borrow()
+    }
+
+    /// Counts as a change even if the value is left alone.
+    #[track_caller]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        let value = self.value.borrow_mut();
+        self.bump();
+        value
+    }
+
+    pub fn set(&self, value: T) {
+        drop(self.replace(value));
+    }
+
+    #[track_caller]
+    pub fn replace(&self, value: T) -> T {
+        std::mem::replace(&mut *self.borrow_mut(), value)
+    }
+
+    #[track_caller]
+    pub fn update<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut T) -> R,
+    {
+        f(&mut self.borrow_mut())
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    fn bump(&self) {
+        self.version.set(self.version.get() + 1);
+    }
+}
+
+impl<T: Clone> VersionedCell<T> {
+    pub fn get(&self) -> T {
+        self.borrow().clone()
+    }
+}
+
+impl<T: Debug> Debug for VersionedCell<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VersionedCell")
+            .field("value", &self.value)
+            .field("version", &self.version())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations_bump_version() {
+        let cell = VersionedCell::new(1);
+        assert_eq!(cell.version(), 0);
+
+        cell.set(2);
+        assert_eq!(cell.replace(3), 2);
+        cell.update(|value| *value += 1);
+
+        assert_eq!(cell.version(), 3);
+        assert_eq!(cell.get(), 4);
+    }
+
+    #[test]
+    fn test_reads_keep_version() {
+        let cell = VersionedCell::new(String::from("a"));
+
+        assert_eq!(*cell.borrow(), "a");
+        assert_eq!(cell.get(), "a");
+
+        assert_eq!(cell.version(), 0);
+    }
+
+    #[test]
+    fn test_get_if_changed() {
+        let cell = VersionedCell::new(vec![1]);
+        let seen = cell.version();
+        assert!(cell.get_if_changed(seen).is_none());
+
+        cell.borrow_mut().push(2);
+
+        assert_eq!(*cell.get_if_changed(seen).unwrap(), vec![1, 2]);
+        assert!(cell.get_if_changed(cell.version()).is_none());
+    }
+
+    #[test]
+    fn test_debug() {
+        let cell = VersionedCell::new(1);
+        cell.set(2);
+        assert_eq!(
+            format!("{:?}", cell),
+            "VersionedCell { value: RefCell { value: Ref { value: 2 } }, version: 1 }"
+        );
+    }
+}